@@ -0,0 +1,89 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::sync::Mutex;
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::{sign, API_KEY_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+// ----------------------------------------------------------------------
+
+/// A minimal HTTP/1.1 client of the API which signs its requests with an API key, for load generation and end-to-end
+/// tests. Requests are sent one at a time over a persistent connection, since nonces must arrive in increasing order.
+pub struct Client {
+    address: SocketAddr,
+    key: String,
+    secret: String,
+    connection: Mutex<(Option<TcpStream>, i64)>, // Open connection and the last nonce signed
+}
+
+impl Client {
+    pub fn new(address: SocketAddr, key: String, secret: String) -> Self {
+        Client { address, key, secret, connection: Mutex::new((None, 0)) }
+    }
+
+    /// Sends a request and returns the status and JSON body of the response, or null if the body is not JSON. A
+    /// connection closed by the server while idle is reopened once, resending the request with the same nonce so that
+    /// it is not executed twice.
+    pub async fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<(u16, Value)> {
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let mut connection = self.connection.lock().await;
+        let (stream, nonce) = &mut *connection;
+        *nonce = (*nonce + 1).max(Utc::now().timestamp_micros()); // Nonces of a previous run are exceeded
+        let timestamp = Utc::now().timestamp_millis();
+        let signature = sign(&self.secret, timestamp, *nonce, method, path, body.as_bytes());
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             {API_KEY_HEADER}: {}\r\n{TIMESTAMP_HEADER}: {timestamp}\r\n{NONCE_HEADER}: {nonce}\r\n\
+             {SIGNATURE_HEADER}: {signature}\r\n\r\n{body}",
+            self.address,
+            body.len(),
+            self.key,
+        );
+        if let Some(open) = stream {
+            match exchange(open, request.as_bytes()).await {
+                Ok(response) => return Ok(response),
+                Err(e) if e.kind() != ErrorKind::InvalidData => *stream = None, // Closed while idle
+                Err(e) => return Err(e),
+            }
+        }
+        let open = stream.insert(TcpStream::connect(self.address).await?);
+        open.set_nodelay(true)?;
+        exchange(open, request.as_bytes()).await.inspect_err(|_| *stream = None)
+    }
+}
+
+/// Writes a request and reads the response, which must have a `Content-Length`.
+async fn exchange(stream: &mut TcpStream, request: &[u8]) -> Result<(u16, Value)> {
+    stream.write_all(request).await?;
+    let mut response = Vec::new();
+    let mut buffer = [0; 4096];
+    let head = loop {
+        if let Some(end) = response.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        match stream.read(&mut buffer).await? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            read => response.extend_from_slice(&buffer[..read]),
+        }
+    };
+    let invalid = || Error::new(ErrorKind::InvalidData, "Invalid response.");
+    let text = std::str::from_utf8(&response[..head]).map_err(|_| invalid())?;
+    let status = text.split(' ').nth(1).and_then(|status| status.parse().ok()).ok_or_else(invalid)?;
+    let length: usize = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, length)| length.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    while response.len() < head + length {
+        match stream.read(&mut buffer).await? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            read => response.extend_from_slice(&buffer[..read]),
+        }
+    }
+    Ok((status, serde_json::from_slice(&response[head..head + length]).unwrap_or(Value::Null)))
+}
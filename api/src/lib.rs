@@ -7,103 +7,144 @@
 // TODO: what about datetime provided as timestamps
 // TODO; Create index.html
 // TODO: Test error responses
+mod client;
+mod market_data;
+mod middleware;
 mod models;
+mod persister;
 mod routes;
 
 use database::{DatabaseConnection, Engine, Migrator, MigratorTrait};
 
-use actix_web::{middleware::Logger, web, App, HttpServer, HttpResponse, post};
+use actix_web::{middleware::Logger, web, App, HttpServer, HttpResponse, get, post};
 
-use std::time::Duration;
+use std::net::TcpListener;
+use std::sync::Arc;
 use actix_web::dev::ServerHandle;
+use futures::future::{self, Either};
 use parking_lot::Mutex;
+use prometheus::{Encoder, TextEncoder};
 
-use rabbitmq_stream_client::{Environment, NoDedup, Producer};
-use rabbitmq_stream_client::types::ByteCapacity;
+use protocol::{Channel, RabbitMQ, Transport};
 
+use market_data::Tickers;
+use middleware::audit::Audit;
+use middleware::authentication::{Admin, Authentication};
+use middleware::metrics::Metrics;
+use middleware::rate_limit::{RateLimit, RateLimiter};
 use routes::router;
 
+pub use client::Client;
+pub use middleware::authentication::{sign, ADMIN_KEY_HEADER, API_KEY_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+pub use middleware::rate_limit::{Limit, Limits, RateLimits};
+
 // ----------------------------------------------------------------------
 
 struct AppState {
     db: DatabaseConnection,
-    producer: Option<Producer<NoDedup>>, // Make optional for unit tests
+    transport: Option<Arc<dyn Transport>>, // Make optional for unit tests
     stop_handle: StopHandle
 }
 
 // ----------------------------------------------------------------------
 
 #[post("/stop/{graceful}")]
-async fn stop(path: web::Path<bool>, data: web::Data<AppState>,) -> HttpResponse {
+async fn stop(path: web::Path<bool>, _admin: Admin, data: web::Data<AppState>,) -> HttpResponse {
     let graceful = path.into_inner();
-    if let Some(producer) = data.producer.clone() { // TODO: Is it correct to clone the producer
-        producer.close().await.unwrap();
+    if let Some(transport) = &data.transport {
+        transport.close().await;
     }
     let _ = &data.stop_handle.stop(graceful);
     HttpResponse::NoContent().finish()
 }
 
+/// The metrics of the process, including those of its database queries and streams, for Prometheus to scrape.
+#[get("/metrics")]
+async fn metrics() -> HttpResponse {
+    let mut text = Vec::new();
+    match TextEncoder::new().encode(&prometheus::gather(), &mut text) {
+        Ok(()) => HttpResponse::Ok().content_type(TextEncoder::new().format_type()).body(text),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 // ----------------------------------------------------------------------
 
 #[actix_web::main]
 async fn run() -> std::io::Result<()> {
     tracing_subscriber::fmt().init(); // Log SQL operations
 
-    // Establish connection to database and apply migrations
+    // Establish connection to database and RabbitMQ
     let db = Engine::connect().await.unwrap(); // Allow to panic if unsuccessful
+    let transport = RabbitMQ::connect().await.unwrap(); // Allow to panic if unsuccessful
+
+    let api = &config::get().api;
+    let listener = TcpListener::bind((api.host.as_str(), api.port))?;
+    serve(db, Arc::new(transport), listener).await
+}
+
+/// Applies migrations and serves the API on the listener until it is stopped. Orders are published to the matching
+/// engine through the transport, and its fills and market data are consumed from it. Must be awaited within an actix
+/// system.
+pub async fn serve(
+    db: DatabaseConnection,
+    transport: Arc<dyn Transport>,
+    listener: TcpListener,
+) -> std::io::Result<()> {
     Migrator::up(&db, None).await.unwrap(); // Allow to panic if unsuccessful
 
-    let producer = if !cfg!(test) {
-        // Establish connection to RabbitMQ
-        let environment = Environment::builder()
-            .host("localhost")
-            .port(5552)
-            .build()
-            .await
-            .unwrap();
-        let _ = environment.delete_stream("orders").await; // Delete stream if it exists
-        environment // Create stream at producer
-            .stream_creator()
-            .max_length(ByteCapacity::MB(50))
-            .max_age(Duration::new(30, 0))
-            .create("orders")
-            .await
-            .unwrap();
-        Some( // TODO: Mutex?
-             environment
-                 .producer()
-                 .build("orders")
-                 .await
-                 .unwrap()
-        )
-    } else {
-        None
-    };
+    let tickers = web::Data::new(Tickers::default()); // Shared by all workers
+    tickers.seed_all(&db).await.map_err(std::io::Error::other)?; // Before any trade is recorded
+
+    transport.create(Channel::Orders).await.map_err(std::io::Error::other)?;
+    // Subscriptions are not Send
+    actix_web::rt::spawn(market_data::consume_market_data(transport.clone(), tickers.clone()));
+    let persister = actix_web::rt::spawn(persister::persist_fills(transport.clone(), db.clone(), tickers.clone()));
 
     let state = web::Data::new(AppState {
         db,
-        producer,
+        transport: Some(transport),
         stop_handle: StopHandle::default()
     }); // Build app state
 
+    let limiter = RateLimiter::new(RateLimits::from(&config::get().rate_limits)).map_err(std::io::Error::other)?;
+    let limiter = Arc::new(limiter); // Shared by all workers
+
     let server = HttpServer::new({
         let state = state.clone(); // Ensure that state isn't moved
         move || {
             App::new()
+                .wrap(Audit) // Inside authentication so that the admin is known
+                .wrap(RateLimit::by_key(limiter.clone())) // Inside authentication so that the API key is known
+                .wrap(Authentication)
+                .wrap(RateLimit::by_ip(limiter.clone())) // Outside authentication to bound verifying signatures
                 .wrap(Logger::new("%r %s (%Ts)"))
+                .wrap(Metrics) // Outermost so that requests rejected by the other middleware are counted
                 .app_data(state.clone())
+                .app_data(tickers.clone())
+                .service(stop)
+                .service(metrics)
                 .configure(router)
         }
     })
-    .bind(("127.0.0.1", 8080))?
-    .workers(1)
+    .listen(listener)?
+    .workers(config::get().api.workers)
     .run();
 
     state.stop_handle.register(server.handle());
 
-    server.await?;
-
-    Ok(())
+    // Stop serving once fills can no longer be persisted, rather than serving orders and positions which miss them
+    match future::select(server, persister).await {
+        Either::Left((served, _)) => served,
+        Either::Right((Ok(Ok(())), server)) => server.await, // Only once the transport is closed
+        Either::Right((persisted, server)) => {
+            let e = persisted.map_or_else(std::io::Error::other, |persisted| persisted.unwrap_err());
+            tracing::error!("Failed to persist fills, stopping: {e}");
+            state.stop_handle.stop(true);
+            server.await?;
+            Err(e)
+        }
+    }
 }
 
 #[derive(Default)]
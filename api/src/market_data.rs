@@ -0,0 +1,192 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use actix_web::web;
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use parking_lot::Mutex;
+
+use database::{CandleResolution, Cursor, DatabaseConnection, DbErr, OrderSide, Query};
+use database::candles;
+use database::fills::Fill;
+use database::markets::{Ticker, TopOfBook};
+use protocol::{Channel, Codec, Offset, Transport};
+use protocol::codec::Header;
+
+// ----------------------------------------------------------------------
+
+/// The trades of a market over the last 24 hours in one minute buckets, along with the top of the book.
+#[derive(Default)]
+struct Market {
+    buckets: VecDeque<candles::Model>, // Oldest first
+    last_price: Option<f32>,
+    best_bid: Option<f32>,
+    best_ask: Option<f32>,
+    subscribers: Vec<UnboundedSender<Ticker>>,
+}
+
+impl Market {
+    fn record(&mut self, fill: &Fill) {
+        let open_time = CandleResolution::OneMinute.open_time(fill.created_at);
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.open_time >= open_time => { // Fills arriving late join the latest bucket
+                bucket.high = bucket.high.max(fill.price);
+                bucket.low = bucket.low.min(fill.price);
+                bucket.close = fill.price;
+                bucket.volume += fill.size;
+                bucket.quote_volume += fill.quote_size;
+                bucket.trade_count += 1;
+            }
+            _ => self.buckets.push_back(candles::Model {
+                id: 0,
+                resolution: CandleResolution::OneMinute,
+                open_time,
+                open: fill.price,
+                high: fill.price,
+                low: fill.price,
+                close: fill.price,
+                volume: fill.size,
+                quote_volume: fill.quote_size,
+                trade_count: 1,
+                market_id: fill.market_id,
+            }),
+        }
+        self.last_price = Some(fill.price);
+    }
+
+    fn ticker(&mut self, market_id: i32, now: NaiveDateTime) -> Ticker {
+        let start = CandleResolution::OneMinute.open_time(now - Duration::days(1));
+        while self.buckets.front().is_some_and(|bucket| bucket.open_time < start) {
+            self.buckets.pop_front();
+        }
+        let open = self.buckets.front().map(|bucket| bucket.open);
+        Ticker {
+            market_id,
+            last_price: self.last_price,
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+            open,
+            high: self.buckets.iter().map(|bucket| bucket.high).reduce(f32::max),
+            low: self.buckets.iter().map(|bucket| bucket.low).reduce(f32::min),
+            volume: self.buckets.iter().map(|bucket| bucket.volume).sum(),
+            quote_volume: self.buckets.iter().map(|bucket| bucket.quote_volume).sum(),
+            change_percent: open
+                .zip(self.buckets.back())
+                .map(|(open, bucket)| (bucket.close - open) / open * 100.0),
+            trade_count: self.buckets.iter().map(|bucket| bucket.trade_count).sum(),
+            updated_at: now,
+        }
+    }
+
+    /// Sends the ticker to every subscriber, dropping those which have disconnected.
+    fn broadcast(&mut self, market_id: i32) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let ticker = self.ticker(market_id, Utc::now().naive_utc());
+        self.subscribers.retain(|subscriber| subscriber.unbounded_send(ticker.clone()).is_ok());
+    }
+}
+
+/// 24 hour tickers of the markets, kept up to date from the streams of the matching engine and shared by all workers.
+#[derive(Default)]
+pub(crate) struct Tickers {
+    markets: Mutex<HashMap<i32, Market>>,
+}
+
+impl Tickers {
+    /// Seeds the tickers of every market from their history. Must complete before the streams of the matching engine
+    /// are consumed, as recording a trade or the top of the book starts tracking a market with an empty history. Markets
+    /// created afterwards have no history to load.
+    pub(crate) async fn seed_all(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let mut cursor = Cursor { limit: Some(1000), ..Default::default() };
+        loop {
+            let page = Query::find_markets(db, cursor.clone()).await?;
+            for market in &page.data {
+                self.seed(db, market.id).await?;
+            }
+            match page.next_cursor {
+                Some(next) => cursor.after = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Loads the one minute candles of a market over the last 24 hours into its ticker, replacing any history it has.
+    pub(crate) async fn seed(&self, db: &DatabaseConnection, market_id: i32) -> Result<(), DbErr> {
+        let now = Utc::now().naive_utc();
+        let buckets = Query::find_market_related_candles(
+            db,
+            market_id,
+            CandleResolution::OneMinute,
+            now - Duration::days(1),
+            now + Duration::minutes(1),
+        ).await?;
+        let mut markets = self.markets.lock();
+        let market = markets.entry(market_id).or_default();
+        market.last_price = buckets.last().map(|bucket| bucket.close);
+        market.buckets = buckets.into();
+        Ok(())
+    }
+
+    pub(crate) fn ticker(&self, market_id: i32) -> Ticker {
+        self.markets
+            .lock()
+            .entry(market_id)
+            .or_default()
+            .ticker(market_id, Utc::now().naive_utc())
+    }
+
+    /// Returns a channel of the ticker of a market, which receives an update after every trade or change of the top of
+    /// the book.
+    pub(crate) fn subscribe(&self, market_id: i32) -> UnboundedReceiver<Ticker> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.markets.lock().entry(market_id).or_default().subscribers.push(sender);
+        receiver
+    }
+
+    /// Folds a trade into the ticker of its market. The matching engine publishes a fill for each side of a match, so
+    /// only the fill of the buyer is recorded.
+    pub(crate) fn record_fill(&self, fill: &Fill) {
+        if !matches!(fill.side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long) {
+            return;
+        }
+        let mut markets = self.markets.lock();
+        let market = markets.entry(fill.market_id).or_default();
+        market.record(fill);
+        market.broadcast(fill.market_id);
+    }
+
+    pub(crate) fn record_top_of_book(&self, top_of_book: &TopOfBook) {
+        let mut markets = self.markets.lock();
+        let market = markets.entry(top_of_book.market_id).or_default();
+        market.best_bid = top_of_book.bid;
+        market.best_ask = top_of_book.ask;
+        market.broadcast(top_of_book.market_id);
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Tracks the top of the book of each market from the market data broadcast by the matching engine.
+pub(crate) async fn consume_market_data(transport: Arc<dyn Transport>, tickers: web::Data<Tickers>) {
+    let mut market_data = match transport.subscribe(Channel::MarketData, Offset::Next).await {
+        Ok(market_data) => market_data,
+        Err(e) => {
+            tracing::error!("Failed to consume market data: {e}");
+            return;
+        }
+    };
+    while let Some(data) = market_data.next().await {
+        let Ok(Some(header)) = Header::decode(&data) else {
+            continue;
+        };
+        if header.template_id != TopOfBook::TEMPLATE_ID {
+            continue; // Status changes, indicative prices and circuit breakers
+        }
+        if let Ok(top_of_book) = header.decode_message::<TopOfBook>(&data) {
+            tickers.record_top_of_book(&top_of_book);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::middleware::authentication::Admin;
+use crate::AppState;
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpMessage};
+use futures::future::LocalBoxFuture;
+
+use database::Mutation;
+
+use super::read_body;
+
+// ----------------------------------------------------------------------
+
+/// Writes every request made with an admin key to the audit log, along with the status of its response. Must be
+/// wrapped inside `Authentication` so that the `Admin` has been attached to the request.
+pub struct Audit;
+
+impl<S, B> Transform<S, ServiceRequest> for Audit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuditMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuditMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct AuditMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let Some(admin) = req.extensions().get::<Admin>().cloned() else {
+                return service.call(req).await;
+            };
+            let data = req
+                .app_data::<web::Data<AppState>>()
+                .cloned()
+                .expect("App state must be registered");
+            let method = req.method().to_string();
+            let path = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/").to_owned();
+            let body = read_body(&mut req).await?;
+            let body = (!body.is_empty()).then(|| String::from_utf8_lossy(&body).into_owned());
+
+            let res = service.call(req).await;
+            let status = match &res {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            // The action has already been performed, so a failure to record it is logged rather than returned
+            if let Err(e) = Mutation::create_audit_log(&data.db, admin.id, method, path, body, status.as_u16() as i16).await {
+                tracing::error!("Failed to write audit log for admin {}: {e}", admin.id);
+            }
+            res
+        })
+    }
+}
@@ -0,0 +1,387 @@
+use crate::models::error::Exception;
+use crate::AppState;
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use chrono::Utc;
+use futures::future::LocalBoxFuture;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use database::{ClientTier, Mutation, Query};
+
+use super::read_body;
+
+// ----------------------------------------------------------------------
+
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+pub const ADMIN_KEY_HEADER: &str = "X-ADMIN-KEY"; // Signed in the same way as an API key, but with the admin secret
+pub const TIMESTAMP_HEADER: &str = "X-API-TIMESTAMP"; // Milliseconds since the epoch
+pub const NONCE_HEADER: &str = "X-API-NONCE"; // Must increase with every request signed by a key
+pub const SIGNATURE_HEADER: &str = "X-API-SIGNATURE";
+const RECV_WINDOW: i64 = 30_000; // Maximum difference in milliseconds between the timestamp of a request and now
+
+/// Signs a request with the secret of an API key or admin. The signature is the hex encoded HMAC-SHA256 of the timestamp,
+/// nonce, method, path and query of the request, each followed by a newline, and then the body.
+pub fn sign(secret: &str, timestamp: i64, nonce: i64, method: &str, path: &str, body: &[u8]) -> String {
+    hex::encode(mac(secret, timestamp, nonce, method, path, body).finalize().into_bytes())
+}
+
+fn mac(secret: &str, timestamp: i64, nonce: i64, method: &str, path: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    for field in [timestamp.to_string().as_bytes(), nonce.to_string().as_bytes(), method.as_bytes(), path.as_bytes()] {
+        mac.update(field);
+        mac.update(b"\n");
+    }
+    mac.update(body);
+    mac
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Trade,
+    #[allow(dead_code)] // Reserved for withdrawal endpoints
+    Withdraw,
+}
+
+/// The API key which signed a request. Extracting it from a request without a valid signature fails with 401.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub api_key_id: i32,
+    pub client_id: i32,
+    pub tier: ClientTier, // Tier of the client, which determines its rate limits
+    pub sub_account_id: Option<i32>,
+    pub read: bool,
+    pub trade: bool,
+    pub withdraw: bool,
+}
+
+impl Principal {
+    /// Checks that the key belongs to the client and has the permission.
+    pub fn authorize(&self, client_id: i32, permission: Permission) -> Result<(), Exception> {
+        let permitted = match permission {
+            Permission::Read => self.read,
+            Permission::Trade => self.trade,
+            Permission::Withdraw => self.withdraw,
+        };
+        if self.client_id != client_id {
+            Err(Exception::Forbidden(format!("API key does not belong to client {client_id}.")))
+        } else if !permitted {
+            Err(Exception::Forbidden(format!("API key does not have the {permission:?} permission.")))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Restricts a sub-account filter to the sub-account of a scoped key.
+    pub fn sub_account(&self, sub_account_id: Option<i32>) -> Result<Option<i32>, Exception> {
+        match (self.sub_account_id, sub_account_id) {
+            (Some(scope), Some(id)) if scope != id => Err(Exception::Forbidden(format!(
+                "API key does not have access to sub-account {id}."
+            ))),
+            (scope, id) => Ok(scope.or(id)),
+        }
+    }
+
+    /// Checks that the key may manage the client, which requires a key that is not scoped to a sub-account.
+    pub fn authorize_management(&self, client_id: i32) -> Result<(), Exception> {
+        if self.client_id != client_id || self.sub_account_id.is_some() {
+            Err(Exception::Forbidden(format!("API key cannot manage client {client_id}.")))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = Exception;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| Exception::Unauthorized("Missing API key.".to_owned()))
+        )
+    }
+}
+
+/// The admin which signed a request. Admins are distinct from clients and their keys cannot be used as API keys.
+#[derive(Clone, Debug)]
+pub struct Admin {
+    pub id: i32,
+}
+
+impl FromRequest for Admin {
+    type Error = Exception;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        ready(match extensions.get::<Admin>() {
+            Some(admin) => Ok(admin.clone()),
+            None if extensions.get::<Principal>().is_some() => {
+                Err(Exception::Forbidden("API keys cannot perform admin actions.".to_owned()))
+            }
+            None => Err(Exception::Unauthorized("Missing admin key.".to_owned())),
+        })
+    }
+}
+
+/// The admin or API key which signed a request, for routes which clients call for themselves and admins for any client.
+#[derive(Clone, Debug)]
+pub enum Caller {
+    Admin,
+    Client(Principal),
+}
+
+impl Caller {
+    /// Checks that the caller is an admin or a key of the client with the permission.
+    pub fn authorize(&self, client_id: i32, permission: Permission) -> Result<(), Exception> {
+        match self {
+            Caller::Admin => Ok(()),
+            Caller::Client(principal) => principal.authorize(client_id, permission),
+        }
+    }
+
+    /// Checks that the caller is an admin or a key which may manage the client.
+    pub fn authorize_management(&self, client_id: i32) -> Result<(), Exception> {
+        match self {
+            Caller::Admin => Ok(()),
+            Caller::Client(principal) => principal.authorize_management(client_id),
+        }
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = Exception;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        ready(match (extensions.get::<Admin>(), extensions.get::<Principal>()) {
+            (Some(_), _) => Ok(Caller::Admin),
+            (None, Some(principal)) => Ok(Caller::Client(principal.clone())),
+            (None, None) => Err(Exception::Unauthorized("Missing API key.".to_owned())),
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Verifies the signature, timestamp and nonce of requests which carry an API key or an admin key and attaches the
+/// `Principal` or `Admin` to them. Requests without a key are passed through so that public routes remain accessible.
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            match authenticate(&mut req).await {
+                Ok(()) => service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(e) => Ok(req.error_response(e).map_into_right_body()),
+            }
+        })
+    }
+}
+
+fn header(req: &ServiceRequest, name: &str) -> Result<Option<String>, Exception> {
+    req.headers()
+        .get(name)
+        .map(|value| value.to_str().map(str::to_owned))
+        .transpose()
+        .map_err(|_| Exception::Unauthorized(format!("Invalid {name} header.")))
+}
+
+fn number(value: Option<String>, name: &str) -> Result<i64, Exception> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| Exception::Unauthorized(format!("Missing or invalid {name} header.")))
+}
+
+async fn authenticate(req: &mut ServiceRequest) -> Result<(), Exception> {
+    let data = req
+        .app_data::<web::Data<AppState>>()
+        .cloned()
+        .expect("App state must be registered");
+    match (header(req, API_KEY_HEADER)?, header(req, ADMIN_KEY_HEADER)?) {
+        (None, None) => Ok(()),
+        (Some(_), Some(_)) => Err(Exception::Unauthorized(
+            "Requests cannot carry both an API key and an admin key.".to_owned()
+        )),
+        (Some(key), None) => {
+            let api_key = Query::find_api_key_by_key(&data.db, key)
+                .await
+                .map_err(|_| Exception::Unauthorized("Invalid API key.".to_owned()))?;
+            let nonce = verify(req, &api_key.secret).await?;
+            if !Mutation::use_api_key_nonce(&data.db, api_key.id, nonce).await.map_err(Exception::Database)? {
+                return Err(Exception::Unauthorized("Nonce has already been used.".to_owned()));
+            }
+            let client = Query::find_client_by_id(&data.db, api_key.client_id)
+                .await
+                .map_err(Exception::Database)?;
+            req.extensions_mut().insert(Principal {
+                api_key_id: api_key.id,
+                client_id: api_key.client_id,
+                tier: client.tier,
+                sub_account_id: api_key.sub_account_id,
+                read: api_key.read,
+                trade: api_key.trade,
+                withdraw: api_key.withdraw,
+            });
+            Ok(())
+        }
+        (None, Some(key)) => {
+            let admin = Query::find_admin_by_key(&data.db, key)
+                .await
+                .map_err(|_| Exception::Unauthorized("Invalid admin key.".to_owned()))?;
+            let nonce = verify(req, &admin.secret).await?;
+            if !Mutation::use_admin_nonce(&data.db, admin.id, nonce).await.map_err(Exception::Database)? {
+                return Err(Exception::Unauthorized("Nonce has already been used.".to_owned()));
+            }
+            req.extensions_mut().insert(Admin { id: admin.id });
+            Ok(())
+        }
+    }
+}
+
+/// Verifies the timestamp and signature of a request against a secret and returns its nonce. Replays are rejected by
+/// the caller only once the signature is known to be valid so that nonces cannot be burned by third parties.
+async fn verify(req: &mut ServiceRequest, secret: &str) -> Result<i64, Exception> {
+    let timestamp = number(header(req, TIMESTAMP_HEADER)?, TIMESTAMP_HEADER)?;
+    let nonce = number(header(req, NONCE_HEADER)?, NONCE_HEADER)?;
+    let signature = header(req, SIGNATURE_HEADER)?
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or_else(|| Exception::Unauthorized(format!("Missing or invalid {SIGNATURE_HEADER} header.")))?;
+    if (Utc::now().timestamp_millis() - timestamp).abs() > RECV_WINDOW {
+        return Err(Exception::Unauthorized("Request timestamp is outside of the receive window.".to_owned()));
+    }
+
+    // Read the body to verify the signature and replay it for the handler
+    let body = read_body(req)
+        .await
+        .map_err(|_| Exception::Unauthorized("Unreadable request body.".to_owned()))?;
+    let path = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/").to_owned();
+    mac(secret, timestamp, nonce, req.method().as_str(), &path, &body)
+        .verify_slice(&signature)
+        .map_err(|_| Exception::Unauthorized("Invalid signature.".to_owned()))?;
+    Ok(nonce)
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use actix_web::http::Method;
+    use actix_web::test::TestRequest;
+    use database::admins;
+    use database::api_keys::Created;
+
+    use super::*;
+
+    static NONCE: AtomicI64 = AtomicI64::new(0);
+
+    fn next_nonce() -> i64 {
+        let now = Utc::now().timestamp_nanos_opt().unwrap();
+        let nonce = NONCE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nonce| Some(nonce.max(now) + 1)).unwrap();
+        nonce.max(now) + 1
+    }
+
+    /// Builds a request signed with an API key.
+    pub(crate) fn signed(method: Method, uri: &str, body: Option<serde_json::Value>, key: &Created) -> TestRequest {
+        signed_with_nonce(method, uri, body, key, next_nonce())
+    }
+
+    pub(crate) fn signed_with_nonce(
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        key: &Created,
+        nonce: i64,
+    ) -> TestRequest {
+        build(method, uri, body, API_KEY_HEADER, &key.key, &key.secret, nonce)
+    }
+
+    /// Builds a request signed with an admin key.
+    pub(crate) fn signed_by_admin(
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        admin: &admins::Model,
+    ) -> TestRequest {
+        build(method, uri, body, ADMIN_KEY_HEADER, &admin.key, &admin.secret, next_nonce())
+    }
+
+    fn build(
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        key_header: &str,
+        key: &str,
+        secret: &str,
+        nonce: i64,
+    ) -> TestRequest {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let timestamp = Utc::now().timestamp_millis();
+        let signature = sign(secret, timestamp, nonce, method.as_str(), uri, body.as_bytes());
+        TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .insert_header((key_header, key))
+            .insert_header((TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((NONCE_HEADER, nonce.to_string()))
+            .insert_header((SIGNATURE_HEADER, signature))
+            .insert_header(("content-type", "application/json"))
+            .set_payload(body)
+    }
+
+    #[test]
+    fn signature() {
+        let signature = sign("secret", 1, 2, "POST", "/orders/1", b"{}");
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign("secret", 1, 2, "POST", "/orders/1", b"{}"));
+        assert_ne!(signature, sign("secret", 1, 3, "POST", "/orders/1", b"{}"));
+        assert_ne!(signature, sign("other", 1, 2, "POST", "/orders/1", b"{}"));
+        assert_ne!(sign("secret", 1, 23, "GET", "/", b""), sign("secret", 12, 3, "GET", "/", b"")); // Fields are delimited
+    }
+}
@@ -0,0 +1,149 @@
+use crate::models::error::Exception;
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::Error;
+use futures::future::LocalBoxFuture;
+use prometheus::{register_histogram_vec, register_int_counter, register_int_counter_vec};
+use prometheus::{HistogramVec, IntCounter, IntCounterVec};
+
+// ----------------------------------------------------------------------
+
+const ORDER_ENTRY: &str = "/orders/{client_id}"; // Route of new orders, when posted
+
+static REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "api_request_duration_seconds",
+        "Time to respond to a request, by method, route and status.",
+        &["method", "route", "status"],
+        vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]
+    )
+    .unwrap()
+});
+
+static ORDERS_ACCEPTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("api_orders_accepted_total", "New orders accepted and sent to the matching engine.").unwrap()
+});
+
+static ORDERS_REJECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("api_orders_rejected_total", "New orders rejected, by reason.", &["reason"]).unwrap()
+});
+
+/// Times every request by the route it matched, so that paths with ids do not each have their own series, and counts
+/// the orders accepted and rejected. Must be wrapped outside of every other middleware, so that requests which they
+/// reject are counted.
+pub struct Metrics;
+
+impl<S, B> Transform<S, ServiceRequest> for Metrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = MetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MetricsMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let start = Instant::now();
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let route = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_owned());
+            let method = res.request().method();
+            REQUEST_DURATION
+                .with_label_values(&[method.as_str(), &route, res.status().as_str()])
+                .observe(start.elapsed().as_secs_f64());
+            if method == Method::POST && route == ORDER_ENTRY {
+                match res.response().error() {
+                    None if res.status().is_success() => ORDERS_ACCEPTED.inc(),
+                    error => {
+                        let reason = error.and_then(|e| e.as_error::<Exception>()).map_or("malformed", Exception::reason);
+                        ORDERS_REJECTED.with_label_values(&[reason]).inc();
+                    }
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+
+    use crate::middleware::rate_limit::{Limit, Limits, RateLimit, RateLimiter, RateLimits};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn main() {
+        let limits = Limits {
+            order_entry: Limit { burst: 2, rate: 0.1 },
+            query: Limit { burst: 10, rate: 0.1 },
+        };
+        let limits = RateLimits { ip: limits, anonymous: limits, tiers: HashMap::new() };
+        let app = init_service(
+            App::new()
+                .wrap(RateLimit::by_key(Arc::new(RateLimiter::new(limits).unwrap())))
+                .wrap(Metrics)
+                .route(ORDER_ENTRY, web::post().to(|path: web::Path<i32>| async move {
+                    match path.into_inner() {
+                        1 => Ok(HttpResponse::Ok().finish()),
+                        _ => Err(Exception::Forbidden("API key does not belong to client 2.".to_owned())),
+                    }
+                }))
+                .route(ORDER_ENTRY, web::get().to(HttpResponse::Ok))
+        ).await;
+        let requests = || REQUEST_DURATION.with_label_values(&["POST", ORDER_ENTRY, "200"]).get_sample_count();
+        let (count, accepted) = (requests(), ORDERS_ACCEPTED.get());
+        let rejected = |reason: &str| ORDERS_REJECTED.with_label_values(&[reason]).get();
+        let (forbidden, rate_limited) = (rejected("forbidden"), rejected("rate_limited"));
+
+        let resp = call_service(&app, TestRequest::post().uri("/orders/1").to_request()).await;
+        assert!(resp.status().is_success());
+        let resp = call_service(&app, TestRequest::post().uri("/orders/2").to_request()).await;
+        assert_eq!(resp.status(), 403);
+        let resp = call_service(&app, TestRequest::get().uri("/orders/1").to_request()).await;
+        assert!(resp.status().is_success());
+        let resp = call_service(&app, TestRequest::post().uri("/orders/1").to_request()).await;
+        assert_eq!(resp.status(), 429);
+
+        assert_eq!(requests(), count + 1); // By route rather than path
+        assert_eq!(ORDERS_ACCEPTED.get(), accepted + 1); // Queries are not orders
+        assert_eq!(rejected("forbidden"), forbidden + 1);
+        assert_eq!(rejected("rate_limited"), rate_limited + 1); // Before reaching the route
+    }
+}
@@ -0,0 +1,18 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::{web, Error};
+
+pub mod audit;
+pub mod authentication;
+pub mod metrics;
+pub mod rate_limit;
+
+// ----------------------------------------------------------------------
+
+/// Reads the body of a request and puts it back so that it can still be extracted by the handler.
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, Error> {
+    let body = req.extract::<web::Bytes>().await?;
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    req.set_payload(payload.into());
+    Ok(body)
+}
@@ -0,0 +1,402 @@
+use crate::middleware::authentication::{Admin, Principal};
+use crate::models::error::Exception;
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::net::{IpAddr, Ipv4Addr};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{Error, HttpMessage};
+use derive_more::{Display, Error};
+use futures::future::LocalBoxFuture;
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use config::RateLimitsConfig;
+use database::ClientTier;
+
+// ----------------------------------------------------------------------
+
+const MAX_BUCKETS: usize = 100_000; // Number of buckets after which full, then least recently used, ones are discarded
+
+/// A token bucket which holds up to `burst` requests and refills at `rate` requests per second. Both must be positive.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Limit {
+    pub burst: u32,
+    pub rate: f64,
+}
+
+/// Separate buckets for order entry and for everything else, so that heavy querying cannot starve order entry.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Limits {
+    pub order_entry: Limit,
+    pub query: Limit,
+}
+
+impl Limits {
+    fn get(&self, class: Class) -> Limit {
+        match class {
+            Class::OrderEntry => self.order_entry,
+            Class::Query => self.query,
+        }
+    }
+}
+
+/// Limits of every request by IP address, before it is authenticated, then of requests signed with an API key, by the
+/// tier of the client, and of anonymous requests, by IP address. Tiers which are left out are limited as anonymous
+/// requests.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RateLimits {
+    pub ip: Limits,
+    pub anonymous: Limits,
+    pub tiers: HashMap<ClientTier, Limits>,
+}
+
+impl RateLimits {
+    fn tier(&self, tier: ClientTier) -> &Limits {
+        self.tiers.get(&tier).unwrap_or(&self.anonymous)
+    }
+
+    /// Every limit which is not positive, by name.
+    fn invalid(&self) -> Vec<String> {
+        let mut named = vec![("ip".to_owned(), &self.ip), ("anonymous".to_owned(), &self.anonymous)];
+        named.extend(self.tiers.iter().map(|(tier, limits)| (format!("{tier:?}"), limits)));
+        named
+            .into_iter()
+            .flat_map(|(name, limits)| {
+                [(format!("{name}.order_entry"), limits.order_entry), (format!("{name}.query"), limits.query)]
+            })
+            .filter(|(_, limit)| limit.burst == 0 || !(limit.rate.is_finite() && limit.rate > 0.0))
+            .map(|(name, Limit { burst, rate })| format!("{name} must have a positive burst and rate, not {burst}, {rate}"))
+            .collect()
+    }
+}
+
+/// The limits of the `rate_limits` section of the configuration.
+impl From<&RateLimitsConfig> for RateLimits {
+    fn from(config: &RateLimitsConfig) -> Self {
+        let limits = |order_entry: (u32, f64), query: (u32, f64)| Limits {
+            order_entry: Limit { burst: order_entry.0, rate: order_entry.1 },
+            query: Limit { burst: query.0, rate: query.1 },
+        };
+        let c = config;
+        RateLimits {
+            ip: limits((c.ip_order_entry_burst, c.ip_order_entry_rate), (c.ip_query_burst, c.ip_query_rate)),
+            anonymous: limits(
+                (c.anonymous_order_entry_burst, c.anonymous_order_entry_rate),
+                (c.anonymous_query_burst, c.anonymous_query_rate),
+            ),
+            tiers: HashMap::from([
+                (
+                    ClientTier::Standard,
+                    limits(
+                        (c.standard_order_entry_burst, c.standard_order_entry_rate),
+                        (c.standard_query_burst, c.standard_query_rate),
+                    ),
+                ),
+                (
+                    ClientTier::Professional,
+                    limits(
+                        (c.professional_order_entry_burst, c.professional_order_entry_rate),
+                        (c.professional_query_burst, c.professional_query_rate),
+                    ),
+                ),
+                (
+                    ClientTier::MarketMaker,
+                    limits(
+                        (c.market_maker_order_entry_burst, c.market_maker_order_entry_rate),
+                        (c.market_maker_query_burst, c.market_maker_query_rate),
+                    ),
+                ),
+            ]),
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits::from(&RateLimitsConfig::default())
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Class {
+    OrderEntry,
+    Query,
+}
+
+impl Class {
+    fn of(req: &ServiceRequest) -> Self {
+        if req.path().starts_with("/orders") && req.method() != Method::GET {
+            Class::OrderEntry
+        } else {
+            Class::Query
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Subject {
+    Ip(IpAddr), // Every request from the address
+    ApiKey(i32),
+    Anonymous(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant, // When the bucket will have refilled completely, after which it can be discarded
+}
+
+#[derive(Debug, Display, Error)]
+#[display(fmt = "Invalid rate limits: {}", "_0.join(\"; \")")]
+pub struct InvalidLimits(#[error(not(source))] Vec<String>);
+
+/// Token buckets shared by all workers of the server.
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<(Subject, Class), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Fails if any limit is not positive, which would never refill its bucket.
+    pub fn new(limits: RateLimits) -> Result<Self, InvalidLimits> {
+        let invalid = limits.invalid();
+        if !invalid.is_empty() {
+            return Err(InvalidLimits(invalid));
+        }
+        Ok(RateLimiter { limits, buckets: Mutex::new(HashMap::new()) })
+    }
+
+    /// Takes a token from the bucket of the subject. Returns the time until a token is available if the bucket is
+    /// empty.
+    fn acquire(&self, subject: Subject, class: Class, limit: Limit, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        if buckets.len() >= MAX_BUCKETS {
+            // Discard the least recently used half, so that the buckets stay bounded while every one is in use
+            let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+            let (_, &mut median, _) = updated.select_nth_unstable(MAX_BUCKETS / 2);
+            buckets.retain(|_, bucket| bucket.updated > median);
+        }
+        let burst = f64::from(limit.burst);
+        let bucket = buckets.entry((subject, class)).or_insert(Bucket { tokens: burst, updated: now, full_at: now });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(burst);
+        bucket.updated = now;
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate))
+        };
+        bucket.full_at = now + Duration::from_secs_f64((burst - bucket.tokens) / limit.rate);
+        result
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+enum Layer {
+    Ip,
+    Key,
+}
+
+/// Rejects requests over their rate limit with 429 and a `Retry-After` header. The limiter is shared by two layers:
+/// one wrapped outside `Authentication`, which limits every request per IP address before its signature is verified,
+/// and one wrapped inside it, which limits requests signed with an API key per key, according to the tier of the
+/// client, and anonymous requests per IP address. Requests made with an admin key are only limited by the first.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+    layer: Layer,
+}
+
+impl RateLimit {
+    /// Limits every request per IP address. Wrapped outside `Authentication`.
+    pub fn by_ip(limiter: Arc<RateLimiter>) -> Self {
+        RateLimit { limiter, layer: Layer::Ip }
+    }
+
+    /// Limits requests per API key, or per IP address when anonymous. Wrapped inside `Authentication` so that the key
+    /// is known.
+    pub fn by_key(limiter: Arc<RateLimiter>) -> Self {
+        RateLimit { limiter, layer: Layer::Key }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), limiter: self.limiter.clone(), layer: self.layer }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+    layer: Layer,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let class = Class::of(&req);
+        let limits = &self.limiter.limits;
+        let ip = req.peer_addr().map(|addr| addr.ip()).unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let result = {
+            let extensions = req.extensions();
+            if let Layer::Ip = self.layer {
+                self.limiter.acquire(Subject::Ip(ip), class, limits.ip.get(class), Instant::now())
+            } else if extensions.get::<Admin>().is_some() {
+                Ok(())
+            } else if let Some(principal) = extensions.get::<Principal>() {
+                let limit = limits.tier(principal.tier).get(class);
+                self.limiter.acquire(Subject::ApiKey(principal.api_key_id), class, limit, Instant::now())
+            } else {
+                self.limiter.acquire(Subject::Anonymous(ip), class, limits.anonymous.get(class), Instant::now())
+            }
+        };
+        match result {
+            Ok(()) => {
+                let service = self.service.clone();
+                Box::pin(async move { service.call(req).await.map(ServiceResponse::map_into_left_body) })
+            }
+            Err(retry_after) => {
+                let e = Exception::TooManyRequests(retry_after.as_secs_f64().ceil().max(1.0) as u64);
+                Box::pin(ready(Ok(req.error_response(e).map_into_right_body())))
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{http::header, web, App, HttpResponse};
+
+    use super::*;
+
+    fn limits(order_entry: Limit, query: Limit) -> Limits {
+        Limits { order_entry, query }
+    }
+
+    #[test]
+    fn bucket() {
+        let limiter = RateLimiter::new(RateLimits::default()).unwrap();
+        let limit = Limit { burst: 2, rate: 4.0 };
+        let subject = Subject::ApiKey(1);
+        let now = Instant::now();
+
+        // Drain the burst
+        assert!(limiter.acquire(subject, Class::Query, limit, now).is_ok());
+        assert!(limiter.acquire(subject, Class::Query, limit, now).is_ok());
+        assert_eq!(limiter.acquire(subject, Class::Query, limit, now), Err(Duration::from_millis(250)));
+
+        // Buckets are separate per class and subject
+        assert!(limiter.acquire(subject, Class::OrderEntry, limit, now).is_ok());
+        assert!(limiter.acquire(Subject::ApiKey(2), Class::Query, limit, now).is_ok());
+
+        // Refill
+        let later = now + Duration::from_millis(250);
+        assert!(limiter.acquire(subject, Class::Query, limit, later).is_ok());
+        assert!(limiter.acquire(subject, Class::Query, limit, later).is_err());
+        let later = now + Duration::from_secs(10);
+        assert!(limiter.acquire(subject, Class::Query, limit, later).is_ok());
+        assert!(limiter.acquire(subject, Class::Query, limit, later).is_ok());
+        assert!(limiter.acquire(subject, Class::Query, limit, later).is_err());
+    }
+
+    #[test]
+    fn bounded() {
+        let limiter = RateLimiter::new(RateLimits::default()).unwrap();
+        let limit = Limit { burst: 2, rate: 0.001 }; // Never full again within the test
+        let now = Instant::now();
+        for id in 0..(MAX_BUCKETS as i32 + 10) {
+            let _ = limiter.acquire(Subject::ApiKey(id), Class::Query, limit, now + Duration::from_micros(id as u64));
+        }
+        let buckets = limiter.buckets.lock();
+        assert!(buckets.len() <= MAX_BUCKETS / 2 + 10); // The least recently used are discarded
+        assert!(buckets.contains_key(&(Subject::ApiKey(MAX_BUCKETS as i32 + 9), Class::Query)));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut limits = RateLimits::default();
+        limits.anonymous.query.rate = 0.0;
+        limits.tiers.get_mut(&ClientTier::Standard).unwrap().order_entry.rate = f64::NAN;
+        limits.ip.query.burst = 0;
+        let Err(InvalidLimits(invalid)) = RateLimiter::new(limits) else {
+            panic!("Invalid limits accepted");
+        };
+        assert_eq!(invalid.len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn main() {
+        let limits = RateLimits {
+            ip: limits(Limit { burst: 1, rate: 0.1 }, Limit { burst: 3, rate: 0.1 }),
+            anonymous: limits(Limit { burst: 5, rate: 0.1 }, Limit { burst: 2, rate: 0.1 }),
+            tiers: HashMap::new(),
+        };
+        let limiter = Arc::new(RateLimiter::new(limits).unwrap());
+        let app = init_service(
+            App::new()
+                .wrap(RateLimit::by_key(limiter.clone()))
+                .wrap(RateLimit::by_ip(limiter))
+                .route("/markets/", web::get().to(HttpResponse::Ok))
+                .route("/orders/1", web::post().to(HttpResponse::Ok))
+        ).await;
+        let request = |method: Method, uri: &str, ip: &str| TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .peer_addr(format!("{ip}:1234").parse().unwrap())
+            .to_request();
+
+        // Query bucket
+        for _ in 0..2 {
+            let resp = call_service(&app, request(Method::GET, "/markets/", "10.0.0.1")).await;
+            assert!(resp.status().is_success());
+        }
+        let resp = call_service(&app, request(Method::GET, "/markets/", "10.0.0.1")).await;
+        assert_eq!(resp.status(), 429);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "10");
+
+        // Order entry bucket and other addresses are unaffected, with the address limited before the key
+        let resp = call_service(&app, request(Method::POST, "/orders/1", "10.0.0.1")).await;
+        assert!(resp.status().is_success());
+        let resp = call_service(&app, request(Method::POST, "/orders/1", "10.0.0.1")).await;
+        assert_eq!(resp.status(), 429);
+        let resp = call_service(&app, request(Method::GET, "/markets/", "10.0.0.2")).await;
+        assert!(resp.status().is_success());
+    }
+}
@@ -7,29 +7,57 @@ use database::DbErr;
 
 use derive_more::{Display, Error};
 
-use rabbitmq_stream_client::error::ProducerPublishError;
+use protocol::TransportError;
 
 // ----------------------------------------------------------------------
 
 #[derive(Debug, Display, Error)]
 pub enum Exception {
     Database(DbErr),
-    RabbitMQ(ProducerPublishError)
+    Transport(TransportError),
+    Unauthorized(#[error(not(source))] String),
+    Forbidden(#[error(not(source))] String),
+    #[display(fmt = "Rate limit exceeded. Retry in {} seconds.", _0)]
+    TooManyRequests(#[error(not(source))] u64), // Seconds until the request would be accepted
+}
+
+impl Exception {
+    /// Why a request was rejected, as a label of metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Exception::Database(DbErr::RecordNotFound(_) | DbErr::Custom(_)) => "invalid",
+            Exception::Database(_) => "database",
+            Exception::Transport(_) => "transport",
+            Exception::Unauthorized(_) => "unauthorized",
+            Exception::Forbidden(_) => "forbidden",
+            Exception::TooManyRequests(_) => "rate_limited",
+        }
+    }
 }
 
 impl ResponseError for Exception {
     fn status_code(&self) -> StatusCode {
         match *self {
             Exception::Database(DbErr::RecordNotFound(_) | DbErr::Custom(_)) => StatusCode::BAD_REQUEST,
-            Exception::Database(_) | Exception::RabbitMQ(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Exception::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Exception::Forbidden(_) => StatusCode::FORBIDDEN,
+            Exception::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Exception::Database(_) | Exception::Transport(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
+        let mut builder = HttpResponse::build(self.status_code());
+        if let Exception::TooManyRequests(retry_after) = self {
+            builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        builder
             .insert_header(header::ContentType::html())
             .body(match self {
-                Exception::Database(DbErr::RecordNotFound(_) | DbErr::Custom(_)) => self.to_string(),
+                Exception::Database(DbErr::RecordNotFound(_) | DbErr::Custom(_))
+                | Exception::Unauthorized(_)
+                | Exception::Forbidden(_)
+                | Exception::TooManyRequests(_) => self.to_string(),
                 _ => "An internal server error occurred. Please try again later.".to_owned()
             })
     }
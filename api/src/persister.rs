@@ -0,0 +1,196 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use futures::StreamExt;
+
+use database::{DatabaseConnection, DbErr, Mutation, Query};
+use database::fills::{self, Fill};
+use database::orders::Cancelled;
+use protocol::{Channel, Codec, Offset, Transport};
+
+use crate::market_data::Tickers;
+
+// ----------------------------------------------------------------------
+
+/// Name under which the persister stores the offset of the last message it processed.
+const CONSUMER: &str = "fill_persister";
+const RETRY_DELAY: Duration = Duration::from_millis(100); // First wait before applying a message which failed again
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Stores the fills published by the matching engine and applies them to the orders, positions and candles they belong
+/// to, and closes the orders pulled from the book. Tickers are updated once a fill is stored. The persister resumes after the last message it applied, so that messages
+/// published while the API is down are not lost and messages redelivered after a restart are not applied twice. A
+/// message which fails to apply is retried until it succeeds, since skipping it would lose it for good. Fails if the
+/// stream no longer retains the messages after the last one applied, which were then lost.
+pub(crate) async fn persist_fills(
+    transport: Arc<dyn Transport>,
+    db: DatabaseConnection,
+    tickers: web::Data<Tickers>,
+) -> std::io::Result<()> {
+    let stored = Query::find_stream_offset(&db, CONSUMER).await.map_err(std::io::Error::other)?;
+    let mut expected = stored.map_or(0, |offset| offset + 1);
+    let offset = stored.map_or(Offset::First, |_| Offset::At(expected));
+    let mut messages = transport
+        .subscribe_with_offsets(Channel::Fills, offset)
+        .await
+        .map_err(std::io::Error::other)?;
+    while let Some((offset, data)) = messages.next().await {
+        if offset > expected { // Earlier messages may be delivered again, but none may be missing
+            return Err(std::io::Error::other(format!(
+                "Fills at offsets {expected} to {} expired before they were persisted", offset - 1
+            )));
+        }
+        expected = expected.max(offset + 1);
+        let mut delay = RETRY_DELAY;
+        while let Err(e) = persist(&db, &tickers, offset, &data).await { // Later messages wait, so the offset never passes it
+            tracing::error!("Failed to persist the message at offset {offset}, retrying in {delay:?}: {e}");
+            actix_web::rt::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+    Ok(())
+}
+
+/// Applies a fill or a cancel read from the fills stream at the offset, unless it was already applied.
+async fn persist(db: &DatabaseConnection, tickers: &Tickers, offset: u64, data: &[u8]) -> Result<(), DbErr> {
+    if let Ok(fill) = Fill::decode(data) {
+        if Mutation::persist_fill(db, CONSUMER, offset, fills::Model::from(fill.clone())).await? {
+            tickers.record_fill(&fill);
+        }
+    } else if let Ok(cancelled) = Cancelled::decode(data) {
+        Mutation::persist_cancelled(db, CONSUMER, offset, &cancelled).await?;
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use database::{Cursor, Engine, Migrator, MigratorTrait, OrderSide, OrderType};
+    use protocol::InMemory;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn main() {
+        // Set up
+        let db = Engine::connect().await.unwrap();
+        Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
+        let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+        let _ = Mutation::create_sub_account(&db, 1, "Test".to_owned()).await;
+        let _ = Mutation::create_market(&db, "BTC".to_owned(), "USD".to_owned(), 0.01, 0.01).await;
+        let order = |side: OrderSide| {
+            let db = db.clone();
+            async move {
+                Mutation::create_order(
+                    &db, 1, 1, 10.0, side, OrderType::Limit, Some(100.0), None, Some(1), None, None
+                ).await.unwrap().id
+            }
+        };
+        let fill = |order_id: i32, side: OrderSide, price: f32, size: f32| Fill {
+            price,
+            size,
+            quote_size: price * size,
+            side,
+            r#type: OrderType::Limit,
+            aggressor: None,
+            created_at: Utc::now().naive_utc(),
+            sub_account_id: 1,
+            market_id: 1,
+            order_id,
+        };
+        let stored = |db: DatabaseConnection| async move {
+            let trades = Query::find_market_related_trades(&db, 1, None, None, Cursor::default()).await.unwrap();
+            trades.data.iter().map(|trade| trade.size).collect::<Vec<_>>()
+        };
+        let filled = |db: DatabaseConnection, id: i32| async move {
+            Query::find_client_related_open_order(&db, 1, Some(id), None, None, None, None, None, None, None)
+                .await
+                .map(|order| order.filled_size)
+                .ok() // None once closed
+        };
+        let position = |db: DatabaseConnection| async move {
+            let positions = Query::find_client_related_positions(&db, 1, Default::default(), Cursor::default())
+                .await
+                .unwrap();
+            positions.data.first().map(|position| (position.side.clone(), position.size, position.avg_entry_price))
+        };
+        let tickers = web::Data::new(Tickers::default());
+        let bid = order(OrderSide::Buy).await;
+
+        // Store the fills retained by the stream, until the transport is closed
+        let transport = Arc::new(InMemory::new());
+        transport.publish(Channel::Fills, fill(bid, OrderSide::Buy, 100.0, 1.0).encode()).await.unwrap();
+        transport.close().await;
+        persist_fills(transport.clone(), db.clone(), tickers.clone()).await.unwrap();
+        assert_eq!(stored(db.clone()).await, vec![1.0]);
+        assert_eq!(Query::find_stream_offset(&db, CONSUMER).await.unwrap(), Some(0));
+
+        // Resume after the last applied fill, applying the fills published while stopped once
+        let transport = Arc::new(InMemory::new());
+        for size in [1.0, 2.0] {
+            transport.publish(Channel::Fills, fill(bid, OrderSide::Buy, 100.0, size).encode()).await.unwrap();
+        }
+        transport.close().await;
+        persist_fills(transport.clone(), db.clone(), tickers.clone()).await.unwrap();
+        persist_fills(transport, db.clone(), tickers.clone()).await.unwrap();
+        assert_eq!(stored(db.clone()).await, vec![2.0, 1.0]); // Newest first
+        assert_eq!(filled(db.clone(), bid).await, Some(3.0));
+        assert_eq!(position(db.clone()).await, Some((OrderSide::Long, 3.0, 100.0)));
+        let redelivered = fill(bid, OrderSide::Buy, 100.0, 2.0).into();
+        assert!(!Mutation::persist_fill(&db, CONSUMER, 1, redelivered).await.unwrap());
+        assert_eq!(filled(db.clone(), bid).await, Some(3.0));
+
+        // Fills close their order once it is filled in full and net into the position, which flips past zero
+        assert!(Mutation::persist_fill(&db, CONSUMER, 2, fill(bid, OrderSide::Buy, 110.0, 7.0).into()).await.unwrap());
+        assert_eq!(filled(db.clone(), bid).await, None);
+        assert_eq!(position(db.clone()).await, Some((OrderSide::Long, 10.0, 107.0)));
+        let ask = order(OrderSide::Sell).await;
+        assert!(Mutation::persist_fill(&db, CONSUMER, 3, fill(ask, OrderSide::Sell, 90.0, 4.0).into()).await.unwrap());
+        assert_eq!(position(db.clone()).await, Some((OrderSide::Long, 6.0, 107.0)));
+        assert!(Mutation::persist_fill(&db, CONSUMER, 4, fill(ask, OrderSide::Sell, 90.0, 6.0).into()).await.unwrap());
+        assert_eq!(position(db.clone()).await, Some((OrderSide::Long, 0.0, 107.0)));
+
+        // A fill which arrives after its order was closed is still stored and applied to the position
+        let pulled = order(OrderSide::Buy).await;
+        let cancelled = |order_id: i32| Cancelled {
+            order_id,
+            sub_account_id: 1,
+            market_id: 1,
+            remaining_size: 10.0,
+            cancelled_at: Utc::now().naive_utc(),
+        };
+        assert!(Mutation::persist_cancelled(&db, CONSUMER, 5, &cancelled(pulled)).await.unwrap());
+        assert!(Mutation::persist_fill(&db, CONSUMER, 6, fill(pulled, OrderSide::Buy, 100.0, 1.0).into()).await.unwrap());
+        assert_eq!(stored(db.clone()).await[0], 1.0);
+        assert_eq!(filled(db.clone(), pulled).await, None);
+        assert_eq!(position(db.clone()).await, Some((OrderSide::Long, 1.0, 100.0)));
+
+        // Orders pulled from the book are closed
+        let resting = order(OrderSide::Buy).await;
+        let transport = Arc::new(InMemory::new());
+        for _ in 0..8 {
+            transport.publish(Channel::Fills, cancelled(resting).encode()).await.unwrap(); // Only the last is unapplied
+        }
+        transport.close().await;
+        persist_fills(transport, db.clone(), tickers.clone()).await.unwrap();
+        assert_eq!(filled(db.clone(), resting).await, None);
+        assert_eq!(Query::find_stream_offset(&db, CONSUMER).await.unwrap(), Some(7));
+
+        // Fail once messages which were not yet applied have expired
+        let transport = Arc::new(InMemory::new());
+        for size in 0..10 {
+            transport.publish(Channel::Fills, fill(bid, OrderSide::Buy, 100.0, size as f32).encode()).await.unwrap();
+        }
+        transport.close().await;
+        transport.truncate(Channel::Fills, 9);
+        assert!(persist_fills(transport, db.clone(), tickers.clone()).await.is_err());
+        assert_eq!(Query::find_stream_offset(&db, CONSUMER).await.unwrap(), Some(7));
+
+        // Tear down
+        Migrator::reset(&db).await.unwrap(); // Rollback migrations
+    }
+}
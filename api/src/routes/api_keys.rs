@@ -0,0 +1,242 @@
+use crate::middleware::authentication::Caller;
+use crate::models::error::Exception;
+use crate::AppState;
+
+use actix_web::{delete, get, post, web, HttpResponse};
+
+use database::{ApiKeyPage, Cursor, Mutation, Query};
+use database::api_keys::{Created, GetRequest, Model, PostRequest};
+use database::utoipa;
+
+// ----------------------------------------------------------------------
+
+#[utoipa::path(
+    context_path = "/clients",
+    params(
+        ("client_id", description = "Client ID for which to list API keys.", example = 1),
+        GetRequest
+    ),
+    responses(
+        (status = 200, description = "Returns a page of API keys of the client without their secrets, sorted by id.", body = ApiKeyPage),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Client with id <client_id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key cannot manage client <client_id>.")),
+    ),
+    tag = "API Keys",
+)]
+#[get("/{client_id}/api_keys")]
+async fn get_client_related(
+    path: web::Path<i32>,
+    query: web::Query<GetRequest>,
+    caller: Caller,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let client_id = path.into_inner();
+    caller.authorize_management(client_id)?;
+    let cursor = Cursor { after: query.after, before: query.before, limit: query.limit };
+    let api_keys = Query::find_client_related_api_keys(&data.db, client_id, cursor)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+#[utoipa::path(
+    context_path = "/clients",
+    params(
+        ("client_id", description = "Client ID for which to create an API key.", example = 1),
+    ),
+    request_body = PostRequest,
+    responses(
+        (status = 200, description = "Returns the created API key including its secret, which is not shown again.", body = Created),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Client with id <client_id> does not exist.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key cannot manage client <client_id>.")),
+    ),
+    tag = "API Keys",
+)]
+#[post("/{client_id}/api_keys")]
+async fn create(
+    path: web::Path<i32>,
+    body: web::Json<PostRequest>,
+    caller: Caller,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let client_id = path.into_inner();
+    caller.authorize_management(client_id)?; // The first key of a client is issued by an admin
+    let api_key = Mutation::create_api_key(
+        &data.db,
+        client_id,
+        body.sub_account_id,
+        body.read,
+        body.trade,
+        body.withdraw,
+    )
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(Created::from(api_key)))
+}
+
+#[utoipa::path(
+    context_path = "/clients",
+    params(
+        ("client_id", description = "Client ID of the API key.", example = 1),
+        ("id", description = "ID of the API key to revoke.", example = 1),
+    ),
+    responses(
+        (status = 200, description = "Returns null."),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("API key with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key cannot manage client <client_id>.")),
+    ),
+    tag = "API Keys",
+)]
+#[delete("/{client_id}/api_keys/{id}")]
+async fn delete(
+    path: web::Path<(i32, i32)>,
+    caller: Caller,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let (client_id, id) = path.into_inner();
+    caller.authorize_management(client_id)?;
+    Mutation::delete_api_key(&data.db, client_id, id)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(get_client_related, create, delete),
+    components(schemas(Model, Created, PostRequest, ApiKeyPage)),
+    tags((name = "API Keys", description = "API key management endpoints.")),
+)]
+pub struct ApiDoc;
+
+pub fn router(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_client_related);
+    cfg.service(create);
+    cfg.service(delete);
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::Method;
+    use actix_web::{test, App};
+    use serde_json::json;
+    use database::{Engine, Migrator, MigratorTrait, Mutation};
+    use crate::middleware::authentication::tests::{signed, signed_by_admin, signed_with_nonce};
+    use crate::middleware::authentication::Authentication;
+    use crate::StopHandle;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn main() {
+        // Set up
+        let db = Engine::connect().await.unwrap();
+        let state = web::Data::new(AppState {
+            db: db.clone(),
+            transport: None,
+            stop_handle: StopHandle::default()
+        }); // Build app state
+        Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
+
+        // Mock server
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap(Authentication)
+                .configure(router)
+        ).await;
+        let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+        let _ = Mutation::create_client(&db, "b@gmail.com".to_owned()).await;
+        let _ = Mutation::create_sub_account(&db, 1, "Test".to_owned()).await;
+        let admin = Mutation::create_admin(&db, "Operations".to_owned()).await.unwrap();
+
+        // Create a key without a signature with error
+        let req = test::TestRequest::post()
+            .uri("/2/api_keys")
+            .set_json(json!({"read": true, "trade": true}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Create the first key as an admin
+        let req = signed_by_admin(Method::POST, "/1/api_keys", Some(json!({"read": true, "trade": true})), &admin)
+            .to_request();
+        let master: Created = test::call_and_read_body_json(&app, req).await;
+        assert!(master.read && master.trade && !master.withdraw);
+
+        // Create a key scoped to a sub-account
+        let req = signed(Method::POST, "/1/api_keys", Some(json!({"sub_account_id": 1, "read": true})), &master)
+            .to_request();
+        let scoped: Created = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(scoped.sub_account_id, Some(1));
+
+        // Create a key scoped to a sub-account of another client with error
+        let req = signed(Method::POST, "/1/api_keys", Some(json!({"sub_account_id": 100})), &master).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Get all
+        let req = signed(Method::GET, "/1/api_keys", None, &master).to_request();
+        let api_keys: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let api_keys = api_keys["data"].as_array().unwrap();
+        assert_eq!(api_keys.len(), 2);
+        assert!(api_keys[0].get("secret").is_none());
+
+        // Get all with a scoped key or for another client with error
+        let req = signed(Method::GET, "/1/api_keys", None, &scoped).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed(Method::GET, "/2/api_keys", None, &master).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        // Replayed, tampered and stale requests
+        let nonce = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let req = signed_with_nonce(Method::GET, "/1/api_keys", None, &master, nonce).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = signed_with_nonce(Method::GET, "/1/api_keys", None, &master, nonce).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed_with_nonce(Method::GET, "/1/api_keys", None, &master, nonce + 1)
+            .insert_header(("X-API-SIGNATURE", "00"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed_with_nonce(Method::GET, "/1/api_keys", None, &master, nonce + 1)
+            .insert_header(("X-API-TIMESTAMP", "0"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Delete one
+        let req = signed(Method::DELETE, &format!("/1/api_keys/{}", scoped.id), None, &master).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = signed(Method::GET, "/1/api_keys", None, &scoped).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Delete one with error
+        let req = signed(Method::DELETE, "/1/api_keys/100", None, &master).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Tear down
+        Migrator::reset(&db).await.unwrap(); // Rollback migrations
+    }
+}
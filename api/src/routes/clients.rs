@@ -1,10 +1,11 @@
 use crate::AppState;
+use crate::middleware::authentication::{Admin, Caller, Permission};
 use crate::models::error::Exception;
 
 use actix_web::{get, post, put, web, HttpResponse};
 
-use database::{Mutation, Query};
-use database::clients::{Model, GetRequest, PutRequest};
+use database::{ClientPage, Cursor, Mutation, Query};
+use database::clients::{Model, GetRequest, PutRequest, PutTierRequest};
 use database::utoipa;
 
 // ----------------------------------------------------------------------
@@ -13,17 +14,20 @@ use database::utoipa;
     context_path = "/clients",
     params(GetRequest),
     responses(
-        (status = 200, description = "Returns all clients.", body = [Model]),
+        (status = 200, description = "Returns a page of clients, sorted by id.", body = ClientPage),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
     ),
     tag = "Clients",
 )]
 #[get("/")]
 async fn get(
     query: web::Query<GetRequest>,
+    _admin: Admin,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
-    let clients = Query::find_clients(&data.db, query.page.clone(), query.page_size.clone())
+    let clients = Query::find_clients(&data.db, Cursor { after: query.after, before: query.before, limit: query.limit })
         .await
         .map_err(|e| Exception::Database(e))?;
 
@@ -36,6 +40,8 @@ async fn get(
         (status = 200, description = "Returns a client with the matching email address.", body = Model),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with email <email> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client with email <email>.")),
     ),
     params(
         ("email", description = "Email of the client to search for.", example = "example@gmail.com")
@@ -45,12 +51,20 @@ async fn get(
 #[get("/{email}")]
 async fn get_by_email(
     path: web::Path<String>,
+    caller: Caller,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let email = path.into_inner();
-    let client = Query::find_client_by_email(&data.db, email.clone())
-        .await
-        .map_err(|e| Exception::Database(e))?;
+    let client = Query::find_client_by_email(&data.db, email.clone()).await;
+    let client = match (&caller, client) {
+        (Caller::Admin, client) => client.map_err(Exception::Database)?,
+        (Caller::Client(principal), Ok(client)) if principal.client_id == client.id => client,
+        // Keys of other clients cannot tell whether the email is registered
+        (Caller::Client(_), _) => return Err(Exception::Forbidden(format!(
+            "API key does not belong to client with email {email}."
+        ))),
+    };
+    caller.authorize(client.id, Permission::Read)?;
 
     Ok(HttpResponse::Ok().json(client))
 }
@@ -64,12 +78,15 @@ async fn get_by_email(
         (status = 201, description = "Returns the created client record.", body = Model),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with email <email> already exists.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
     ),
     tag = "Clients",
 )]
 #[post("/{email}")]
 async fn create(
     path: web::Path<String>,
+    _admin: Admin,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let email = path.into_inner();
@@ -91,6 +108,8 @@ async fn create(
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with id <id> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with email <new_email> already exists.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key cannot manage client <id>.")),
     ),
     tag = "Clients",
 )]
@@ -98,9 +117,11 @@ async fn create(
 async fn update(
     path: web::Path<i32>,
     body: web::Query<PutRequest>,
+    caller: Caller,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let id = path.into_inner();
+    caller.authorize_management(id)?;
     Mutation::update_client(&data.db, id, body.new_email.clone())
         .await
         .map_err(|e| Exception::Database(e))?;
@@ -108,12 +129,42 @@ async fn update(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[utoipa::path(
+    context_path = "/clients",
+    params(
+        ("id", description = "ID of the client to update.", example = 1),
+        PutTierRequest,
+    ),
+    responses(
+        (status = 200, description = "Returns null."),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Client with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
+    ),
+    tag = "Clients",
+)]
+#[put("/{id}/tier")]
+async fn update_tier(
+    path: web::Path<i32>,
+    query: web::Query<PutTierRequest>,
+    _admin: Admin,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let id = path.into_inner();
+    Mutation::update_client_tier(&data.db, id, query.tier)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 // ----------------------------------------------------------------------
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(get, get_by_email, create, update),
-    components(schemas(Model, PutRequest)),
+    paths(get, get_by_email, create, update, update_tier),
+    components(schemas(Model, ClientPage, PutRequest)),
     tags((name = "Clients", description = "Client management endpoints.")),
 )]
 pub struct ApiDoc;
@@ -123,15 +174,21 @@ pub fn router(cfg: &mut web::ServiceConfig) {
     cfg.service(get_by_email);
     cfg.service(create);
     cfg.service(update);
+    cfg.service(update_tier);
 }
 
 // ----------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
+    use actix_web::http::Method;
     use actix_web::{test, App};
-    use database::{Engine, Migrator, MigratorTrait};
+    use database::{ClientTier, Engine, Migrator, MigratorTrait, Mutation, Query};
+    use crate::middleware::audit::Audit;
+    use crate::middleware::authentication::tests::{signed, signed_by_admin};
+    use crate::middleware::authentication::Authentication;
     use crate::StopHandle;
+    use database::api_keys::Created;
 
     use super::*;
 
@@ -141,7 +198,7 @@ mod tests {
         let db = Engine::connect().await.unwrap();
         let state = web::Data::new(AppState {
             db: db.clone(),
-            producer: None,
+            transport: None,
             stop_handle: StopHandle::default()
         }); // Build app state
         Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
@@ -150,76 +207,124 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(state.clone())
+                .wrap(Audit)
+                .wrap(Authentication)
                 .configure(router)
         ).await;
+        let admin = Mutation::create_admin(&db, "Operations".to_owned()).await.unwrap();
 
         // Create records
-        let req = test::TestRequest::post()
-            .uri("/a@gmail.com")
-            .to_request();
+        for email in ["a@gmail.com", "b@gmail.com", "c@gmail.com"] {
+            let req = signed_by_admin(Method::POST, &format!("/{email}"), None, &admin).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+        let key = Created::from(Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap());
+        let scoped_key = {
+            let _ = Mutation::create_sub_account(&db, 2, "Test".to_owned()).await;
+            Created::from(Mutation::create_api_key(&db, 2, Some(1), true, true, false).await.unwrap())
+        };
+
+        // Create one with error
+        let req = signed_by_admin(Method::POST, "/a@gmail.com", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
+        assert!(resp.status().is_client_error());
         let req = test::TestRequest::post()
-            .uri("/b@gmail.com")
+            .uri("/d@gmail.com")
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
-        let req = test::TestRequest::post()
-            .uri("/c@gmail.com")
-            .to_request();
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::POST, "/d@gmail.com", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
-        // Create one with error
-        let req = test::TestRequest::post()
-            .uri("/a@gmail.com")
-            .to_request();
-        let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_client_error());
+        assert_eq!(resp.status(), 403);
 
         // Get all
-        let req = test::TestRequest::get()
-            .uri("/")
-            .to_request();
+        let req = signed_by_admin(Method::GET, "/", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Get some
-        let req = test::TestRequest::get()
-            .uri("/?page=1&page_size=2")
-            .to_request();
+        let req = signed_by_admin(Method::GET, "/?after=1&limit=2", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
-        // Get one
+        // Get all without an admin key or with an API key with error
         let req = test::TestRequest::get()
-            .uri("/a@gmail.com")
+            .uri("/")
             .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::GET, "/", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let cursor = Cursor { limit: Some(5), ..Cursor::default() };
+        let audit_logs = Query::find_admin_related_audit_logs(&db, admin.id, cursor).await.unwrap();
+        assert_eq!(audit_logs.data.len(), 5);
+        let cursor = Cursor { after: audit_logs.next_cursor, ..Cursor::default() };
+        let audit_logs = Query::find_admin_related_audit_logs(&db, admin.id, cursor).await.unwrap();
+        assert_eq!((audit_logs.data.len(), audit_logs.next_cursor), (1, None));
+        assert_eq!(audit_logs.data[0].path, "/?after=1&limit=2");
+        assert!(audit_logs.data[0].body.is_none());
+
+        // Get one
+        let req = signed(Method::GET, "/a@gmail.com", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = signed_by_admin(Method::GET, "/b@gmail.com", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Get one with error
         let req = test::TestRequest::get()
-            .uri("/unknown@gmail.com")
+            .uri("/a@gmail.com")
             .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::GET, "/b@gmail.com", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed(Method::GET, "/unknown@gmail.com", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403); // Indistinguishable from the email of another client
+        let req = signed_by_admin(Method::GET, "/unknown@gmail.com", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
 
         // Update one
-        let req = test::TestRequest::put()
-            .uri("/1?new_email=joe@gmail.com")
-            .to_request();
+        let req = signed(Method::PUT, "/1?new_email=joe@gmail.com", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Update one with error
         let req = test::TestRequest::put()
-            .uri("/100?new_email=joe@gmail.com")
+            .uri("/1?new_email=jim@gmail.com")
             .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::PUT, "/2?new_email=jim@gmail.com", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed(Method::PUT, "/2?new_email=jim@gmail.com", None, &scoped_key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed_by_admin(Method::PUT, "/100?new_email=jim@gmail.com", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
-        let req = test::TestRequest::put()
-            .uri("/1?new_email=joe@gmail.com")
-            .to_request();
+        let req = signed(Method::PUT, "/1?new_email=joe@gmail.com", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Update a tier
+        let req = signed_by_admin(Method::PUT, "/1/tier?tier=Professional", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(Query::find_client_by_id(&db, 1).await.unwrap().tier, ClientTier::Professional);
+
+        // Update a tier with error
+        let req = signed(Method::PUT, "/1/tier?tier=MarketMaker", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed_by_admin(Method::PUT, "/100/tier?tier=Professional", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
 
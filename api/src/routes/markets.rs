@@ -1,10 +1,19 @@
+use crate::market_data::Tickers;
+use crate::middleware::authentication::Admin;
 use crate::models::error::Exception;
 use crate::AppState;
 
-use actix_web::{get, post, put, web, HttpResponse};
+use actix_web::{get, http::header, post, put, web, HttpRequest, HttpResponse};
+use futures::future::{self, Either};
+use futures::StreamExt;
 
-use database::{Mutation, Query};
-use database::markets::{Model, GetRequest, PostRequest, PutRequest};
+use database::{Cursor, Dataset, Export, ExportFormat, ExportRequest, MarketPage, Mutation, Query, TradePage};
+use database::{candles, circuit_breakers, CircuitBreakerPage};
+use database::candles::CandlePage;
+use database::fills::{Trade, TradesRequest};
+use database::markets::{Model, GetRequest, PostRequest, PutCircuitBreakerRequest, PutRequest, PutStatusRequest, StatusChange, Ticker};
+use database::markets::CircuitBreakerChange;
+use protocol::{Channel, Codec};
 
 use database::utoipa;
 
@@ -14,7 +23,7 @@ use database::utoipa;
     context_path = "/markets",
     params(GetRequest),
     responses(
-        (status = 200, description = "Returns all markets.", body = [Model]),
+        (status = 200, description = "Returns a page of markets, sorted by id.", body = MarketPage),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
     ),
     tag = "Markets",
@@ -24,7 +33,7 @@ async fn get(
     query: web::Query<GetRequest>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
-    let markets = Query::find_markets(&data.db, query.page.clone(), query.page_size.clone())
+    let markets = Query::find_markets(&data.db, Cursor { after: query.after, before: query.before, limit: query.limit })
         .await
         .map_err(|e| Exception::Database(e))?;
 
@@ -68,6 +77,8 @@ async fn get_by_ticker(
         (status = 200, description = "Returns the created market record.", body = Model),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> already exists.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
     ),
     tag = "Markets",
 )]
@@ -75,6 +86,7 @@ async fn get_by_ticker(
 async fn create(
     path: web::Path<(String, String)>,
     body: web::Json<PostRequest>,
+    _admin: Admin,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let (base_currency, quote_currency) = path.into_inner();
@@ -101,6 +113,8 @@ context_path = "/markets",
         (status = 200, description = "Returns null."),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Market with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
     ),
     tag = "Markets",
 )]
@@ -108,6 +122,7 @@ context_path = "/markets",
 async fn update(
     path: web::Path<i32>,
     body: web::Json<PutRequest>,
+    _admin: Admin,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let id = path.into_inner();
@@ -125,12 +140,329 @@ async fn update(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("id", description = "ID of the market whose trading status to change.", example = 1)
+    ),
+    request_body = PutStatusRequest,
+    responses(
+        (status = 200, description = "Returns the status change, which is also sent to the matching engine and broadcast as market data.", body = StatusChange),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
+    ),
+    tag = "Markets",
+)]
+#[put("/{id}/status")]
+async fn update_status(
+    path: web::Path<i32>,
+    body: web::Json<PutStatusRequest>,
+    _admin: Admin,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let id = path.into_inner();
+    let change = Mutation::update_market_status(&data.db, id, body.status, body.cancel_resting)
+        .await
+        .map_err(Exception::Database)?;
+
+    if let Some(transport) = &data.transport {
+        transport
+            .publish(Channel::Orders, change.encode())
+            .await
+            .map_err(Exception::Transport)?;
+    }
+
+    Ok(HttpResponse::Ok().json(change))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("id", description = "ID of the market whose circuit breaker to configure.", example = 1)
+    ),
+    request_body = PutCircuitBreakerRequest,
+    responses(
+        (status = 200, description = "Returns the updated market. The circuit breaker is also sent to the matching engine, which applies it to the following trades.", body = Model),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Circuit breaker thresholds must be positive.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
+    ),
+    tag = "Markets",
+)]
+#[put("/{id}/circuit_breaker")]
+async fn update_circuit_breaker(
+    path: web::Path<i32>,
+    body: web::Json<PutCircuitBreakerRequest>,
+    _admin: Admin,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let id = path.into_inner();
+    let market = Mutation::update_market_circuit_breaker(
+        &data.db,
+        id,
+        body.band_percent,
+        body.band_window,
+        body.band_action,
+        body.auction_duration,
+    )
+        .await
+        .map_err(Exception::Database)?;
+
+    if let Some(transport) = &data.transport {
+        let change = CircuitBreakerChange {
+            market_id: market.id,
+            band_percent: market.band_percent,
+            band_window: market.band_window,
+            band_action: market.band_action,
+            auction_duration: market.auction_duration,
+            changed_at: chrono::Utc::now().naive_utc(),
+        };
+        transport
+            .publish(Channel::Orders, change.encode())
+            .await
+            .map_err(Exception::Transport)?;
+    }
+
+    Ok(HttpResponse::Ok().json(market))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD"),
+        circuit_breakers::GetRequest
+    ),
+    responses(
+        (status = 200, description = "Returns a page of circuit breakers triggered in the market, oldest first.", body = CircuitBreakerPage),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/circuit_breakers")]
+async fn get_circuit_breakers(
+    path: web::Path<(String, String)>,
+    query: web::Query<circuit_breakers::GetRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let (base_currency, quote_currency) = path.into_inner();
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+    let cursor = Cursor { after: query.after, before: query.before, limit: query.limit };
+    let circuit_breakers = Query::find_market_related_circuit_breakers(&data.db, market.id, cursor)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(circuit_breakers))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD"),
+        candles::GetRequest
+    ),
+    responses(
+        (status = 200, description = "Returns a page of OHLCV candles of the market at the resolution, oldest first. Ranges default to the last 1000 bars, and longer ranges are split into pages of 1000 bars.", body = CandlePage),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/candles")]
+async fn get_candles(
+    path: web::Path<(String, String)>,
+    query: web::Query<candles::GetRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let (base_currency, quote_currency) = path.into_inner();
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+    let (start, end, next_start) = query.page(chrono::Utc::now().naive_utc());
+    let candles = Query::find_market_related_candles(&data.db, market.id, query.resolution, start, end)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(CandlePage { data: candles, next_start }))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD"),
+        TradesRequest
+    ),
+    responses(
+        (status = 200, description = "Returns a page of trades of the market, newest first.", body = TradePage),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/trades")]
+async fn get_trades(
+    path: web::Path<(String, String)>,
+    query: web::Query<TradesRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let (base_currency, quote_currency) = path.into_inner();
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+    let trades = Query::find_market_related_trades(
+        &data.db,
+        market.id,
+        query.start_time,
+        query.end_time,
+        Cursor { after: query.after, before: query.before, limit: query.limit },
+    )
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(trades))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD"),
+        ("dataset", description = "Dataset to export, one of fills, orders, trades or candles.", example = "fills"),
+        ExportRequest
+    ),
+    responses(
+        (status = 200, description = "Streams the dataset of the market as a CSV or Parquet file, oldest first.", body = String),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Dataset <dataset> does not exist.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API keys cannot perform admin actions.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/export/{dataset}")]
+async fn export(
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ExportRequest>,
+    _admin: Admin,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let (base_currency, quote_currency, dataset) = path.into_inner();
+    let dataset: Dataset = dataset.parse().map_err(Exception::Database)?;
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+    let format = query.format;
+    let filename = format!("{}-{}-{dataset}.{}", market.base_currency, market.quote_currency, format.extension());
+    let chunks = Export::stream(data.db.clone(), dataset, market.id, query.into_inner())
+        .map(|chunk| chunk.map(web::Bytes::from).map_err(Exception::Database));
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")))
+        .streaming(chunks))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD")
+    ),
+    responses(
+        (status = 200, description = "Returns the statistics of the trades of the market over the last 24 hours and the top of the book.", body = Ticker),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/ticker")]
+async fn get_ticker(
+    path: web::Path<(String, String)>,
+    data: web::Data<AppState>,
+    tickers: web::Data<Tickers>,
+) -> Result<HttpResponse, Exception> {
+    let (base_currency, quote_currency) = path.into_inner();
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+
+    Ok(HttpResponse::Ok().json(tickers.ticker(market.id)))
+}
+
+#[utoipa::path(
+    context_path = "/markets",
+    params(
+        ("base_currency", description = "Base currency of the market.", example = "BTC"),
+        ("quote_currency", description = "Quote currency of the market.", example = "USD")
+    ),
+    responses(
+        (status = 101, description = "Upgrades to a websocket which sends the ticker of the market on connecting and after every trade or change of the top of the book.", body = Ticker),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Market with base currency <base_currency> and quote currency <quote_currency> does not exist.")),
+    ),
+    tag = "Markets",
+)]
+#[get("/{base_currency}/{quote_currency}/ticker/ws")]
+async fn ticker_ws(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<(String, String)>,
+    data: web::Data<AppState>,
+    tickers: web::Data<Tickers>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (base_currency, quote_currency) = path.into_inner();
+    let market = Query::find_market_by_ticker(&data.db, base_currency, quote_currency)
+        .await
+        .map_err(Exception::Database)?;
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut updates = tickers.subscribe(market.id);
+    let ticker = tickers.ticker(market.id);
+    actix_web::rt::spawn(async move {
+        if session.text(serde_json::to_string(&ticker).unwrap()).await.is_err() {
+            return; // Closed by the client
+        }
+        loop {
+            match future::select(updates.next(), messages.next()).await {
+                Either::Left((Some(ticker), _)) => {
+                    if session.text(serde_json::to_string(&ticker).unwrap()).await.is_err() {
+                        return;
+                    }
+                }
+                Either::Right((Some(Ok(actix_ws::Message::Ping(bytes))), _)) => {
+                    let _ = session.pong(&bytes).await;
+                }
+                Either::Left((None, _)) | Either::Right((Some(Ok(actix_ws::Message::Close(_)) | Err(_)) | None, _)) => break,
+                Either::Right(_) => {} // Messages from the client are ignored
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
 // ----------------------------------------------------------------------
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(get, get_by_ticker, create, update),
-    components(schemas(Model, PostRequest, PutRequest)),
+    paths(
+        get, get_by_ticker, create, update, update_status, update_circuit_breaker, get_circuit_breakers, get_candles,
+        get_trades, export, get_ticker, ticker_ws
+    ),
+    components(schemas(
+        Model, PostRequest, PutRequest, PutStatusRequest, StatusChange, PutCircuitBreakerRequest,
+        circuit_breakers::Model, candles::Model, Trade, Ticker, MarketPage, TradePage, CircuitBreakerPage, CandlePage,
+        Dataset, ExportFormat
+    )),
     tags((name = "Markets", description = "Market management endpoints.")),
 )]
 pub struct ApiDoc;
@@ -140,16 +472,33 @@ pub fn router(cfg: &mut web::ServiceConfig) {
     cfg.service(get_by_ticker);
     cfg.service(create);
     cfg.service(update);
+    cfg.service(update_status);
+    cfg.service(update_circuit_breaker);
+    cfg.service(get_circuit_breakers);
+    cfg.service(get_candles);
+    cfg.service(get_trades);
+    cfg.service(export);
+    cfg.service(get_ticker);
+    cfg.service(ticker_ws);
 }
 
 // ----------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
+    use actix_web::http::Method;
     use actix_web::{test, App};
     use serde_json::json;
-    use database::{Engine, Migrator, MigratorTrait};
+    use database::{Engine, MarketStatus, Migrator, MigratorTrait, Mutation, OrderSide, OrderType, Query};
+    use crate::middleware::audit::Audit;
+    use crate::middleware::authentication::tests::{signed, signed_by_admin};
+    use crate::middleware::authentication::Authentication;
     use crate::StopHandle;
+    use database::api_keys::Created;
+    use database::fills::{self, Fill};
+    use database::markets::TopOfBook;
+    use database::orders::Cancelled;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
 
     use super::*;
 
@@ -159,41 +508,51 @@ mod tests {
         let db = Engine::connect().await.unwrap();
         let state = web::Data::new(AppState {
             db: db.clone(),
-            producer: None,
+            transport: None,
             stop_handle: StopHandle::default()
         }); // Build app state
         Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
 
         // Mock server
+        let tickers = web::Data::new(Tickers::default());
         let app = test::init_service(
             App::new()
                 .app_data(state.clone())
+                .app_data(tickers.clone())
+                .wrap(Audit)
+                .wrap(Authentication)
                 .configure(router)
         ).await;
+        let admin = Mutation::create_admin(&db, "Operations".to_owned()).await.unwrap();
+        let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+        let key = Created::from(Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap());
 
         // Create records
-        let req = test::TestRequest::post()
-            .uri("/BTC/USD")
-            .set_json(json!({"price_increment": 0.01, "size_increment": 0.01}))
+        let req = signed_by_admin(Method::POST, "/BTC/USD", Some(json!({"price_increment": 0.01, "size_increment": 0.01})), &admin)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-        let req = test::TestRequest::post()
-            .uri("/ETH/USD")
-            .set_json(json!({"price_increment": 0.01, "size_increment": 0.01}))
+        let req = signed_by_admin(Method::POST, "/ETH/USD", Some(json!({"price_increment": 0.01, "size_increment": 0.01})), &admin)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = signed_by_admin(Method::POST, "/XRP/USD", Some(json!({"price_increment": 0.01, "size_increment": 0.01})), &admin)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+        // Create one without an admin key or with an API key with error
         let req = test::TestRequest::post()
-            .uri("/XRP/USD")
+            .uri("/LTC/USD")
             .set_json(json!({"price_increment": 0.01, "size_increment": 0.01}))
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::POST, "/LTC/USD", Some(json!({"price_increment": 0.01, "size_increment": 0.01})), &key)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
         // Create one with error
-        let req = test::TestRequest::post()
-            .uri("/BTC/USD")
-            .set_json(json!({"price_increment": 0.01, "price_increment": 0.01}))
+        let req = signed_by_admin(Method::POST, "/BTC/USD", Some(json!({"price_increment": 0.01, "price_increment": 0.01})), &admin)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
@@ -205,12 +564,27 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
-        // Get some
+        // Get some, paging forwards and back by cursor
+        let req = test::TestRequest::get()
+            .uri("/?limit=1")
+            .to_request();
+        let first: MarketPage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((first.data.len(), first.prev_cursor), (1, None));
+        let req = test::TestRequest::get()
+            .uri(&format!("/?limit=1&after={}", first.next_cursor.unwrap()))
+            .to_request();
+        let second: MarketPage = test::call_and_read_body_json(&app, req).await;
+        assert!(second.data[0].id > first.data[0].id);
         let req = test::TestRequest::get()
-            .uri("/?page=1&page_size=2")
+            .uri(&format!("/?limit=1&before={}", second.prev_cursor.unwrap()))
+            .to_request();
+        let back: MarketPage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((back.data, back.prev_cursor), (first.data, None));
+        let req = test::TestRequest::get()
+            .uri("/?after=1&before=2")
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
+        assert!(resp.status().is_client_error());
 
         // Get one with error
         let req = test::TestRequest::get()
@@ -227,40 +601,344 @@ mod tests {
         assert!(resp.status().is_success());
 
         // Update one
-        let req = test::TestRequest::put()
-            .uri("/1")
-            .set_json(json!({
-                "base_currency": "BUSD",
-                "quote_currency": "USD",
-                "price_increment": 0.01,
-                "size_increment": 0.01
-            }))
+        let req = signed_by_admin(Method::PUT, "/1", Some(json!({
+            "base_currency": "BUSD",
+            "quote_currency": "USD",
+            "price_increment": 0.01,
+            "size_increment": 0.01
+        })), &admin)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Update one with error
-        let req = test::TestRequest::put()
-            .uri("/100")
-            .set_json(json!({
-                "base_currency": "BTC",
-                "quote_currency": "USD",
-                "price_increment": 0.01,
-                "size_increment": 0.01
-            }))
+        let req = signed_by_admin(Method::PUT, "/100", Some(json!({
+            "base_currency": "BTC",
+            "quote_currency": "USD",
+            "price_increment": 0.01,
+            "size_increment": 0.01
+        })), &admin)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+        let req = signed_by_admin(Method::PUT, "/2", Some(json!({
+            "base_currency": "BUSD",
+            "quote_currency": "USD",
+            "price_increment": 0.01,
+            "size_increment": 0.01
+        })), &admin)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Every admin action is audited, including failed ones
+        let audit_logs = Query::find_admin_related_audit_logs(&db, admin.id, Cursor::default()).await.unwrap().data;
+        assert_eq!(audit_logs.len(), 7);
+        assert_eq!(audit_logs[0].method, "POST");
+        assert_eq!(audit_logs[0].path, "/BTC/USD");
+        assert_eq!(audit_logs[0].status, 200);
+        assert!(audit_logs[0].body.as_ref().unwrap().contains("price_increment"));
+        assert_eq!(audit_logs[6].status, 400);
+
+        // Halt a market while keeping resting orders
+        let _ = Mutation::create_sub_account(&db, 1, "Test".to_owned()).await;
+        let order = |r#type: OrderType, price: Option<f32>| Mutation::create_order(
+            &db, 1, 1, 1.0, OrderSide::Buy, r#type, price, None, Some(2), None, None
+        );
+        let resting = order(OrderType::Limit, Some(1.0)).await.unwrap();
+        let req = signed_by_admin(Method::PUT, "/2/status", Some(json!({"status": "Halted"})), &admin).to_request();
+        let change: StatusChange = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((change.market_id, change.status, change.cancel_resting), (2, MarketStatus::Halted, false));
+        assert!(order(OrderType::Limit, Some(1.0)).await.is_err());
+        assert!(Mutation::amend_order(&db, 1, resting.id, None, Some(0.5)).await.is_err());
+
+        // Halt a market while cancelling resting orders
+        let req = signed_by_admin(Method::PUT, "/2/status", Some(json!({"status": "Halted", "cancel_resting": true})), &admin)
+            .to_request();
+        let change: StatusChange = test::call_and_read_body_json(&app, req).await;
+        assert!(change.cancel_resting);
+        let open = |id: i32| Query::find_client_related_open_order(
+            &db, 1, Some(id), None, None, None, None, None, None, None
+        );
+        assert!(open(resting.id).await.is_ok()); // Until the matching engine reports it cancelled
+        let cancelled = Cancelled {
+            order_id: resting.id,
+            sub_account_id: resting.sub_account_id,
+            market_id: 2,
+            remaining_size: 1.0,
+            cancelled_at: change.changed_at,
+        };
+        Mutation::close_cancelled_order(&db, &cancelled).await.unwrap();
+        assert!(open(resting.id).await.is_err());
+
+        // Collect limit orders only before the open
+        let req = signed_by_admin(Method::PUT, "/2/status", Some(json!({"status": "PreOpen"})), &admin).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(order(OrderType::Limit, Some(1.0)).await.is_ok());
+        assert!(order(OrderType::Market, None).await.is_err());
+        let req = signed_by_admin(Method::GET, "/ETH/USD", None, &admin).to_request();
+        let market: Model = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(market.status, MarketStatus::PreOpen);
+
+        // Change a status with error
+        let req = signed(Method::PUT, "/2/status", Some(json!({"status": "Continuous"})), &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed_by_admin(Method::PUT, "/100/status", Some(json!({"status": "Continuous"})), &admin)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Configure a circuit breaker
+        let req = signed_by_admin(Method::PUT, "/3/circuit_breaker", Some(json!({
+            "band_percent": 10.0,
+            "band_window": 300,
+            "band_action": "Auction",
+            "auction_duration": 60
+        })), &admin)
+            .to_request();
+        let market: Model = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((market.band_percent, market.band_action), (Some(10.0), MarketStatus::Auction));
+
+        // Configure a circuit breaker with error
+        for body in [
+            json!({"band_percent": -1.0, "band_window": 300, "band_action": "Auction", "auction_duration": 60}),
+            json!({"band_percent": 10.0, "band_window": 300, "band_action": "Closed", "auction_duration": 60}),
+        ] {
+            let req = signed_by_admin(Method::PUT, "/3/circuit_breaker", Some(body), &admin).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_client_error());
+        }
+
+        // Circuit breakers triggered by the matching engine switch the market status
+        let now = chrono::Utc::now().naive_utc();
+        let circuit_breaker = Mutation::create_circuit_breaker(&db, circuit_breakers::Model {
+            id: 0,
+            reference_price: 100.0,
+            price: 112.5,
+            status: MarketStatus::Auction,
+            created_at: now,
+            resumes_at: Some(now + chrono::Duration::seconds(60)),
+            market_id: 3,
+        }).await.unwrap();
+        let req = test::TestRequest::get().uri("/XRP/USD/circuit_breakers").to_request();
+        let circuit_breakers: CircuitBreakerPage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((circuit_breakers.data, circuit_breakers.next_cursor), (vec![circuit_breaker], None));
+        assert_eq!(Query::find_market_by_id(&db, 3).await.unwrap().status, MarketStatus::Auction);
+
+        // Aggregate fills into candles incrementally
+        const HOUR_OF_CANDLES: &str = "/XRP/USD/candles?resolution=1m&start=2023-01-01T00:00:00&end=2023-01-01T01:00:00";
+        let time = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").unwrap();
+        let fill = |price: f32, size: f32, created_at: &str| Fill {
+            price,
+            size,
+            quote_size: price * size,
+            side: OrderSide::Buy,
+            r#type: OrderType::Limit,
+            aggressor: Some(OrderSide::Buy),
+            created_at: time(created_at),
+            sub_account_id: 1,
+            market_id: 3,
+            order_id: resting.id,
+        };
+        for (price, size, created_at) in [
+            (10.0, 1.0, "2023-01-01T00:00:10"),
+            (12.0, 2.0, "2023-01-01T00:00:50"),
+            (9.0, 1.0, "2023-01-01T00:01:20"),
+            (11.0, 1.0, "2023-01-01T00:06:00"),
+        ] {
+            Mutation::upsert_candles_from_fill(&db, &fill(price, size, created_at).into()).await.unwrap();
+        }
+        let req = test::TestRequest::get().uri(HOUR_OF_CANDLES).to_request();
+        let candles: CandlePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(candles.next_start, None);
+        let candles = candles.data;
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[0].open_time, time("2023-01-01T00:00:00"));
+        assert_eq!(
+            (candles[0].open, candles[0].high, candles[0].low, candles[0].close, candles[0].volume, candles[0].trade_count),
+            (10.0, 12.0, 10.0, 12.0, 3.0, 2)
+        );
+        assert_eq!(candles[0].quote_volume, 34.0);
+        let req = test::TestRequest::get()
+            .uri("/XRP/USD/candles?resolution=5m&start=2023-01-01T00:00:00&end=2023-01-01T00:05:00")
+            .to_request();
+        let candles = test::call_and_read_body_json::<_, _, CandlePage>(&app, req).await.data;
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].open, candles[0].high, candles[0].low, candles[0].close), (10.0, 12.0, 9.0, 9.0));
+        assert_eq!(candles[0].trade_count, 3);
+
+        // Get candles over more than 1000 bars in pages
+        let req = test::TestRequest::get()
+            .uri("/XRP/USD/candles?resolution=1m&start=2023-01-01T00:00:00&end=2023-01-02T00:00:00")
+            .to_request();
+        let candles: CandlePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((candles.data.len(), candles.next_start), (3, Some(time("2023-01-01T16:40:00"))));
+        let req = test::TestRequest::get()
+            .uri("/XRP/USD/candles?resolution=1m&start=2023-01-01T16:40:00&end=2023-01-02T00:00:00")
+            .to_request();
+        let candles: CandlePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((candles.data.len(), candles.next_start), (0, None));
+
+        // Get candles with error
+        for uri in [
+            "/LTC/USD/candles?resolution=1m",
+            "/XRP/USD/candles?resolution=2m",
+            "/XRP/USD/candles",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_client_error());
+        }
+
+        // Backfill candles from stored fills, leaving bars without stored fills untouched
+        let seller = fills::Model {
+            id: 0,
+            price: 20.0,
+            size: 1.0,
+            quote_size: 20.0,
+            side: OrderSide::Sell,
+            r#type: OrderType::Limit,
+            aggressor: None,
+            created_at: time("2023-01-01T00:00:30"),
+            sub_account_id: 1,
+            market_id: 3,
+            order_id: resting.id,
+        };
+        Mutation::create_fill(&db, fills::Model { side: OrderSide::Buy, ..seller.clone() }).await.unwrap();
+        Mutation::create_fill(&db, seller).await.unwrap(); // The other side of the match is not counted again
+        assert_eq!(Mutation::backfill_candles(&db, 3).await.unwrap(), 5);
+        let req = test::TestRequest::get().uri(HOUR_OF_CANDLES).to_request();
+        let candles = test::call_and_read_body_json::<_, _, CandlePage>(&app, req).await.data;
+        assert_eq!(candles.len(), 3);
+        assert_eq!((candles[0].open, candles[0].close, candles[0].trade_count), (20.0, 20.0, 1));
+        assert_eq!(candles[1].open_time, time("2023-01-01T00:01:00"));
+
+        // Both fills of a match persisted from the engine count as one trade, in the transaction of the fill
+        let created_at = "2023-01-01T00:30:00";
+        for (offset, side) in [OrderSide::Sell, OrderSide::Buy].into_iter().enumerate() {
+            let fill = Fill { side, market_id: 2, ..fill(15.0, 2.0, created_at) };
+            assert!(Mutation::persist_fill(&db, "candles", offset as u64, fill.into()).await.unwrap());
+        }
+        let uri = "/ETH/USD/candles?resolution=1m&start=2023-01-01T00:30:00&end=2023-01-01T00:31:00";
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let candles = test::call_and_read_body_json::<_, _, CandlePage>(&app, req).await.data;
+        assert_eq!((candles[0].volume, candles[0].quote_volume, candles[0].trade_count), (2.0, 30.0, 1));
+
+        // Public trades are anonymized, newest first and paginated by cursor
+        for (side, aggressor, created_at) in [
+            (OrderSide::Sell, Some(OrderSide::Buy), "2023-01-01T00:01:00"),
+            (OrderSide::Buy, Some(OrderSide::Buy), "2023-01-01T00:01:00"),
+            (OrderSide::Ask, Some(OrderSide::Ask), "2023-01-01T00:02:00"),
+            (OrderSide::Bid, Some(OrderSide::Ask), "2023-01-01T00:02:00"),
+        ] {
+            Mutation::create_fill(&db, fills::Model {
+                id: 0,
+                price: 21.0,
+                size: 2.0,
+                quote_size: 42.0,
+                side,
+                r#type: OrderType::Limit,
+                aggressor,
+                created_at: time(created_at),
+                sub_account_id: 1,
+                market_id: 3,
+                order_id: resting.id,
+            }).await.unwrap();
+        }
+        let req = test::TestRequest::get().uri("/XRP/USD/trades").to_request();
+        let trades: TradePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((trades.next_cursor, trades.prev_cursor), (None, None));
+        let trades = trades.data;
+        assert_eq!(trades.len(), 3);
+        assert_eq!((trades[0].aggressor.clone(), trades[0].created_at), (Some(OrderSide::Ask), time("2023-01-01T00:02:00")));
+        assert_eq!(trades[2].aggressor, None);
+        let req = test::TestRequest::get().uri("/XRP/USD/trades").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(body["data"][0].get("sub_account_id").is_none() && body["data"][0].get("order_id").is_none());
+        let req = test::TestRequest::get().uri(&format!("/XRP/USD/trades?limit=1&after={}", trades[0].id)).to_request();
+        let page: TradePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.data, vec![trades[1].clone()]);
+        assert_eq!((page.next_cursor, page.prev_cursor), (Some(trades[1].id), Some(trades[1].id)));
+        let req = test::TestRequest::get()
+            .uri("/XRP/USD/trades?start_time=2023-01-01T00:00:00&end_time=2023-01-01T00:02:00")
             .to_request();
+        let page: TradePage = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.data, trades[1..].to_vec()); // The end is exclusive
+        let req = test::TestRequest::get().uri("/LTC/USD/trades").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error());
+
+        // Export fills, trades and candles as CSV or Parquet
+        let req = signed_by_admin(Method::GET, "/XRP/USD/export/fills?format=csv", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/csv");
+        let body = test::read_body(resp).await;
+        let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "id,price,size,quote_size,side,type,aggressor,created_at,sub_account_id,market_id,order_id");
+        assert!(lines[1].ends_with(",Buy,Limit,,2023-01-01T00:00:30,1,3,1")); // Auction fills have no aggressor
+        let uri = "/XRP/USD/export/trades?format=csv&start_time=2023-01-01T00:00:00&end_time=2023-01-01T00:02:00";
+        let req = signed_by_admin(Method::GET, uri, None, &admin).to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+        let ids: Vec<String> = lines[1..].iter().map(|line| line.split(',').next().unwrap().to_owned()).collect();
+        assert_eq!(ids, [trades[2].id.to_string(), trades[1].id.to_string()]); // Oldest first
+        assert_eq!(lines[2], format!("{},21,2,Buy,2023-01-01T00:01:00", trades[1].id));
+        let req = signed_by_admin(Method::GET, "/XRP/USD/export/fills?format=parquet", None, &admin).to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let reader = SerializedFileReader::new(body).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 6);
+        let req = signed_by_admin(Method::GET, "/XRP/USD/export/candles?format=parquet&resolution=1m", None, &admin).to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(body.starts_with(b"PAR1") && body.ends_with(b"PAR1"));
+        let req = signed_by_admin(Method::GET, "/XRP/USD/export/positions?format=csv", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
-        let req = test::TestRequest::put()
-            .uri("/2")
-            .set_json(json!({
-                "base_currency": "BUSD",
-                "quote_currency": "USD",
-                "price_increment": 0.01,
-                "size_increment": 0.01
-            }))
+        let req = test::TestRequest::get().uri("/XRP/USD/export/fills?format=csv").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Seed the ticker from the candles of the last 24 hours
+        let now = chrono::Utc::now().naive_utc();
+        for (price, created_at) in [(8.0, now - chrono::Duration::hours(25)), (10.0, now - chrono::Duration::hours(2))] {
+            let fill = Fill { created_at, market_id: 2, ..fill(price, 1.0, "2023-01-01T00:00:00") };
+            Mutation::upsert_candles_from_fill(&db, &fill.into()).await.unwrap();
+        }
+        assert_eq!(tickers.ticker(2).trade_count, 0); // Tracked before seeding
+        tickers.seed_all(&db).await.unwrap();
+        let req = test::TestRequest::get().uri("/ETH/USD/ticker").to_request();
+        let ticker: Ticker = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((ticker.market_id, ticker.last_price, ticker.open, ticker.trade_count), (2, Some(10.0), Some(10.0), 1));
+
+        // Update the ticker from trades and the top of the book
+        let mut updates = tickers.subscribe(2);
+        for side in [OrderSide::Sell, OrderSide::Buy] { // Both fills of a match count as one trade
+            tickers.record_fill(&Fill { side, created_at: now, market_id: 2, ..fill(12.0, 2.0, "2023-01-01T00:00:00") });
+        }
+        tickers.record_top_of_book(&TopOfBook { market_id: 2, bid: Some(11.5), ask: Some(12.5), created_at: now });
+        let req = test::TestRequest::get().uri("/ETH/USD/ticker").to_request();
+        let ticker: Ticker = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((ticker.last_price, ticker.best_bid, ticker.best_ask), (Some(12.0), Some(11.5), Some(12.5)));
+        assert_eq!((ticker.open, ticker.high, ticker.low), (Some(10.0), Some(12.0), Some(10.0)));
+        assert_eq!((ticker.volume, ticker.quote_volume, ticker.trade_count), (3.0, 34.0, 2));
+        assert_eq!(ticker.change_percent, Some(20.0));
+        assert_eq!(updates.next().await.unwrap().last_price, Some(12.0));
+        assert_eq!(updates.next().await.unwrap().best_bid, Some(11.5));
+
+        // Subscribe to the ticker channel
+        let req = test::TestRequest::get()
+            .uri("/ETH/USD/ticker/ws")
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
             .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 101);
+        let req = test::TestRequest::get().uri("/LTC/USD/ticker").to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
 
         // Tear down
@@ -4,6 +4,7 @@ use database::utoipa::OpenApi;
 
 use utoipa_swagger_ui::{SwaggerUi, Url};
 
+mod api_keys;
 mod clients;
 mod markets;
 mod orders;
@@ -14,7 +15,7 @@ mod fills;
 // ----------------------------------------------------------------------
 
 pub fn router(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/clients").configure(clients::router));
+    cfg.service(web::scope("/clients").configure(clients::router).configure(api_keys::router));
     cfg.service(web::scope("/markets").configure(markets::router));
     cfg.service(web::scope("/sub_accounts").configure(sub_accounts::router));
     cfg.service(web::scope("/orders").configure(orders::router));
@@ -25,6 +26,10 @@ pub fn router(cfg: &mut web::ServiceConfig) {
             Url::with_primary("clients", "/clients-schema/openapi.json", true),
             clients::ApiDoc::openapi(),
         ),
+        (
+            Url::new("api_keys", "/api_keys-schema/openapi.json"),
+            api_keys::ApiDoc::openapi(),
+        ),
         (
             Url::new("markets", "/markets-schema/openapi.json"),
             markets::ApiDoc::openapi(),
@@ -1,12 +1,18 @@
+use crate::middleware::authentication::{Caller, Permission, Principal};
 use crate::models::error::Exception;
 use crate::AppState;
 
-use actix_web::{get, post, web, HttpResponse};
-use rabbitmq_stream_client::types::Message;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use futures::future::{self, Either};
+use futures::StreamExt;
+use std::time::{Duration, Instant};
 
 use database::utoipa;
-use database::{Mutation, Query};
-use database::orders::{Response, ClientGetOpenRequest, ClientGetRequest, PostRequest};
+use database::{Cursor, DbErr, Mutation, OrderPage, OrderSide, Query};
+use database::orders::{ClientFilter, Command, MassCancel, Order, Response};
+use database::orders::{CancelAllRequest, ClientGetOpenRequest, ClientGetRequest, OrderSessionRequest, PostRequest};
+use protocol::codec::MAX_MASS_CANCEL;
+use protocol::{Channel, Codec};
 
 // ----------------------------------------------------------------------
 
@@ -19,6 +25,8 @@ use database::orders::{Response, ClientGetOpenRequest, ClientGetRequest, PostReq
     responses(
         (status = 200, description = "Returns all orders.", body = Response),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with name <sub_account_name> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with id <client_id> does not exist.")),
@@ -33,15 +41,17 @@ use database::orders::{Response, ClientGetOpenRequest, ClientGetRequest, PostReq
 async fn get_client_related_open(
     path: web::Path<i32>,
     query: web::Query<ClientGetOpenRequest>,
+    principal: Principal,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    principal.authorize(client_id, Permission::Read)?;
     let order = Query::find_client_related_open_order(
         &data.db,
         client_id,
         query.id.clone(),
         query.client_order_id.clone(),
-        query.sub_account_id.clone(),
+        principal.sub_account(query.sub_account_id)?,
         query.sub_account_name.clone(),
         query.market_id.clone(),
         query.base_currency.clone(),
@@ -61,8 +71,10 @@ async fn get_client_related_open(
         ClientGetRequest
     ),
     responses(
-        (status = 200, description = "Returns all orders.", body = [Response]),
+        (status = 200, description = "Returns a page of orders, sorted by id.", body = OrderPage),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with name <sub_account_name> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with id <client_id> does not exist.")),
@@ -77,25 +89,17 @@ async fn get_client_related_open(
 async fn get_client_related(
     path: web::Path<i32>,
     query: web::Query<ClientGetRequest>,
+    principal: Principal,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    principal.authorize(client_id, Permission::Read)?;
+    let filter = ClientFilter { sub_account_id: principal.sub_account(query.sub_account_id)?, ..query.filter() };
     let orders = Query::find_client_related_orders(
         &data.db,
         client_id,
-        query.sub_account_id.clone(),
-        query.sub_account_name.clone(),
-        query.market_id.clone(),
-        query.base_currency.clone(),
-        query.quote_currency.clone(),
-        query.client_order_id.clone(),
-        query.side.clone(),
-        query.r#type.clone(),
-        query.status.clone(),
-        query.start_time.clone(),
-        query.end_time.clone(),
-        query.page.clone(),
-        query.page_size.clone()
+        filter,
+        Cursor { after: query.after, before: query.before, limit: query.limit },
     )
         .await
         .map_err(|e| Exception::Database(e))?;
@@ -149,6 +153,8 @@ async fn get_client_related(
     responses(
         (status = 200, description = "Returns the created order record", body = Order),
         (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
         (status = 400, description = "Bad request", body = String, example = json!("Missing query arguments.")),
         (status = 400, description = "Bad request", body = String, example = json!("Invalid order parameters.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
@@ -161,9 +167,12 @@ async fn get_client_related(
 async fn create(
     path: web::Path<i32>,
     body: web::Json<PostRequest>,
+    principal: Principal,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    principal.authorize(client_id, Permission::Trade)?;
+    principal.sub_account(Some(body.sub_account_id))?;
     let order = Mutation::create_order(
         &data.db,
         client_id,
@@ -180,26 +189,158 @@ async fn create(
         .await
         .map_err(|e| Exception::Database(e))?;
 
-    if let Some(producer) = &data.producer {
-        let _ = producer
-            .send_with_confirm(
-                Message::builder()
-                    .body(serde_json::to_string(&order).unwrap()) // TODO: Dont confirm otherwise api will halt
-                    .build()
-            )
+    if let Some(transport) = &data.transport {
+        transport
+            .publish(Channel::Orders, Command::New(order.clone()).encode()) // TODO: Dont confirm otherwise api will halt
             .await
-            .map_err(|e| Exception::RabbitMQ(e))?;
+            .map_err(Exception::Transport)?;
     }
 
     Ok(HttpResponse::Ok().json(order))
 }
 
+/// Seconds between the pings of a websocket session, which is closed after two intervals without a message.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Scopes a mass cancel to a sub-account of the client, or to all of its sub-accounts.
+async fn mass_cancel(
+    data: &AppState,
+    client_id: i32,
+    sub_account_id: Option<i32>,
+    market_id: Option<i32>,
+    side: Option<OrderSide>,
+) -> Result<MassCancel, Exception> {
+    let mut sub_account_ids = Query::find_sub_account_ids_by_client_id(&data.db, client_id)
+        .await
+        .map_err(Exception::Database)?;
+    if let Some(id) = sub_account_id {
+        if !sub_account_ids.contains(&id) {
+            return Err(Exception::Database(DbErr::RecordNotFound(format!("Sub-account with id {id} does not exist."))));
+        }
+        sub_account_ids = vec![id];
+    }
+    if sub_account_ids.len() > MAX_MASS_CANCEL {
+        return Err(Exception::Database(DbErr::Custom(format!(
+            "Client {client_id} has more than {MAX_MASS_CANCEL} sub-accounts, cancel them one at a time."
+        ))));
+    }
+
+    Ok(MassCancel { market_id, sub_account_ids, side })
+}
+
+/// Sends a mass cancel to the matching engine, which reports every order it pulls so that it is closed.
+async fn publish_mass_cancel(data: &AppState, mass_cancel: &MassCancel) -> Result<(), Exception> {
+    if let Some(transport) = &data.transport {
+        transport
+            .publish(Channel::Orders, mass_cancel.encode())
+            .await
+            .map_err(Exception::Transport)?;
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/orders",
+    params(
+        ("client_id", description = "Client ID whose resting orders to cancel.", example = 1),
+        CancelAllRequest
+    ),
+    responses(
+        (status = 202, description = "Returns the mass cancel sent to the matching engine, which closes every order it pulls.", body = MassCancel),
+        (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
+        (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
+    ),
+    tag = "Orders",
+)]
+#[post("/{client_id}/cancel_all")]
+async fn cancel_all(
+    path: web::Path<i32>,
+    query: web::Query<CancelAllRequest>,
+    caller: Caller, // Admins may pull the orders of any client
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Exception> {
+    let client_id = path.into_inner();
+    caller.authorize(client_id, Permission::Trade)?;
+    let sub_account_id = match &caller {
+        Caller::Admin => query.sub_account_id,
+        Caller::Client(principal) => principal.sub_account(query.sub_account_id)?,
+    };
+    let mass_cancel = mass_cancel(&data, client_id, sub_account_id, query.market_id, query.side.clone()).await?;
+    publish_mass_cancel(&data, &mass_cancel).await?;
+
+    Ok(HttpResponse::Accepted().json(mass_cancel))
+}
+
+#[utoipa::path(
+    context_path = "/orders",
+    params(
+        ("client_id", description = "Client ID whose orders the session guards.", example = 1),
+        OrderSessionRequest
+    ),
+    responses(
+        (status = 101, description = "Upgrades to a websocket which is pinged every 30 seconds and closed after 60 seconds without a message. With cancel_on_disconnect, the resting orders of the sub-account of the API key, or of every sub-account of the client, are cancelled when it closes for any reason."),
+        (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
+    ),
+    tag = "Orders",
+)]
+#[get("/{client_id}/ws")]
+async fn session_ws(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<i32>,
+    query: web::Query<OrderSessionRequest>,
+    principal: Principal,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let client_id = path.into_inner();
+    principal.authorize(client_id, Permission::Trade)?;
+    let cancel_on_disconnect = query.cancel_on_disconnect.unwrap_or(false);
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(async move {
+        let mut heartbeat = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+        loop {
+            match future::select(Box::pin(heartbeat.tick()), messages.next()).await {
+                Either::Left(_) => {
+                    if last_seen.elapsed() > 2 * HEARTBEAT_INTERVAL || session.ping(b"").await.is_err() {
+                        break; // The client is gone
+                    }
+                }
+                Either::Right((Some(Ok(actix_ws::Message::Ping(bytes))), _)) => {
+                    last_seen = Instant::now();
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Either::Right((Some(Ok(actix_ws::Message::Close(_)) | Err(_)) | None, _)) => break,
+                Either::Right(_) => last_seen = Instant::now(), // Any other message keeps the session alive
+            }
+        }
+        let _ = session.close(None).await;
+        if cancel_on_disconnect {
+            let cancelled = match mass_cancel(&data, client_id, principal.sub_account_id, None, None).await {
+                Ok(mass_cancel) => publish_mass_cancel(&data, &mass_cancel).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = cancelled {
+                tracing::error!("Failed to cancel the orders of client {client_id} on disconnect: {e}");
+            }
+        }
+    });
+
+    Ok(response)
+}
+
 // ----------------------------------------------------------------------
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(get_client_related_open, get_client_related, create),
-    components(schemas(Response)),
+    paths(get_client_related_open, get_client_related, create, cancel_all, session_ws),
+    components(schemas(Response, OrderPage, Order, MassCancel)),
     tags((name = "Orders", description = "Order management endpoints.")),
 )]
 pub struct ApiDoc;
@@ -208,15 +349,24 @@ pub fn router(cfg: &mut web::ServiceConfig) {
     cfg.service(get_client_related_open);
     cfg.service(get_client_related);
     cfg.service(create);
+    cfg.service(cancel_all);
+    cfg.service(session_ws);
 }
 
 // ----------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
+    use actix_web::http::Method;
     use actix_web::{test, App};
     use serde_json::json;
     use database::{Engine, Migrator, MigratorTrait, Mutation, OrderSide, OrderType};
+    use database::api_keys::Created;
+    use database::orders::Cancelled;
+    use protocol::{InMemory, Offset, Transport};
+    use std::sync::Arc;
+    use crate::middleware::authentication::tests::{signed, signed_by_admin};
+    use crate::middleware::authentication::Authentication;
     use crate::StopHandle;
 
     use super::*;
@@ -225,9 +375,10 @@ mod tests {
     async fn main() {
         // Set up
         let db = Engine::connect().await.unwrap();
+        let transport = Arc::new(InMemory::new());
         let state = web::Data::new(AppState {
             db: db.clone(),
-            producer: None,
+            transport: Some(transport.clone()),
             stop_handle: StopHandle::default()
         }); // Build app state
         Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
@@ -236,6 +387,7 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(state.clone())
+                .wrap(Authentication)
                 .configure(router)
         ).await;
         let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
@@ -254,10 +406,30 @@ mod tests {
             0.01,
             0.01
         ).await;
-        // Create records
+        let key = Created::from(Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap());
+        let read_only = Created::from(Mutation::create_api_key(&db, 1, Some(1), true, false, false).await.unwrap());
+
+        // Create one without a key or permission with error
+        let order = json!({
+            "sub_account_id": 1,
+            "size": 100.0,
+            "side": OrderSide::Buy,
+            "type": OrderType::Limit,
+            "price": 100.0,
+            "market_id": 1,
+        });
         let req = test::TestRequest::post()
             .uri("/1")
-            .set_json(json!({
+            .set_json(&order)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::POST, "/1", Some(order), &read_only).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        // Create records
+        let req = signed(Method::POST, "/1", Some(json!({
                 "sub_account_id": 1,
                 "size": 100.0,
                 "side": OrderSide::Buy,
@@ -267,13 +439,11 @@ mod tests {
                 "market_id": 1,
                 "base_currency": "BTC",
                 "quote_currency": "USD",
-            }))
+            })), &key)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-        let req = test::TestRequest::post()
-            .uri("/1")
-            .set_json(json!({
+        let req = signed(Method::POST, "/1", Some(json!({
                 "sub_account_id": 1,
                 "size": 100.0,
                 "side": OrderSide::Buy,
@@ -283,44 +453,111 @@ mod tests {
                 "market_id": 1,
                 "base_currency": "ETH",
                 "quote_currency": "USD",
-            }))
+            })), &key)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
+        // Create or amend one with a price or size which is not a positive number with error
+        let order = |price: Option<f32>, size: f32| Mutation::create_order(
+            &db, 1, 1, size, OrderSide::Buy, OrderType::Limit, price, None, Some(1), None, None
+        );
+        let invalid = [(f32::NAN, 1.0), (100.0, f32::NAN), (f32::INFINITY, 1.0), (100.0, -1.0)];
+        for (price, size) in invalid {
+            assert!(order(Some(price), size).await.is_err());
+        }
+        assert!(Mutation::amend_order(&db, 1, 1, Some(f32::NAN), None).await.is_err());
+        assert!(Mutation::amend_order(&db, 1, 1, None, Some(f32::INFINITY)).await.is_err());
+
         // Get all for client with error
-        let req = test::TestRequest::get()
-            .uri("/2")
-            .to_request();
+        let req = signed(Method::GET, "/2", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
 
         // Get all for client
-        let req = test::TestRequest::get()
-            .uri("/1")
-            .to_request();
+        let req = signed(Method::GET, "/1", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Get some for client
-        let req = test::TestRequest::get()
-            .uri("/1?sub_account_id=1&market_id=1&side=Buy&type=Limit&status=Closed")
-            .to_request();
+        let req = signed(Method::GET, "/1?sub_account_id=1&market_id=1&side=Buy&type=Limit&status=Closed", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-        let req = test::TestRequest::get()
-            .uri("/1?sub_account_name=Test&base_currency=BTC&quote_currency=USD&side=Buy&type=Limit&status=Open")
-            .to_request();
+        let req = signed(Method::GET, "/1?sub_account_name=Test&base_currency=BTC&quote_currency=USD&side=Buy&type=Limit&status=Open", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
+        // Get all for a sub-account of another scope with error
+        let req = signed(Method::GET, "/1?sub_account_id=2", None, &read_only).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
         // Get all open for client
-        let req = test::TestRequest::get()
-            .uri("/open/1")
-            .to_request();
+        let req = signed(Method::GET, "/open/1", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
+        // Cancel all with error
+        let req = test::TestRequest::post().uri("/1/cancel_all").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::POST, "/1/cancel_all", None, &read_only).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        // Cancel all in a market and on a side
+        let req = signed(Method::POST, "/1/cancel_all?sub_account_id=2", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let mut published = transport.subscribe(Channel::Orders, Offset::Next).await.unwrap();
+        let req = signed(Method::POST, "/1/cancel_all?market_id=1&side=Buy", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+        let mass_cancel = MassCancel { market_id: Some(1), sub_account_ids: vec![1], side: Some(OrderSide::Buy) };
+        assert_eq!(test::read_body_json::<MassCancel, _>(resp).await, mass_cancel);
+        assert_eq!(MassCancel::decode(&published.next().await.unwrap()).unwrap(), mass_cancel);
+
+        // Close the orders pulled by the matching engine
+        let req = signed(Method::GET, "/1?status=Open", None, &key).to_request();
+        let open: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        for order in open["data"].as_array().unwrap() {
+            let cancelled = Cancelled {
+                order_id: order["id"].as_i64().unwrap() as i32,
+                sub_account_id: 1,
+                market_id: 1,
+                remaining_size: 100.0,
+                cancelled_at: chrono::Utc::now().naive_utc(),
+            };
+            Mutation::close_cancelled_order(&db, &cancelled).await.unwrap();
+            Mutation::close_cancelled_order(&db, &cancelled).await.unwrap(); // Redelivered
+        }
+        let req = signed(Method::GET, "/1?status=Open", None, &key).to_request();
+        let open: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(open["data"], json!([]));
+
+        // Cancel all as an admin
+        let admin = Mutation::create_admin(&db, "Risk".to_owned()).await.unwrap();
+        let req = signed_by_admin(Method::POST, "/1/cancel_all", None, &admin).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+        let mass_cancel = MassCancel { market_id: None, sub_account_ids: vec![1], side: None };
+        assert_eq!(MassCancel::decode(&published.next().await.unwrap()).unwrap(), mass_cancel);
+
+        // Open a session which cancels on disconnect
+        let req = signed(Method::GET, "/1/ws?cancel_on_disconnect=true", None, &key)
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 101);
+        drop(resp); // Disconnects
+        assert_eq!(MassCancel::decode(&published.next().await.unwrap()).unwrap(), mass_cancel);
+        let req = test::TestRequest::get().uri("/1/ws").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
         // Tear down
         Migrator::reset(&db).await.unwrap(); // Rollback migrations
     }
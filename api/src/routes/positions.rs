@@ -1,11 +1,12 @@
+use crate::middleware::authentication::{Permission, Principal};
 use crate::models::error::Exception;
 use crate::AppState;
 
 use actix_web::{get, web, HttpResponse};
 
 use database::utoipa;
-use database::Query;
-use database::positions::{ClientGetRequest, Model};
+use database::{Cursor, PositionPage, Query};
+use database::positions::{ClientFilter, ClientGetRequest, Model, Response};
 
 // ----------------------------------------------------------------------
 
@@ -16,8 +17,10 @@ use database::positions::{ClientGetRequest, Model};
         ClientGetRequest
     ),
     responses(
-        (status = 200, description = "Returns all positions.", body = [Model]),
+        (status = 200, description = "Returns a page of positions, sorted by id.", body = PositionPage),
         (status = 500, description = "Internal server error.", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized.", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden.", body = String, example = json!("API key does not belong to client <client_id>.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Sub-account with id <sub_account_id> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Client with id <client_id> does not exist.")),
         (status = 400, description = "Bad request.", body = String, example = json!("Market with id <market_id> does not exist.")),
@@ -31,20 +34,17 @@ use database::positions::{ClientGetRequest, Model};
 async fn get_client_related(
     path: web::Path<i32>,
     query: web::Query<ClientGetRequest>,
+    principal: Principal,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    principal.authorize(client_id, Permission::Read)?;
+    let filter = ClientFilter { sub_account_id: principal.sub_account(query.sub_account_id)?, ..query.filter() };
     let positions = Query::find_client_related_positions(
         &data.db,
         client_id,
-        query.sub_account_id.clone(),
-        query.sub_account_name.clone(),
-        query.market_id.clone(),
-        query.base_currency.clone(),
-        query.quote_currency.clone(),
-        query.side.clone(),
-        query.page.clone(),
-        query.page_size.clone()
+        filter,
+        Cursor { after: query.after, before: query.before, limit: query.limit },
     )
         .await
         .map_err(|e| Exception::Database(e))?;
@@ -57,7 +57,7 @@ async fn get_client_related(
 #[derive(utoipa::OpenApi)]
 #[openapi(
     paths(get_client_related),
-    components(schemas(Model)),
+    components(schemas(Model, Response, PositionPage)),
     tags((name = "Positions", description = "Position management endpoints.")),
 )]
 pub struct ApiDoc;
@@ -72,6 +72,10 @@ pub fn router(cfg: &mut web::ServiceConfig) {
 mod tests {
     use actix_web::{test, App};
     use database::{Engine, Migrator, MigratorTrait, Mutation, SubAccountStatus};
+    use actix_web::http::Method;
+    use database::api_keys::Created;
+    use crate::middleware::authentication::tests::signed;
+    use crate::middleware::authentication::Authentication;
     use crate::StopHandle;
 
     use super::*;
@@ -82,7 +86,7 @@ mod tests {
         let db = Engine::connect().await.unwrap();
         let state = web::Data::new(AppState {
             db: db.clone(),
-            producer: None,
+            transport: None,
             stop_handle: StopHandle::default()
         }); // Build app state
         Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
@@ -91,22 +95,27 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(state.clone())
+                .wrap(Authentication)
                 .configure(router)
         ).await;
         let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+        let key = Created::from(Mutation::create_api_key(&db, 1, None, true, false, false).await.unwrap());
 
         // Get all for client with error
-        let req = test::TestRequest::get()
-            .uri("/100")
-            .to_request();
+        let req = signed(Method::GET, "/100", None, &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
 
-        // Get all for client
+        // Get all for client without a key with error
         let req = test::TestRequest::get()
             .uri("/1")
             .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Get all for client
+        let req = signed(Method::GET, "/1", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Tear down
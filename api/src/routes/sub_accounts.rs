@@ -1,9 +1,10 @@
+use crate::middleware::authentication::{Admin, Caller, Permission, Principal};
 use crate::models::error::Exception;
 use crate::AppState;
 
 use actix_web::{get, post, put, web, HttpResponse};
 
-use database::{Query, Mutation};
+use database::{Cursor, Query, Mutation, SubAccountPage};
 use database::utoipa;
 use database::sub_accounts::{GetRequest, PostRequest, PutRequest, Model};
 
@@ -13,21 +14,23 @@ use database::sub_accounts::{GetRequest, PostRequest, PutRequest, Model};
     context_path = "/sub_accounts",
     params(GetRequest),
     responses(
-        (status = 200, description = "Returns all sub-accounts", body = [Model]),
+        (status = 200, description = "Returns a page of sub-accounts, sorted by id", body = SubAccountPage),
         (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
+        (status = 401, description = "Unauthorized", body = String, example = json!("Missing admin key.")),
+        (status = 403, description = "Forbidden", body = String, example = json!("API keys cannot perform admin actions.")),
     ),
     tag = "Sub-Accounts",
 )]
 #[get("/")]
 async fn get(
     query: web::Query<GetRequest>,
+    _admin: Admin,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let sub_accounts = Query::find_sub_accounts(
         &data.db,
         query.status.clone(),
-        query.page.clone(),
-        query.page_size.clone(),
+        Cursor { after: query.after, before: query.before, limit: query.limit },
     )
         .await
         .map_err(|e| Exception::Database(e))?;
@@ -41,6 +44,8 @@ async fn get(
         (status = 200, description = "Returns all sub-accounts with the matching client id", body = [Model]),
         (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request", body = String, example = json!("Client with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden", body = String, example = json!("API key does not belong to client <client_id>.")),
     ),
     params(
         ("client_id", description = "Client ID for which to search sub-accounts", example = 1),
@@ -50,12 +55,17 @@ async fn get(
 #[get("/{client_id}")]
 async fn get_by_client_id(
     path: web::Path<i32>,
+    caller: Caller,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
-    let sub_accounts = Query::find_sub_accounts_by_client_id(&data.db, client_id)
+    caller.authorize(client_id, Permission::Read)?;
+    let mut sub_accounts = Query::find_sub_accounts_by_client_id(&data.db, client_id)
         .await
         .map_err(|e| Exception::Database(e))?;
+    if let Caller::Client(Principal { sub_account_id: Some(scope), .. }) = caller {
+        sub_accounts.retain(|sub_account| sub_account.id == scope); // Keys scoped to a sub-account only see their own
+    }
 
     Ok(HttpResponse::Ok().json(sub_accounts))
 }
@@ -71,6 +81,8 @@ async fn get_by_client_id(
         (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request", body = String, example = json!("Client with id <client_id> does not exist.")),
         (status = 400, description = "Bad request", body = String, example = json!("Sub-account with name <name> already exists.")),
+        (status = 401, description = "Unauthorized", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden", body = String, example = json!("API key cannot manage client <client_id>.")),
     ),
     tag = "Sub-Accounts",
 )]
@@ -78,9 +90,11 @@ async fn get_by_client_id(
 async fn create(
     path: web::Path<i32>,
     body: web::Json<PostRequest>,
+    caller: Caller,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    caller.authorize_management(client_id)?;
     let sub_account = Mutation::create_sub_account(
         &data.db,
         client_id,
@@ -103,6 +117,8 @@ async fn create(
         (status = 500, description = "Internal server error", body = String, example = json!("An internal server error occurred. Please try again later.")),
         (status = 400, description = "Bad request", body = String, example = json!("Client with id <client_id> does not exist.")),
         (status = 400, description = "Bad request", body = String, example = json!("Sub-Account with id <id> does not exist.")),
+        (status = 401, description = "Unauthorized", body = String, example = json!("Missing API key.")),
+        (status = 403, description = "Forbidden", body = String, example = json!("API key cannot manage client <client_id>.")),
     ),
     tag = "Sub-Accounts",
 )]
@@ -110,9 +126,11 @@ async fn create(
 async fn update(
     path: web::Path<i32>,
     body: web::Json<PutRequest>,
+    caller: Caller,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Exception> {
     let client_id = path.into_inner();
+    caller.authorize_management(client_id)?;
     Mutation::update_sub_account(
         &data.db,
         client_id,
@@ -131,7 +149,7 @@ async fn update(
 #[derive(utoipa::OpenApi)]
 #[openapi(
     paths(get, get_by_client_id, create, update),
-    components(schemas(Model, PostRequest, PutRequest)),
+    components(schemas(Model, SubAccountPage, PostRequest, PutRequest)),
     tags((name = "Sub-Accounts", description = "Sub-account management endpoints.")),
 )]
 pub struct ApiDoc;
@@ -147,9 +165,13 @@ pub fn router(cfg: &mut web::ServiceConfig) {
 
 #[cfg(test)]
 mod tests {
+    use actix_web::http::Method;
     use actix_web::{test, App};
     use serde_json::json;
     use database::{Engine, Migrator, MigratorTrait, SubAccountStatus};
+    use database::api_keys::Created;
+    use crate::middleware::authentication::tests::{signed, signed_by_admin};
+    use crate::middleware::authentication::Authentication;
     use crate::StopHandle;
 
     use super::*;
@@ -160,7 +182,7 @@ mod tests {
         let db = Engine::connect().await.unwrap();
         let state = web::Data::new(AppState {
             db: db.clone(),
-            producer: None,
+            transport: None,
             stop_handle: StopHandle::default()
         }); // Build app state
         Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
@@ -168,100 +190,94 @@ mod tests {
         // Mock server
         let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(state.clone()))
+                .app_data(state.clone())
+                .wrap(Authentication)
                 .configure(router)
         ).await;
         let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
         let _ = Mutation::create_client(&db, "b@gmail.com".to_owned()).await;
+        let admin = Mutation::create_admin(&db, "Operations".to_owned()).await.unwrap();
+        let key = Created::from(Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap());
 
         // Create records
-        let req = test::TestRequest::post()
-            .uri("/1")
-            .set_json(json!({"name": "Test1"}))
-            .to_request();
+        let req = signed(Method::POST, "/1", Some(json!({"name": "Test1"})), &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-        let req = test::TestRequest::post()
-            .uri("/1")
-            .set_json(json!({"name": "Test2"}))
-            .to_request();
+        let req = signed(Method::POST, "/1", Some(json!({"name": "Test2"})), &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
-        let req = test::TestRequest::post()
-            .uri("/2")
-            .set_json(json!({"name": "Test1"}))
-            .to_request();
+        let req = signed_by_admin(Method::POST, "/2", Some(json!({"name": "Test1"})), &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+        let scoped = Created::from(Mutation::create_api_key(&db, 1, Some(2), true, true, false).await.unwrap());
 
         // Get all
-        let req = test::TestRequest::get()
-            .uri("/")
-            .to_request();
+        let req = signed_by_admin(Method::GET, "/", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Get some
-        let req = test::TestRequest::get()
-            .uri("/?status=Active&page=1&page_size=2")
-            .to_request();
+        let req = signed_by_admin(Method::GET, "/?status=Active&after=1&limit=2", None, &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
+        // Get all without an admin key with error
+        let req = signed(Method::GET, "/", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
         // Get all for client
+        let req = signed(Method::GET, "/1", None, &key).to_request();
+        let sub_accounts: Vec<Model> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(sub_accounts.len(), 2);
+        let req = signed(Method::GET, "/1", None, &scoped).to_request();
+        let sub_accounts: Vec<Model> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(sub_accounts.len(), 1);
+        assert_eq!(sub_accounts[0].id, 2);
+
+        // Get all for client with error
         let req = test::TestRequest::get()
             .uri("/1")
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert!(resp.status().is_success());
+        assert_eq!(resp.status(), 401);
+        let req = signed(Method::GET, "/2", None, &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
 
         // Create one with error
-        let req = test::TestRequest::post()
-            .uri("/100")
-            .set_json(json!({"name": "Test"}))
-            .to_request();
+        let req = signed_by_admin(Method::POST, "/100", Some(json!({"name": "Test"})), &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
-        let req = test::TestRequest::post()
-            .uri("/2")
-            .set_json(json!({"name": "Test1"}))
-            .to_request();
+        let req = signed_by_admin(Method::POST, "/2", Some(json!({"name": "Test1"})), &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
+        let req = signed(Method::POST, "/2", Some(json!({"name": "Test2"})), &key).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let req = signed(Method::POST, "/1", Some(json!({"name": "Test3"})), &scoped).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
 
         // Update one
-        let req = test::TestRequest::put()
-            .uri("/1")
-            .set_json(json!({
-                "id": 1,
-                "name": "Test100",
-                "status": SubAccountStatus::Inactive,
-            }))
-            .to_request();
+        let body = json!({"id": 1, "name": "Test100", "status": SubAccountStatus::Inactive});
+        let req = signed(Method::PUT, "/1", Some(body), &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
         // Update one with error
-        let req = test::TestRequest::put()
-            .uri("/100")
-            .set_json(json!({
-                "id": 1,
-                "name": "Test",
-                "status": SubAccountStatus::Inactive,
-            }))
-            .to_request();
+        let body = json!({"id": 1, "name": "Test", "status": SubAccountStatus::Inactive});
+        let req = signed_by_admin(Method::PUT, "/100", Some(body), &admin).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
-        let req = test::TestRequest::put()
-            .uri("/1")
-            .set_json(json!({
-                "id": 100,
-                "name": "Test2",
-                "status": SubAccountStatus::Inactive,
-            }))
-            .to_request();
+        let body = json!({"id": 100, "name": "Test2", "status": SubAccountStatus::Inactive});
+        let req = signed(Method::PUT, "/1", Some(body), &key).to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_client_error());
+        let body = json!({"id": 3, "name": "Test3", "status": SubAccountStatus::Inactive});
+        let req = test::TestRequest::put().uri("/2").set_json(body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
 
         // Tear down
         Migrator::reset(&db).await.unwrap(); // Rollback migrations
@@ -1,28 +1,80 @@
-use dotenv::dotenv;
+use std::sync::LazyLock;
 
-use std::env;
+use prometheus::{register_histogram_vec, HistogramVec};
+use sea_orm::metric::Info;
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, Statement};
+use url::Url;
 
-use sea_orm::{Database, DatabaseConnection, DbErr};
+static QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "db_query_duration_seconds",
+        "Duration of database queries by operation and outcome.",
+        &["operation", "outcome"],
+        vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]
+    )
+    .unwrap()
+});
 
 pub struct Engine;
 
 impl Engine {
     pub async fn connect() -> Result<DatabaseConnection, DbErr> {
-        dotenv().ok(); // Load the environment variables from the ".env" file
-
-        // Get credentials from environment
-        let db_url = env::var("POSTGRES_URL").unwrap_or_else(|_| {
-            let name = env::var("POSTGRES_DB").expect("POSTGRES_DB environment variable not found");
-            let host = env::var("POSTGRES_HOST").unwrap_or_else(|_| "localhost".to_owned());
-            let password =
-                env::var("POSTGRES_PASSWORD").expect("POSTGRES_PASSWORD environment variable not found");
-            let username = env::var("POSTGRES_USER").unwrap_or_else(|_| "postgres".to_owned());
-            format!(
-                "postgresql://{}:{}@{}:5432/{}",
-                username, password, host, name
-            )
-        });
-
-        Database::connect(db_url).await // Create database connection pool
+        Self::open(Self::url(None)?).await
     }
+
+    /// Creates a connection pool which times every query.
+    async fn open(url: String) -> Result<DatabaseConnection, DbErr> {
+        let mut db = Database::connect(url).await?;
+        db.set_metric_callback(observe);
+        Ok(db)
+    }
+
+    /// Creates an empty database on the server of `connect` and connects to it, so that tests can run against a
+    /// database of their own. The name must be a valid identifier.
+    pub async fn create_database(name: &str) -> Result<DatabaseConnection, DbErr> {
+        let server = Self::connect().await?;
+        server
+            .execute(Statement::from_string(DbBackend::Postgres, format!("CREATE DATABASE \"{name}\"")))
+            .await?;
+        Self::open(Self::url(Some(name))?).await
+    }
+
+    /// Drops a database created by `create_database`, terminating any connections to it.
+    pub async fn drop_database(name: &str) -> Result<(), DbErr> {
+        let server = Self::connect().await?;
+        server
+            .execute(Statement::from_string(
+                DbBackend::Postgres,
+                format!("DROP DATABASE IF EXISTS \"{name}\" WITH (FORCE)"),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// The URL of the configured database, or of another database on the same server. Only the path is replaced, so
+    /// connection parameters in the query are kept.
+    fn url(database: Option<&str>) -> Result<String, DbErr> {
+        let url = config::get().database.url().map_err(|e| DbErr::Custom(e.to_string()))?;
+        let Some(database) = database else {
+            return Ok(url);
+        };
+        let mut url = Url::parse(&url).map_err(|e| DbErr::Custom(format!("Invalid database URL: {e}")))?;
+        url.path_segments_mut()
+            .map_err(|_| DbErr::Custom("Invalid database URL: it has no path".to_owned()))?
+            .clear()
+            .push(database);
+        Ok(url.into())
+    }
+}
+
+fn observe(info: &Info<'_>) {
+    let operation = match info.statement.sql.split_whitespace().next().map(str::to_ascii_uppercase).as_deref() {
+        Some("SELECT") => "select",
+        Some("INSERT") => "insert",
+        Some("UPDATE") => "update",
+        Some("DELETE") => "delete",
+        _ => "other", // Schema changes and statements of migrations
+    };
+    let outcome = if info.failed { "error" } else { "ok" };
+    QUERY_DURATION.with_label_values(&[operation, outcome]).observe(info.elapsed.as_secs_f64());
 }
@@ -0,0 +1,512 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::stream::{LocalBoxStream, StreamExt};
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use sea_orm::prelude::DateTime;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::core::query::trades;
+use crate::entities::{candles, fills, orders, sea_orm_active_enums::CandleResolution};
+
+// ----------------------------------------------------------------------
+
+const CHUNK_ROWS: usize = 10_000; // Rows per CSV chunk and per Parquet row group
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = DbErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(DbErr::Custom(format!("Export format {s} does not exist."))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Dataset {
+    Fills,
+    Orders,
+    Trades,
+    Candles,
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dataset::Fills => "fills",
+            Dataset::Orders => "orders",
+            Dataset::Trades => "trades",
+            Dataset::Candles => "candles",
+        })
+    }
+}
+
+impl FromStr for Dataset {
+    type Err = DbErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fills" => Ok(Dataset::Fills),
+            "orders" => Ok(Dataset::Orders),
+            "trades" => Ok(Dataset::Trades),
+            "candles" => Ok(Dataset::Candles),
+            _ => Err(DbErr::Custom(format!("Dataset {s} does not exist."))),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportRequest {
+    #[param(example = "csv")]
+    pub format: ExportFormat,
+    #[param(example = "1970-01-01T00:00:00")]
+    pub start_time: Option<DateTime>, // Inclusive
+    #[param(example = "1970-01-01T00:00:00")]
+    pub end_time: Option<DateTime>, // Exclusive
+    #[param(example = "1m")]
+    pub resolution: Option<CandleResolution>, // Candles of every resolution if omitted
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Int,
+    Float,
+    Text,
+    Time, // Microseconds since the epoch in Parquet
+}
+
+/// A column of an exported dataset.
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    nullable: bool,
+}
+
+const fn field(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, nullable: false }
+}
+
+const fn nullable(name: &'static str, kind: Kind) -> Field {
+    Field { name, kind, nullable: true }
+}
+
+enum Value {
+    Int(Option<i32>),
+    Float(Option<f32>),
+    Text(Option<String>),
+    Time(Option<DateTime>),
+}
+
+impl Value {
+    fn is_some(&self) -> bool {
+        match self {
+            Value::Int(value) => value.is_some(),
+            Value::Float(value) => value.is_some(),
+            Value::Text(value) => value.is_some(),
+            Value::Time(value) => value.is_some(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Value::Int(value) => value.map(|value| value.to_string()).unwrap_or_default(),
+            Value::Float(value) => value.map(|value| value.to_string()).unwrap_or_default(),
+            Value::Text(value) => value.clone().unwrap_or_default(),
+            Value::Time(value) => value.map(|value| value.format("%Y-%m-%dT%H:%M:%S%.f").to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Name of an enum as it is serialized by the API.
+fn name(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// A row of an exported dataset.
+trait Record: FromQueryResult + Send + 'static {
+    const TABLE: &'static str;
+    const FIELDS: &'static [Field];
+
+    fn values(&self) -> Vec<Value>; // In the order of the fields
+}
+
+impl Record for fills::Model {
+    const TABLE: &'static str = "fills";
+    const FIELDS: &'static [Field] = &[
+        field("id", Kind::Int),
+        field("price", Kind::Float),
+        field("size", Kind::Float),
+        field("quote_size", Kind::Float),
+        field("side", Kind::Text),
+        field("type", Kind::Text),
+        nullable("aggressor", Kind::Text),
+        field("created_at", Kind::Time),
+        field("sub_account_id", Kind::Int),
+        field("market_id", Kind::Int),
+        field("order_id", Kind::Int),
+    ];
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Int(Some(self.id)),
+            Value::Float(Some(self.price)),
+            Value::Float(Some(self.size)),
+            Value::Float(Some(self.quote_size)),
+            Value::Text(Some(name(&self.side))),
+            Value::Text(Some(name(&self.r#type))),
+            Value::Text(self.aggressor.as_ref().map(name)),
+            Value::Time(Some(self.created_at)),
+            Value::Int(Some(self.sub_account_id)),
+            Value::Int(Some(self.market_id)),
+            Value::Int(Some(self.order_id)),
+        ]
+    }
+}
+
+impl Record for orders::Model {
+    const TABLE: &'static str = "orders";
+    const FIELDS: &'static [Field] = &[
+        field("id", Kind::Int),
+        nullable("client_order_id", Kind::Text),
+        nullable("price", Kind::Float),
+        field("size", Kind::Float),
+        field("filled_size", Kind::Float),
+        field("side", Kind::Text),
+        field("type", Kind::Text),
+        field("status", Kind::Text),
+        field("open_at", Kind::Time),
+        nullable("closed_at", Kind::Time),
+        field("sub_account_id", Kind::Int),
+        field("market_id", Kind::Int),
+    ];
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Int(Some(self.id)),
+            Value::Text(self.client_order_id.clone()),
+            Value::Float(self.price),
+            Value::Float(Some(self.size)),
+            Value::Float(Some(self.filled_size)),
+            Value::Text(Some(name(&self.side))),
+            Value::Text(Some(name(&self.r#type))),
+            Value::Text(Some(name(&self.status))),
+            Value::Time(Some(self.open_at)),
+            Value::Time(self.closed_at),
+            Value::Int(Some(self.sub_account_id)),
+            Value::Int(Some(self.market_id)),
+        ]
+    }
+}
+
+impl Record for fills::Trade {
+    const TABLE: &'static str = "trades";
+    const FIELDS: &'static [Field] = &[
+        field("id", Kind::Int),
+        field("price", Kind::Float),
+        field("size", Kind::Float),
+        nullable("aggressor", Kind::Text),
+        field("created_at", Kind::Time),
+    ];
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Int(Some(self.id)),
+            Value::Float(Some(self.price)),
+            Value::Float(Some(self.size)),
+            Value::Text(self.aggressor.as_ref().map(name)),
+            Value::Time(Some(self.created_at)),
+        ]
+    }
+}
+
+impl Record for candles::Model {
+    const TABLE: &'static str = "candles";
+    const FIELDS: &'static [Field] = &[
+        field("id", Kind::Int),
+        field("resolution", Kind::Text),
+        field("open_time", Kind::Time),
+        field("open", Kind::Float),
+        field("high", Kind::Float),
+        field("low", Kind::Float),
+        field("close", Kind::Float),
+        field("volume", Kind::Float),
+        field("quote_volume", Kind::Float),
+        field("trade_count", Kind::Int),
+        field("market_id", Kind::Int),
+    ];
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Int(Some(self.id)),
+            Value::Text(Some(name(&self.resolution))),
+            Value::Time(Some(self.open_time)),
+            Value::Float(Some(self.open)),
+            Value::Float(Some(self.high)),
+            Value::Float(Some(self.low)),
+            Value::Float(Some(self.close)),
+            Value::Float(Some(self.volume)),
+            Value::Float(Some(self.quote_volume)),
+            Value::Int(Some(self.trade_count)),
+            Value::Int(Some(self.market_id)),
+        ]
+    }
+}
+
+// ----------------------------------------------------------------------
+
+fn export_error(e: impl fmt::Display) -> DbErr {
+    DbErr::Custom(format!("Failed to export: {e}"))
+}
+
+/// Encodes rows into chunks of a file, holding at most one chunk of rows in memory.
+enum Encoder {
+    Csv,
+    Parquet(Box<SerializedFileWriter<Vec<u8>>>),
+}
+
+impl Encoder {
+    fn new<R: Record>(format: ExportFormat) -> Result<Self, DbErr> {
+        match format {
+            ExportFormat::Csv => Ok(Encoder::Csv),
+            ExportFormat::Parquet => {
+                let columns: String = R::FIELDS
+                    .iter()
+                    .map(|field| {
+                        let repetition = if field.nullable { "optional" } else { "required" };
+                        let (r#type, annotation) = match field.kind {
+                            Kind::Int => ("int32", ""),
+                            Kind::Float => ("float", ""),
+                            Kind::Text => ("binary", " (UTF8)"),
+                            Kind::Time => ("int64", " (TIMESTAMP(MICROS,false))"),
+                        };
+                        format!("{repetition} {type} {}{annotation};", field.name)
+                    })
+                    .collect();
+                let schema = parse_message_type(&format!("message {} {{ {columns} }}", R::TABLE)).map_err(export_error)?;
+                let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+                SerializedFileWriter::new(Vec::new(), Arc::new(schema), Arc::new(properties))
+                    .map(|writer| Encoder::Parquet(Box::new(writer)))
+                    .map_err(export_error)
+            }
+        }
+    }
+
+    /// Bytes which precede the first chunk.
+    fn header<R: Record>(&self) -> Result<Vec<u8>, DbErr> {
+        match self {
+            Encoder::Csv => csv_rows(&[R::FIELDS.iter().map(|field| field.name.to_owned()).collect()]),
+            Encoder::Parquet(_) => Ok(Vec::new()),
+        }
+    }
+
+    fn chunk<R: Record>(&mut self, rows: &[Vec<Value>]) -> Result<Vec<u8>, DbErr> {
+        match self {
+            Encoder::Csv => csv_rows(
+                &rows.iter().map(|row| row.iter().map(Value::to_csv).collect()).collect::<Vec<_>>()
+            ),
+            Encoder::Parquet(writer) => {
+                write_row_group(writer, R::FIELDS, rows).map_err(export_error)?;
+                Ok(std::mem::take(writer.inner_mut()))
+            }
+        }
+    }
+
+    /// Bytes which follow the last chunk.
+    fn finish(self) -> Result<Vec<u8>, DbErr> {
+        match self {
+            Encoder::Csv => Ok(Vec::new()),
+            Encoder::Parquet(writer) => writer.into_inner().map_err(export_error), // Writes the footer
+        }
+    }
+}
+
+fn csv_rows(rows: &[Vec<String>]) -> Result<Vec<u8>, DbErr> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.write_record(row).map_err(export_error)?;
+    }
+    writer.into_inner().map_err(export_error)
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<Vec<u8>>,
+    fields: &[Field],
+    rows: &[Vec<Value>],
+) -> Result<(), parquet::errors::ParquetError> {
+    let mut row_group = writer.next_row_group()?;
+    for (i, field) in fields.iter().enumerate() {
+        let Some(mut column) = row_group.next_column()? else {
+            break;
+        };
+        let values = rows.iter().map(|row| &row[i]);
+        let levels: Vec<i16> = values.clone().map(|value| value.is_some() as i16).collect();
+        let levels = field.nullable.then_some(levels.as_slice());
+        match column.untyped() {
+            ColumnWriter::Int32ColumnWriter(writer) => {
+                let values: Vec<i32> = values.filter_map(|value| match value {
+                    Value::Int(value) => *value,
+                    _ => None,
+                }).collect();
+                writer.write_batch(&values, levels, None)?;
+            }
+            ColumnWriter::FloatColumnWriter(writer) => {
+                let values: Vec<f32> = values.filter_map(|value| match value {
+                    Value::Float(value) => *value,
+                    _ => None,
+                }).collect();
+                writer.write_batch(&values, levels, None)?;
+            }
+            ColumnWriter::ByteArrayColumnWriter(writer) => {
+                let values: Vec<ByteArray> = values.filter_map(|value| match value {
+                    Value::Text(value) => value.as_deref().map(ByteArray::from),
+                    _ => None,
+                }).collect();
+                writer.write_batch(&values, levels, None)?;
+            }
+            ColumnWriter::Int64ColumnWriter(writer) => {
+                let values: Vec<i64> = values.filter_map(|value| match value {
+                    Value::Time(value) => value.map(|value| value.and_utc().timestamp_micros()),
+                    _ => None,
+                }).collect();
+                writer.write_batch(&values, levels, None)?;
+            }
+            _ => unreachable!("Exported columns are int32, int64, float or binary"),
+        }
+        column.close()?;
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+// ----------------------------------------------------------------------
+
+pub struct Export;
+
+impl Export {
+    /// Streams a dataset of a market as a CSV or Parquet file, optionally between a start (inclusive) and end
+    /// (exclusive) time. Rows are read from the database as the stream is polled rather than all at once.
+    pub fn stream(
+        db: DatabaseConnection,
+        dataset: Dataset,
+        market_id: i32,
+        request: ExportRequest,
+    ) -> LocalBoxStream<'static, Result<Vec<u8>, DbErr>> {
+        let ExportRequest { format, start_time, end_time, resolution } = request;
+        match dataset {
+            Dataset::Fills => encode::<_, fills::Model>(
+                db,
+                fills::Entity::find()
+                    .filter(fills::Column::MarketId.eq(market_id))
+                    .filter(between(fills::Column::CreatedAt, start_time, end_time))
+                    .order_by_asc(fills::Column::Id),
+                format,
+            ),
+            Dataset::Orders => encode::<_, orders::Model>(
+                db,
+                orders::Entity::find()
+                    .filter(orders::Column::MarketId.eq(market_id))
+                    .filter(between(orders::Column::OpenAt, start_time, end_time))
+                    .order_by_asc(orders::Column::Id),
+                format,
+            ),
+            Dataset::Trades => encode::<_, fills::Trade>(
+                db,
+                trades(market_id)
+                    .filter(between(fills::Column::CreatedAt, start_time, end_time))
+                    .order_by_asc(fills::Column::Id),
+                format,
+            ),
+            Dataset::Candles => {
+                let mut query = candles::Entity::find()
+                    .filter(candles::Column::MarketId.eq(market_id))
+                    .filter(between(candles::Column::OpenTime, start_time, end_time));
+                if let Some(resolution) = resolution {
+                    query = query.filter(candles::Column::Resolution.eq(resolution));
+                }
+                encode::<_, candles::Model>(
+                    db,
+                    query
+                        .order_by_asc(candles::Column::Resolution)
+                        .order_by_asc(candles::Column::OpenTime),
+                    format,
+                )
+            }
+        }
+    }
+}
+
+fn between(column: impl ColumnTrait, start_time: Option<DateTime>, end_time: Option<DateTime>) -> Condition {
+    let mut condition = Condition::all();
+    if let Some(start_time) = start_time {
+        condition = condition.add(column.gte(start_time));
+    }
+    if let Some(end_time) = end_time {
+        condition = condition.add(column.lt(end_time));
+    }
+    condition
+}
+
+fn encode<E, R>(db: DatabaseConnection, query: Select<E>, format: ExportFormat) -> LocalBoxStream<'static, Result<Vec<u8>, DbErr>>
+where
+    E: EntityTrait,
+    R: Record,
+{
+    async_stream::try_stream! {
+        let mut encoder = Encoder::new::<R>(format)?;
+        yield encoder.header::<R>()?;
+        let mut records = query.into_model::<R>().stream(&db).await?;
+        let mut rows = Vec::with_capacity(CHUNK_ROWS);
+        while let Some(record) = records.next().await {
+            rows.push(record?.values());
+            if rows.len() == CHUNK_ROWS {
+                yield encoder.chunk::<R>(&rows)?;
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            yield encoder.chunk::<R>(&rows)?;
+        }
+        yield encoder.finish()?;
+    }
+    .boxed_local()
+}
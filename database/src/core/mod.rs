@@ -1,7 +1,11 @@
 mod engine;
+mod export;
 mod mutation;
+mod pagination;
 mod query;
 
 pub use engine::*;
+pub use export::*;
 pub use mutation::*;
+pub use pagination::*;
 pub use query::*;
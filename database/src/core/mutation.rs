@@ -1,9 +1,12 @@
-use crate::entities::{clients, fills, markets, orders, positions, sub_accounts};
-use crate::{OrderSide, OrderStatus, OrderType, SubAccountStatus};
+use crate::entities::{admins, api_keys, audit_logs, candles, circuit_breakers, clients, fills, markets, orders, positions};
+use crate::entities::{stream_offsets, sub_accounts};
+use crate::{CandleResolution, ClientTier, MarketStatus, OrderSide, OrderStatus, OrderType, SubAccountStatus};
 use chrono::{Utc};
 use sea_orm::prelude::*;
 use sea_orm::*;
 use sea_orm_migration::sea_query::Query as SeaQuery;
+use futures::StreamExt;
+use rand::Rng;
 
 // ----------------------------------------------------------------------
 
@@ -52,6 +55,19 @@ impl Mutation {
             ))),
         }
     }
+
+    pub async fn update_client_tier(db: &DbConn, id: i32, tier: ClientTier) -> Result<(), DbErr> {
+        let client = clients::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "Client with id {id} does not exist."
+            )))?;
+        let mut client: clients::ActiveModel = client.into();
+        client.tier = Set(tier);
+        client.update(db).await?;
+        Ok(())
+    }
     // ----------------------------------------------------------------------
 
     // Markets
@@ -128,6 +144,87 @@ impl Mutation {
         }
     }
 
+    /// Changes the trading status of a market. Resting orders are cancelled when the market closes, or when it halts if
+    /// `cancel_resting` is set. They stay open here and are closed once the matching engine reports them cancelled, so
+    /// fills of a closing auction are still applied.
+    pub async fn update_market_status(
+        db: &DbConn,
+        market_id: i32,
+        status: MarketStatus,
+        cancel_resting: bool,
+    ) -> Result<markets::StatusChange, DbErr> {
+        let market = markets::Entity::find_by_id(market_id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "Market with id {market_id} does not exist."
+            )))?;
+        let cancel_resting = status == MarketStatus::Closed || (status == MarketStatus::Halted && cancel_resting);
+        let mut market: markets::ActiveModel = market.into();
+        market.status = Set(status);
+        market.update(db).await?;
+        Ok(markets::StatusChange { market_id, status, cancel_resting, changed_at: Utc::now().naive_utc() })
+    }
+
+    /// Configures the volatility circuit breaker of a market. The matching engine loads it on start-up, and applies
+    /// changes published to it as a `markets::CircuitBreakerChange`.
+    pub async fn update_market_circuit_breaker(
+        db: &DbConn,
+        market_id: i32,
+        band_percent: Option<f32>,
+        band_window: i32,
+        band_action: MarketStatus,
+        auction_duration: i32,
+    ) -> Result<markets::Model, DbErr> {
+        let market = markets::Entity::find_by_id(market_id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "Market with id {market_id} does not exist."
+            )))?;
+        let band_percent_invalid = band_percent.is_some_and(|band_percent| band_percent.is_nan() || band_percent <= 0.0);
+        if band_percent_invalid || band_window <= 0 || auction_duration <= 0 {
+            return Err(DbErr::Custom("Circuit breaker thresholds must be positive.".to_owned()));
+        }
+        if !matches!(band_action, MarketStatus::Auction | MarketStatus::Halted) {
+            return Err(DbErr::Custom("A circuit breaker may only switch a market to Auction or Halted.".to_owned()));
+        }
+        let mut market: markets::ActiveModel = market.into();
+        market.band_percent = Set(band_percent);
+        market.band_window = Set(band_window);
+        market.band_action = Set(band_action);
+        market.auction_duration = Set(auction_duration);
+        market.update(db).await
+    }
+
+    /// Records a circuit breaker triggered by the matching engine together with the status the market switched to.
+    pub async fn create_circuit_breaker(
+        db: &DbConn,
+        circuit_breaker: circuit_breakers::Model,
+    ) -> Result<circuit_breakers::Model, DbErr> {
+        let txn = db.begin().await?;
+        markets::ActiveModel {
+            id: Unchanged(circuit_breaker.market_id),
+            status: Set(circuit_breaker.status),
+            ..Default::default()
+        }
+        .update(&txn)
+        .await?;
+        let circuit_breaker = circuit_breakers::ActiveModel {
+            reference_price: Set(circuit_breaker.reference_price),
+            price: Set(circuit_breaker.price),
+            status: Set(circuit_breaker.status),
+            created_at: Set(circuit_breaker.created_at),
+            resumes_at: Set(circuit_breaker.resumes_at),
+            market_id: Set(circuit_breaker.market_id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok(circuit_breaker)
+    }
+
     // ----------------------------------------------------------------------
 
     // SubAccounts
@@ -223,29 +320,33 @@ impl Mutation {
         Ok(())
     }
 
-    pub async fn update_order_from_fill(
-        db: &DbConn,
-        fill: fills::Model,
+    /// Adds a fill to the filled size of its order, closing the order once it is filled in full. Orders which were
+    /// closed while the fill was in flight, such as by a cancel, are left as they are.
+    pub async fn update_order_from_fill<C: ConnectionTrait>(
+        db: &C,
+        fill: &fills::Model,
     ) -> Result<(), DbErr> {
-        if let Some(order) = fill.find_related(orders::Entity)
-            .filter(orders::Column::Status.eq(OrderStatus::Open))
+        let order = orders::Entity::find_by_id(fill.order_id)
             .filter(orders::Column::SubAccountId.eq(fill.sub_account_id))
             .filter(orders::Column::MarketId.eq(fill.market_id))
             .one(db)
             .await?
-        {
-            let mut order = order.into_active_model();
-            order.filled_size = Set(order.filled_size.unwrap() + fill.size);
-            if order.filled_size == order.size {
-                order.status = Set(OrderStatus::Closed);
-                order.closed_at = Set(Some(Utc::now().naive_utc()));
-            }
-            Ok(())
-        } else {
-            Err(DbErr::RecordNotFound(format!(
+            .ok_or(DbErr::RecordNotFound(format!(
                 "Found no order matching fill {:?}", fill
-            )))
+            )))?;
+        if order.status != OrderStatus::Open {
+            return Ok(());
+        }
+        let filled_size = order.filled_size + fill.size;
+        let closed = filled_size >= order.size;
+        let mut order = order.into_active_model();
+        order.filled_size = Set(filled_size);
+        if closed {
+            order.status = Set(OrderStatus::Closed);
+            order.closed_at = Set(Some(fill.created_at));
         }
+        order.update(db).await?;
+        Ok(())
     }
 
     pub async fn create_order(
@@ -287,6 +388,10 @@ impl Mutation {
         };
         match (sub_account_and_client, market) {
             (Some((sub_account, Some(_))), Some(market)) => {
+                Self::check_market_status(&market, Some(&r#type))?;
+                if !is_positive(size) || price.is_some_and(|price| !is_positive(price)) {
+                    return Err(DbErr::Custom("Invalid order parameters.".to_owned()))
+                }
                 let price: ActiveValue<Option<f32>> = if let Some(price) = price {
                     if price < market.price_increment || r#type == OrderType::Market || size < market.size_increment {
                         return Err(DbErr::Custom(format!(
@@ -339,6 +444,112 @@ impl Mutation {
             ))),
         }
     }
+
+    /// Checks that an open order belongs to the client and returns the cancel to send to the matching engine. The order
+    /// stays open until the engine reports it cancelled, so that fills already in flight are still applied to it.
+    pub async fn cancel_order(
+        db: &DbConn,
+        client_id: i32,
+        id: i32,
+    ) -> Result<orders::Order, DbErr> {
+        let order = Self::find_open_order(db, client_id, id).await?;
+        Ok(orders::Order {
+            id: order.id,
+            sub_account_id: order.sub_account_id,
+            price: order.price,
+            size: order.size - order.filled_size,
+            side: order.side,
+            r#type: order.r#type,
+            open_at: order.open_at,
+        })
+    }
+
+    /// Closes an order pulled from the book, as reported by the matching engine. Orders which are already closed are
+    /// left as they are, so that reports can be applied more than once.
+    pub async fn close_cancelled_order<C: ConnectionTrait>(
+        db: &C,
+        cancelled: &orders::Cancelled,
+    ) -> Result<(), DbErr> {
+        orders::Entity::update_many()
+            .col_expr(
+                orders::Column::Status,
+                sea_query::Expr::val(OrderStatus::Closed.to_value()).as_enum(OrderStatus::name()),
+            )
+            .col_expr(orders::Column::ClosedAt, sea_query::Expr::value(cancelled.cancelled_at))
+            .filter(orders::Column::Id.eq(cancelled.order_id))
+            .filter(orders::Column::Status.eq(OrderStatus::Open))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn amend_order(
+        db: &DbConn,
+        client_id: i32,
+        id: i32,
+        price: Option<f32>,
+        size: Option<f32>,
+    ) -> Result<orders::Order, DbErr> {
+        let order = Self::find_open_order(db, client_id, id).await?;
+        let market = order.find_related(markets::Entity).one(db).await?.unwrap();
+        Self::check_market_status(&market, None)?;
+        if order.r#type == OrderType::Market
+            || price.is_some_and(|price| !is_positive(price) || price < market.price_increment)
+            || size.is_some_and(|size| !is_positive(size) || size < market.size_increment || size <= order.filled_size)
+        {
+            return Err(DbErr::Custom("Invalid order parameters.".to_owned()))
+        }
+        let mut order = order.into_active_model();
+        if let Some(price) = price {
+            order.price = Set(Some(price));
+        }
+        if let Some(size) = size {
+            order.size = Set(size);
+        }
+        let order = order.update(db).await?;
+        Ok(orders::Order {
+            id: order.id,
+            sub_account_id: order.sub_account_id,
+            price: order.price,
+            size: order.size - order.filled_size, // The engine only needs to know the remaining size
+            side: order.side,
+            r#type: order.r#type,
+            open_at: order.open_at,
+        })
+    }
+
+    /// Rejects orders, or amendments if no type is given, which the market does not accept in its current status.
+    fn check_market_status(market: &markets::Model, r#type: Option<&OrderType>) -> Result<(), DbErr> {
+        match (market.status, r#type) {
+            (MarketStatus::Halted | MarketStatus::Closed, _) => Err(DbErr::Custom(format!(
+                "Market with id {} is {:?} and does not accept orders.", market.id, market.status
+            ))),
+            (MarketStatus::PreOpen | MarketStatus::Auction, Some(OrderType::Market)) => Err(DbErr::Custom(
+                "Market orders are only accepted during continuous trading.".to_owned()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn find_open_order(db: &DbConn, client_id: i32, id: i32) -> Result<orders::Model, DbErr> {
+        orders::Entity::find_by_id(id)
+            .filter(orders::Column::Status.eq(OrderStatus::Open))
+            .filter(
+                orders::Column::SubAccountId.in_subquery(
+                    SeaQuery::select()
+                        .column(sub_accounts::Column::Id)
+                        .from(sub_accounts::Entity)
+                        .and_where(sub_accounts::Column::ClientId.eq(client_id))
+                        .to_owned(),
+                ),
+            )
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "Open order with id {id} does not exist."
+            )))
+    }
+
     // ----------------------------------------------------------------------
 
     // Fills
@@ -346,15 +557,16 @@ impl Mutation {
         db: &DbConn,
         fill: fills::Model,
     ) -> Result<fills::Response, DbErr> {
-        let fill = fill.into_active_model().insert(db).await?;
+        let mut fill = fill.into_active_model();
+        fill.id = NotSet; // Assigned by the database
+        let fill = fill.insert(db).await?;
         let sub_account = fill.find_related(sub_accounts::Entity)
-            .select_only()
-            .column(sub_accounts::Column::Name)
             .one(db)
             .await?
             .unwrap();
         let market = fill.find_related(markets::Entity).one(db).await?.unwrap();
         Ok(fills::Response {
+            id: fill.id,
             price: fill.price,
             size: fill.size,
             quote_size: fill.quote_size,
@@ -369,13 +581,60 @@ impl Mutation {
             order_id: fill.order_id,
         })
     }
-    // ----------------------------------------------------------------------
 
-    // Positions
-    pub async fn upsert_position_from_fill(
+    /// Stores a fill which a durable consumer read from the fills stream at the offset and applies it to its order and
+    /// position and to the candles of its market, unless the consumer already processed the offset. The fill, its effects
+    /// and the offset are written in one transaction, so a fill redelivered after a restart is applied once. The fill is
+    /// stored and applied to the position even if its order was closed in the meantime. Returns whether the fill was
+    /// applied.
+    pub async fn persist_fill(
         db: &DbConn,
+        consumer: &str,
+        offset: u64,
         fill: fills::Model,
+    ) -> Result<bool, DbErr> {
+        let txn = db.begin().await?;
+        if !advance_stream_offset(&txn, consumer, offset).await? {
+            return Ok(false); // Rolled back when dropped
+        }
+        Self::update_order_from_fill(&txn, &fill).await?;
+        Self::upsert_position_from_fill(&txn, &fill).await?;
+        Self::upsert_candles_from_fill(&txn, &fill).await?;
+        fills::ActiveModel { id: NotSet, ..fill.into_active_model() }.insert(&txn).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Closes an order pulled from the book which a durable consumer read from the fills stream at the offset, in
+    /// the same transaction as the offset. Returns whether the report was applied.
+    pub async fn persist_cancelled(
+        db: &DbConn,
+        consumer: &str,
+        offset: u64,
+        cancelled: &orders::Cancelled,
+    ) -> Result<bool, DbErr> {
+        let txn = db.begin().await?;
+        if !advance_stream_offset(&txn, consumer, offset).await? {
+            return Ok(false); // Rolled back when dropped
+        }
+        Self::close_cancelled_order(&txn, cancelled).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+    // ----------------------------------------------------------------------
+
+    // Positions
+    /// Nets a fill into the position of its sub-account in its market. The position is long or short depending on the
+    /// sign of the net size, and its average entry price only moves when the position grows or flips.
+    pub async fn upsert_position_from_fill<C: ConnectionTrait>(
+        db: &C,
+        fill: &fills::Model,
     ) -> Result<(), DbErr> {
+        let signed = |side: &OrderSide, size: f32| match side {
+            OrderSide::Buy | OrderSide::Bid | OrderSide::Long => size,
+            OrderSide::Sell | OrderSide::Ask | OrderSide::Short => -size,
+        };
+        let traded = signed(&fill.side, fill.size);
         if let Some(position) = positions::Entity::find()
             .filter(
                 Condition::all().add(
@@ -399,20 +658,30 @@ impl Mutation {
             )
             .one(db).await?
         {
+            let held = signed(&position.side, position.size);
+            let net = held + traded;
+            let avg_entry_price = if held * traded >= 0.0 && net != 0.0 { // Grows
+                (position.avg_entry_price * held.abs() + fill.price * fill.size) / net.abs()
+            } else if held * net < 0.0 { // Flips
+                fill.price
+            } else { // Shrinks
+                position.avg_entry_price
+            };
+            let side = match net {
+                net if net > 0.0 => OrderSide::Long,
+                net if net < 0.0 => OrderSide::Short,
+                _ => position.side.clone(),
+            };
             let mut position = position.into_active_model();
-            position.size = Set(position.size.unwrap() + fill.size.clone());
-            position.avg_entry_price = Set(
-                (
-                    position.avg_entry_price.unwrap() * position.size.clone().unwrap()
-                        + fill.price.clone() * fill.size
-                ) / position.size.clone().unwrap()
-            );
-            let _ = position.update(db).await;
+            position.size = Set(net.abs());
+            position.side = Set(side);
+            position.avg_entry_price = Set(avg_entry_price);
+            position.update(db).await?;
         } else {
             positions::ActiveModel {
                 avg_entry_price: Set(fill.price),
                 size: Set(fill.size),
-                side: Set(fill.side),
+                side: Set(if traded > 0.0 { OrderSide::Long } else { OrderSide::Short }),
                 sub_account_id: Set(fill.sub_account_id),
                 market_id: Set(fill.market_id),
                 ..Default::default()
@@ -423,4 +692,273 @@ impl Mutation {
         Ok(())
     }
     // ----------------------------------------------------------------------
+
+    // Candles
+    /// Folds a fill into the bar containing it at every resolution. The matching engine publishes a fill for each
+    /// side of a match, so only the fill of the buyer is counted.
+    pub async fn upsert_candles_from_fill<C: ConnectionTrait>(
+        db: &C,
+        fill: &fills::Model,
+    ) -> Result<(), DbErr> {
+        if !matches!(fill.side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long) {
+            return Ok(());
+        }
+        for resolution in CandleResolution::iter() {
+            let open_time = resolution.open_time(fill.created_at);
+            if let Some(candle) = candles::Entity::find()
+                .filter(candles::Column::MarketId.eq(fill.market_id))
+                .filter(candles::Column::Resolution.eq(resolution))
+                .filter(candles::Column::OpenTime.eq(open_time))
+                .one(db)
+                .await?
+            {
+                candles::ActiveModel {
+                    id: Unchanged(candle.id),
+                    high: Set(candle.high.max(fill.price)),
+                    low: Set(candle.low.min(fill.price)),
+                    close: Set(fill.price),
+                    volume: Set(candle.volume + fill.size),
+                    quote_volume: Set(candle.quote_volume + fill.quote_size),
+                    trade_count: Set(candle.trade_count + 1),
+                    ..Default::default()
+                }
+                .update(db)
+                .await?;
+            } else {
+                candles::ActiveModel {
+                    resolution: Set(resolution),
+                    open_time: Set(open_time),
+                    open: Set(fill.price),
+                    high: Set(fill.price),
+                    low: Set(fill.price),
+                    close: Set(fill.price),
+                    volume: Set(fill.size),
+                    quote_volume: Set(fill.quote_size),
+                    trade_count: Set(1),
+                    market_id: Set(fill.market_id),
+                    ..Default::default()
+                }
+                .insert(db)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the bars of a market from the stored fills of its buyers, one per trade. Fills are streamed from the
+    /// database and every bar is written once it is complete. Bars without stored fills are left untouched. Returns
+    /// the number of bars written.
+    pub async fn backfill_candles(
+        db: &DbConn,
+        market_id: i32,
+    ) -> Result<usize, DbErr> {
+        let mut fills = fills::Entity::find()
+            .filter(fills::Column::MarketId.eq(market_id))
+            .filter(
+                Condition::any()
+                    .add(fills::Column::Side.eq(OrderSide::Buy))
+                    .add(fills::Column::Side.eq(OrderSide::Bid))
+                    .add(fills::Column::Side.eq(OrderSide::Long))
+            )
+            .order_by_asc(fills::Column::CreatedAt)
+            .order_by_asc(fills::Column::Id)
+            .stream(db)
+            .await?;
+        let txn = db.begin().await?;
+        let mut bars: Vec<(CandleResolution, Option<candles::Model>)> =
+            CandleResolution::iter().map(|resolution| (resolution, None)).collect();
+        let mut written = 0;
+        while let Some(fill) = fills.next().await {
+            let fill = fill?;
+            for (resolution, bar) in &mut bars {
+                let open_time = resolution.open_time(fill.created_at);
+                match bar {
+                    Some(bar) if bar.open_time == open_time => {
+                        bar.high = bar.high.max(fill.price);
+                        bar.low = bar.low.min(fill.price);
+                        bar.close = fill.price;
+                        bar.volume += fill.size;
+                        bar.quote_volume += fill.quote_size;
+                        bar.trade_count += 1;
+                    }
+                    _ => {
+                        let next = candles::Model {
+                            id: 0,
+                            resolution: *resolution,
+                            open_time,
+                            open: fill.price,
+                            high: fill.price,
+                            low: fill.price,
+                            close: fill.price,
+                            volume: fill.size,
+                            quote_volume: fill.quote_size,
+                            trade_count: 1,
+                            market_id,
+                        };
+                        if let Some(complete) = bar.replace(next) {
+                            replace_candle(&txn, complete).await?;
+                            written += 1;
+                        }
+                    }
+                }
+            }
+        }
+        for (_, bar) in bars {
+            if let Some(bar) = bar {
+                replace_candle(&txn, bar).await?;
+                written += 1;
+            }
+        }
+        txn.commit().await?;
+        Ok(written)
+    }
+    // ----------------------------------------------------------------------
+
+    // API keys
+    pub async fn create_api_key(
+        db: &DbConn,
+        client_id: i32,
+        sub_account_id: Option<i32>,
+        read: bool,
+        trade: bool,
+        withdraw: bool,
+    ) -> Result<api_keys::Model, DbErr> {
+        if clients::Entity::find_by_id(client_id).one(db).await?.is_none() {
+            return Err(DbErr::RecordNotFound(format!(
+                "Client with id {client_id} does not exist."
+            )));
+        }
+        if let Some(sub_account_id) = sub_account_id {
+            sub_accounts::Entity::find_by_id(sub_account_id)
+                .filter(sub_accounts::Column::ClientId.eq(client_id))
+                .filter(sub_accounts::Column::Status.eq(SubAccountStatus::Active))
+                .one(db)
+                .await?
+                .ok_or(DbErr::RecordNotFound(format!(
+                    "Sub-account with id {sub_account_id} does not exist."
+                )))?;
+        }
+        let mut rng = rand::thread_rng();
+        api_keys::ActiveModel {
+            key: Set(hex::encode(rng.gen::<[u8; 16]>())),
+            secret: Set(hex::encode(rng.gen::<[u8; 32]>())),
+            read: Set(read),
+            trade: Set(trade),
+            withdraw: Set(withdraw),
+            nonce: Set(0),
+            created_at: Set(Utc::now().naive_utc()),
+            client_id: Set(client_id),
+            sub_account_id: Set(sub_account_id),
+            ..Default::default()
+        }
+            .insert(db)
+            .await
+    }
+
+    pub async fn delete_api_key(db: &DbConn, client_id: i32, id: i32) -> Result<(), DbErr> {
+        let result = api_keys::Entity::delete_many()
+            .filter(api_keys::Column::Id.eq(id))
+            .filter(api_keys::Column::ClientId.eq(client_id))
+            .exec(db)
+            .await?;
+        if result.rows_affected == 0 {
+            Err(DbErr::RecordNotFound(format!(
+                "API key with id {id} does not exist."
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Records the nonce of a signed request. Returns false if the nonce is not larger than every nonce used before
+    /// with the key, in which case the request is a replay.
+    pub async fn use_api_key_nonce(db: &DbConn, id: i32, nonce: i64) -> Result<bool, DbErr> {
+        let result = api_keys::Entity::update_many()
+            .col_expr(api_keys::Column::Nonce, sea_query::Expr::value(nonce))
+            .filter(api_keys::Column::Id.eq(id))
+            .filter(api_keys::Column::Nonce.lt(nonce))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    // ----------------------------------------------------------------------
+
+    // Admins
+    pub async fn create_admin(db: &DbConn, name: String) -> Result<admins::Model, DbErr> {
+        let mut rng = rand::thread_rng();
+        admins::ActiveModel {
+            name: Set(name),
+            key: Set(hex::encode(rng.gen::<[u8; 16]>())),
+            secret: Set(hex::encode(rng.gen::<[u8; 32]>())),
+            nonce: Set(0),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+            .insert(db)
+            .await
+    }
+
+    /// Records the nonce of a request signed by an admin. Returns false if the request is a replay.
+    pub async fn use_admin_nonce(db: &DbConn, id: i32, nonce: i64) -> Result<bool, DbErr> {
+        let result = admins::Entity::update_many()
+            .col_expr(admins::Column::Nonce, sea_query::Expr::value(nonce))
+            .filter(admins::Column::Id.eq(id))
+            .filter(admins::Column::Nonce.lt(nonce))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    pub async fn create_audit_log(
+        db: &DbConn,
+        admin_id: i32,
+        method: String,
+        path: String,
+        body: Option<String>,
+        status: i16,
+    ) -> Result<audit_logs::Model, DbErr> {
+        audit_logs::ActiveModel {
+            method: Set(method),
+            path: Set(path),
+            body: Set(body),
+            status: Set(status),
+            created_at: Set(Utc::now().naive_utc()),
+            admin_id: Set(admin_id),
+            ..Default::default()
+        }
+            .insert(db)
+            .await
+    }
+    // ----------------------------------------------------------------------
+}
+
+/// Whether a price or size is a finite number above zero, which NaN fails unlike comparisons with increments.
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// Replaces the bar of a market at the resolution and open time of the given one.
+async fn replace_candle(txn: &DatabaseTransaction, bar: candles::Model) -> Result<(), DbErr> {
+    candles::Entity::delete_many()
+        .filter(candles::Column::MarketId.eq(bar.market_id))
+        .filter(candles::Column::Resolution.eq(bar.resolution))
+        .filter(candles::Column::OpenTime.eq(bar.open_time))
+        .exec(txn)
+        .await?;
+    candles::ActiveModel { id: NotSet, ..bar.into_active_model() }.insert(txn).await?;
+    Ok(())
+}
+
+/// Moves the stored offset of a consumer to the offset of the message it is processing. Returns false if the consumer
+/// already processed the message.
+async fn advance_stream_offset(txn: &DatabaseTransaction, consumer: &str, offset: u64) -> Result<bool, DbErr> {
+    let stored = stream_offsets::Entity::find_by_id(consumer.to_owned()).one(txn).await?;
+    let last_offset = Set(offset as i64);
+    match stored {
+        Some(stored) if stored.last_offset >= offset as i64 => return Ok(false),
+        Some(stored) => stream_offsets::ActiveModel { last_offset, ..stored.into_active_model() }.update(txn).await?,
+        None => stream_offsets::ActiveModel { consumer: Set(consumer.to_owned()), last_offset }.insert(txn).await?,
+    };
+    Ok(true)
 }
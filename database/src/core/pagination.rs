@@ -0,0 +1,92 @@
+use std::cmp::min;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::entities::api_keys::Model as ApiKey;
+use crate::entities::audit_logs::Model as AuditLog;
+use crate::entities::circuit_breakers::Model as CircuitBreaker;
+use crate::entities::clients::Model as Client;
+use crate::entities::fills::{Response as Fill, Trade};
+use crate::entities::markets::Model as Market;
+use crate::entities::orders::Response as Order;
+use crate::entities::positions::Response as Position;
+use crate::entities::sub_accounts::Model as SubAccount;
+
+// ----------------------------------------------------------------------
+
+pub const DEFAULT_PAGE_LIMIT: u64 = 100;
+pub const MAX_PAGE_LIMIT: u64 = 1000;
+
+/// Position of a page in a list sorted by id. `after` and `before` take the `next_cursor` and `prev_cursor` of a
+/// previous page, and at most one of them can be set.
+#[derive(Clone, Debug, Default)]
+pub struct Cursor {
+    pub after: Option<i32>,
+    pub before: Option<i32>,
+    pub limit: Option<u64>, // Defaults to 100, capped at 1000
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[aliases(
+    ClientPage = Page<Client>,
+    MarketPage = Page<Market>,
+    SubAccountPage = Page<SubAccount>,
+    OrderPage = Page<Order>,
+    FillPage = Page<Fill>,
+    TradePage = Page<Trade>,
+    PositionPage = Page<Position>,
+    CircuitBreakerPage = Page<CircuitBreaker>,
+    ApiKeyPage = Page<ApiKey>,
+    AuditLogPage = Page<AuditLog>
+)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    #[schema(example = 100)]
+    pub next_cursor: Option<i32>, // None on the last page
+    #[schema(example = 1)]
+    pub prev_cursor: Option<i32>, // None on the first page
+}
+
+impl Cursor {
+    /// Fetches a page of the query, sorted by the id column in the given order. One extra row is fetched to tell
+    /// whether the page is the last one in its direction.
+    pub(crate) async fn fetch<E, M>(
+        self,
+        db: &DbConn,
+        query: Select<E>,
+        id: E::Column,
+        order: sea_orm::Order,
+        key: impl Fn(&M) -> i32,
+    ) -> Result<Page<M>, DbErr>
+    where
+        E: EntityTrait,
+        M: FromQueryResult,
+    {
+        let limit = min(self.limit.unwrap_or(DEFAULT_PAGE_LIMIT), MAX_PAGE_LIMIT).max(1);
+        let ascending = order == sea_orm::Order::Asc;
+        let query = match (self.after, self.before) {
+            (Some(_), Some(_)) => {
+                return Err(DbErr::Custom("Only one of after and before can be set.".to_owned()))
+            }
+            (Some(after), None) => query
+                .filter(if ascending { id.gt(after) } else { id.lt(after) })
+                .order_by(id, order),
+            (None, Some(before)) => query
+                .filter(if ascending { id.lt(before) } else { id.gt(before) })
+                .order_by(id, if ascending { sea_orm::Order::Desc } else { sea_orm::Order::Asc }),
+            (None, None) => query.order_by(id, order),
+        };
+        let mut data = query.limit(limit + 1).into_model::<M>().all(db).await?;
+        let more = data.len() as u64 > limit;
+        data.truncate(limit as usize);
+
+        let (next_cursor, prev_cursor) = if self.before.is_some() {
+            data.reverse(); // Fetched backwards
+            (data.last().map(&key), data.first().map(&key).filter(|_| more))
+        } else {
+            (data.last().map(&key).filter(|_| more), data.first().map(&key).filter(|_| self.after.is_some()))
+        };
+        Ok(Page { data, next_cursor, prev_cursor })
+    }
+}
@@ -1,9 +1,10 @@
-use std::cmp::min;
 use sea_orm::prelude::*;
 use sea_orm::*;
 use sea_orm_migration::sea_query::Query as SeaQuery;
 
-use crate::entities::{clients, fills, markets, orders, positions, sea_orm_active_enums::{OrderSide, OrderStatus, OrderType, SubAccountStatus}, sub_accounts};
+use crate::core::pagination::{Cursor, Page};
+
+use crate::entities::{admins, api_keys, audit_logs, candles, circuit_breakers, clients, fills, markets, orders, positions, sea_orm_active_enums::{CandleResolution, OrderSide, OrderStatus, SubAccountStatus}, stream_offsets, sub_accounts};
 
 // ----------------------------------------------------------------------
 
@@ -32,12 +33,10 @@ impl Query {
 
     pub async fn find_clients(
         db: &DbConn,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<clients::Model>, DbErr> {
-        clients::Entity::find()
-            .paginate(db, min(page_size.unwrap_or(1), 1000))
-            .fetch_page(page.unwrap_or(1) - 1)
+        cursor: Cursor,
+    ) -> Result<Page<clients::Model>, DbErr> {
+        cursor
+            .fetch(db, clients::Entity::find(), clients::Column::Id, Order::Asc, |client: &clients::Model| client.id)
             .await
     }
     // ----------------------------------------------------------------------
@@ -69,12 +68,71 @@ impl Query {
 
     pub async fn find_markets(
         db: &DbConn,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<markets::Model>, DbErr> {
-        markets::Entity::find()
-            .paginate(db, min(page_size.unwrap_or(1), 1000))
-            .fetch_page(page.unwrap_or(1) - 1)
+        cursor: Cursor,
+    ) -> Result<Page<markets::Model>, DbErr> {
+        cursor
+            .fetch(db, markets::Entity::find(), markets::Column::Id, Order::Asc, |market: &markets::Model| market.id)
+            .await
+    }
+
+    pub async fn find_market_related_circuit_breakers(
+        db: &DbConn,
+        market_id: i32,
+        cursor: Cursor,
+    ) -> Result<Page<circuit_breakers::Model>, DbErr> {
+        let query = circuit_breakers::Entity::find().filter(circuit_breakers::Column::MarketId.eq(market_id));
+        cursor
+            .fetch(db, query, circuit_breakers::Column::Id, Order::Asc, |breaker: &circuit_breakers::Model| breaker.id)
+            .await
+    }
+
+    /// Finds the bars of a market which open within `[start, end)`, oldest first. Callers bound the range, see
+    /// `candles::GetRequest::range`.
+    pub async fn find_market_related_candles(
+        db: &DbConn,
+        market_id: i32,
+        resolution: CandleResolution,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<Vec<candles::Model>, DbErr> {
+        candles::Entity::find()
+            .filter(candles::Column::MarketId.eq(market_id))
+            .filter(candles::Column::Resolution.eq(resolution))
+            .filter(candles::Column::OpenTime.gte(start))
+            .filter(candles::Column::OpenTime.lt(end))
+            .order_by_asc(candles::Column::OpenTime)
+            .all(db)
+            .await
+    }
+
+    /// Offset of the last message which a durable consumer processed, if it processed any.
+    pub async fn find_stream_offset(
+        db: &DbConn,
+        consumer: &str,
+    ) -> Result<Option<u64>, DbErr> {
+        let stored = stream_offsets::Entity::find_by_id(consumer.to_owned()).one(db).await?;
+
+        Ok(stored.map(|stored| stored.last_offset as u64))
+    }
+
+    /// Finds the trades of a market, newest first. Every trade is represented by the fill of its buyer.
+    pub async fn find_market_related_trades(
+        db: &DbConn,
+        market_id: i32,
+        start_time: Option<DateTime>,
+        end_time: Option<DateTime>,
+        cursor: Cursor,
+    ) -> Result<Page<fills::Trade>, DbErr> {
+        let mut query = trades(market_id);
+        if let Some(start_time) = start_time {
+            query = query.filter(fills::Column::CreatedAt.gte(start_time));
+        }
+        if let Some(end_time) = end_time {
+            query = query.filter(fills::Column::CreatedAt.lt(end_time));
+        }
+
+        cursor
+            .fetch(db, query, fills::Column::Id, Order::Desc, |trade: &fills::Trade| trade.id)
             .await
     }
     // ----------------------------------------------------------------------
@@ -109,20 +167,32 @@ impl Query {
         }
     }
 
+    /// Ids of every sub-account of a client, active or not, in ascending order.
+    pub async fn find_sub_account_ids_by_client_id(
+        db: &DbConn,
+        id: i32,
+    ) -> Result<Vec<i32>, DbErr> {
+        let sub_accounts = sub_accounts::Entity::find()
+            .filter(sub_accounts::Column::ClientId.eq(id))
+            .order_by_asc(sub_accounts::Column::Id)
+            .all(db)
+            .await?;
+
+        Ok(sub_accounts.into_iter().map(|sub_account| sub_account.id).collect())
+    }
+
     pub async fn find_sub_accounts(
         db: &DbConn,
         status: Option<SubAccountStatus>,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<sub_accounts::Model>, DbErr> {
+        cursor: Cursor,
+    ) -> Result<Page<sub_accounts::Model>, DbErr> {
         let mut query = sub_accounts::Entity::find();
         if let Some(status) = status {
             query = query.filter(sub_accounts::Column::Status.eq(status));
         }
 
-        query
-            .paginate(db, min(page_size.unwrap_or(1), 1000))
-            .fetch_page(page.unwrap_or(1) - 1)
+        cursor
+            .fetch(db, query, sub_accounts::Column::Id, Order::Asc, |sub_account: &sub_accounts::Model| sub_account.id)
             .await
     }
     // ----------------------------------------------------------------------
@@ -313,20 +383,22 @@ impl Query {
     pub async fn find_client_related_orders(
         db: &DbConn,
         client_id: i32,
-        sub_account_id: Option<i32>,
-        sub_account_name: Option<String>,
-        market_id: Option<i32>,
-        base_currency: Option<String>,
-        quote_currency: Option<String>,
-        client_order_id: Option<String>,
-        side: Option<OrderSide>,
-        r#type: Option<OrderType>,
-        status: Option<OrderStatus>,
-        start_time: Option<DateTime>,
-        end_time: Option<DateTime>,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<orders::Response>, DbErr> {
+        filter: orders::ClientFilter,
+        cursor: Cursor,
+    ) -> Result<Page<orders::Response>, DbErr> {
+        let orders::ClientFilter {
+            sub_account_id,
+            sub_account_name,
+            market_id,
+            base_currency,
+            quote_currency,
+            client_order_id,
+            side,
+            r#type,
+            status,
+            start_time,
+            end_time,
+        } = filter;
         if let Some(client) = clients::Entity::find_by_id(client_id).one(db).await? {
             let mut conditions = Condition::all().add(
                 orders::Column::SubAccountId.in_subquery(
@@ -494,21 +566,17 @@ impl Query {
                 });
             }
 
-            query
+            let query = query
                 .inner_join(sub_accounts::Entity)
                 .column_as(sub_accounts::Column::Name, "sub_account")
                 .inner_join(markets::Entity)
                 .column(markets::Column::BaseCurrency)
                 .column(markets::Column::QuoteCurrency)
                 .column(markets::Column::PriceIncrement)
-                .column(markets::Column::SizeIncrement)
-                .order_by_asc(match status {
-                    Some(OrderStatus::Open) => orders::Column::OpenAt,
-                    _ => orders::Column::ClosedAt,
-                })
-                .into_model::<orders::Response>()
-                .paginate(db, min(page_size.unwrap_or(1), 1000))
-                .fetch_page(page.unwrap_or(1) - 1)
+                .column(markets::Column::SizeIncrement);
+
+            cursor
+                .fetch(db, query, orders::Column::Id, Order::Asc, |order: &orders::Response| order.id)
                 .await
         } else {
             Err(DbErr::RecordNotFound(format!(
@@ -520,14 +588,10 @@ impl Query {
     pub async fn find_market_related_orders(
         db: &DbConn,
         id: i32,
-        side: Option<OrderSide>,
-        r#type: Option<OrderType>,
-        status: Option<OrderStatus>,
-        start_time: Option<DateTime>,
-        end_time: Option<DateTime>,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<orders::Response>, DbErr> {
+        filter: orders::MarketFilter,
+        cursor: Cursor,
+    ) -> Result<Page<orders::Response>, DbErr> {
+        let orders::MarketFilter { side, r#type, status, start_time, end_time } = filter;
         let mut query = orders::Entity::find().filter(
             Condition::all().add(
                 if let Some(market) = markets::Entity::find_by_id(id)
@@ -570,21 +634,17 @@ impl Query {
             });
         }
 
-        query
+        let query = query
             .inner_join(sub_accounts::Entity)
             .column_as(sub_accounts::Column::Name, "sub_account")
             .inner_join(markets::Entity)
             .column(markets::Column::BaseCurrency)
             .column(markets::Column::QuoteCurrency)
             .column(markets::Column::PriceIncrement)
-            .column(markets::Column::SizeIncrement)
-            .order_by_asc(match status {
-                Some(OrderStatus::Open) => orders::Column::OpenAt,
-                _ => orders::Column::ClosedAt,
-            })
-            .into_model::<orders::Response>()
-            .paginate(db, min(page_size.unwrap_or(1), 1000))
-            .fetch_page(page.unwrap_or(1) - 1)
+            .column(markets::Column::SizeIncrement);
+
+        cursor
+            .fetch(db, query, orders::Column::Id, Order::Asc, |order: &orders::Response| order.id)
             .await
     }
     // ----------------------------------------------------------------------
@@ -593,19 +653,21 @@ impl Query {
     pub async fn find_client_related_fills(
         db: &DbConn,
         client_id: i32,
-        sub_account_id: Option<i32>,
-        sub_account_name: Option<String>,
-        market_id: Option<i32>,
-        base_currency: Option<String>,
-        quote_currency: Option<String>,
-        order_id: Option<i32>,
-        side: Option<OrderSide>,
-        r#type: Option<OrderType>,
-        start_time: Option<DateTime>,
-        end_time: Option<DateTime>,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<fills::Response>, DbErr> {
+        filter: fills::ClientFilter,
+        cursor: Cursor,
+    ) -> Result<Page<fills::Response>, DbErr> {
+        let fills::ClientFilter {
+            sub_account_id,
+            sub_account_name,
+            market_id,
+            base_currency,
+            quote_currency,
+            order_id,
+            side,
+            r#type,
+            start_time,
+            end_time,
+        } = filter;
         if let Some(client) = clients::Entity::find_by_id(client_id).one(db).await? {
             let mut conditions = Condition::all().add(
                 fills::Column::SubAccountId.in_subquery(
@@ -773,18 +835,17 @@ impl Query {
                 query = query.filter(fills::Column::CreatedAt.lt(end_time));
             }
 
-            query
+            let query = query
                 .inner_join(sub_accounts::Entity)
                 .column_as(sub_accounts::Column::Name, "sub_account")
                 .inner_join(markets::Entity)
                 .column(markets::Column::BaseCurrency)
                 .column(markets::Column::QuoteCurrency)
                 .column(markets::Column::PriceIncrement)
-                .column(markets::Column::SizeIncrement)
-                .order_by_asc(fills::Column::CreatedAt)
-                .into_model::<fills::Response>()
-                .paginate(db, min(page_size.unwrap_or(1), 1000))
-                .fetch_page(page.unwrap_or(1) - 1)
+                .column(markets::Column::SizeIncrement);
+
+            cursor
+                .fetch(db, query, fills::Column::Id, Order::Asc, |fill: &fills::Response| fill.id)
                 .await
         } else {
             Err(DbErr::RecordNotFound(format!(
@@ -798,15 +859,17 @@ impl Query {
     pub async fn find_client_related_positions(
         db: &DbConn,
         client_id: i32,
-        sub_account_id: Option<i32>,
-        sub_account_name: Option<String>,
-        market_id: Option<i32>,
-        base_currency: Option<String>,
-        quote_currency: Option<String>,
-        side: Option<OrderSide>,
-        page: Option<u64>,
-        page_size: Option<u64>,
-    ) -> Result<Vec<positions::Response>, DbErr> {
+        filter: positions::ClientFilter,
+        cursor: Cursor,
+    ) -> Result<Page<positions::Response>, DbErr> {
+        let positions::ClientFilter {
+            sub_account_id,
+            sub_account_name,
+            market_id,
+            base_currency,
+            quote_currency,
+            side,
+        } = filter;
         if let Some(client) = clients::Entity::find_by_id(client_id).one(db).await? {
             let mut conditions = Condition::all().add(
                 positions::Column::SubAccountId.in_subquery(
@@ -954,17 +1017,17 @@ impl Query {
                 query = query.filter(positions::Column::Side.eq(side));
             }
 
-            query
+            let query = query
                 .inner_join(sub_accounts::Entity)
                 .column_as(sub_accounts::Column::Name, "sub_account")
                 .inner_join(markets::Entity)
                 .column(markets::Column::BaseCurrency)
                 .column(markets::Column::QuoteCurrency)
                 .column(markets::Column::PriceIncrement)
-                .column(markets::Column::SizeIncrement)
-                .into_model::<positions::Response>()
-                .paginate(db, min(page_size.unwrap_or(1), 1000))
-                .fetch_page(page.unwrap_or(1) - 1)
+                .column(markets::Column::SizeIncrement);
+
+            cursor
+                .fetch(db, query, positions::Column::Id, Order::Asc, |position: &positions::Response| position.id)
                 .await
         } else {
             Err(DbErr::RecordNotFound(format!(
@@ -973,4 +1036,75 @@ impl Query {
         }
     }
     // ----------------------------------------------------------------------
+
+    // API keys
+    pub async fn find_api_key_by_key(db: &DbConn, key: String) -> Result<api_keys::Model, DbErr> {
+        api_keys::Entity::find()
+            .filter(api_keys::Column::Key.eq(key.clone()))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "API key {key} does not exist."
+            )))
+    }
+
+    pub async fn find_client_related_api_keys(
+        db: &DbConn,
+        client_id: i32,
+        cursor: Cursor,
+    ) -> Result<Page<api_keys::Model>, DbErr> {
+        if let Some(client) = clients::Entity::find_by_id(client_id).one(db).await? {
+            let query = api_keys::Entity::find().filter(api_keys::Column::ClientId.eq(client.id));
+            cursor
+                .fetch(db, query, api_keys::Column::Id, Order::Asc, |api_key: &api_keys::Model| api_key.id)
+                .await
+        } else {
+            Err(DbErr::RecordNotFound(format!(
+                "Client with id {client_id} does not exist."
+            )))
+        }
+    }
+
+    // ----------------------------------------------------------------------
+
+    // Admins
+    pub async fn find_admin_by_key(db: &DbConn, key: String) -> Result<admins::Model, DbErr> {
+        admins::Entity::find()
+            .filter(admins::Column::Key.eq(key.clone()))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!(
+                "Admin key {key} does not exist."
+            )))
+    }
+
+    pub async fn find_admin_related_audit_logs(
+        db: &DbConn,
+        admin_id: i32,
+        cursor: Cursor,
+    ) -> Result<Page<audit_logs::Model>, DbErr> {
+        let query = audit_logs::Entity::find().filter(audit_logs::Column::AdminId.eq(admin_id));
+        cursor
+            .fetch(db, query, audit_logs::Column::Id, Order::Asc, |audit_log: &audit_logs::Model| audit_log.id)
+            .await
+    }
+    // ----------------------------------------------------------------------
+}
+
+/// The public trades of a market, one per buy-side fill, without the sub-accounts or orders involved.
+pub(crate) fn trades(market_id: i32) -> Select<fills::Entity> {
+    fills::Entity::find()
+        .select_only()
+        .column(fills::Column::Id)
+        .column(fills::Column::Price)
+        .column(fills::Column::Size)
+        .column(fills::Column::Aggressor)
+        .column(fills::Column::CreatedAt)
+        .filter(fills::Column::MarketId.eq(market_id))
+        .filter(
+            Condition::any()
+                .add(fills::Column::Side.eq(OrderSide::Buy))
+                .add(fills::Column::Side.eq(OrderSide::Bid))
+                .add(fills::Column::Side.eq(OrderSide::Long))
+        )
 }
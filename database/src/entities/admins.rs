@@ -0,0 +1,39 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, ToSchema)]
+#[sea_orm(table_name = "admins")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[schema(example = 1)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    #[schema(example = "Operations")]
+    pub name: String,
+    #[sea_orm(unique)]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015")]
+    pub key: String,
+    #[serde(skip_serializing)] // The secret is only shown once when the admin is created
+    pub secret: String,
+    #[serde(skip_serializing)]
+    pub nonce: i64,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::audit_logs::Entity")]
+    AuditLogs,
+}
+
+impl Related<super::audit_logs::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuditLogs.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
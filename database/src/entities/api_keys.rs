@@ -0,0 +1,125 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, ToSchema)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[schema(example = 1)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015")]
+    pub key: String,
+    #[serde(skip_serializing)] // The secret is only returned once when the key is created
+    pub secret: String,
+    #[schema(example = true)]
+    pub read: bool,
+    #[schema(example = true)]
+    pub trade: bool,
+    #[schema(example = false)]
+    pub withdraw: bool,
+    #[serde(skip_serializing)]
+    pub nonce: i64,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+    #[schema(example = 1)]
+    pub client_id: i32,
+    #[schema(example = 1)]
+    pub sub_account_id: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::clients::Entity",
+        from = "Column::ClientId",
+        to = "super::clients::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Clients,
+    #[sea_orm(
+        belongs_to = "super::sub_accounts::Entity",
+        from = "Column::SubAccountId",
+        to = "super::sub_accounts::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    SubAccounts,
+}
+
+impl Related<super::clients::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Clients.def()
+    }
+}
+
+impl Related<super::sub_accounts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SubAccounts.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// ----------------------------------------------------------------------
+
+#[derive(Deserialize, IntoParams)]
+pub struct GetRequest {
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostRequest {
+    #[schema(example = 1)]
+    pub sub_account_id: Option<i32>, // Restrict the key to a single sub-account
+    #[serde(default)]
+    #[schema(example = true)]
+    pub read: bool,
+    #[serde(default)]
+    #[schema(example = true)]
+    pub trade: bool,
+    #[serde(default)]
+    #[schema(example = false)]
+    pub withdraw: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Created {
+    #[schema(example = 1)]
+    pub id: i32,
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015")]
+    pub key: String,
+    #[schema(example = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae")]
+    pub secret: String,
+    #[schema(example = true)]
+    pub read: bool,
+    #[schema(example = true)]
+    pub trade: bool,
+    #[schema(example = false)]
+    pub withdraw: bool,
+    #[schema(example = 1)]
+    pub sub_account_id: Option<i32>,
+}
+
+impl From<Model> for Created {
+    fn from(model: Model) -> Self {
+        Created {
+            id: model.id,
+            key: model.key,
+            secret: model.secret,
+            read: model.read,
+            trade: model.trade,
+            withdraw: model.withdraw,
+            sub_account_id: model.sub_account_id,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, ToSchema)]
+#[sea_orm(table_name = "audit_logs")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[schema(example = 1)]
+    pub id: i32,
+    #[schema(example = "POST")]
+    pub method: String,
+    #[schema(example = "/markets/BTC/USD")]
+    pub path: String,
+    #[schema(example = "{\"price_increment\": 0.01}")]
+    pub body: Option<String>,
+    #[schema(example = 200)]
+    pub status: i16,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+    #[schema(example = 1)]
+    pub admin_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::admins::Entity",
+        from = "Column::AdminId",
+        to = "super::admins::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Admins,
+}
+
+impl Related<super::admins::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Admins.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
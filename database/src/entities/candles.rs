@@ -0,0 +1,110 @@
+use super::sea_orm_active_enums::CandleResolution;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[sea_orm(table_name = "candles")]
+pub struct Model { // An OHLCV bar aggregated from the fills of a market
+    #[sea_orm(primary_key)]
+    #[schema(example = 1)]
+    pub id: i32,
+    #[schema(example = "1m")]
+    pub resolution: CandleResolution,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub open_time: DateTime, // Aligned to the resolution
+    #[schema(example = 50.0)]
+    pub open: f32,
+    #[schema(example = 55.0)]
+    pub high: f32,
+    #[schema(example = 45.0)]
+    pub low: f32,
+    #[schema(example = 52.0)]
+    pub close: f32,
+    #[schema(example = 100.0)]
+    pub volume: f32,
+    #[schema(example = 5000.0)]
+    pub quote_volume: f32,
+    #[schema(example = 10)]
+    pub trade_count: i32,
+    #[schema(example = 1)]
+    pub market_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::markets::Entity",
+        from = "Column::MarketId",
+        to = "super::markets::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Markets,
+}
+
+impl Related<super::markets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Markets.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// ----------------------------------------------------------------------
+
+/// Largest number of bars returned at once.
+pub const MAX_CANDLES: i64 = 1000;
+
+impl CandleResolution {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleResolution::OneMinute => 60,
+            CandleResolution::FiveMinutes => 300,
+            CandleResolution::FifteenMinutes => 900,
+            CandleResolution::OneHour => 3600,
+            CandleResolution::OneDay => 86400,
+        }
+    }
+
+    /// Start of the bar containing the given time.
+    pub fn open_time(&self, time: DateTime) -> DateTime {
+        let timestamp = time.and_utc().timestamp();
+        chrono::DateTime::from_timestamp(timestamp - timestamp.rem_euclid(self.seconds()), 0).unwrap().naive_utc()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GetRequest {
+    #[param(example = "1m")]
+    pub resolution: CandleResolution,
+    #[param(example = "1970-01-01T00:00:00")]
+    pub start: Option<DateTime>, // Inclusive open time of the first bar
+    #[param(example = "1970-01-01T00:00:00")]
+    pub end: Option<DateTime>, // Exclusive open time of the last bar
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CandlePage {
+    pub data: Vec<Model>, // Oldest first
+    #[schema(example = "1970-01-01T16:40:00")]
+    pub next_start: Option<DateTime>, // Start of the next page, None on the last page
+}
+
+impl GetRequest {
+    /// Resolves the range of open times to fetch and the start of the next page. The end defaults to now and the start
+    /// to `MAX_CANDLES` bars before the end. Ranges of more than `MAX_CANDLES` bars are split into pages of that many
+    /// bars, each of which is requested again from the start of the next page.
+    pub fn page(&self, now: DateTime) -> (DateTime, DateTime, Option<DateTime>) {
+        let span = chrono::Duration::seconds(self.resolution.seconds() * MAX_CANDLES);
+        let end = self.end.unwrap_or(now);
+        let start = self.start.unwrap_or(end - span);
+        if end - start > span {
+            (start, start + span, Some(start + span))
+        } else {
+            (start, end, None)
+        }
+    }
+}
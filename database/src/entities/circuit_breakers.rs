@@ -0,0 +1,58 @@
+use super::sea_orm_active_enums::MarketStatus;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[sea_orm(table_name = "circuit_breakers")]
+pub struct Model { // A volatility interruption, persisted and broadcast as market data by the matching engine
+    #[sea_orm(primary_key)]
+    #[schema(example = 1)]
+    pub id: i32,
+    #[schema(example = 100.0)]
+    pub reference_price: f32,
+    #[schema(example = 112.5)]
+    pub price: f32, // Price at which the trade would have printed
+    #[schema(example = "Auction")]
+    pub status: MarketStatus,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+    #[schema(example = "1970-01-01T00:01:00")]
+    pub resumes_at: Option<DateTime>, // None if the market was halted
+    #[schema(example = 1)]
+    pub market_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::markets::Entity",
+        from = "Column::MarketId",
+        to = "super::markets::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Markets,
+}
+
+impl Related<super::markets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Markets.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+// ----------------------------------------------------------------------
+
+#[derive(Deserialize, IntoParams)]
+pub struct GetRequest {
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
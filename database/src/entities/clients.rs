@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.2
 
+use super::sea_orm_active_enums::ClientTier;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::{ToSchema, IntoParams};
@@ -17,6 +18,8 @@ pub struct Model {
     pub email: String,
     #[schema(example = "1970-01-01T00:00:00")]
     pub created_at: DateTime,
+    #[schema(example = ClientTier::Standard)]
+    pub tier: ClientTier,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -37,14 +40,22 @@ impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Deserialize, IntoParams)]
 pub struct GetRequest {
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
 }
 
 #[derive(Deserialize, ToSchema)]
 pub struct PutRequest {
     #[schema(example = "example@gmail.com")]
     pub new_email: String,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PutTierRequest {
+    #[param(example = "Professional")]
+    pub tier: ClientTier,
+}
@@ -25,6 +25,8 @@ pub struct Model {
     pub side: OrderSide,
     #[schema(example = OrderType::Market)]
     pub r#type: OrderType,
+    #[schema(example = OrderSide::Buy)]
+    pub aggressor: Option<OrderSide>, // None for trades of auction uncrosses
     #[schema(example = "1970-01-01T00:00:00")]
     pub created_at: DateTime,
     #[schema(example = 1)]
@@ -87,6 +89,8 @@ impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Clone, Debug, PartialEq, FromQueryResult, Serialize, ToSchema)]
 pub struct Response {
+    #[schema(example = 1)]
+    pub id: i32,
     #[schema(example = 50.0)]
     pub price: f32,
     #[schema(example = 100.0)]
@@ -113,19 +117,39 @@ pub struct Response {
     pub order_id: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug)] // TODO: Derive into active model
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Fill {
     pub price: f32,
     pub size: f32,
     pub quote_size: f32,
     pub side: OrderSide,
     pub r#type: OrderType,
+    pub aggressor: Option<OrderSide>, // None for trades of auction uncrosses
     pub created_at: DateTime,
     pub sub_account_id: i32,
     pub market_id: i32,
     pub order_id: i32,
 }
 
+impl From<Fill> for Model {
+    /// A fill to be stored, whose id is assigned by the database.
+    fn from(fill: Fill) -> Self {
+        Model {
+            id: 0,
+            price: fill.price,
+            size: fill.size,
+            quote_size: fill.quote_size,
+            side: fill.side,
+            r#type: fill.r#type,
+            aggressor: fill.aggressor,
+            created_at: fill.created_at,
+            sub_account_id: fill.sub_account_id,
+            market_id: fill.market_id,
+            order_id: fill.order_id,
+        }
+    }
+}
+
 #[derive(Deserialize, IntoParams)]
 pub struct ClientGetRequest {
     #[param(example = 1)]
@@ -148,8 +172,70 @@ pub struct ClientGetRequest {
     pub start_time: Option<DateTime>,
     #[param(example = "1970-01-01T00:00:00")]
     pub end_time: Option<DateTime>,
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
+
+/// Filters of the fills of a client. A sub-account is given by id or name, and a market by id or currencies.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter {
+    pub sub_account_id: Option<i32>,
+    pub sub_account_name: Option<String>,
+    pub market_id: Option<i32>,
+    pub base_currency: Option<String>,
+    pub quote_currency: Option<String>,
+    pub order_id: Option<i32>,
+    pub side: Option<OrderSide>,
+    pub r#type: Option<OrderType>,
+    pub start_time: Option<DateTime>,
+    pub end_time: Option<DateTime>,
+}
+
+impl ClientGetRequest {
+    pub fn filter(&self) -> ClientFilter {
+        ClientFilter {
+            sub_account_id: self.sub_account_id,
+            sub_account_name: self.sub_account_name.clone(),
+            market_id: self.market_id,
+            base_currency: self.base_currency.clone(),
+            quote_currency: self.quote_currency.clone(),
+            order_id: self.order_id,
+            side: self.side.clone(),
+            r#type: self.r#type.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, FromQueryResult, Serialize, Deserialize, ToSchema)]
+pub struct Trade { // A public trade, which does not reveal the sub-accounts or orders involved
+    #[schema(example = 1)]
+    pub id: i32,
+    #[schema(example = 50.0)]
+    pub price: f32,
+    #[schema(example = 100.0)]
+    pub size: f32,
+    #[schema(example = OrderSide::Buy)]
+    pub aggressor: Option<OrderSide>, // None for trades of auction uncrosses
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct TradesRequest {
+    #[param(example = "1970-01-01T00:00:00")]
+    pub start_time: Option<DateTime>, // Inclusive
+    #[param(example = "1970-01-01T00:00:00")]
+    pub end_time: Option<DateTime>, // Exclusive
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
 }
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.2
 
+use super::sea_orm_active_enums::MarketStatus;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::{ToSchema, IntoParams};
@@ -22,6 +23,16 @@ pub struct Model {
     pub size_increment: f32,
     #[schema(example = "1970-01-01T00:00:00")]
     pub created_at: DateTime,
+    #[schema(example = MarketStatus::Continuous)]
+    pub status: MarketStatus,
+    #[schema(example = 10.0)]
+    pub band_percent: Option<f32>, // Circuit breaker disabled if None
+    #[schema(example = 300)]
+    pub band_window: i32,
+    #[schema(example = MarketStatus::Auction)]
+    pub band_action: MarketStatus,
+    #[schema(example = 60)]
+    pub auction_duration: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -32,6 +43,10 @@ pub enum Relation {
     Positions,
     #[sea_orm(has_many = "super::fills::Entity")]
     Fills,
+    #[sea_orm(has_many = "super::circuit_breakers::Entity")]
+    CircuitBreakers,
+    #[sea_orm(has_many = "super::candles::Entity")]
+    Candles,
 }
 
 impl Related<super::orders::Entity> for Entity {
@@ -52,16 +67,30 @@ impl Related<super::fills::Entity> for Entity {
     }
 }
 
+impl Related<super::circuit_breakers::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CircuitBreakers.def()
+    }
+}
+
+impl Related<super::candles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Candles.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 // ----------------------------------------------------------------------
 
 #[derive(Deserialize, IntoParams)]
 pub struct GetRequest {
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
 }
 
 #[derive(Deserialize, ToSchema)] // Body parameters require ToSchema macro
@@ -83,3 +112,106 @@ pub struct PutRequest {
     #[schema(example = 0.01)]
     pub size_increment: Option<f32>,
 }
+
+#[derive(Deserialize, ToSchema)]
+pub struct PutStatusRequest {
+    #[schema(example = "Halted")]
+    pub status: MarketStatus,
+    #[serde(default)]
+    #[schema(example = false)]
+    pub cancel_resting: bool, // Cancel resting orders when halting. Closing a market always cancels them
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PutCircuitBreakerRequest {
+    #[schema(example = 10.0)]
+    pub band_percent: Option<f32>, // Largest move of a trade from the reference price. Disables the breaker if null
+    #[schema(example = 300)]
+    pub band_window: i32, // Seconds of trades from which the reference price is taken
+    #[schema(example = "Auction")]
+    pub band_action: MarketStatus, // Either Auction or Halted
+    #[schema(example = 60)]
+    pub auction_duration: i32, // Seconds until continuous trading resumes after a volatility auction
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct StatusChange { // Published to the matching engine on the orders stream and broadcast as market data
+    #[schema(example = 1)]
+    pub market_id: i32,
+    #[schema(example = "Halted")]
+    pub status: MarketStatus,
+    #[schema(example = false)]
+    pub cancel_resting: bool,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub changed_at: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct CircuitBreakerChange { // Published to the matching engine on the orders stream
+    #[schema(example = 1)]
+    pub market_id: i32,
+    #[schema(example = 10.0)]
+    pub band_percent: Option<f32>, // Disables the breaker if None
+    #[schema(example = 300)]
+    pub band_window: i32,
+    #[schema(example = "Auction")]
+    pub band_action: MarketStatus,
+    #[schema(example = 60)]
+    pub auction_duration: i32,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub changed_at: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Indicative { // Broadcast as market data during the call phase of an auction
+    #[schema(example = 1)]
+    pub market_id: i32,
+    #[schema(example = 100.0)]
+    pub price: Option<f32>, // None if the orders collected so far do not cross
+    #[schema(example = 25.0)]
+    pub volume: f32,
+    #[schema(example = -5.0)]
+    pub imbalance: f32, // Bid volume less ask volume which is executable at the price
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct TopOfBook { // Broadcast as market data whenever the best bid or ask price changes
+    #[schema(example = 1)]
+    pub market_id: i32,
+    #[schema(example = 99.5)]
+    pub bid: Option<f32>, // None if there are no bids
+    #[schema(example = 100.5)]
+    pub ask: Option<f32>, // None if there are no asks
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub created_at: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Ticker { // Statistics of the trades of a market over the last 24 hours
+    #[schema(example = 1)]
+    pub market_id: i32,
+    #[schema(example = 100.0)]
+    pub last_price: Option<f32>,
+    #[schema(example = 99.5)]
+    pub best_bid: Option<f32>,
+    #[schema(example = 100.5)]
+    pub best_ask: Option<f32>,
+    #[schema(example = 95.0)]
+    pub open: Option<f32>, // None if there were no trades in the last 24 hours
+    #[schema(example = 105.0)]
+    pub high: Option<f32>,
+    #[schema(example = 90.0)]
+    pub low: Option<f32>,
+    #[schema(example = 1000.0)]
+    pub volume: f32,
+    #[schema(example = 100000.0)]
+    pub quote_volume: f32,
+    #[schema(example = 5.26)]
+    pub change_percent: Option<f32>, // Change of the last price from the open
+    #[schema(example = 250)]
+    pub trade_count: i32,
+    #[schema(example = "1970-01-01T00:00:00")]
+    pub updated_at: DateTime,
+}
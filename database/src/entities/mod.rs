@@ -2,10 +2,16 @@
 
 pub mod prelude;
 
+pub mod admins;
+pub mod api_keys;
+pub mod audit_logs;
+pub mod candles;
+pub mod circuit_breakers;
 pub mod clients;
 pub mod fills;
 pub mod markets;
 pub mod orders;
 pub mod positions;
 pub mod sea_orm_active_enums;
+pub mod stream_offsets;
 pub mod sub_accounts;
@@ -86,6 +86,8 @@ impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Clone, Debug, PartialEq, FromQueryResult, Serialize, ToSchema)]
 pub struct Response {
+    #[schema(example = 1)]
+    pub id: i32,
     #[schema(example = "Test")]
     pub client_order_id: Option<String>,
     #[schema(example = 50.0)]
@@ -140,10 +142,46 @@ pub struct ClientGetRequest {
     pub start_time: Option<DateTime>,
     #[param(example = "1970-01-01T00:00:00")]
     pub end_time: Option<DateTime>,
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
+
+/// Filters of the orders of a client. A sub-account is given by id or name, and a market by id or currencies.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter {
+    pub sub_account_id: Option<i32>,
+    pub sub_account_name: Option<String>,
+    pub market_id: Option<i32>,
+    pub base_currency: Option<String>,
+    pub quote_currency: Option<String>,
+    pub client_order_id: Option<String>,
+    pub side: Option<OrderSide>,
+    pub r#type: Option<OrderType>,
+    pub status: Option<OrderStatus>,
+    pub start_time: Option<DateTime>,
+    pub end_time: Option<DateTime>,
+}
+
+impl ClientGetRequest {
+    pub fn filter(&self) -> ClientFilter {
+        ClientFilter {
+            sub_account_id: self.sub_account_id,
+            sub_account_name: self.sub_account_name.clone(),
+            market_id: self.market_id,
+            base_currency: self.base_currency.clone(),
+            quote_currency: self.quote_currency.clone(),
+            client_order_id: self.client_order_id.clone(),
+            side: self.side.clone(),
+            r#type: self.r#type.clone(),
+            status: self.status.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+        }
+    }
 }
 
 #[derive(Deserialize, IntoParams)]
@@ -178,10 +216,50 @@ pub struct MarketGetRequest {
     pub start_time: Option<DateTime>,
     #[param(example = "1970-01-01T00:00:00")]
     pub end_time: Option<DateTime>,
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
+
+/// Filters of the orders of a market.
+#[derive(Clone, Debug, Default)]
+pub struct MarketFilter {
+    pub side: Option<OrderSide>,
+    pub r#type: Option<OrderType>,
+    pub status: Option<OrderStatus>,
+    pub start_time: Option<DateTime>,
+    pub end_time: Option<DateTime>,
+}
+
+impl MarketGetRequest {
+    pub fn filter(&self) -> MarketFilter {
+        MarketFilter {
+            side: self.side.clone(),
+            r#type: self.r#type.clone(),
+            status: self.status.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CancelAllRequest {
+    #[param(example = 1)]
+    pub sub_account_id: Option<i32>,
+    #[param(example = 1)]
+    pub market_id: Option<i32>,
+    #[param(example = "Buy")]
+    pub side: Option<OrderSide>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct OrderSessionRequest {
+    #[param(example = true)]
+    pub cancel_on_disconnect: Option<bool>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -223,3 +301,29 @@ pub struct Order {
     #[schema(example = "1970-01-01T00:00:00")]
     pub open_at: DateTime
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Command { // Instructions published to the matching engine on the orders stream
+    New(Order),
+    Cancel(Order),
+    Amend(Order), // Carries the replacement price and remaining size
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct MassCancel { // Published to the matching engine to pull the resting orders of sub-accounts at once
+    #[schema(example = 1)]
+    pub market_id: Option<i32>, // Every market when none
+    #[schema(example = json!([1, 2]))]
+    pub sub_account_ids: Vec<i32>,
+    #[schema(example = "Buy")]
+    pub side: Option<OrderSide>, // Both sides when none
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cancelled { // Published by the matching engine for every resting order it pulls without a fill
+    pub order_id: i32,
+    pub sub_account_id: i32,
+    pub market_id: i32,
+    pub remaining_size: f32,
+    pub cancelled_at: DateTime,
+}
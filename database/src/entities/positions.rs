@@ -64,6 +64,8 @@ impl ActiveModelBehavior for ActiveModel {}
 
 #[derive(Clone, Debug, PartialEq, FromQueryResult, Serialize, ToSchema)]
 pub struct Response {
+    #[schema(example = 1)]
+    pub id: i32,
     #[schema(example = 50.0)]
     pub avg_entry_price: f32,
     #[schema(example = 100.0)]
@@ -96,8 +98,34 @@ pub struct ClientGetRequest {
     pub quote_currency: Option<String>,
     #[param(example = "Buy")]
     pub side: Option<OrderSide>,
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
+}
+
+/// Filters of the positions of a client. A sub-account is given by id or name, and a market by id or currencies.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter {
+    pub sub_account_id: Option<i32>,
+    pub sub_account_name: Option<String>,
+    pub market_id: Option<i32>,
+    pub base_currency: Option<String>,
+    pub quote_currency: Option<String>,
+    pub side: Option<OrderSide>,
+}
+
+impl ClientGetRequest {
+    pub fn filter(&self) -> ClientFilter {
+        ClientFilter {
+            sub_account_id: self.sub_account_id,
+            sub_account_name: self.sub_account_name.clone(),
+            market_id: self.market_id,
+            base_currency: self.base_currency.clone(),
+            quote_currency: self.quote_currency.clone(),
+            side: self.side.clone(),
+        }
+    }
 }
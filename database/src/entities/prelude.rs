@@ -1,8 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.2
 
+pub use super::admins::Entity as Admins;
+pub use super::api_keys::Entity as ApiKeys;
+pub use super::audit_logs::Entity as AuditLogs;
+pub use super::candles::Entity as Candles;
+pub use super::circuit_breakers::Entity as CircuitBreakers;
 pub use super::clients::Entity as Clients;
 pub use super::fills::Entity as Fills;
 pub use super::markets::Entity as Markets;
 pub use super::orders::Entity as Orders;
 pub use super::positions::Entity as Positions;
+pub use super::stream_offsets::Entity as StreamOffsets;
 pub use super::sub_accounts::Entity as SubAccounts;
@@ -43,3 +43,46 @@ pub enum OrderType {
     #[sea_orm(string_value = "market")]
     Market,
 }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "client_tier")]
+pub enum ClientTier {
+    #[sea_orm(string_value = "market_maker")]
+    MarketMaker,
+    #[sea_orm(string_value = "professional")]
+    Professional,
+    #[sea_orm(string_value = "standard")]
+    Standard,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "candle_resolution")]
+pub enum CandleResolution {
+    #[sea_orm(string_value = "1m")]
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[sea_orm(string_value = "5m")]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[sea_orm(string_value = "15m")]
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[sea_orm(string_value = "1h")]
+    #[serde(rename = "1h")]
+    OneHour,
+    #[sea_orm(string_value = "1d")]
+    #[serde(rename = "1d")]
+    OneDay,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "market_status")]
+pub enum MarketStatus {
+    #[sea_orm(string_value = "auction")]
+    Auction,
+    #[sea_orm(string_value = "closed")]
+    Closed,
+    #[sea_orm(string_value = "continuous")]
+    Continuous,
+    #[sea_orm(string_value = "halted")]
+    Halted,
+    #[sea_orm(string_value = "pre_open")]
+    PreOpen,
+}
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "stream_offsets")]
+pub struct Model { // Position of a durable consumer in a stream of the matching engine
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub consumer: String,
+    pub last_offset: i64, // Offset of the last message which the consumer processed
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -73,10 +73,12 @@ impl ActiveModelBehavior for ActiveModel {}
 pub struct GetRequest {
     #[param(example = "Active")]
     pub status: Option<SubAccountStatus>,
-    #[param(example = 0)]
-    pub page: Option<u64>,
-    #[param(example = 1000)]
-    pub page_size: Option<u64>,
+    #[param(example = 100)]
+    pub after: Option<i32>, // Next cursor of the previous page
+    #[param(example = 1)]
+    pub before: Option<i32>, // Previous cursor of the next page
+    #[param(example = 100)]
+    pub limit: Option<u64>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -1,7 +1,95 @@
-use database::Migrator;
+use std::io::Write;
+
+use futures::StreamExt;
+
+use database::{CandleResolution, Cursor, Dataset, Engine, Export, ExportRequest, Migrator, Mutation, Query};
 use sea_orm_migration::prelude::*;
 
 #[async_std::main]
 async fn main() {
-    cli::run_cli(Migrator).await;
+    let args: Vec<String> = std::env::args().collect();
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("create-admin"), Some(name)) => create_admin(name.to_owned()).await,
+        (Some("create-admin"), None) => eprintln!("Usage: database create-admin <name>"),
+        (Some("backfill-candles"), market_id) => match market_id.map(|id| id.parse()).transpose() {
+            Ok(market_id) => backfill_candles(market_id).await,
+            Err(_) => eprintln!("Usage: database backfill-candles [market_id]"),
+        },
+        (Some("export"), _) => match parse_export(&args[2..]) {
+            Some((dataset, market_id, path, request)) => export(dataset, market_id, path, request).await,
+            None => eprintln!(
+                "Usage: database export <fills|orders|trades|candles> <market_id> <csv|parquet> <path> [start_time] \
+                [end_time] [resolution]"
+            ),
+        },
+        _ => cli::run_cli(Migrator).await, // Fall back to the migration CLI
+    }
+}
+
+/// Creates an admin and prints its credentials, which are not shown again.
+async fn create_admin(name: String) {
+    let db = Engine::connect().await.expect("Failed to connect to the database");
+    let admin = Mutation::create_admin(&db, name).await.expect("Failed to create admin");
+    println!("Admin key: {}", admin.key);
+    println!("Admin secret: {}", admin.secret);
+}
+
+/// Rebuilds the candles of a market, or of every market, from the stored fills.
+async fn backfill_candles(market_id: Option<i32>) {
+    let db = Engine::connect().await.expect("Failed to connect to the database");
+    let market_ids = match market_id {
+        Some(market_id) => vec![market_id],
+        None => {
+            let mut market_ids = Vec::new();
+            let mut cursor = Cursor::default();
+            loop {
+                let page = Query::find_markets(&db, cursor.clone()).await.expect("Failed to find markets");
+                market_ids.extend(page.data.iter().map(|market| market.id));
+                match page.next_cursor {
+                    Some(after) => cursor.after = Some(after),
+                    None => break market_ids,
+                }
+            }
+        }
+    };
+    for market_id in market_ids {
+        let count = Mutation::backfill_candles(&db, market_id).await.expect("Failed to backfill candles");
+        println!("Market {market_id}: {count} candles");
+    }
+}
+
+fn parse_export(args: &[String]) -> Option<(Dataset, i32, String, ExportRequest)> {
+    let [dataset, market_id, format, path, rest @ ..] = args else {
+        return None;
+    };
+    if rest.len() > 3 {
+        return None;
+    }
+    let time = |i: usize| rest.get(i).map(|time| time.parse()).transpose().ok();
+    Some((
+        dataset.parse().ok()?,
+        market_id.parse().ok()?,
+        path.to_owned(),
+        ExportRequest {
+            format: format.parse().ok()?,
+            start_time: time(0)?,
+            end_time: time(1)?,
+            resolution: rest
+                .get(2)
+                .map(|resolution| serde_json::from_value::<CandleResolution>(resolution.as_str().into()))
+                .transpose()
+                .ok()?,
+        },
+    ))
+}
+
+/// Writes a dataset of a market to a CSV or Parquet file as it is read from the database.
+async fn export(dataset: Dataset, market_id: i32, path: String, request: ExportRequest) {
+    let db = Engine::connect().await.expect("Failed to connect to the database");
+    let mut file = std::fs::File::create(&path).expect("Failed to create the file");
+    let mut chunks = Export::stream(db, dataset, market_id, request);
+    while let Some(chunk) = chunks.next().await {
+        file.write_all(&chunk.expect("Failed to export")).expect("Failed to write to the file");
+    }
+    println!("Exported {dataset} of market {market_id} to {path}");
 }
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230301_000001_create_api_keys_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiKeys::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiKeys::Key).string().unique_key().not_null())
+                    .col(ColumnDef::new(ApiKeys::Secret).string().not_null())
+                    .col(ColumnDef::new(ApiKeys::Read).boolean().not_null())
+                    .col(ColumnDef::new(ApiKeys::Trade).boolean().not_null())
+                    .col(ColumnDef::new(ApiKeys::Withdraw).boolean().not_null())
+                    .col(ColumnDef::new(ApiKeys::Nonce).big_integer().not_null().default(0))
+                    .col(ColumnDef::new(ApiKeys::CreatedAt).timestamp().not_null())
+                    .col(ColumnDef::new(ApiKeys::ClientId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("client_id")
+                            .from(ApiKeys::Table, ApiKeys::ClientId)
+                            .to(Clients::Table, Clients::Id),
+                    )
+                    .col(ColumnDef::new(ApiKeys::SubAccountId).integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("sub_account_id")
+                            .from(ApiKeys::Table, ApiKeys::SubAccountId)
+                            .to(SubAccounts::Table, SubAccounts::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKeys::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum ApiKeys {
+    Table,
+    Id, // Primary key
+    Key,
+    Secret,
+    Read,
+    Trade,
+    Withdraw,
+    Nonce, // Largest nonce used to sign a request
+    CreatedAt,
+    ClientId, // Foreign key
+    SubAccountId, // Foreign key, null if the key may act on all sub-accounts of the client
+}
+
+#[derive(Iden)]
+enum Clients {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum SubAccounts {
+    Table,
+    Id,
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230302_000001_create_admins_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Admins::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Admins::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Admins::Name).string().unique_key().not_null())
+                    .col(ColumnDef::new(Admins::Key).string().unique_key().not_null())
+                    .col(ColumnDef::new(Admins::Secret).string().not_null())
+                    .col(ColumnDef::new(Admins::Nonce).big_integer().not_null().default(0))
+                    .col(ColumnDef::new(Admins::CreatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLogs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLogs::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLogs::Method).string().not_null())
+                    .col(ColumnDef::new(AuditLogs::Path).string().not_null())
+                    .col(ColumnDef::new(AuditLogs::Body).text())
+                    .col(ColumnDef::new(AuditLogs::Status).small_integer().not_null())
+                    .col(ColumnDef::new(AuditLogs::CreatedAt).timestamp().not_null())
+                    .col(ColumnDef::new(AuditLogs::AdminId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("admin_id")
+                            .from(AuditLogs::Table, AuditLogs::AdminId)
+                            .to(Admins::Table, Admins::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLogs::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Admins::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Admins {
+    Table,
+    Id, // Primary key
+    Name,
+    Key,
+    Secret,
+    Nonce, // Largest nonce used to sign a request
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum AuditLogs {
+    Table,
+    Id, // Primary key
+    Method,
+    Path, // Path and query of the request
+    Body,
+    Status, // HTTP status of the response
+    CreatedAt,
+    AdminId, // Foreign key
+}
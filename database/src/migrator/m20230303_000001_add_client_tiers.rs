@@ -0,0 +1,77 @@
+use sea_orm::sea_query::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230303_000001_add_client_tiers"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(ClientTier::Table)
+                    .values([ClientTier::Standard, ClientTier::Professional, ClientTier::MarketMaker])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Clients::Table)
+                    .add_column(
+                        ColumnDef::new(Clients::Tier)
+                            .enumeration(
+                                ClientTier::Table,
+                                [ClientTier::Standard, ClientTier::Professional, ClientTier::MarketMaker],
+                            )
+                            .not_null()
+                            .default("standard"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Clients::Table)
+                    .drop_column(Clients::Tier)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(ClientTier::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Clients {
+    Table,
+    Tier, // Determines the rate limits of the client
+}
+
+#[derive(Iden)]
+pub enum ClientTier {
+    Table,
+    #[iden = "standard"]
+    Standard,
+    #[iden = "professional"]
+    Professional,
+    #[iden = "market_maker"]
+    MarketMaker,
+}
@@ -0,0 +1,93 @@
+use sea_orm::sea_query::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230304_000001_add_market_status"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(MarketStatus::Table)
+                    .values([
+                        MarketStatus::PreOpen,
+                        MarketStatus::Auction,
+                        MarketStatus::Continuous,
+                        MarketStatus::Halted,
+                        MarketStatus::Closed,
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Markets::Table)
+                    .add_column(
+                        ColumnDef::new(Markets::Status)
+                            .enumeration(
+                                MarketStatus::Table,
+                                [
+                                    MarketStatus::PreOpen,
+                                    MarketStatus::Auction,
+                                    MarketStatus::Continuous,
+                                    MarketStatus::Halted,
+                                    MarketStatus::Closed,
+                                ],
+                            )
+                            .not_null()
+                            .default("continuous"), // Existing markets remain live
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Markets::Table)
+                    .drop_column(Markets::Status)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(MarketStatus::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Markets {
+    Table,
+    Status, // Trading state enforced by the matching engine
+}
+
+#[derive(Iden)]
+pub enum MarketStatus {
+    Table,
+    #[iden = "pre_open"]
+    PreOpen,
+    #[iden = "auction"]
+    Auction,
+    #[iden = "continuous"]
+    Continuous,
+    #[iden = "halted"]
+    Halted,
+    #[iden = "closed"]
+    Closed,
+}
@@ -0,0 +1,130 @@
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230305_000001_add_circuit_breakers"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let statuses = [
+            MarketStatus::PreOpen,
+            MarketStatus::Auction,
+            MarketStatus::Continuous,
+            MarketStatus::Halted,
+            MarketStatus::Closed,
+        ];
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Markets::Table)
+                    .add_column(ColumnDef::new(Markets::BandPercent).float()) // Circuit breaker disabled if null
+                    .add_column(ColumnDef::new(Markets::BandWindow).integer().not_null().default(300))
+                    .add_column(
+                        ColumnDef::new(Markets::BandAction)
+                            .enumeration(MarketStatus::Table, statuses)
+                            .not_null()
+                            .default("auction"),
+                    )
+                    .add_column(ColumnDef::new(Markets::AuctionDuration).integer().not_null().default(60))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CircuitBreakers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CircuitBreakers::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CircuitBreakers::ReferencePrice).float().not_null())
+                    .col(ColumnDef::new(CircuitBreakers::Price).float().not_null())
+                    .col(
+                        ColumnDef::new(CircuitBreakers::Status)
+                            .enumeration(MarketStatus::Table, statuses)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CircuitBreakers::CreatedAt).timestamp().not_null())
+                    .col(ColumnDef::new(CircuitBreakers::ResumesAt).timestamp())
+                    .col(ColumnDef::new(CircuitBreakers::MarketId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("market_id")
+                            .from(CircuitBreakers::Table, CircuitBreakers::MarketId)
+                            .to(Markets::Table, Markets::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CircuitBreakers::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Markets::Table)
+                    .drop_column(Markets::BandPercent)
+                    .drop_column(Markets::BandWindow)
+                    .drop_column(Markets::BandAction)
+                    .drop_column(Markets::AuctionDuration)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Markets {
+    Table,
+    Id,
+    BandPercent, // Largest move of a trade from the reference price, in percent
+    BandWindow, // Seconds of trades from which the reference price is taken
+    BandAction, // Status the market switches to when the band is breached, either auction or halted
+    AuctionDuration, // Seconds after which continuous trading resumes following a volatility auction
+}
+
+#[derive(Iden)]
+enum CircuitBreakers {
+    Table,
+    Id, // Primary key
+    ReferencePrice,
+    Price, // Price at which the trade would have printed
+    Status,
+    CreatedAt,
+    ResumesAt, // Null if the market was halted
+    MarketId, // Foreign key
+}
+
+#[derive(Iden, Clone, Copy)]
+enum MarketStatus {
+    Table,
+    #[iden = "pre_open"]
+    PreOpen,
+    #[iden = "auction"]
+    Auction,
+    #[iden = "continuous"]
+    Continuous,
+    #[iden = "halted"]
+    Halted,
+    #[iden = "closed"]
+    Closed,
+}
@@ -0,0 +1,127 @@
+use sea_orm::sea_query::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230306_000001_create_candles_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let resolutions = [
+            CandleResolution::OneMinute,
+            CandleResolution::FiveMinutes,
+            CandleResolution::FifteenMinutes,
+            CandleResolution::OneHour,
+            CandleResolution::OneDay,
+        ];
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(CandleResolution::Table)
+                    .values(resolutions)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Candles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Candles::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Candles::Resolution)
+                            .enumeration(CandleResolution::Table, resolutions)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Candles::OpenTime).timestamp().not_null())
+                    .col(ColumnDef::new(Candles::Open).float().not_null())
+                    .col(ColumnDef::new(Candles::High).float().not_null())
+                    .col(ColumnDef::new(Candles::Low).float().not_null())
+                    .col(ColumnDef::new(Candles::Close).float().not_null())
+                    .col(ColumnDef::new(Candles::Volume).float().not_null())
+                    .col(ColumnDef::new(Candles::QuoteVolume).float().not_null())
+                    .col(ColumnDef::new(Candles::TradeCount).integer().not_null())
+                    .col(ColumnDef::new(Candles::MarketId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("market_id")
+                            .from(Candles::Table, Candles::MarketId)
+                            .to(Markets::Table, Markets::Id),
+                    )
+                    .index(
+                        Index::create()
+                            .name("candles_market_id_resolution_open_time")
+                            .col(Candles::MarketId)
+                            .col(Candles::Resolution)
+                            .col(Candles::OpenTime)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Candles::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(CandleResolution::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Markets {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Candles {
+    Table,
+    Id, // Primary key
+    Resolution,
+    OpenTime, // Start of the bar, aligned to the resolution
+    Open,
+    High,
+    Low,
+    Close,
+    Volume, // Base currency
+    QuoteVolume,
+    TradeCount,
+    MarketId, // Foreign key
+}
+
+#[derive(Iden, Clone, Copy)]
+enum CandleResolution {
+    Table,
+    #[iden = "1m"]
+    OneMinute,
+    #[iden = "5m"]
+    FiveMinutes,
+    #[iden = "15m"]
+    FifteenMinutes,
+    #[iden = "1h"]
+    OneHour,
+    #[iden = "1d"]
+    OneDay,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230307_000001_add_fill_aggressor"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Fills::Table)
+                    .add_column(
+                        ColumnDef::new(Fills::Aggressor).enumeration(
+                            OrderSide::Table,
+                            [
+                                OrderSide::Buy,
+                                OrderSide::Long,
+                                OrderSide::Bid,
+                                OrderSide::Sell,
+                                OrderSide::Short,
+                                OrderSide::Ask,
+                            ],
+                        ), // Null for trades of auction uncrosses
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Fills::Table)
+                    .drop_column(Fills::Aggressor)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum Fills {
+    Table,
+    Aggressor, // Side of the order which took liquidity in the trade
+}
+
+#[derive(Iden)]
+enum OrderSide {
+    Table,
+    #[iden = "buy"]
+    Buy,
+    #[iden = "long"]
+    Long,
+    #[iden = "bid"]
+    Bid,
+    #[iden = "sell"]
+    Sell,
+    #[iden = "short"]
+    Short,
+    #[iden = "ask"]
+    Ask,
+}
@@ -0,0 +1,42 @@
+use sea_orm_migration::prelude::*;
+
+// ----------------------------------------------------------------------
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20230308_000001_create_stream_offsets_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(StreamOffsets::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(StreamOffsets::Consumer).string().not_null().primary_key())
+                    .col(ColumnDef::new(StreamOffsets::LastOffset).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(StreamOffsets::Table).to_owned())
+            .await
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Iden)]
+enum StreamOffsets {
+    Table,
+    Consumer, // Primary key
+    LastOffset, // Offset of the last message which the consumer processed
+}
@@ -2,12 +2,30 @@ pub use sea_orm_migration::prelude::MigratorTrait;
 use sea_orm_migration::{async_trait, MigrationTrait};
 
 mod m20220101_000001_create_table;
+mod m20230301_000001_create_api_keys_table;
+mod m20230302_000001_create_admins_table;
+mod m20230303_000001_add_client_tiers;
+mod m20230304_000001_add_market_status;
+mod m20230305_000001_add_circuit_breakers;
+mod m20230306_000001_create_candles_table;
+mod m20230307_000001_add_fill_aggressor;
+mod m20230308_000001_create_stream_offsets_table;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20230301_000001_create_api_keys_table::Migration),
+            Box::new(m20230302_000001_create_admins_table::Migration),
+            Box::new(m20230303_000001_add_client_tiers::Migration),
+            Box::new(m20230304_000001_add_market_status::Migration),
+            Box::new(m20230305_000001_add_circuit_breakers::Migration),
+            Box::new(m20230306_000001_create_candles_table::Migration),
+            Box::new(m20230307_000001_add_fill_aggressor::Migration),
+            Box::new(m20230308_000001_create_stream_offsets_table::Migration),
+        ]
     }
 }
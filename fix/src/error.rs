@@ -0,0 +1,15 @@
+use database::DbErr;
+
+use derive_more::{Display, Error};
+
+use protocol::TransportError;
+
+// ----------------------------------------------------------------------
+
+#[derive(Debug, Display, Error)]
+pub enum Exception {
+    Io(std::io::Error),
+    Garbled(#[error(not(source))] String),
+    Database(DbErr),
+    Transport(TransportError)
+}
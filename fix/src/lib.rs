@@ -0,0 +1,167 @@
+mod error;
+mod message;
+mod session;
+mod store;
+
+pub use error::Exception;
+pub use message::{msg_types, tags, Message, BEGIN_STRING};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_std::channel::Sender;
+use async_std::net::{TcpListener, ToSocketAddrs};
+use async_std::task;
+use chrono::Utc;
+use futures::StreamExt;
+
+use database::{DatabaseConnection, Engine, OrderSide};
+use database::fills::Fill;
+use database::orders::Cancelled;
+use protocol::{Channel, Codec, Offset, RabbitMQ, Transport};
+
+// ----------------------------------------------------------------------
+
+/// The state of an order entered through a FIX session, used to build execution reports from engine fills.
+#[derive(Clone, Debug)]
+struct OrderState {
+    target_comp_id: String,
+    cl_ord_id: String,
+    symbol: String,
+    side: OrderSide,
+    price: Option<f32>,
+    size: f32,
+    cum_qty: f32,
+    avg_px: f32,
+}
+
+pub struct Acceptor {
+    sender_comp_id: String,
+    store_path: PathBuf,
+    db: DatabaseConnection,
+    transport: Option<Arc<dyn Transport>>, // Make optional for integration tests
+    sessions: Mutex<HashMap<String, Sender<Message>>>, // Outbound queues of logged on sessions keyed by TargetCompID
+    orders: Mutex<HashMap<i32, OrderState>>, // Keyed by engine order id
+    exec_id: AtomicU64,
+}
+
+impl Acceptor {
+    pub fn new(
+        sender_comp_id: &str,
+        store_path: impl Into<PathBuf>,
+        db: DatabaseConnection,
+        transport: Option<Arc<dyn Transport>>,
+    ) -> Self {
+        Acceptor {
+            sender_comp_id: sender_comp_id.to_owned(),
+            store_path: store_path.into(),
+            db,
+            transport,
+            sessions: Mutex::new(HashMap::new()),
+            orders: Mutex::new(HashMap::new()),
+            exec_id: AtomicU64::new(Utc::now().timestamp_millis() as u64),
+        }
+    }
+
+    /// Connects to the database and to the orders stream of the matching engine.
+    pub async fn connect(sender_comp_id: &str, store_path: impl Into<PathBuf>) -> Self {
+        let db = Engine::connect().await.unwrap(); // Allow to panic if unsuccessful
+        let transport = RabbitMQ::connect().await.unwrap(); // Allow to panic if unsuccessful
+        Acceptor::new(sender_comp_id, store_path, db, Some(Arc::new(transport)))
+    }
+
+    pub async fn run(self, address: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        Arc::new(self).serve(listener).await
+    }
+
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        if let Some(transport) = self.transport.clone() {
+            let acceptor = self.clone();
+            std::thread::spawn(move || task::block_on(acceptor.consume_fills(transport))); // Subscribing is not Send
+        }
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let acceptor = self.clone();
+            task::spawn(async move {
+                if let Err(e) = session::handle(acceptor, stream).await {
+                    tracing::error!("Session with {address} terminated: {e}");
+                }
+            });
+        }
+    }
+
+    async fn consume_fills(self: Arc<Self>, transport: Arc<dyn Transport>) {
+        let mut fills = transport.subscribe(Channel::Fills, Offset::Next).await.unwrap();
+        while let Some(data) = fills.next().await {
+            if let Ok(fill) = Fill::decode(&data) {
+                self.on_fill(&fill);
+            } else if let Ok(cancelled) = Cancelled::decode(&data) {
+                self.on_cancelled(&cancelled);
+            }
+        }
+    }
+
+    /// Sends an execution report for a fill to the session that entered the order, if it is logged on.
+    pub fn on_fill(&self, fill: &Fill) {
+        let (target_comp_id, report) = {
+            let mut orders = self.orders.lock().unwrap();
+            let Some(order) = orders.get_mut(&fill.order_id) else {
+                return; // The order was not entered through FIX
+            };
+            order.avg_px = (order.avg_px * order.cum_qty + fill.price * fill.size) / (order.cum_qty + fill.size);
+            order.cum_qty += fill.size;
+            let ord_status = if order.cum_qty >= order.size { '2' } else { '1' };
+            let report = self.execution_report(fill.order_id, order, 'F', ord_status)
+                .set(tags::LAST_PX, fill.price)
+                .set(tags::LAST_QTY, fill.size);
+            let target_comp_id = order.target_comp_id.clone();
+            if ord_status == '2' {
+                orders.remove(&fill.order_id); // Filled orders can no longer be cancelled or replaced
+            }
+            (target_comp_id, report)
+        };
+        self.send(&target_comp_id, report);
+    }
+
+    /// Sends an execution report for an order pulled from the book by the engine to the session that entered it, if it
+    /// is logged on. Orders canceled on request carry the ClOrdID of the cancel.
+    pub fn on_cancelled(&self, cancelled: &Cancelled) {
+        let Some(order) = self.orders.lock().unwrap().remove(&cancelled.order_id) else {
+            return; // The order was not entered through FIX
+        };
+        self.send(&order.target_comp_id, self.execution_report(cancelled.order_id, &order, '4', '4'));
+    }
+
+    async fn publish(&self, message: &impl Codec) -> Result<(), Exception> {
+        if let Some(transport) = &self.transport {
+            transport.publish(Channel::Orders, message.encode()).await.map_err(Exception::Transport)?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, target_comp_id: &str, message: Message) {
+        if let Some(outbound) = self.sessions.lock().unwrap().get(target_comp_id) {
+            let _ = outbound.try_send(message);
+        }
+    }
+
+    fn execution_report(&self, order_id: i32, order: &OrderState, exec_type: char, ord_status: char) -> Message {
+        Message::new(msg_types::EXECUTION_REPORT)
+            .set(tags::ORDER_ID, order_id)
+            .set(tags::CL_ORD_ID, &order.cl_ord_id)
+            .set(tags::EXEC_ID, self.exec_id.fetch_add(1, Ordering::Relaxed))
+            .set(tags::EXEC_TYPE, exec_type)
+            .set(tags::ORD_STATUS, ord_status)
+            .set(tags::SYMBOL, &order.symbol)
+            .set(tags::SIDE, session::side_to_fix(&order.side))
+            .set(tags::ORDER_QTY, order.size)
+            .set(tags::PRICE, order.price.unwrap_or_default())
+            .set(tags::CUM_QTY, order.cum_qty)
+            .set(tags::LEAVES_QTY, if ord_status == '4' { 0.0 } else { (order.size - order.cum_qty).max(0.0) })
+            .set(tags::AVG_PX, order.avg_px)
+            .set(tags::TRANSACT_TIME, session::timestamp())
+    }
+}
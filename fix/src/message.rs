@@ -0,0 +1,220 @@
+use std::str::FromStr;
+
+use crate::error::Exception;
+
+// ----------------------------------------------------------------------
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+
+pub mod tags {
+    pub const ACCOUNT: u32 = 1;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const RAW_DATA_LENGTH: u32 = 95;
+    pub const RAW_DATA: u32 = 96;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const USERNAME: u32 = 553;
+    pub const CANCEL_ON_DISCONNECT: u32 = 8013; // User defined
+    pub const NONCE: u32 = 8014; // User defined
+}
+
+pub mod msg_types {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+}
+
+// ----------------------------------------------------------------------
+
+/// A FIX message excluding the BeginString, BodyLength and CheckSum fields, which are derived on encoding.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    fields: Vec<(u32, String)>,
+}
+
+impl Message {
+    pub fn new(msg_type: &str) -> Self {
+        Message {
+            fields: vec![(tags::MSG_TYPE, msg_type.to_owned())],
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or_default()
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn parse<T: FromStr>(&self, tag: u32) -> Option<T> {
+        self.get(tag)?.parse().ok()
+    }
+
+    /// Sets the value of a tag, replacing the existing value if the tag is already present.
+    pub fn set(mut self, tag: u32, value: impl ToString) -> Self {
+        let value = value.to_string();
+        if let Some(field) = self.fields.iter_mut().find(|(t, _)| *t == tag) {
+            field.1 = value;
+        } else {
+            self.fields.push((tag, value));
+        }
+        self
+    }
+
+    /// Inserts the standard header fields directly after the MsgType.
+    pub fn with_header(mut self, sender_comp_id: &str, target_comp_id: &str, seq_num: u64, sending_time: &str) -> Self {
+        self.fields.retain(|(tag, _)| !matches!(
+            *tag,
+            tags::SENDER_COMP_ID | tags::TARGET_COMP_ID | tags::MSG_SEQ_NUM | tags::SENDING_TIME
+        ));
+        self.fields.splice(1..1, [
+            (tags::SENDER_COMP_ID, sender_comp_id.to_owned()),
+            (tags::TARGET_COMP_ID, target_comp_id.to_owned()),
+            (tags::MSG_SEQ_NUM, seq_num.to_string()),
+            (tags::SENDING_TIME, sending_time.to_owned()),
+        ]);
+        self
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(256);
+        for (tag, value) in &self.fields {
+            body.extend_from_slice(format!("{tag}={value}").as_bytes());
+            body.push(SOH);
+        }
+        let mut buf = format!("8={BEGIN_STRING}\u{1}9={}\u{1}", body.len()).into_bytes();
+        buf.append(&mut body);
+        let checksum = checksum(&buf);
+        buf.extend_from_slice(format!("10={checksum:03}\u{1}").as_bytes());
+        buf
+    }
+
+    /// Decodes the first complete message in the buffer, returning it with the number of bytes consumed. Returns
+    /// `None` if the buffer does not yet contain a complete message.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Message, usize)>, Exception> {
+        let Some(begin_end) = buf.iter().position(|b| *b == SOH) else {
+            return Ok(None);
+        };
+        if &buf[..begin_end] != format!("8={BEGIN_STRING}").as_bytes() {
+            return Err(Exception::Garbled("Invalid BeginString.".to_owned()));
+        }
+        let Some(length_end) = buf[begin_end + 1..].iter().position(|b| *b == SOH).map(|i| i + begin_end + 1) else {
+            return Ok(None);
+        };
+        let body_length: usize = std::str::from_utf8(&buf[begin_end + 1..length_end])
+            .ok()
+            .and_then(|field| field.strip_prefix("9="))
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| Exception::Garbled("Invalid BodyLength.".to_owned()))?;
+        let body_start = length_end + 1;
+        let body_end = body_start + body_length;
+        let total = body_end + 7; // "10=nnn" followed by SOH
+        if buf.len() < total {
+            return Ok(None);
+        }
+        let expected = format!("10={:03}\u{1}", checksum(&buf[..body_end]));
+        if &buf[body_end..total] != expected.as_bytes() {
+            return Err(Exception::Garbled("Invalid CheckSum.".to_owned()));
+        }
+        let body = std::str::from_utf8(&buf[body_start..body_end])
+            .map_err(|_| Exception::Garbled("Message body is not valid UTF-8.".to_owned()))?;
+        let mut fields = Vec::new();
+        for field in body.split('\u{1}').filter(|field| !field.is_empty()) {
+            let (tag, value) = field
+                .split_once('=')
+                .and_then(|(tag, value)| Some((tag.parse().ok()?, value.to_owned())))
+                .ok_or_else(|| Exception::Garbled(format!("Invalid field {field}.")))?;
+            fields.push((tag, value));
+        }
+        if fields.first().map(|(tag, _)| *tag) != Some(tags::MSG_TYPE) {
+            return Err(Exception::Garbled("MsgType must be the first field of the body.".to_owned()));
+        }
+        Ok(Some((Message { fields }, total)))
+    }
+}
+
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let message = Message::new(msg_types::LOGON)
+            .set(tags::ENCRYPT_METHOD, 0)
+            .set(tags::HEART_BT_INT, 30)
+            .with_header("CLIENT", "EXCHANGE", 1, "20230101-00:00:00.000");
+        let mut buf = message.encode();
+        let length = buf.len();
+        buf.extend_from_slice(b"8=FIX"); // Start of the next message
+        let (decoded, consumed) = Message::decode(&buf).unwrap().unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(consumed, length);
+        assert_eq!(decoded.parse::<u64>(tags::MSG_SEQ_NUM), Some(1));
+    }
+
+    #[test]
+    fn incomplete() {
+        let buf = Message::new(msg_types::HEARTBEAT).encode();
+        assert!(Message::decode(&buf[..buf.len() - 1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn garbled() {
+        let mut buf = Message::new(msg_types::HEARTBEAT).encode();
+        let length = buf.len();
+        buf[length - 2] ^= 1; // Corrupt the checksum
+        assert!(Message::decode(&buf).is_err());
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::channel::{self, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use chrono::{NaiveDateTime, Utc};
+
+use database::{api_keys, Mutation, OrderSide, OrderType};
+use database::orders::{Command, MassCancel};
+use protocol::auth;
+
+use crate::error::Exception;
+use crate::message::{msg_types, tags, Message};
+use crate::store::FileStore;
+use crate::{Acceptor, OrderState};
+
+// ----------------------------------------------------------------------
+
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_HEART_BT_INT: u64 = 30;
+
+pub(crate) fn timestamp() -> String {
+    Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+pub(crate) fn side_to_fix(side: &OrderSide) -> char {
+    match side {
+        OrderSide::Buy | OrderSide::Bid | OrderSide::Long => '1',
+        OrderSide::Sell | OrderSide::Ask | OrderSide::Short => '2',
+    }
+}
+
+fn side_from_fix(side: &str) -> Option<OrderSide> {
+    match side {
+        "1" => Some(OrderSide::Buy),
+        "2" => Some(OrderSide::Sell),
+        _ => None,
+    }
+}
+
+fn order_type_from_fix(ord_type: &str) -> Option<OrderType> {
+    match ord_type {
+        "1" => Some(OrderType::Market),
+        "2" => Some(OrderType::Limit),
+        _ => None,
+    }
+}
+
+async fn read_message(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Result<Option<Message>, Exception> {
+    loop {
+        if let Some((message, consumed)) = Message::decode(buf)? {
+            buf.drain(..consumed);
+            return Ok(Some(message));
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.map_err(Exception::Io)?;
+        if n == 0 {
+            return Ok(None); // Connection closed by the counterparty
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Verifies the API key in Username and the signature in RawData of a Logon. The signature covers the SendingTime, the
+/// Nonce, and the SenderCompID, TargetCompID and MsgSeqNum of the Logon.
+async fn authenticate(acceptor: &Acceptor, logon: &Message) -> Result<api_keys::Model, protocol::Exception> {
+    let unauthorized = |field: &str| protocol::Exception::Unauthorized(format!("Missing or invalid {field}."));
+    let timestamp = logon
+        .get(tags::SENDING_TIME)
+        .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y%m%d-%H:%M:%S%.f").ok())
+        .ok_or_else(|| unauthorized("SendingTime"))?
+        .and_utc()
+        .timestamp_millis();
+    let nonce = logon.parse(tags::NONCE).ok_or_else(|| unauthorized("Nonce"))?;
+    let signature = logon
+        .get(tags::RAW_DATA)
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or_else(|| unauthorized("RawData"))?;
+    let fields = [tags::SENDER_COMP_ID, tags::TARGET_COMP_ID, tags::MSG_SEQ_NUM]
+        .map(|tag| logon.get(tag).unwrap_or_default());
+    auth::authenticate(
+        &acceptor.db,
+        logon.get(tags::USERNAME).unwrap_or_default(),
+        timestamp,
+        nonce,
+        &fields,
+        &signature,
+    ).await
+}
+
+// ----------------------------------------------------------------------
+
+struct Session {
+    acceptor: Arc<Acceptor>,
+    target_comp_id: String,
+    client_id: i32,
+    store: Arc<Mutex<FileStore>>,
+    outbound: Sender<Message>,
+    sub_accounts: HashSet<i32>, // Sub-accounts which orders were entered for over the connection
+}
+
+/// Runs a session over an accepted connection from Logon until Logout or disconnection.
+pub(crate) async fn handle(acceptor: Arc<Acceptor>, mut stream: TcpStream) -> Result<(), Exception> {
+    let mut buf = Vec::with_capacity(4096);
+    let logon = match timeout(LOGON_TIMEOUT, read_message(&mut stream, &mut buf)).await {
+        Ok(Ok(Some(logon))) if logon.msg_type() == msg_types::LOGON => logon,
+        Ok(Err(e)) => return Err(e),
+        _ => return Ok(()), // Silently drop connections which do not log on
+    };
+    let target_comp_id = logon.get(tags::SENDER_COMP_ID).unwrap_or_default().to_owned();
+    if target_comp_id.is_empty() || logon.get(tags::TARGET_COMP_ID) != Some(acceptor.sender_comp_id.as_str()) {
+        return Ok(());
+    }
+    let heart_bt_int = Duration::from_secs(logon.parse(tags::HEART_BT_INT).unwrap_or(DEFAULT_HEART_BT_INT));
+    let cancel_on_disconnect = logon.get(tags::CANCEL_ON_DISCONNECT) == Some("Y");
+
+    // Restore the sequence numbers of the session
+    let mut store = FileStore::open(
+        &acceptor.store_path,
+        &format!("{}-{}", acceptor.sender_comp_id, target_comp_id),
+    ).map_err(Exception::Io)?;
+    if logon.get(tags::RESET_SEQ_NUM_FLAG) == Some("Y") {
+        store.reset().map_err(Exception::Io)?;
+    }
+    let store = Arc::new(Mutex::new(store));
+
+    let (outbound, receiver) = channel::unbounded();
+    let writer = task::spawn(write_messages(
+        stream.clone(),
+        acceptor.sender_comp_id.clone(),
+        target_comp_id.clone(),
+        store.clone(),
+        receiver,
+        heart_bt_int,
+    ));
+
+    // Authenticate the client and register the session
+    let client = authenticate(&acceptor, &logon).await;
+    let registered = client.is_ok() && {
+        let mut sessions = acceptor.sessions.lock().unwrap();
+        if sessions.contains_key(&target_comp_id) {
+            false
+        } else {
+            sessions.insert(target_comp_id.clone(), outbound.clone());
+            true
+        }
+    };
+    let mut session = Session {
+        acceptor: acceptor.clone(),
+        target_comp_id: target_comp_id.clone(),
+        client_id: client.as_ref().map(|api_key| api_key.client_id).unwrap_or_default(),
+        store,
+        outbound,
+        sub_accounts: HashSet::new(),
+    };
+    let result = if let Err(e) = client {
+        session.send(Message::new(msg_types::LOGOUT).set(tags::TEXT, e));
+        Ok(())
+    } else if !registered {
+        session.send(Message::new(msg_types::LOGOUT).set(tags::TEXT, "Session is already logged on."));
+        Ok(())
+    } else {
+        session.send(
+            Message::new(msg_types::LOGON)
+                .set(tags::ENCRYPT_METHOD, 0)
+                .set(tags::HEART_BT_INT, heart_bt_int.as_secs())
+                .set(tags::CANCEL_ON_DISCONNECT, if cancel_on_disconnect { 'Y' } else { 'N' })
+        );
+        let result = if session.check_seq_num(&logon) {
+            session.run(&mut stream, &mut buf, heart_bt_int).await
+        } else {
+            Ok(())
+        };
+        acceptor.sessions.lock().unwrap().remove(&target_comp_id);
+        let cancelled = if cancel_on_disconnect { session.cancel_orders().await } else { Ok(()) };
+        result.and(cancelled)
+    };
+    drop(session); // Close the outbound queue so that the writer flushes and exits
+    writer.await;
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    result
+}
+
+/// Writes outbound messages in sequence, sending a Heartbeat whenever the session has been idle for the
+/// heartbeat interval.
+async fn write_messages(
+    mut stream: TcpStream,
+    sender_comp_id: String,
+    target_comp_id: String,
+    store: Arc<Mutex<FileStore>>,
+    receiver: Receiver<Message>,
+    heart_bt_int: Duration,
+) {
+    loop {
+        let message = match timeout(heart_bt_int, receiver.recv()).await {
+            Ok(Ok(message)) => message,
+            Ok(Err(_)) => break, // Session has ended
+            Err(_) => Message::new(msg_types::HEARTBEAT),
+        };
+        let buf = {
+            let mut store = store.lock().unwrap();
+            let seq_num = if message.msg_type() == msg_types::SEQUENCE_RESET {
+                message.parse(tags::MSG_SEQ_NUM).unwrap_or(store.next_sender_seq_num) // Gap fills reuse old numbers
+            } else {
+                store.next_sender_seq_num += 1;
+                let _ = store.save();
+                store.next_sender_seq_num - 1
+            };
+            message.with_header(&sender_comp_id, &target_comp_id, seq_num, &timestamp()).encode()
+        };
+        if stream.write_all(&buf).await.is_err() {
+            break;
+        }
+    }
+}
+
+impl Session {
+    fn send(&self, message: Message) {
+        let _ = self.outbound.try_send(message);
+    }
+
+    async fn run(&mut self, stream: &mut TcpStream, buf: &mut Vec<u8>, heart_bt_int: Duration) -> Result<(), Exception> {
+        let mut test_request_pending = false;
+        loop {
+            match timeout(heart_bt_int + heart_bt_int / 5, read_message(stream, buf)).await {
+                Ok(Ok(Some(message))) => {
+                    test_request_pending = false;
+                    if !self.check_seq_num(&message) {
+                        return Ok(());
+                    }
+                    if !self.on_message(message).await? {
+                        return Ok(());
+                    }
+                }
+                Ok(Ok(None)) => return Ok(()),
+                Ok(Err(Exception::Garbled(_))) => buf.clear(), // Garbled messages are ignored
+                Ok(Err(e)) => return Err(e),
+                Err(_) if test_request_pending => {
+                    self.send(Message::new(msg_types::LOGOUT).set(tags::TEXT, "Heartbeat timeout."));
+                    return Ok(());
+                }
+                Err(_) => {
+                    self.send(Message::new(msg_types::TEST_REQUEST).set(tags::TEST_REQ_ID, timestamp()));
+                    test_request_pending = true;
+                }
+            }
+        }
+    }
+
+    /// Validates the sequence number of an inbound message, requesting a resend if a gap is detected. Returns false
+    /// if the session must be terminated.
+    fn check_seq_num(&self, message: &Message) -> bool {
+        let seq_num: u64 = message.parse(tags::MSG_SEQ_NUM).unwrap_or_default();
+        let mut store = self.store.lock().unwrap();
+        let expected = store.next_target_seq_num;
+        if message.msg_type() == msg_types::SEQUENCE_RESET && message.get(tags::GAP_FILL_FLAG) != Some("Y") {
+            return true; // Sequence resets in reset mode ignore the sequence number
+        }
+        if seq_num < expected {
+            if message.get(tags::POSS_DUP_FLAG) == Some("Y") {
+                return true; // Duplicates are processed by on_message as no-ops
+            }
+            drop(store);
+            self.send(Message::new(msg_types::LOGOUT).set(
+                tags::TEXT,
+                format!("MsgSeqNum too low, expecting {expected} but received {seq_num}."),
+            ));
+            return false;
+        }
+        if seq_num > expected {
+            self.send(
+                Message::new(msg_types::RESEND_REQUEST)
+                    .set(tags::BEGIN_SEQ_NO, expected)
+                    .set(tags::END_SEQ_NO, 0)
+            );
+        }
+        store.next_target_seq_num = seq_num + 1;
+        let _ = store.save();
+        true
+    }
+
+    /// Processes a sequenced inbound message. Returns false if the session has been logged out.
+    async fn on_message(&mut self, message: Message) -> Result<bool, Exception> {
+        if message.get(tags::POSS_DUP_FLAG) == Some("Y") && message.msg_type() != msg_types::SEQUENCE_RESET {
+            return Ok(true); // Orders are never replayed
+        }
+        match message.msg_type() {
+            msg_types::HEARTBEAT | msg_types::REJECT => {}
+            msg_types::TEST_REQUEST => self.send(
+                Message::new(msg_types::HEARTBEAT)
+                    .set(tags::TEST_REQ_ID, message.get(tags::TEST_REQ_ID).unwrap_or_default())
+            ),
+            msg_types::RESEND_REQUEST => {
+                // Application messages are not resent, so the requested range is filled with a gap fill
+                let next_sender_seq_num = self.store.lock().unwrap().next_sender_seq_num;
+                self.send(
+                    Message::new(msg_types::SEQUENCE_RESET)
+                        .set(tags::MSG_SEQ_NUM, message.get(tags::BEGIN_SEQ_NO).unwrap_or("1"))
+                        .set(tags::POSS_DUP_FLAG, "Y")
+                        .set(tags::GAP_FILL_FLAG, "Y")
+                        .set(tags::NEW_SEQ_NO, next_sender_seq_num)
+                );
+            }
+            msg_types::SEQUENCE_RESET => {
+                if let Some(new_seq_no) = message.parse(tags::NEW_SEQ_NO) {
+                    let mut store = self.store.lock().unwrap();
+                    store.next_target_seq_num = store.next_target_seq_num.max(new_seq_no);
+                    let _ = store.save();
+                }
+            }
+            msg_types::LOGOUT => {
+                self.send(Message::new(msg_types::LOGOUT));
+                return Ok(false);
+            }
+            msg_types::NEW_ORDER_SINGLE => self.on_new_order_single(message).await?,
+            msg_types::ORDER_CANCEL_REQUEST => self.on_order_cancel_request(message).await?,
+            msg_types::ORDER_CANCEL_REPLACE_REQUEST => self.on_order_cancel_replace_request(message).await?,
+            msg_type => self.send(
+                Message::new(msg_types::REJECT)
+                    .set(tags::REF_SEQ_NUM, message.get(tags::MSG_SEQ_NUM).unwrap_or_default())
+                    .set(tags::TEXT, format!("Unsupported MsgType {msg_type}."))
+            ),
+        }
+        Ok(true)
+    }
+
+    async fn on_new_order_single(&mut self, message: Message) -> Result<(), Exception> {
+        let cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or_default().to_owned();
+        let symbol = message.get(tags::SYMBOL).unwrap_or_default().to_owned();
+        let (base_currency, quote_currency) = symbol.split_once('/').unwrap_or((&symbol, ""));
+        let mut order = OrderState {
+            target_comp_id: self.target_comp_id.clone(),
+            cl_ord_id: cl_ord_id.clone(),
+            symbol: symbol.clone(),
+            side: side_from_fix(message.get(tags::SIDE).unwrap_or_default()).unwrap_or(OrderSide::Buy),
+            price: message.parse(tags::PRICE),
+            size: message.parse(tags::ORDER_QTY).unwrap_or_default(),
+            cum_qty: 0.0,
+            avg_px: 0.0,
+        };
+        let (Some(side), Some(r#type), Some(sub_account_id)) = (
+            side_from_fix(message.get(tags::SIDE).unwrap_or_default()),
+            order_type_from_fix(message.get(tags::ORD_TYPE).unwrap_or_default()),
+            message.parse(tags::ACCOUNT),
+        ) else {
+            self.reject_order(&order, "Missing or invalid Account, Side or OrdType.");
+            return Ok(());
+        };
+        let created = Mutation::create_order(
+            &self.acceptor.db,
+            self.client_id,
+            sub_account_id,
+            order.size,
+            side,
+            r#type,
+            order.price,
+            Some(cl_ord_id),
+            None,
+            Some(base_currency.to_owned()),
+            Some(quote_currency.to_owned()),
+        ).await;
+        match created {
+            Ok(created) => {
+                self.acceptor.publish(&Command::New(created.clone())).await?;
+                order.side = created.side;
+                self.send(self.acceptor.execution_report(created.id, &order, '0', '0'));
+                self.acceptor.orders.lock().unwrap().insert(created.id, order);
+                self.sub_accounts.insert(created.sub_account_id);
+            }
+            Err(e) => self.reject_order(&order, &e.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Sends the cancel to the matching engine and acknowledges it as pending. The order is reported canceled once the
+    /// engine pulls it, so that fills already in flight are reported first.
+    async fn on_order_cancel_request(&mut self, message: Message) -> Result<(), Exception> {
+        let Some((order_id, mut order)) = self.find_order(&message) else {
+            self.reject_cancel(&message, '1', "Unknown order.");
+            return Ok(());
+        };
+        match Mutation::cancel_order(&self.acceptor.db, self.client_id, order_id).await {
+            Ok(cancelled) => {
+                self.acceptor.publish(&Command::Cancel(cancelled)).await?;
+                order.cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or_default().to_owned();
+                self.send(self.acceptor.execution_report(order_id, &order, '6', '6'));
+                self.acceptor.orders.lock().unwrap().insert(order_id, order);
+            }
+            Err(e) => self.reject_cancel(&message, '1', &e.to_string()),
+        }
+        Ok(())
+    }
+
+    async fn on_order_cancel_replace_request(&mut self, message: Message) -> Result<(), Exception> {
+        let Some((order_id, mut order)) = self.find_order(&message) else {
+            self.reject_cancel(&message, '2', "Unknown order.");
+            return Ok(());
+        };
+        let price = message.parse(tags::PRICE);
+        let size = message.parse(tags::ORDER_QTY);
+        match Mutation::amend_order(&self.acceptor.db, self.client_id, order_id, price, size).await {
+            Ok(amended) => {
+                self.acceptor.publish(&Command::Amend(amended)).await?;
+                order.cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or_default().to_owned();
+                order.price = price.or(order.price);
+                order.size = size.unwrap_or(order.size);
+                self.send(self.acceptor.execution_report(order_id, &order, '5', '0'));
+                self.acceptor.orders.lock().unwrap().insert(order_id, order);
+            }
+            Err(e) => self.reject_cancel(&message, '2', &e.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Pulls the resting orders of the sub-accounts traded over the connection at once. The matching engine reports
+    /// every order it pulls, which closes it in the database.
+    async fn cancel_orders(&self) -> Result<(), Exception> {
+        if self.sub_accounts.is_empty() {
+            return Ok(());
+        }
+        let sub_account_ids = self.sub_accounts.iter().copied().collect();
+        self.acceptor.publish(&MassCancel { market_id: None, sub_account_ids, side: None }).await
+    }
+
+    /// Finds an order of this session by OrderID or else by OrigClOrdID.
+    fn find_order(&self, message: &Message) -> Option<(i32, OrderState)> {
+        let orders = self.acceptor.orders.lock().unwrap();
+        let order_id: Option<i32> = message.parse(tags::ORDER_ID);
+        let orig_cl_ord_id = message.get(tags::ORIG_CL_ORD_ID);
+        orders
+            .iter()
+            .find(|(id, order)| {
+                order.target_comp_id == self.target_comp_id
+                    && (order_id == Some(**id) || (order_id.is_none() && orig_cl_ord_id == Some(order.cl_ord_id.as_str())))
+            })
+            .map(|(id, order)| (*id, order.clone()))
+    }
+
+    fn reject_order(&self, order: &OrderState, text: &str) {
+        self.send(
+            self.acceptor.execution_report(0, order, '8', '8')
+                .set(tags::ORDER_ID, "NONE")
+                .set(tags::LEAVES_QTY, 0)
+                .set(tags::TEXT, text)
+        );
+    }
+
+    fn reject_cancel(&self, message: &Message, response_to: char, text: &str) {
+        self.send(
+            Message::new(msg_types::ORDER_CANCEL_REJECT)
+                .set(tags::ORDER_ID, message.get(tags::ORDER_ID).unwrap_or("NONE"))
+                .set(tags::CL_ORD_ID, message.get(tags::CL_ORD_ID).unwrap_or_default())
+                .set(tags::ORIG_CL_ORD_ID, message.get(tags::ORIG_CL_ORD_ID).unwrap_or_default())
+                .set(tags::ORD_STATUS, '8')
+                .set(tags::CXL_REJ_RESPONSE_TO, response_to)
+                .set(tags::CXL_REJ_REASON, 1)
+                .set(tags::TEXT, text)
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// ----------------------------------------------------------------------
+
+/// Persists the sequence numbers of a session so that they survive reconnects and restarts of the acceptor.
+pub struct FileStore {
+    path: PathBuf,
+    pub next_sender_seq_num: u64,
+    pub next_target_seq_num: u64,
+}
+
+impl FileStore {
+    pub fn open(directory: &Path, session_id: &str) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(format!("{session_id}.seqnums"));
+        let (next_sender_seq_num, next_target_seq_num) = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .trim()
+                .split_once(':')
+                .and_then(|(sender, target)| Some((sender.parse().ok()?, target.parse().ok()?)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt store {path:?}.")))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (1, 1),
+            Err(e) => return Err(e),
+        };
+        Ok(FileStore {
+            path,
+            next_sender_seq_num,
+            next_target_seq_num,
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(&self.path, format!("{}:{}", self.next_sender_seq_num, self.next_target_seq_num))
+    }
+
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.next_sender_seq_num = 1;
+        self.next_target_seq_num = 1;
+        self.save()
+    }
+}
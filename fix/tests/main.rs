@@ -0,0 +1,343 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use chrono::{NaiveDateTime, Utc};
+
+use database::{Engine, Migrator, MigratorTrait, Mutation, OrderSide, OrderType};
+use database::api_keys;
+use database::fills::Fill;
+use database::orders::{Cancelled, MassCancel};
+use futures::stream::BoxStream;
+use fix::{msg_types, tags, Acceptor, Message};
+use protocol::{auth, Channel, Codec, InMemory, Offset, Transport};
+
+// ----------------------------------------------------------------------
+
+static NONCE: AtomicI64 = AtomicI64::new(0);
+
+fn sending_time() -> String {
+    Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+/// A minimal FIX initiator for driving the acceptor.
+struct Initiator {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    seq_num: u64,
+}
+
+impl Initiator {
+    async fn connect(address: std::net::SocketAddr, seq_num: u64) -> Self {
+        Initiator {
+            stream: TcpStream::connect(address).await.unwrap(),
+            buf: Vec::new(),
+            seq_num,
+        }
+    }
+
+    async fn send(&mut self, message: Message) {
+        self.send_at(message, &sending_time()).await;
+    }
+
+    async fn send_at(&mut self, message: Message, sending_time: &str) {
+        let buf = message
+            .with_header("CLIENT", "EXCHANGE", self.seq_num, sending_time)
+            .encode();
+        self.seq_num += 1;
+        self.stream.write_all(&buf).await.unwrap();
+    }
+
+    /// Signs a Logon with an API key and sends it.
+    async fn send_logon(&mut self, logon: Message, key: &api_keys::Model) {
+        let sending_time = sending_time();
+        let timestamp = NaiveDateTime::parse_from_str(&sending_time, "%Y%m%d-%H:%M:%S%.f")
+            .unwrap()
+            .and_utc()
+            .timestamp_millis();
+        let nonce = NONCE.fetch_add(1, Ordering::Relaxed) + 1;
+        let seq_num = self.seq_num.to_string();
+        let signature = auth::sign(&key.secret, timestamp, nonce, &["CLIENT", "EXCHANGE", &seq_num]);
+        let logon = logon
+            .set(tags::USERNAME, &key.key)
+            .set(tags::NONCE, nonce)
+            .set(tags::RAW_DATA_LENGTH, 2 * signature.len())
+            .set(tags::RAW_DATA, hex::encode(signature));
+        self.send_at(logon, &sending_time).await;
+    }
+
+    /// Receives the next message which is not an unsolicited Heartbeat or TestRequest.
+    async fn receive(&mut self) -> Message {
+        loop {
+            let message = self.receive_any().await;
+            match message.msg_type() {
+                msg_types::HEARTBEAT if message.get(tags::TEST_REQ_ID).is_none() => {}
+                msg_types::TEST_REQUEST => self.send(
+                    Message::new(msg_types::HEARTBEAT)
+                        .set(tags::TEST_REQ_ID, message.get(tags::TEST_REQ_ID).unwrap())
+                ).await,
+                _ => return message,
+            }
+        }
+    }
+
+    async fn receive_any(&mut self) -> Message {
+        loop {
+            if let Some((message, consumed)) = Message::decode(&self.buf).unwrap() {
+                self.buf.drain(..consumed);
+                return message;
+            }
+            let mut chunk = [0u8; 4096];
+            let n = self.stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "Connection closed by the acceptor.");
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    async fn logon(&mut self, reset: bool, key: &api_keys::Model) -> Message {
+        let mut logon = Message::new(msg_types::LOGON)
+            .set(tags::ENCRYPT_METHOD, 0)
+            .set(tags::HEART_BT_INT, 1);
+        if reset {
+            logon = logon.set(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        self.send_logon(logon, key).await;
+        self.receive().await
+    }
+}
+
+fn new_order_single(cl_ord_id: &str) -> Message {
+    Message::new(msg_types::NEW_ORDER_SINGLE)
+        .set(tags::CL_ORD_ID, cl_ord_id)
+        .set(tags::ACCOUNT, 1)
+        .set(tags::SYMBOL, "BTC/USD")
+        .set(tags::SIDE, 1)
+        .set(tags::ORDER_QTY, 10.0)
+        .set(tags::ORD_TYPE, 2)
+        .set(tags::PRICE, 100.0)
+        .set(tags::TRANSACT_TIME, "20230101-00:00:00.000")
+}
+
+/// Reads the orders stream up to the next mass cancel, returning it with the number of commands published before it.
+async fn next_mass_cancel(orders: &mut BoxStream<'static, Vec<u8>>) -> (usize, MassCancel) {
+    let mut commands = 0;
+    loop {
+        match MassCancel::decode(&orders.next().await.unwrap()) {
+            Ok(mass_cancel) => return (commands, mass_cancel),
+            Err(_) => commands += 1,
+        }
+    }
+}
+
+#[async_std::test]
+async fn main() {
+    // Set up
+    let db = Engine::connect().await.unwrap();
+    Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
+    let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+    let _ = Mutation::create_sub_account(&db, 1, "Test".to_owned()).await;
+    let _ = Mutation::create_market(&db, "BTC".to_owned(), "USD".to_owned(), 0.01, 0.01).await;
+    let key = Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap();
+    let read_only = Mutation::create_api_key(&db, 1, None, true, false, false).await.unwrap();
+    let store_path = std::env::temp_dir().join("fix_store_test");
+    let _ = std::fs::remove_dir_all(&store_path);
+
+    // Mock acceptor
+    let transport = Arc::new(InMemory::new());
+    let acceptor = Arc::new(Acceptor::new("EXCHANGE", &store_path, db.clone(), Some(transport.clone())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    task::spawn(acceptor.clone().serve(listener));
+
+    // Unsigned logon
+    let mut initiator = Initiator::connect(address, 1).await;
+    initiator.send(
+        Message::new(msg_types::LOGON)
+            .set(tags::ENCRYPT_METHOD, 0)
+            .set(tags::HEART_BT_INT, 1)
+            .set(tags::USERNAME, "a@gmail.com")
+    ).await;
+    let logout = initiator.receive().await;
+    assert_eq!(logout.msg_type(), msg_types::LOGOUT);
+    assert_eq!(logout.get(tags::TEXT), Some("Missing or invalid Nonce."));
+
+    // Logon signed with another secret
+    let forged = api_keys::Model { secret: "forged".to_owned(), ..key.clone() };
+    let mut initiator = Initiator::connect(address, 1).await;
+    initiator.send_logon(Message::new(msg_types::LOGON).set(tags::HEART_BT_INT, 1), &forged).await;
+    assert_eq!(initiator.receive().await.get(tags::TEXT), Some("Invalid signature."));
+
+    // Key without the trade permission
+    let mut initiator = Initiator::connect(address, 1).await;
+    initiator.send_logon(Message::new(msg_types::LOGON).set(tags::HEART_BT_INT, 1), &read_only).await;
+    assert_eq!(initiator.receive().await.msg_type(), msg_types::LOGOUT);
+
+    // Log on
+    let mut initiator = Initiator::connect(address, 1).await;
+    let logon = initiator.logon(true, &key).await;
+    assert_eq!(logon.msg_type(), msg_types::LOGON);
+    assert_eq!(logon.get(tags::MSG_SEQ_NUM), Some("1"));
+
+    // Test request
+    initiator.send(Message::new(msg_types::TEST_REQUEST).set(tags::TEST_REQ_ID, "TEST")).await;
+    let heartbeat = initiator.receive().await;
+    assert_eq!(heartbeat.msg_type(), msg_types::HEARTBEAT);
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), Some("TEST"));
+
+    // Idle session heartbeats
+    let heartbeat = initiator.receive_any().await;
+    assert_eq!(heartbeat.msg_type(), msg_types::HEARTBEAT);
+    assert_eq!(heartbeat.get(tags::TEST_REQ_ID), None);
+
+    // New order
+    initiator.send(new_order_single("A")).await;
+    let report = initiator.receive().await;
+    assert_eq!(report.msg_type(), msg_types::EXECUTION_REPORT);
+    assert_eq!(report.get(tags::EXEC_TYPE), Some("0"));
+    assert_eq!(report.get(tags::CL_ORD_ID), Some("A"));
+    let order_id: i32 = report.parse(tags::ORDER_ID).unwrap();
+
+    // Rejected order
+    initiator.send(new_order_single("B").set(tags::SYMBOL, "LTC/USD")).await;
+    let report = initiator.receive().await;
+    assert_eq!(report.get(tags::EXEC_TYPE), Some("8"));
+    assert_eq!(report.get(tags::ORD_STATUS), Some("8"));
+
+    // Partial fill from the engine
+    acceptor.on_fill(&Fill {
+        price: 100.0,
+        size: 4.0,
+        quote_size: 400.0,
+        side: OrderSide::Buy,
+        r#type: OrderType::Limit,
+        aggressor: Some(OrderSide::Buy),
+        created_at: Utc::now().naive_utc(),
+        sub_account_id: 1,
+        market_id: 1,
+        order_id,
+    });
+    let report = initiator.receive().await;
+    assert_eq!(report.get(tags::EXEC_TYPE), Some("F"));
+    assert_eq!(report.get(tags::ORD_STATUS), Some("1"));
+    assert_eq!(report.parse::<f32>(tags::CUM_QTY), Some(4.0));
+    assert_eq!(report.parse::<f32>(tags::LEAVES_QTY), Some(6.0));
+
+    // Replace
+    initiator.send(
+        Message::new(msg_types::ORDER_CANCEL_REPLACE_REQUEST)
+            .set(tags::ORIG_CL_ORD_ID, "A")
+            .set(tags::CL_ORD_ID, "C")
+            .set(tags::SYMBOL, "BTC/USD")
+            .set(tags::SIDE, 1)
+            .set(tags::ORDER_QTY, 20.0)
+            .set(tags::ORD_TYPE, 2)
+            .set(tags::PRICE, 101.0)
+    ).await;
+    let report = initiator.receive().await;
+    assert_eq!(report.get(tags::EXEC_TYPE), Some("5"));
+    assert_eq!(report.parse::<f32>(tags::LEAVES_QTY), Some(16.0));
+
+    // Cancel
+    initiator.send(
+        Message::new(msg_types::ORDER_CANCEL_REQUEST)
+            .set(tags::ORIG_CL_ORD_ID, "C")
+            .set(tags::CL_ORD_ID, "D")
+            .set(tags::SYMBOL, "BTC/USD")
+            .set(tags::SIDE, 1)
+    ).await;
+    let report = initiator.receive().await;
+    assert_eq!((report.get(tags::EXEC_TYPE), report.get(tags::ORD_STATUS)), (Some("6"), Some("6")));
+    assert_eq!(report.get(tags::CL_ORD_ID), Some("D"));
+
+    // Canceled once the engine pulls the order
+    let cancelled = Cancelled {
+        order_id,
+        sub_account_id: 1,
+        market_id: 1,
+        remaining_size: 16.0,
+        cancelled_at: Utc::now().naive_utc(),
+    };
+    Mutation::close_cancelled_order(&db, &cancelled).await.unwrap();
+    acceptor.on_cancelled(&cancelled);
+    let report = initiator.receive().await;
+    assert_eq!((report.get(tags::EXEC_TYPE), report.get(tags::ORD_STATUS)), (Some("4"), Some("4")));
+    assert_eq!(report.get(tags::CL_ORD_ID), Some("D"));
+
+    // Cancel unknown
+    initiator.send(
+        Message::new(msg_types::ORDER_CANCEL_REQUEST)
+            .set(tags::ORIG_CL_ORD_ID, "C")
+            .set(tags::CL_ORD_ID, "E")
+            .set(tags::SYMBOL, "BTC/USD")
+            .set(tags::SIDE, 1)
+    ).await;
+    let reject = initiator.receive().await;
+    assert_eq!(reject.msg_type(), msg_types::ORDER_CANCEL_REJECT);
+
+    // Mass cancel from the engine
+    initiator.send(new_order_single("H")).await;
+    let order_id: i32 = initiator.receive().await.parse(tags::ORDER_ID).unwrap();
+    acceptor.on_cancelled(&Cancelled {
+        order_id,
+        sub_account_id: 1,
+        market_id: 1,
+        remaining_size: 10.0,
+        cancelled_at: Utc::now().naive_utc(),
+    });
+    let report = initiator.receive().await;
+    assert_eq!((report.get(tags::EXEC_TYPE), report.get(tags::ORD_STATUS)), (Some("4"), Some("4")));
+    assert_eq!(report.parse::<f32>(tags::LEAVES_QTY), Some(0.0));
+
+    // Log out
+    initiator.send(Message::new(msg_types::LOGOUT)).await;
+    let logout = initiator.receive().await;
+    assert_eq!(logout.msg_type(), msg_types::LOGOUT);
+    let next_sender_seq_num: u64 = logout.parse::<u64>(tags::MSG_SEQ_NUM).unwrap() + 1;
+
+    // Reconnect with persisted sequence numbers
+    let mut initiator = Initiator::connect(address, initiator.seq_num).await;
+    let logon = initiator.logon(false, &key).await;
+    assert_eq!(logon.msg_type(), msg_types::LOGON);
+    assert_eq!(logon.parse::<u64>(tags::MSG_SEQ_NUM), Some(next_sender_seq_num));
+    initiator.send(Message::new(msg_types::LOGOUT)).await;
+    assert_eq!(initiator.receive().await.msg_type(), msg_types::LOGOUT);
+
+    // Reconnect with a sequence number that is too low
+    let mut initiator = Initiator::connect(address, 1).await;
+    assert_eq!(initiator.logon(false, &key).await.msg_type(), msg_types::LOGON);
+    let logout = initiator.receive().await;
+    assert_eq!(logout.msg_type(), msg_types::LOGOUT);
+    assert!(logout.get(tags::TEXT).unwrap().starts_with("MsgSeqNum too low"));
+
+    // Orders remain open after a disconnect without cancel-on-disconnect
+    let mut orders = transport.subscribe(Channel::Orders, Offset::Next).await.unwrap();
+    let mut initiator = Initiator::connect(address, 1).await;
+    assert_eq!(initiator.logon(true, &key).await.msg_type(), msg_types::LOGON);
+    initiator.send(new_order_single("F")).await;
+    assert_eq!(initiator.receive().await.get(tags::EXEC_TYPE), Some("0"));
+    drop(initiator);
+    task::sleep(std::time::Duration::from_millis(100)).await; // Let the acceptor end the session
+
+    // Cancel on disconnect
+    let mut initiator = Initiator::connect(address, 1).await;
+    initiator.send_logon(
+        Message::new(msg_types::LOGON)
+            .set(tags::ENCRYPT_METHOD, 0)
+            .set(tags::HEART_BT_INT, 1)
+            .set(tags::RESET_SEQ_NUM_FLAG, "Y")
+            .set(tags::CANCEL_ON_DISCONNECT, "Y"),
+        &key,
+    ).await;
+    assert_eq!(initiator.receive().await.get(tags::CANCEL_ON_DISCONNECT), Some("Y"));
+    initiator.send(new_order_single("G")).await;
+    assert_eq!(initiator.receive().await.get(tags::EXEC_TYPE), Some("0"));
+    drop(initiator);
+    let (commands, mass_cancel) = next_mass_cancel(&mut orders).await; // The engine closes the orders it pulls
+    assert_eq!(commands, 2);
+    assert_eq!(mass_cancel, MassCancel { market_id: None, sub_account_ids: vec![1], side: None });
+
+    // Tear down
+    Migrator::reset(&db).await.unwrap(); // Rollback migrations
+}
@@ -0,0 +1,83 @@
+use chrono::{NaiveDateTime, Utc};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use database::orders::{Command, Order};
+use database::{OrderSide, OrderType};
+use orderbook::OrderBook;
+
+// ----------------------------------------------------------------------
+
+const DEPTHS: [usize; 3] = [1, 10, 100]; // Price levels on each side of the book
+const ORDERS_PER_LEVEL: usize = 10;
+const MID: f32 = 1_000.0;
+
+fn order(id: usize, side: OrderSide, r#type: OrderType, price: Option<f32>, size: f32, now: NaiveDateTime) -> Order {
+    Order { id: id as i32, sub_account_id: 1, price, size, side, r#type, open_at: now }
+}
+
+/// A book with the given number of levels of unit orders on each side around the mid price. Bids have the ids up to
+/// the number of resting orders on a side and asks the ids above.
+fn book(levels: usize, now: NaiveDateTime) -> OrderBook {
+    let mut orderbook = OrderBook::detached(1);
+    let resting = levels * ORDERS_PER_LEVEL;
+    for i in 0..resting {
+        let level = (i / ORDERS_PER_LEVEL) as f32 + 1.0;
+        let bid = order(i + 1, OrderSide::Bid, OrderType::Limit, Some(MID - level), 1.0, now);
+        let ask = order(resting + i + 1, OrderSide::Ask, OrderType::Limit, Some(MID + level), 1.0, now);
+        orderbook.execute(Command::New(bid), now);
+        orderbook.execute(Command::New(ask), now);
+    }
+    orderbook
+}
+
+/// Times a single command against a freshly built book of every depth.
+fn bench(c: &mut Criterion, name: &str, command: impl Fn(usize, NaiveDateTime) -> Command) {
+    let mut group = c.benchmark_group(name);
+    let now = Utc::now().naive_utc();
+    for levels in DEPTHS {
+        let command = command(levels, now);
+        group.bench_with_input(BenchmarkId::from_parameter(levels), &levels, |b, &levels| {
+            b.iter_batched(
+                || (book(levels, now), command.clone()),
+                |(mut orderbook, command)| {
+                    orderbook.execute(command, now);
+                    orderbook // Dropped outside of the measurement
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn insert(c: &mut Criterion) {
+    bench(c, "insert", |levels, now| { // Behind the worst bid, without matching
+        let id = 2 * levels * ORDERS_PER_LEVEL + 1;
+        Command::New(order(id, OrderSide::Bid, OrderType::Limit, Some(MID - levels as f32), 1.0, now))
+    });
+}
+
+fn cancel(c: &mut Criterion) {
+    bench(c, "cancel", |levels, now| { // A bid in the middle of the book
+        let id = levels * ORDERS_PER_LEVEL / 2 + 1;
+        Command::Cancel(order(id, OrderSide::Bid, OrderType::Limit, None, 1.0, now))
+    });
+}
+
+fn fill(c: &mut Criterion) {
+    bench(c, "match", |levels, now| { // Fills the first order at the best bid
+        let id = 2 * levels * ORDERS_PER_LEVEL + 1;
+        Command::New(order(id, OrderSide::Ask, OrderType::Limit, Some(MID - 1.0), 1.0, now))
+    });
+}
+
+fn sweep(c: &mut Criterion) {
+    bench(c, "sweep", |levels, now| { // Fills every bid
+        let id = 2 * levels * ORDERS_PER_LEVEL + 1;
+        let size = (levels * ORDERS_PER_LEVEL) as f32;
+        Command::New(order(id, OrderSide::Sell, OrderType::Market, None, size, now))
+    });
+}
+
+criterion_group!(benches, insert, cancel, fill, sweep);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+use database::orders::Order;
+
+// ----------------------------------------------------------------------
+
+/// The single price at which a call auction uncrosses and the volume which executes at it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Equilibrium {
+    pub price: f32,
+    pub volume: f32,
+    pub imbalance: f32, // Bid volume less ask volume which is executable at the price
+}
+
+/// Chooses the clearing price among the limit prices of the orders by maximum executable volume, then minimum
+/// imbalance, then proximity to the reference price. Without a reference price the middle of the remaining prices is
+/// used. Returns `None` if the orders do not cross.
+pub fn equilibrium<'a>(
+    bids: impl Iterator<Item = &'a Order>,
+    asks: impl Iterator<Item = &'a Order>,
+    reference: Option<f32>,
+) -> Option<Equilibrium> {
+    let bids: Vec<(f32, f32)> = bids.filter_map(|order| Some((order.price?, order.size))).collect();
+    let asks: Vec<(f32, f32)> = asks.filter_map(|order| Some((order.price?, order.size))).collect();
+    let mut prices: Vec<f32> = bids.iter().chain(asks.iter()).map(|(price, _)| *price).collect();
+    prices.sort_by(f32::total_cmp);
+    prices.dedup();
+
+    let candidates: Vec<Equilibrium> = prices
+        .into_iter()
+        .map(|price| {
+            let demand: f32 = bids.iter().filter(|(bid, _)| *bid >= price).map(|(_, size)| size).sum();
+            let supply: f32 = asks.iter().filter(|(ask, _)| *ask <= price).map(|(_, size)| size).sum();
+            Equilibrium { price, volume: demand.min(supply), imbalance: demand - supply }
+        })
+        .filter(|candidate| candidate.volume > 0.0)
+        .collect();
+
+    let volume = candidates.iter().map(|candidate| candidate.volume).fold(0.0, f32::max);
+    let candidates: Vec<Equilibrium> = candidates.into_iter().filter(|c| same(c.volume, volume)).collect();
+    let imbalance = candidates.iter().map(|candidate| candidate.imbalance.abs()).fold(f32::INFINITY, f32::min);
+    let candidates: Vec<Equilibrium> = candidates
+        .into_iter()
+        .filter(|candidate| same(candidate.imbalance.abs(), imbalance))
+        .collect();
+
+    let (first, last) = (candidates.first()?, candidates.last()?);
+    let reference = reference.unwrap_or((first.price + last.price) / 2.0);
+    candidates
+        .iter()
+        .min_by(|a, b| (a.price - reference).abs().total_cmp(&(b.price - reference).abs())) // Keeps the lower on ties
+        .copied()
+}
+
+/// Compares sums of sizes, which may differ by rounding, for equality.
+fn same(a: f32, b: f32) -> bool {
+    (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0)
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use database::{OrderSide, OrderType};
+
+    fn orders(side: OrderSide, levels: &[(f32, f32)]) -> Vec<Order> {
+        levels
+            .iter()
+            .enumerate()
+            .map(|(id, (price, size))| Order {
+                id: id as i32,
+                sub_account_id: 1,
+                price: Some(*price),
+                size: *size,
+                side: side.clone(),
+                r#type: OrderType::Limit,
+                open_at: Utc::now().naive_utc(),
+            })
+            .collect()
+    }
+
+    fn solve(bids: &[(f32, f32)], asks: &[(f32, f32)], reference: Option<f32>) -> Option<Equilibrium> {
+        let (bids, asks) = (orders(OrderSide::Bid, bids), orders(OrderSide::Ask, asks));
+        equilibrium(bids.iter(), asks.iter(), reference)
+    }
+
+    #[test]
+    fn maximum_volume() {
+        let equilibrium = solve(&[(10.0, 5.0), (11.0, 5.0)], &[(9.0, 4.0), (10.0, 4.0), (12.0, 10.0)], None).unwrap();
+        assert_eq!(equilibrium, Equilibrium { price: 10.0, volume: 8.0, imbalance: 2.0 });
+    }
+
+    #[test]
+    fn minimum_imbalance() {
+        // 5 executes at both 10 and 11, but only 11 leaves no imbalance
+        let equilibrium = solve(&[(11.0, 5.0), (10.0, 2.0)], &[(10.0, 5.0)], None).unwrap();
+        assert_eq!(equilibrium, Equilibrium { price: 11.0, volume: 5.0, imbalance: 0.0 });
+    }
+
+    #[test]
+    fn reference_price() {
+        // 10 executes at every price between 10 and 12 without imbalance
+        let bids = [(12.0, 10.0)];
+        let asks = [(10.0, 10.0)];
+        assert_eq!(solve(&bids, &asks, Some(11.8)).unwrap().price, 12.0);
+        assert_eq!(solve(&bids, &asks, Some(5.0)).unwrap().price, 10.0);
+        assert_eq!(solve(&bids, &asks, None).unwrap().price, 10.0);
+    }
+
+    #[test]
+    fn no_cross() {
+        assert_eq!(solve(&[(9.0, 5.0)], &[(10.0, 5.0)], None), None);
+        assert_eq!(solve(&[], &[(10.0, 5.0)], None), None);
+    }
+}
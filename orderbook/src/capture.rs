@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use protocol::transport::Channel;
+
+// ----------------------------------------------------------------------
+
+const MAGIC: &[u8; 8] = b"RXCAP001";
+
+/// The stream an event of a recording was consumed from or published to, in the order of the variants of `Channel`.
+fn channel(value: u8) -> io::Result<Channel> {
+    match value {
+        0 => Ok(Channel::Orders),
+        1 => Ok(Channel::Fills),
+        2 => Ok(Channel::MarketData),
+        _ => Err(io::Error::new(ErrorKind::InvalidData, format!("Unknown channel {value}."))),
+    }
+}
+
+/// A message of the engine as encoded on its stream, stamped with the time it was consumed or published.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub timestamp: i64, // Nanoseconds since the epoch
+    pub channel: Channel,
+    pub data: Vec<u8>,
+}
+
+impl Event {
+    pub fn time(&self) -> NaiveDateTime {
+        DateTime::from_timestamp_nanos(self.timestamp).naive_utc()
+    }
+}
+
+pub fn timestamp(time: NaiveDateTime) -> i64 {
+    time.and_utc().timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+// ----------------------------------------------------------------------
+
+/// Writes the events of an engine to a gzip compressed file.
+///
+/// Layout: magic (8 bytes), market_id (i32), then per event timestamp (i64), channel (u8), length (u32) and the
+/// encoded message, all little-endian.
+pub struct Recorder {
+    writer: GzEncoder<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, market_id: i32) -> io::Result<Self> {
+        let mut writer = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::fast());
+        writer.write_all(MAGIC)?;
+        writer.write_all(&market_id.to_le_bytes())?;
+        Ok(Recorder { writer })
+    }
+
+    pub fn record(&mut self, timestamp: i64, channel: Channel, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        self.writer.write_all(&[channel as u8])?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    /// Makes the events recorded so far readable, even if the engine is killed before the file is finished.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the events of a recording in the order they were recorded. A recording cut off mid-event, as happens when the
+/// engine is killed, ends at the last complete event.
+pub struct Recording {
+    pub market_id: i32,
+    reader: GzDecoder<BufReader<File>>,
+}
+
+impl Recording {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = GzDecoder::new(BufReader::new(File::open(path)?));
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Not a recording of the engine."));
+        }
+        let mut market_id = [0; 4];
+        reader.read_exact(&mut market_id)?;
+        Ok(Recording { market_id: i32::from_le_bytes(market_id), reader })
+    }
+
+    fn next_event(&mut self) -> io::Result<Event> {
+        let mut header = [0; 13];
+        self.reader.read_exact(&mut header)?;
+        let mut data = vec![0; u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Event {
+            timestamp: i64::from_le_bytes(header[..8].try_into().unwrap()),
+            channel: channel(header[8])?,
+            data,
+        })
+    }
+}
+
+impl Iterator for Recording {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_event() {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+            result => Some(result),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+
+use chrono::{Duration, NaiveDateTime};
+use database::markets;
+use database::MarketStatus;
+
+// ----------------------------------------------------------------------
+
+/// A volatility interruption which trips when a trade would print further than `percent` from the reference price,
+/// the earliest price traded within the rolling window. If no trade printed within the window, the last traded price
+/// is the reference.
+pub struct CircuitBreaker {
+    percent: f32,
+    window: Duration,
+    pub action: MarketStatus, // Auction or Halted
+    pub auction: Duration, // Duration of volatility auctions
+    trades: VecDeque<(NaiveDateTime, f32)>,
+}
+
+impl CircuitBreaker {
+    pub fn new(percent: f32, window: Duration, action: MarketStatus, auction: Duration) -> Self {
+        CircuitBreaker { percent, window, action, auction, trades: VecDeque::new() }
+    }
+
+    /// Returns the circuit breaker configured on the market, if any.
+    pub fn from_market(market: &markets::Model) -> Option<Self> {
+        Some(Self::new(
+            market.band_percent?,
+            Duration::seconds(market.band_window.into()),
+            market.band_action,
+            Duration::seconds(market.auction_duration.into()),
+        ))
+    }
+
+    /// Returns the circuit breaker configured by the change, if any. Trades recorded by the previous circuit breaker are
+    /// kept, so that the reference price carries over.
+    pub fn from_change(change: &markets::CircuitBreakerChange, previous: Option<Self>) -> Option<Self> {
+        let mut circuit_breaker = Self::new(
+            change.band_percent?,
+            Duration::seconds(change.band_window.into()),
+            change.band_action,
+            Duration::seconds(change.auction_duration.into()),
+        );
+        circuit_breaker.trades = previous.map(|previous| previous.trades).unwrap_or_default();
+        Some(circuit_breaker)
+    }
+
+    pub fn record(&mut self, price: f32, now: NaiveDateTime) {
+        self.trades.push_back((now, price));
+    }
+
+    /// Restarts the window at the price at which an auction uncrossed.
+    pub fn reset(&mut self, price: f32, now: NaiveDateTime) {
+        self.trades.clear();
+        self.record(price, now);
+    }
+
+    /// Returns the reference price if a trade at the price would breach the band.
+    pub fn breach(&mut self, price: f32, now: NaiveDateTime) -> Option<f32> {
+        while self.trades.len() > 1 && self.trades[0].0 < now - self.window { // Always keep the last trade
+            self.trades.pop_front();
+        }
+        let reference = self.trades.front()?.1;
+        ((price - reference).abs() > reference.abs() * self.percent / 100.0).then_some(reference)
+    }
+}
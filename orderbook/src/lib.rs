@@ -1,63 +1,324 @@
-#![feature(binary_heap_retain)]
+mod auction;
+mod capture;
+mod circuit_breaker;
+mod metrics;
+mod pipeline;
 mod queue;
+#[cfg(test)]
+mod reference;
+mod replay;
+mod ring;
+
+pub use capture::{Event, Recorder, Recording};
+pub use metrics::serve_metrics;
+pub use protocol::transport::Channel;
+pub use replay::{replay, Report};
 
-use futures::StreamExt;
-use futures::executor;
-use chrono::Utc;
-use rabbitmq_stream_client::{Environment, Producer, Dedup};
-use rabbitmq_stream_client::types::{ByteCapacity, Message, OffsetSpecification};
-use database::orders::Order;
-use database::{OrderSide, OrderType};
+use chrono::{NaiveDateTime, Utc};
+use database::orders::{Cancelled, Command, MassCancel, Order};
+use database::{circuit_breakers, DatabaseConnection, Engine, MarketStatus, OrderSide, OrderType, Query};
 use database::fills::Fill;
+use database::markets::{CircuitBreakerChange, Indicative, StatusChange, TopOfBook};
+use protocol::{Codec, Transport};
+use crate::capture::timestamp;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::pipeline::{Input, Output};
 use crate::queue::Queue;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
-const QUEUE_CAPACITY: usize = 500;
+const OUTBOX_CAPACITY: usize = 64; // Messages produced by an input before the outbox has to grow
+const RESUME_INTERVAL: Duration = Duration::from_millis(100); // How often to check whether a volatility auction ended
+
+pub type Level = (f32, f32); // Price and resting size
 
 pub struct OrderBook { // TODO: price and size increment
     id: i32,
     bids: Queue,
     asks: Queue,
-    producer: Option<Producer<Dedup>>
+    status: MarketStatus,
+    last_price: Option<f32>, // Reference price of auctions
+    circuit_breaker: Option<CircuitBreaker>,
+    resumes_at: Option<NaiveDateTime>, // End of the current volatility auction
+    quoted: (Option<f32>, Option<f32>), // Best bid and ask last broadcast
+    db: Option<DatabaseConnection>, // Persists circuit breakers and the end of volatility auctions, see `run`
+    transport: Option<Arc<dyn Transport>>, // Publishes fills, and market data such as status changes, indicative auction prices and the top of the book
+    outbox: Vec<Output>, // Messages produced by the last input, published after it is processed
+    cancelled: Vec<Order>, // Orders pulled by the last input, kept to reuse its allocation
+    sub_account_ids: HashSet<i32>, // Sub-accounts of the last mass cancel, kept to reuse its allocation
+    recorder: Option<Recorder>,
+    clock: Option<NaiveDateTime>, // Time of the recorded input being replayed, used instead of the wall clock
 }
 
 impl OrderBook {
-    pub async fn new(market_id: i32) -> Self {
-        let producer = if !cfg!(test) {
-            // Establish connection to RabbitMQ
-            let environment = Environment::builder()
-                .host("localhost")
-                .port(5552)
-                .build()
-                .await
-                .unwrap();
-            let _ = environment.delete_stream("fills").await; // Delete stream if it exists
-            environment // Create stream at producer
-                .stream_creator()
-                .max_length(ByteCapacity::MB(50))
-                .max_age(Duration::new(30, 0))
-                .create("fills")
-                .await
-                .unwrap();
-            Some(
-                environment
-                    .producer()
-                    .name("fills")
-                    .build("fills")
-                    .await
-                    .unwrap()
-            )
-        } else {
-            None
-        };
+    /// Creates an order book which consumes the orders stream of the transport and publishes to its fills and market
+    /// data streams. The market data stream is created anew, while the fills stream keeps the fills of previous runs
+    /// for the fill persister of the API to resume from.
+    pub async fn new(market_id: i32, transport: Arc<dyn Transport>) -> Self {
+        transport.declare(Channel::Fills).await.unwrap(); // Allow to panic if unsuccessful
+        transport.create(Channel::MarketData).await.unwrap();
+        Self::with_transport(market_id, Some(transport))
+    }
+
+    /// Creates an order book which publishes nothing, for replaying recordings and for tests.
+    pub fn detached(market_id: i32) -> Self {
+        Self::with_transport(market_id, None)
+    }
+
+    fn with_transport(market_id: i32, transport: Option<Arc<dyn Transport>>) -> Self {
+        let capacity = config::get().engine.queue_capacity;
         OrderBook {
             id: market_id,
-            bids: Queue::new(QUEUE_CAPACITY),
-            asks: Queue::new(QUEUE_CAPACITY),
-            producer
+            bids: Queue::new(capacity),
+            asks: Queue::new(capacity),
+            status: MarketStatus::Continuous,
+            last_price: None,
+            circuit_breaker: None,
+            resumes_at: None,
+            quoted: (None, None),
+            db: None,
+            transport,
+            outbox: Vec::with_capacity(OUTBOX_CAPACITY),
+            cancelled: Vec::new(),
+            sub_account_ids: HashSet::new(),
+            recorder: None,
+            clock: None,
         }
     }
-    
+
+    /// Records every message consumed and published by the engine to a compressed file from now on. The recording
+    /// starts with the current status of the market, so it should be started before any orders rest in the book.
+    pub fn record_to(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut recorder = Recorder::create(path, self.id)?;
+        let now = Utc::now().naive_utc();
+        let status = StatusChange { market_id: self.id, status: self.status, cancel_resting: false, changed_at: now };
+        recorder.record(timestamp(now), Channel::Orders, &status.encode())?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    fn now(&self) -> NaiveDateTime {
+        self.clock.unwrap_or_else(|| Utc::now().naive_utc())
+    }
+
+    /// Queues a message to be published once the current input is processed.
+    fn emit(&mut self, message: Output) {
+        self.outbox.push(message);
+    }
+
+    /// Creates an order book in the status and with the circuit breaker of the market stored in the database.
+    pub async fn connect(market_id: i32, transport: Arc<dyn Transport>) -> Self {
+        let db = Engine::connect().await.unwrap(); // Allow to panic if unsuccessful
+        Self::load(market_id, transport, db).await
+    }
+
+    /// Creates an order book in the status and with the circuit breaker of the market stored in the given database,
+    /// which also persists the circuit breakers it trips.
+    pub async fn load(market_id: i32, transport: Arc<dyn Transport>, db: DatabaseConnection) -> Self {
+        let mut orderbook = Self::new(market_id, transport).await;
+        let market = Query::find_market_by_id(&db, market_id).await.unwrap();
+        orderbook.status = market.status;
+        orderbook.circuit_breaker = CircuitBreaker::from_market(&market);
+        orderbook.db = Some(db);
+        orderbook
+    }
+
+    /// Applies the circuit breaker of the market stored in the database, without persisting anything to it.
+    pub async fn load_circuit_breaker(&mut self) {
+        let db = Engine::connect().await.unwrap(); // Allow to panic if unsuccessful
+        let market = Query::find_market_by_id(&db, self.id).await.unwrap();
+        self.circuit_breaker = CircuitBreaker::from_market(&market);
+    }
+
+    /// Processes an order command in the current status of the market. New orders which the status rejects are
+    /// reported cancelled, which closes them in the database.
+    fn process_command(&mut self, command: Command) -> bool {
+        let processed = match (command, self.status) {
+            (Command::Cancel(order), _) => self.process_cancel(order), // Orders may always be cancelled
+            (Command::New(order), MarketStatus::Halted | MarketStatus::Closed) => self.reject(order),
+            (_, MarketStatus::Halted | MarketStatus::Closed) => false,
+            (Command::New(order), MarketStatus::Continuous) => self.process(order),
+            (Command::New(order), _) => match order.r#type { // Collect limit orders without matching them
+                OrderType::Limit => self.store(order),
+                OrderType::Market => self.reject(order),
+            },
+            (Command::Amend(order), _) => self.process_amend(order),
+        };
+        if processed && self.status == MarketStatus::Auction {
+            self.publish_indicative();
+        }
+        processed
+    }
+
+    /// Pulls all resting orders of the sub-accounts at once, optionally only those on one side, which is allowed in
+    /// any status. Every pulled order is reported, which closes it in the database.
+    fn process_mass_cancel(&mut self, mass_cancel: MassCancel) -> bool {
+        if mass_cancel.market_id.is_some_and(|market_id| market_id != self.id) {
+            return false;
+        }
+        self.sub_account_ids.clear();
+        self.sub_account_ids.extend(mass_cancel.sub_account_ids);
+        let mut cancelled = std::mem::take(&mut self.cancelled);
+        cancelled.clear();
+        if !matches!(mass_cancel.side, Some(OrderSide::Sell | OrderSide::Ask | OrderSide::Short)) {
+            self.bids.cancel_many(&self.sub_account_ids, &mut cancelled);
+        }
+        if !matches!(mass_cancel.side, Some(OrderSide::Buy | OrderSide::Bid | OrderSide::Long)) {
+            self.asks.cancel_many(&self.sub_account_ids, &mut cancelled);
+        }
+        self.publish_cancelled(&cancelled);
+        let processed = !cancelled.is_empty();
+        self.cancelled = cancelled;
+        if processed && self.status == MarketStatus::Auction {
+            self.publish_indicative();
+        }
+        processed
+    }
+
+    fn reject(&mut self, order: Order) -> bool {
+        self.publish_cancelled(&[order]);
+        false
+    }
+
+    /// Reports orders pulled from the book by the engine rather than by a fill, which closes them in the database.
+    fn publish_cancelled(&mut self, cancelled: &[Order]) {
+        let now = self.now();
+        for order in cancelled {
+            self.emit(Output::Cancelled(Cancelled {
+                order_id: order.id,
+                sub_account_id: order.sub_account_id,
+                market_id: self.id,
+                remaining_size: order.size,
+                cancelled_at: now,
+            }));
+        }
+    }
+
+    /// Applies a change of the trading status of the market and broadcasts it. Orders collected before the open, during
+    /// an auction or during a halt are uncrossed when continuous trading starts or when a closing auction ends. Resting
+    /// orders which the change cancels are reported one by one.
+    fn process_status(&mut self, change: StatusChange) -> bool {
+        if change.market_id != self.id {
+            return false;
+        }
+        self.resumes_at = None;
+        let previous = std::mem::replace(&mut self.status, change.status);
+        match (previous, self.status) {
+            (MarketStatus::PreOpen | MarketStatus::Auction | MarketStatus::Halted, MarketStatus::Continuous)
+            | (MarketStatus::Auction, MarketStatus::Closed) => self.uncross(),
+            _ => {}
+        }
+        if change.cancel_resting {
+            let mut cancelled = std::mem::take(&mut self.cancelled);
+            cancelled.clear();
+            self.bids.clear(&mut cancelled);
+            self.asks.clear(&mut cancelled);
+            self.publish_cancelled(&cancelled);
+            self.cancelled = cancelled;
+        }
+        self.emit(Output::StatusChange(change));
+        if self.status == MarketStatus::Auction {
+            self.publish_indicative();
+        }
+        true
+    }
+
+    /// Reconfigures the circuit breaker of the market as changed by an admin, or disables it.
+    fn process_circuit_breaker(&mut self, change: CircuitBreakerChange) -> bool {
+        if change.market_id != self.id {
+            return false;
+        }
+        self.circuit_breaker = CircuitBreaker::from_change(&change, self.circuit_breaker.take());
+        true
+    }
+
+    /// Returns the price at which the collected orders would uncross and the volume which would execute.
+    fn indicative(&self) -> Indicative {
+        let equilibrium = auction::equilibrium(self.bids.orders(), self.asks.orders(), self.last_price);
+        Indicative {
+            market_id: self.id,
+            price: equilibrium.map(|equilibrium| equilibrium.price),
+            volume: equilibrium.map_or(0.0, |equilibrium| equilibrium.volume),
+            imbalance: equilibrium.map_or(0.0, |equilibrium| equilibrium.imbalance),
+            created_at: self.now(),
+        }
+    }
+
+    fn publish_indicative(&mut self) {
+        let indicative = self.indicative();
+        self.emit(Output::Indicative(indicative));
+    }
+
+    /// Fills all crossing orders at the single equilibrium price of the collected orders, in price-time priority.
+    fn uncross(&mut self) {
+        let Some(price) = self.indicative().price else {
+            return;
+        };
+        while let (Some(bid), Some(ask)) = (self.bids.peek().cloned(), self.asks.peek().cloned()) {
+            if bid.price.unwrap() < price || ask.price.unwrap() > price {
+                break;
+            }
+            let size = f32::min(bid.size, ask.size);
+            self.publish_fill(price, size, &bid, None);
+            self.publish_fill(price, size, &ask, None);
+            for (queue, order) in [(&mut self.bids, bid), (&mut self.asks, ask)] {
+                if order.size > size {
+                    queue.modify_tob(order.size - size);
+                } else {
+                    queue.pop();
+                }
+            }
+        }
+        self.last_price = Some(price);
+        let now = self.now();
+        if let Some(circuit_breaker) = &mut self.circuit_breaker {
+            circuit_breaker.reset(price, now);
+        }
+    }
+
+    /// Switches the market to the action of the circuit breaker instead of filling at the price if the price breaches
+    /// the band. The circuit breaker is persisted and broadcast by the publisher.
+    fn interrupt(&mut self, price: f32) -> bool {
+        let now = self.now();
+        let Some(circuit_breaker) = &mut self.circuit_breaker else {
+            return false;
+        };
+        let Some(reference_price) = circuit_breaker.breach(price, now) else {
+            return false;
+        };
+        let (status, auction) = (circuit_breaker.action, circuit_breaker.auction);
+        self.process_status(StatusChange { market_id: self.id, status, cancel_resting: false, changed_at: now });
+        self.resumes_at = (status == MarketStatus::Auction).then(|| now + auction);
+        let event = circuit_breakers::Model {
+            id: 0,
+            reference_price,
+            price,
+            status,
+            created_at: now,
+            resumes_at: self.resumes_at,
+            market_id: self.id,
+        };
+        self.emit(Output::CircuitBreaker(event));
+        true
+    }
+
+    /// Resumes continuous trading once a volatility auction has lasted its duration.
+    fn resume(&mut self, now: NaiveDateTime) -> bool {
+        if self.resumes_at.is_none_or(|resumes_at| now < resumes_at) {
+            return false;
+        }
+        self.emit(Output::Persist(MarketStatus::Continuous));
+        self.process_status(StatusChange {
+            market_id: self.id,
+            status: MarketStatus::Continuous,
+            cancel_resting: false,
+            changed_at: now,
+        })
+    }
+
     fn process(&mut self, order: Order) -> bool {
         match order.r#type {
             OrderType::Limit => self.process_limit(order),
@@ -74,6 +335,9 @@ impl OrderBook {
                 OrderSide::Buy | OrderSide::Bid | OrderSide::Long => order.price >= contra_order.price,
                 OrderSide::Sell | OrderSide::Ask | OrderSide::Short => order.price <= contra_order.price
             } {
+                if self.interrupt(contra_order.price.unwrap()) { // Rest the order in the auction or halt instead
+                    return self.store(order);
+                }
                 let mut order = order; // Take the previous value out of scope
                 if !self.cross(&mut order, contra_order) {
                     self.process_limit(order)
@@ -88,15 +352,23 @@ impl OrderBook {
         }
     }
     
+    /// Fills a market order against the book. The remainder which the book cannot fill, or which would trip the
+    /// circuit breaker, is reported cancelled.
     fn process_market(&mut self, order: Order) -> bool {
         if let Some(contra_order) = match order.side {
             OrderSide::Buy | OrderSide::Bid | OrderSide::Long => (&mut self.asks).peek().cloned(),
             OrderSide::Sell | OrderSide::Ask | OrderSide::Short => (&mut self.bids).peek().cloned()
         } {
+            if self.interrupt(contra_order.price.unwrap()) { // The remainder of the order is not filled
+                self.publish_cancelled(&[order]);
+                return true;
+            }
             let mut order = order;
             if !self.cross(&mut order, contra_order) {
                 self.process_market(order);
             }
+        } else {
+            self.publish_cancelled(&[order]);
         }
         true
     }
@@ -110,22 +382,14 @@ impl OrderBook {
 
     fn cross(&mut self, order: &mut Order, contra_order: Order) -> bool {
         {
-            self.publish_fill(
-                contra_order.price.unwrap(),
-                f32::min(order.size, contra_order.size),
-                contra_order.side,
-                contra_order.r#type,
-                contra_order.sub_account_id,
-                contra_order.id
-            );
-            self.publish_fill(
-                contra_order.price.unwrap(),
-                f32::min(order.size, contra_order.size),
-                order.side.clone(),
-                order.r#type.clone(),
-                order.sub_account_id,
-                order.id
-            );
+            let size = f32::min(order.size, contra_order.size);
+            self.publish_fill(contra_order.price.unwrap(), size, &contra_order, Some(order.side.clone()));
+            self.publish_fill(contra_order.price.unwrap(), size, order, Some(order.side.clone()));
+        }
+        self.last_price = contra_order.price;
+        let now = self.now();
+        if let Some(circuit_breaker) = &mut self.circuit_breaker {
+            circuit_breaker.record(contra_order.price.unwrap(), now);
         }
         let contra_queue = match order.side {
             OrderSide::Buy | OrderSide::Bid | OrderSide::Long => &mut self.asks,
@@ -143,12 +407,58 @@ impl OrderBook {
         }
     }
 
-    // fn process_order_cancel(&mut self, order: Order) -> bool {
-    //     match order.side {
-    //         OrderSide::Buy | OrderSide::Bid | OrderSide::Long => (&mut self.bids).cancel(order.id),
-    //         OrderSide::Sell | OrderSide::Ask | OrderSide::Short => (&mut self.asks).cancel(order.id),
-    //     }
-    // }
+    /// Pulls a resting order and reports it, which closes it in the database. Orders filled in the meantime are left
+    /// as they are.
+    fn process_cancel(&mut self, order: Order) -> bool {
+        let cancelled = match order.side {
+            OrderSide::Buy | OrderSide::Bid | OrderSide::Long => self.bids.cancel(order.id),
+            OrderSide::Sell | OrderSide::Ask | OrderSide::Short => self.asks.cancel(order.id),
+        };
+        match cancelled {
+            Some(cancelled) => {
+                self.publish_cancelled(&[cancelled]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn process_amend(&mut self, order: Order) -> bool {
+        let queue = match order.side {
+            OrderSide::Buy | OrderSide::Bid | OrderSide::Long => &mut self.bids,
+            OrderSide::Sell | OrderSide::Ask | OrderSide::Short => &mut self.asks
+        };
+        if let Some(resting) = queue.get(order.id) {
+            if resting.price == order.price && order.size <= resting.size { // Keep time priority
+                queue.amend(order.id, order.size)
+            } else { // Lose time priority and re-match at the new price
+                queue.cancel(order.id);
+                let order = Order { open_at: self.now(), ..order };
+                if self.status == MarketStatus::Continuous {
+                    self.process_limit(order)
+                } else {
+                    self.store(order)
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Broadcasts the best bid and ask prices if either changed since they were last broadcast.
+    fn publish_top_of_book(&mut self) -> bool {
+        let quoted = (
+            self.bids.peek().and_then(|order| order.price),
+            self.asks.peek().and_then(|order| order.price),
+        );
+        if quoted == self.quoted {
+            return false;
+        }
+        self.quoted = quoted;
+        let top_of_book = TopOfBook { market_id: self.id, bid: quoted.0, ask: quoted.1, created_at: self.now() };
+        self.emit(Output::TopOfBook(top_of_book));
+        true
+    }
 
     pub fn spread(&mut self) -> Option<(f32, f32)> {
         let bid = self.bids.peek()?.price.unwrap();
@@ -156,61 +466,90 @@ impl OrderBook {
         Some((bid, ask))
     }
 
-    fn publish_fill(
-        &mut self,
-        price: f32,
-        size: f32,
-        side: OrderSide,
-        r#type: OrderType,
-        sub_account_id: i32,
-        order_id: i32
-    ) {
-        if let Some(producer) = &mut self.producer {
-            let fill = Fill {
-                price,
-                size,
-                quote_size: price * size,
-                side,
-                r#type,
-                created_at: Utc::now().naive_utc(),
-                sub_account_id,
-                market_id: self.id,
-                order_id,
-            };
-            let _ = executor::block_on(
-                producer
-                    .send_with_confirm(
-                        Message::builder()
-                            .body(serde_json::to_string(&fill).unwrap()) // TODO: Dont confirm otherwise api will halt
-                            .build()
-                    )
-            );
-        }
+    /// Best bid and ask prices as of the last processed message.
+    pub fn top_of_book(&self) -> (Option<f32>, Option<f32>) {
+        self.quoted
     }
-    
-    pub async fn run(&mut self) {
-        let mut consumer = Environment::builder()
-            .host("localhost")
-            .port(5552)
-            .build()
-            .await
-            .unwrap()
-            .consumer()
-            .offset(OffsetSpecification::First)
-            .build("orders")
-            .await
-            .unwrap();
-        while let Ok(delivery) = consumer.next().await.unwrap() { // TODO: Handle errors
-            if let Some(order) = delivery
-                .message()
-                .data()
-                .map(|data| serde_json::from_str::<Order>(
-                    std::str::from_utf8(&data.to_vec()).unwrap()).unwrap()
-                ) {
-                self.process(order);
+
+    /// Resting size aggregated per price level, best first, of up to `levels` levels of the bids and the asks.
+    pub fn depth(&self, levels: usize) -> (Vec<Level>, Vec<Level>) {
+        fn aggregate<'a>(orders: impl Iterator<Item = &'a Order>, levels: usize, descending: bool) -> Vec<Level> {
+            let mut orders: Vec<(f32, i32, f32)> = orders
+                .filter_map(|order| Some((order.price?, order.id, order.size)))
+                .collect();
+            orders.sort_by(|a, b| {
+                let by_price = if descending { b.0.total_cmp(&a.0) } else { a.0.total_cmp(&b.0) };
+                by_price.then(a.1.cmp(&b.1)) // Sum sizes in a fixed order
+            });
+            let mut depth: Vec<Level> = Vec::new();
+            for (price, _, size) in orders {
+                if let Some(level) = depth.last_mut().filter(|level| level.0 == price) {
+                    level.1 += size;
+                } else if depth.len() < levels {
+                    depth.push((price, size));
+                } else {
+                    break;
+                }
             }
+            depth
+        }
+        (aggregate(self.bids.orders(), levels, true), aggregate(self.asks.orders(), levels, false))
+    }
+
+    /// Processes a command in-process at the given time instead of consuming it from the orders stream, and returns
+    /// the fills it produced. Used by simulations, which run on their own clock.
+    pub fn execute(&mut self, command: Command, now: NaiveDateTime) -> Vec<Fill> {
+        self.clock = Some(now);
+        self.resume(now);
+        self.process_command(command);
+        self.publish_top_of_book();
+        self.drain_fills()
+    }
+
+    /// Takes the fills out of the queued messages, discarding the market data.
+    fn drain_fills(&mut self) -> Vec<Fill> {
+        self.outbox
+            .drain(..)
+            .filter_map(|message| match message {
+                Output::Fill(fill) => Some(fill),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn publish_fill(&mut self, price: f32, size: f32, order: &Order, aggressor: Option<OrderSide>) {
+        let fill = Fill {
+            price,
+            size,
+            quote_size: price * size,
+            side: order.side.clone(),
+            r#type: order.r#type.clone(),
+            aggressor,
+            created_at: self.now(),
+            sub_account_id: order.sub_account_id,
+            market_id: self.id,
+            order_id: order.id,
+        };
+        self.emit(Output::Fill(fill));
+    }
+
+    /// Processes a message of the orders stream.
+    fn handle(&mut self, data: &[u8]) {
+        if let Some(input) = Input::decode(data) {
+            self.apply(input);
         }
     }
+
+    /// Processes a decoded message of the orders stream and broadcasts the top of the book if it changed.
+    fn apply(&mut self, input: Input) {
+        match input {
+            Input::Command(command) => self.process_command(command),
+            Input::StatusChange(change) => self.process_status(change),
+            Input::MassCancel(mass_cancel) => self.process_mass_cancel(mass_cancel),
+            Input::CircuitBreakerChange(change) => self.process_circuit_breaker(change),
+        };
+        self.publish_top_of_book();
+    }
 }
 
 #[cfg(test)]
@@ -218,10 +557,12 @@ mod test {
 
     use super::*;
     use database::orders::Order;
+    use futures::StreamExt;
+    use protocol::Offset;
 
     #[async_std::test]
     async fn add_limit_order_to_empty() {
-        let mut orderbook = OrderBook::new(1).await;
+        let mut orderbook = OrderBook::detached(1);
         assert!(orderbook.process(Order {
             id: 1,
             sub_account_id: 1,
@@ -244,7 +585,7 @@ mod test {
 
     #[async_std::test]
     async fn add_limit_crossing_spread() {
-        let mut orderbook = OrderBook::new(1).await;
+        let mut orderbook = OrderBook::detached(1);
         assert!(orderbook.process(Order {
             id: 1,
             sub_account_id: 1,
@@ -285,7 +626,7 @@ mod test {
 
     #[async_std::test]
     async fn add_limit_to_existing_price_queue() {
-        let mut orderbook = OrderBook::new(1).await;
+        let mut orderbook = OrderBook::detached(1);
         assert!(orderbook.process(Order {
             id: 1,
             sub_account_id: 1,
@@ -326,7 +667,7 @@ mod test {
 
     #[async_std::test]
     async fn add_market_order_to_empty() {
-        let mut orderbook = OrderBook::new(1).await;
+        let mut orderbook = OrderBook::detached(1);
         assert!(orderbook.process(Order {
             id: 1,
             sub_account_id: 1,
@@ -345,11 +686,18 @@ mod test {
             r#type: OrderType::Market,
             open_at: Utc::now().naive_utc(),
         }));
+
+        // Report the unfilled orders, which closes them in the database
+        let cancelled: Vec<(i32, f32)> = orderbook.outbox.drain(..).filter_map(|output| match output {
+            Output::Cancelled(cancelled) => Some((cancelled.order_id, cancelled.remaining_size)),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![(1, 10.0), (2, 10.0)]);
     }
 
     #[async_std::test]
     async fn add_market_with_liquidity() {
-        let mut orderbook = OrderBook::new(1).await;
+        let mut orderbook = OrderBook::detached(1);
         assert!(orderbook.process(Order {
             id: 1,
             sub_account_id: 1,
@@ -423,4 +771,428 @@ mod test {
             open_at: Utc::now().naive_utc(),
         }));
     }
-}
\ No newline at end of file
+
+    #[async_std::test]
+    async fn cancel_and_amend() {
+        let mut orderbook = OrderBook::detached(1);
+        let order = Order {
+            id: 1,
+            sub_account_id: 1,
+            price: Some(10.0),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+        assert!(orderbook.process_command(Command::New(order.clone())));
+        assert!(orderbook.process_command(Command::Amend(Order { size: 5.0, ..order.clone() })));
+        assert_eq!(orderbook.bids.peek().unwrap().size, 5.0);
+        assert!(orderbook.process_command(Command::Amend(Order { price: Some(11.0), ..order.clone() })));
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.bids.peek().unwrap().price, Some(11.0));
+        let remaining_size = orderbook.bids.peek().unwrap().size;
+        orderbook.outbox.clear();
+        assert!(orderbook.process_command(Command::Cancel(order.clone())));
+        assert!(!orderbook.process_command(Command::Cancel(order)));
+        assert!(orderbook.bids.peek().is_none());
+
+        // Report the cancelled order once, which closes it in the database
+        let cancelled: Vec<(i32, f32)> = orderbook.outbox.drain(..).filter_map(|output| match output {
+            Output::Cancelled(cancelled) => Some((cancelled.order_id, cancelled.remaining_size)),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![(1, remaining_size)]);
+    }
+
+    #[async_std::test]
+    async fn market_status() {
+        let mut orderbook = OrderBook::detached(1);
+        let order = |id: i32, side: OrderSide, price: Option<f32>| Order {
+            id,
+            sub_account_id: 1,
+            price,
+            size: 10.0,
+            side,
+            r#type: if price.is_some() { OrderType::Limit } else { OrderType::Market },
+            open_at: Utc::now().naive_utc(),
+        };
+        let change = |status: MarketStatus, cancel_resting: bool| StatusChange {
+            market_id: 1,
+            status,
+            cancel_resting,
+            changed_at: Utc::now().naive_utc(),
+        };
+
+        // Collect crossing limit orders without matching them before the open
+        assert!(orderbook.process_status(change(MarketStatus::PreOpen, false)));
+        assert!(orderbook.process_command(Command::New(order(1, OrderSide::Bid, Some(11.0)))));
+        assert!(orderbook.process_command(Command::New(order(2, OrderSide::Ask, Some(10.0)))));
+        assert!(!orderbook.process_command(Command::New(order(3, OrderSide::Ask, None))));
+        assert_eq!(orderbook.spread(), Some((11.0, 10.0)));
+        assert!(matches!(orderbook.outbox.last(), Some(Output::Cancelled(cancelled)) if cancelled.order_id == 3));
+
+        // Uncross on the open
+        assert!(orderbook.process_status(change(MarketStatus::Continuous, false)));
+        assert_eq!(orderbook.spread(), None);
+
+        // Halt while keeping resting orders, which may still be cancelled
+        assert!(orderbook.process_command(Command::New(order(4, OrderSide::Bid, Some(9.0)))));
+        assert!(orderbook.process_command(Command::New(order(5, OrderSide::Ask, Some(12.0)))));
+        assert!(orderbook.process_status(change(MarketStatus::Halted, false)));
+        assert_eq!(orderbook.spread(), Some((9.0, 12.0)));
+        assert!(!orderbook.process_command(Command::New(order(6, OrderSide::Ask, Some(12.0)))));
+        assert!(matches!(orderbook.outbox.last(), Some(Output::Cancelled(cancelled)) if cancelled.order_id == 6));
+        assert!(!orderbook.process_command(Command::Amend(Order { size: 5.0, ..order(4, OrderSide::Bid, Some(9.0)) })));
+        assert!(orderbook.process_command(Command::Cancel(order(4, OrderSide::Bid, Some(9.0)))));
+
+        // Halt while cancelling resting orders, reporting each of them
+        orderbook.outbox.clear();
+        assert!(orderbook.process_status(change(MarketStatus::Halted, true)));
+        assert!(orderbook.asks.peek().is_none());
+        let cancelled: Vec<(i32, f32)> = orderbook.outbox.drain(..).filter_map(|output| match output {
+            Output::Cancelled(cancelled) => Some((cancelled.order_id, cancelled.remaining_size)),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![(5, 10.0)]);
+
+        // Ignore changes of other markets
+        assert!(!orderbook.process_status(StatusChange { market_id: 2, ..change(MarketStatus::Closed, true) }));
+        assert_eq!(orderbook.status, MarketStatus::Halted);
+    }
+
+    #[async_std::test]
+    async fn call_auction() {
+        let mut orderbook = OrderBook::detached(1);
+        let order = |id: i32, side: OrderSide, price: f32, size: f32| Order {
+            id,
+            sub_account_id: 1,
+            price: Some(price),
+            size,
+            side,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+        let change = |status: MarketStatus| StatusChange {
+            market_id: 1,
+            status,
+            cancel_resting: false,
+            changed_at: Utc::now().naive_utc(),
+        };
+
+        // Orders accumulate without matching while the indicative price follows them
+        assert!(orderbook.process_status(change(MarketStatus::Auction)));
+        assert_eq!(orderbook.indicative().price, None);
+        assert!(orderbook.process_command(Command::New(order(1, OrderSide::Bid, 11.0, 5.0))));
+        assert!(orderbook.process_command(Command::New(order(2, OrderSide::Bid, 10.0, 5.0))));
+        assert!(orderbook.process_command(Command::New(order(3, OrderSide::Ask, 9.0, 4.0))));
+        let indicative = orderbook.indicative();
+        assert_eq!((indicative.price, indicative.volume, indicative.imbalance), (Some(11.0), 4.0, 1.0));
+        assert!(orderbook.process_command(Command::New(order(4, OrderSide::Ask, 10.0, 4.0))));
+        assert!(orderbook.process_command(Command::New(order(5, OrderSide::Ask, 12.0, 10.0))));
+        let indicative = orderbook.indicative();
+        assert_eq!((indicative.price, indicative.volume, indicative.imbalance), (Some(10.0), 8.0, 2.0));
+        assert_eq!(orderbook.spread(), Some((11.0, 9.0)));
+
+        // All crossing orders fill at the single clearing price
+        assert!(orderbook.process_status(change(MarketStatus::Continuous)));
+        assert_eq!(orderbook.last_price, Some(10.0));
+        assert_eq!(orderbook.spread(), Some((10.0, 12.0)));
+        assert_eq!(orderbook.bids.peek().map(|bid| (bid.id, bid.size)), Some((2, 2.0)));
+        assert_eq!(orderbook.asks.peek().map(|ask| (ask.id, ask.size)), Some((5, 10.0)));
+    }
+
+    #[async_std::test]
+    async fn circuit_breaker() {
+        let mut orderbook = OrderBook::detached(1);
+        orderbook.circuit_breaker = Some(CircuitBreaker::new(
+            5.0,
+            chrono::Duration::seconds(300),
+            MarketStatus::Auction,
+            chrono::Duration::seconds(60),
+        ));
+        let order = |id: i32, side: OrderSide, price: f32, size: f32| Order {
+            id,
+            sub_account_id: 1,
+            price: Some(price),
+            size,
+            side,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+
+        // Set the reference price
+        assert!(orderbook.process_command(Command::New(order(1, OrderSide::Ask, 100.0, 1.0))));
+        assert!(orderbook.process_command(Command::New(order(2, OrderSide::Bid, 100.0, 1.0))));
+
+        // Fill within the band, then switch to an auction instead of filling beyond it
+        assert!(orderbook.process_command(Command::New(order(3, OrderSide::Ask, 104.0, 1.0))));
+        assert!(orderbook.process_command(Command::New(order(4, OrderSide::Ask, 120.0, 5.0))));
+        assert!(orderbook.process_command(Command::New(order(5, OrderSide::Bid, 130.0, 3.0))));
+        assert_eq!(orderbook.status, MarketStatus::Auction);
+        assert_eq!(orderbook.spread(), Some((130.0, 120.0)));
+        assert_eq!(orderbook.bids.peek().unwrap().size, 2.0);
+
+        // Resume continuous trading after the auction and uncross
+        let resumes_at = orderbook.resumes_at.unwrap();
+        assert!(!orderbook.resume(resumes_at - chrono::Duration::seconds(1)));
+        assert!(orderbook.resume(resumes_at));
+        assert_eq!(orderbook.status, MarketStatus::Continuous);
+        assert_eq!(orderbook.resumes_at, None);
+        assert_eq!(orderbook.last_price, Some(120.0));
+        assert_eq!(orderbook.asks.peek().unwrap().size, 3.0);
+
+        // The auction price is the new reference
+        assert!(orderbook.process_command(Command::New(order(6, OrderSide::Bid, 125.0, 1.0))));
+        assert_eq!(orderbook.asks.peek().unwrap().size, 2.0);
+
+        // Halt instead of an auction once reconfigured, keeping the reference price
+        let change = CircuitBreakerChange {
+            market_id: 1,
+            band_percent: Some(5.0),
+            band_window: 300,
+            band_action: MarketStatus::Halted,
+            auction_duration: 60,
+            changed_at: Utc::now().naive_utc(),
+        };
+        assert!(!orderbook.process_circuit_breaker(CircuitBreakerChange { market_id: 2, ..change.clone() }));
+        assert!(orderbook.process_circuit_breaker(change.clone()));
+        assert!(orderbook.process_command(Command::New(order(7, OrderSide::Ask, 90.0, 1.0))));
+        assert!(orderbook.process_command(Command::New(order(8, OrderSide::Bid, 90.0, 1.0))));
+        assert_eq!(orderbook.status, MarketStatus::Halted);
+        assert_eq!(orderbook.resumes_at, None);
+        assert!(!orderbook.resume(Utc::now().naive_utc()));
+
+        // Uncross the orders resting through the halt once it is lifted
+        assert!(orderbook.process_status(StatusChange {
+            market_id: 1,
+            status: MarketStatus::Continuous,
+            cancel_resting: false,
+            changed_at: Utc::now().naive_utc(),
+        }));
+        assert_eq!(orderbook.last_price, Some(90.0));
+        assert_eq!(orderbook.spread(), None); // The bid filled against the ask at 90 rather than crossing it
+        assert_eq!(orderbook.asks.peek().unwrap().price, Some(120.0));
+
+        // Report the remainder of a market order which would breach the band
+        orderbook.outbox.clear();
+        let market = Order { price: None, r#type: OrderType::Market, ..order(9, OrderSide::Bid, 0.0, 1.0) };
+        assert!(orderbook.process_command(Command::New(market)));
+        assert_eq!(orderbook.status, MarketStatus::Halted);
+        let cancelled: Vec<(i32, f32)> = orderbook.outbox.drain(..).filter_map(|output| match output {
+            Output::Cancelled(cancelled) => Some((cancelled.order_id, cancelled.remaining_size)),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![(9, 1.0)]);
+
+        // Disable the circuit breaker
+        assert!(orderbook.process_circuit_breaker(CircuitBreakerChange { band_percent: None, ..change }));
+        assert!(orderbook.circuit_breaker.is_none());
+    }
+
+    #[async_std::test]
+    async fn mass_cancel() {
+        let mut orderbook = OrderBook::detached(1);
+        let order = |id: i32, sub_account_id: i32, side: OrderSide, price: f32| Order {
+            id,
+            sub_account_id,
+            price: Some(price),
+            size: 10.0,
+            side,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+        assert!(orderbook.process_command(Command::New(order(1, 1, OrderSide::Bid, 9.0))));
+        assert!(orderbook.process_command(Command::New(order(2, 2, OrderSide::Bid, 8.0))));
+        assert!(orderbook.process_command(Command::New(order(3, 1, OrderSide::Ask, 11.0))));
+        assert!(orderbook.process_command(Command::New(order(4, 2, OrderSide::Ask, 12.0))));
+        assert!(orderbook.process_command(Command::New(order(5, 2, OrderSide::Bid, 7.0))));
+        orderbook.outbox.clear();
+
+        // Pull the orders of a sub-account on both sides at once, also while halted
+        assert!(orderbook.process_status(StatusChange {
+            market_id: 1,
+            status: MarketStatus::Halted,
+            cancel_resting: false,
+            changed_at: Utc::now().naive_utc(),
+        }));
+        let mass_cancel = |market_id: Option<i32>, sub_account_ids: Vec<i32>, side: Option<OrderSide>| {
+            MassCancel { market_id, sub_account_ids, side }
+        };
+        assert!(orderbook.process_mass_cancel(mass_cancel(None, vec![1, 99], None)));
+        assert_eq!(orderbook.spread(), Some((8.0, 12.0)));
+        let cancelled: Vec<i32> = orderbook.outbox.drain(..).filter_map(|output| match output {
+            Output::Cancelled(cancelled) => Some(cancelled.order_id),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![1, 3]); // Every pulled order is reported
+        assert!(!orderbook.process_mass_cancel(mass_cancel(None, vec![1], None)));
+
+        // Only in this market and on one side
+        assert!(!orderbook.process_mass_cancel(mass_cancel(Some(2), vec![2], None)));
+        assert!(orderbook.process_mass_cancel(mass_cancel(Some(1), vec![2], Some(OrderSide::Buy))));
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.asks.peek().unwrap().id, 4);
+    }
+
+    #[test]
+    fn decode() {
+        let order = Order {
+            id: 1,
+            sub_account_id: 1,
+            price: Some(10.0),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+        assert!(matches!(Input::decode(&Command::New(order).encode()), Some(Input::Command(Command::New(_)))));
+        let cancelled = Cancelled {
+            order_id: 1,
+            sub_account_id: 1,
+            market_id: 1,
+            remaining_size: 10.0,
+            cancelled_at: Utc::now().naive_utc(),
+        };
+        assert!(Input::decode(&cancelled.encode()).is_none()); // Not misread as a command
+    }
+
+    #[async_std::test]
+    async fn top_of_book() {
+        let mut orderbook = OrderBook::detached(1);
+        let order = |id: i32, side: OrderSide, price: f32| Order {
+            id,
+            sub_account_id: 1,
+            price: Some(price),
+            size: 10.0,
+            side,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        };
+        assert!(!orderbook.publish_top_of_book());
+
+        // Broadcast only when the best prices change
+        orderbook.process_command(Command::New(order(1, OrderSide::Bid, 9.0)));
+        assert!(orderbook.publish_top_of_book());
+        assert_eq!(orderbook.quoted, (Some(9.0), None));
+        orderbook.process_command(Command::New(order(2, OrderSide::Bid, 8.0)));
+        assert!(!orderbook.publish_top_of_book());
+        orderbook.process_command(Command::New(order(3, OrderSide::Ask, 11.0)));
+        assert!(orderbook.publish_top_of_book());
+        assert_eq!(orderbook.quoted, (Some(9.0), Some(11.0)));
+        orderbook.process_command(Command::Cancel(order(1, OrderSide::Bid, 9.0)));
+        assert!(orderbook.publish_top_of_book());
+        assert_eq!(orderbook.quoted, (Some(8.0), Some(11.0)));
+    }
+
+    #[async_std::test]
+    async fn capture_and_replay() {
+        let path = std::env::temp_dir().join("orderbook_capture_test.rxcap");
+        let order = |id: i32, side: OrderSide, r#type: OrderType, price: Option<f32>| Order {
+            id,
+            sub_account_id: id,
+            price,
+            size: 10.0,
+            side,
+            r#type,
+            open_at: Utc::now().naive_utc(),
+        };
+        let inputs = [
+            Command::New(order(1, OrderSide::Bid, OrderType::Limit, Some(9.0))).encode(),
+            Command::New(order(2, OrderSide::Ask, OrderType::Limit, Some(11.0))).encode(),
+            Command::New(order(3, OrderSide::Ask, OrderType::Limit, Some(9.0))).encode(),
+            Command::New(order(4, OrderSide::Buy, OrderType::Market, None)).encode(),
+        ];
+        let transport = Arc::new(protocol::InMemory::new());
+        let mut orderbook = OrderBook::new(1, transport.clone()).await;
+        orderbook.record_to(&path).unwrap();
+        for input in inputs {
+            transport.publish(Channel::Orders, input).await.unwrap();
+        }
+        transport.close().await; // The engine consumes the published orders and stops
+        orderbook.run().await;
+        drop(orderbook); // Finish the file
+
+        // Inputs and outputs are recorded in order
+        let events: Vec<Event> = Recording::open(&path).unwrap().map(Result::unwrap).collect();
+        let channels: Vec<Channel> = events.iter().map(|event| event.channel).collect();
+        assert_eq!(channels.iter().filter(|channel| **channel == Channel::Orders).count(), 5); // Including the status
+        assert_eq!(channels.iter().filter(|channel| **channel == Channel::Fills).count(), 4);
+        assert!(events.windows(2).all(|events| events[0].timestamp <= events[1].timestamp));
+
+        // A fresh book reproduces the fills
+        let report = replay(&mut OrderBook::detached(1), Recording::open(&path).unwrap(), None).await.unwrap();
+        assert_eq!(report, Report { inputs: 5, recorded: 4, replayed: 4, mismatch: None });
+        assert!(report.matches());
+
+        // A book in a different state does not
+        let mut orderbook = OrderBook::detached(1);
+        orderbook.process_command(Command::New(order(5, OrderSide::Bid, OrderType::Limit, Some(10.0))));
+        let report = replay(&mut orderbook, Recording::open(&path).unwrap(), None).await.unwrap();
+        assert_eq!(report.mismatch, Some(0));
+        assert!(!report.matches());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn execute_in_process() {
+        let mut orderbook = OrderBook::detached(1);
+        let now = Utc::now().naive_utc();
+        let order = |id: i32, side: OrderSide, r#type: OrderType, price: Option<f32>, size: f32| Order {
+            id,
+            sub_account_id: 1,
+            price,
+            size,
+            side,
+            r#type,
+            open_at: now + chrono::Duration::nanoseconds(id.into()),
+        };
+        assert!(orderbook.execute(Command::New(order(1, OrderSide::Bid, OrderType::Limit, Some(9.0), 5.0)), now).is_empty());
+        assert!(orderbook.execute(Command::New(order(2, OrderSide::Bid, OrderType::Limit, Some(9.0), 5.0)), now).is_empty());
+        assert!(orderbook.execute(Command::New(order(3, OrderSide::Bid, OrderType::Limit, Some(8.0), 5.0)), now).is_empty());
+        assert_eq!(orderbook.top_of_book(), (Some(9.0), None));
+        assert_eq!(orderbook.depth(5), (vec![(9.0, 10.0), (8.0, 5.0)], vec![]));
+        assert_eq!(orderbook.depth(1), (vec![(9.0, 10.0)], vec![]));
+
+        // Fills of both sides are returned, stamped with the given time
+        let fills = orderbook.execute(Command::New(order(4, OrderSide::Sell, OrderType::Market, None, 7.0)), now);
+        assert_eq!(fills.iter().map(|fill| (fill.order_id, fill.size)).collect::<Vec<_>>(), [(1, 5.0), (4, 5.0), (2, 2.0), (4, 2.0)]);
+        assert!(fills.iter().all(|fill| fill.created_at == now));
+        assert_eq!(orderbook.depth(5), (vec![(9.0, 3.0), (8.0, 5.0)], vec![]));
+        assert!(orderbook.outbox.is_empty());
+    }
+
+    #[async_std::test]
+    async fn run_over_transport() {
+        let transport = Arc::new(protocol::InMemory::new());
+        let mut orderbook = OrderBook::new(1, transport.clone()).await;
+        let order = |id: i32, side: OrderSide, r#type: OrderType, price: Option<f32>| Order {
+            id,
+            sub_account_id: 1,
+            price,
+            size: 5.0,
+            side,
+            r#type,
+            open_at: Utc::now().naive_utc(),
+        };
+        let fills = transport.subscribe(Channel::Fills, Offset::Next).await.unwrap();
+        for command in [
+            Command::New(order(1, OrderSide::Ask, OrderType::Limit, Some(10.0))),
+            Command::New(order(2, OrderSide::Buy, OrderType::Market, None)),
+        ] {
+            transport.publish(Channel::Orders, command.encode()).await.unwrap();
+        }
+        let consume = async {
+            let fills: Vec<Fill> = fills.take(2).map(|data| Fill::decode(&data).unwrap()).collect().await;
+            transport.close().await; // Ends the subscription of the engine
+            fills
+        };
+        let ((), fills) = futures::join!(orderbook.run(), consume);
+        assert_eq!(fills.iter().map(|fill| (fill.order_id, fill.price, fill.size)).collect::<Vec<_>>(), [(1, 10.0, 5.0), (2, 10.0, 5.0)]);
+        let market_data = transport.subscribe(Channel::MarketData, Offset::First).await.unwrap();
+        let tops: Vec<(Option<f32>, Option<f32>)> = market_data
+            .map(|data| TopOfBook::decode(&data).map(|top| (top.bid, top.ask)).unwrap())
+            .collect()
+            .await;
+        assert_eq!(tops, [(None, Some(10.0)), (None, None)]);
+    }
+}
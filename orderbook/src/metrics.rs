@@ -0,0 +1,129 @@
+use std::io;
+use std::sync::LazyLock;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use prometheus::{register_gauge_vec, register_int_counter_vec, register_int_gauge_vec};
+use prometheus::{Encoder, GaugeVec, IntCounterVec, IntGaugeVec, TextEncoder};
+
+use crate::OrderBook;
+
+// ----------------------------------------------------------------------
+
+const MAX_REQUEST: usize = 8192; // Bytes of the headers of a request to the admin port
+
+pub(crate) static MATCHES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("engine_matches_total", "Trades matched by the engine, by market.", &["market"]).unwrap()
+});
+
+pub(crate) static PUBLISH_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let help = "Messages the engine failed to publish, by market and stream.";
+    register_int_counter_vec!("engine_publish_failures_total", help, &["market", "stream"]).unwrap()
+});
+
+pub(crate) static RECORD_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    let help = "Messages the engine failed to record, by market and stream.";
+    register_int_counter_vec!("engine_record_failures_total", help, &["market", "stream"]).unwrap()
+});
+
+static BOOK_SIZE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!("engine_book_size", "Resting size in the book, by market and side.", &["market", "side"]).unwrap()
+});
+
+static BOOK_ORDERS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!("engine_book_orders", "Resting orders in the book, by market and side.", &["market", "side"])
+        .unwrap()
+});
+
+impl OrderBook {
+    /// Sets the depth gauges of the market to the current contents of the book.
+    pub(crate) fn sample_depth(&self) {
+        let market = self.id.to_string();
+        for (side, queue) in [("bid", &self.bids), ("ask", &self.asks)] {
+            let (orders, size) = queue
+                .orders()
+                .fold((0, 0.0), |(orders, size), order| (orders + 1, size + order.size as f64));
+            BOOK_ORDERS.with_label_values(&[&market, side]).set(orders);
+            BOOK_SIZE.with_label_values(&[&market, side]).set(size);
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// The metrics of the process in the text format of Prometheus.
+fn render() -> String {
+    let mut text = Vec::new();
+    let _ = TextEncoder::new().encode(&prometheus::gather(), &mut text);
+    String::from_utf8(text).unwrap_or_default()
+}
+
+/// Serves the metrics of the process at `/metrics` to Prometheus, for the engine has no HTTP server of its own.
+pub async fn serve_metrics(listener: TcpListener) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        task::spawn(async move {
+            let _ = respond(stream).await; // Scrapes which fail are retried by Prometheus
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        match stream.read(&mut buffer).await? {
+            0 => return Ok(()),
+            read => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let (status, body) = if request.starts_with(b"GET /metrics ") {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", String::new())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len(),
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use database::orders::{Command, Order};
+    use database::{OrderSide, OrderType};
+
+    #[async_std::test]
+    async fn scrape() {
+        let mut orderbook = OrderBook::detached(1001);
+        let now = Utc::now().naive_utc();
+        for (id, side, price) in [(1, OrderSide::Bid, 9.0), (2, OrderSide::Bid, 8.0), (3, OrderSide::Ask, 11.0)] {
+            let r#type = OrderType::Limit;
+            let order = Order { id, sub_account_id: 1, price: Some(price), size: 2.0, side, r#type, open_at: now };
+            orderbook.execute(Command::New(order), now);
+        }
+        orderbook.sample_depth();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        task::spawn(serve_metrics(listener));
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(r#"engine_book_orders{market="1001",side="bid"} 2"#));
+        assert!(response.contains(r#"engine_book_size{market="1001",side="ask"} 2"#));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use crossbeam_utils::Backoff;
+use futures::StreamExt;
+use prometheus::IntCounter;
+
+use database::orders::{Cancelled, Command, MassCancel};
+use database::{circuit_breakers, DatabaseConnection, MarketStatus, Mutation, OrderSide};
+use database::fills::Fill;
+use database::markets::{CircuitBreakerChange, Indicative, StatusChange, TopOfBook};
+use protocol::{Codec, Offset, Transport};
+use protocol::codec::Header;
+use protocol::transport::Channel;
+
+use crate::capture::{timestamp, Recorder};
+use crate::metrics::{MATCHES, PUBLISH_FAILURES, RECORD_FAILURES};
+use crate::ring::{Ring, Unpark};
+use crate::{OrderBook, RESUME_INTERVAL};
+
+// ----------------------------------------------------------------------
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1); // How often to measure the depth of the book
+const MAX_FEED_DELAY: Duration = Duration::from_millis(1); // Longest wait for room in the input ring between attempts
+
+/// A message of the orders stream, decoded before it reaches the order book.
+pub(crate) enum Input {
+    Command(Command),
+    StatusChange(StatusChange),
+    MassCancel(MassCancel),
+    CircuitBreakerChange(CircuitBreakerChange),
+}
+
+impl Input {
+    pub(crate) fn decode(data: &[u8]) -> Option<Self> {
+        let header = Header::decode(data).ok()??; // Other inputs share the orders stream with commands
+        match header.template_id {
+            Command::TEMPLATE_ID => header.decode_message(data).ok().map(Input::Command),
+            StatusChange::TEMPLATE_ID => header.decode_message(data).ok().map(Input::StatusChange),
+            MassCancel::TEMPLATE_ID => header.decode_message(data).ok().map(Input::MassCancel),
+            CircuitBreakerChange::TEMPLATE_ID => header.decode_message(data).ok().map(Input::CircuitBreakerChange),
+            _ => None, // Skipped rather than misread as a command
+        }
+    }
+}
+
+/// A message produced by the order book, encoded and published by the publisher.
+#[derive(Clone, Debug)]
+pub(crate) enum Output {
+    Fill(Fill),
+    Cancelled(Cancelled),
+    TopOfBook(TopOfBook),
+    StatusChange(StatusChange),
+    Indicative(Indicative),
+    CircuitBreaker(circuit_breakers::Model), // Persisted before it is broadcast, which assigns its id
+    Persist(MarketStatus), // The status of the market changed by the engine, persisted without being broadcast
+}
+
+impl Output {
+    fn encode(&self) -> Option<(Channel, Vec<u8>)> {
+        match self {
+            Output::Fill(fill) => Some((Channel::Fills, fill.encode())),
+            Output::Cancelled(cancelled) => Some((Channel::Fills, cancelled.encode())),
+            Output::TopOfBook(top_of_book) => Some((Channel::MarketData, top_of_book.encode())),
+            Output::StatusChange(change) => Some((Channel::MarketData, change.encode())),
+            Output::Indicative(indicative) => Some((Channel::MarketData, indicative.encode())),
+            Output::CircuitBreaker(event) => Some((Channel::MarketData, event.encode())),
+            Output::Persist(_) => None,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Publishes what the order book produced, off the matching thread. Persisting and publishing wait on the database
+/// and the broker, which only delays the messages behind them rather than the matching.
+struct Publisher {
+    market_id: i32,
+    transport: Arc<dyn Transport>,
+    db: Option<DatabaseConnection>,
+    recorder: Option<Arc<Mutex<Recorder>>>,
+    matches: IntCounter,
+}
+
+impl Publisher {
+    async fn publish(&self, output: Output) {
+        if let Output::Fill(fill) = &output {
+            if matches!(fill.side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long) { // One of the two fills of a match
+                self.matches.inc();
+            }
+        }
+        let output = match (output, &self.db) {
+            (Output::CircuitBreaker(event), Some(db)) => match Mutation::create_circuit_breaker(db, event).await {
+                Ok(created) => Output::CircuitBreaker(created),
+                Err(e) => { // Without an id, which subscribers could not tell apart from the others
+                    tracing::error!("Failed to persist circuit breaker of market {}: {e}", self.market_id);
+                    return;
+                }
+            },
+            (Output::Persist(status), Some(db)) => {
+                if let Err(e) = Mutation::update_market_status(db, self.market_id, status, false).await {
+                    tracing::error!("Failed to persist status {status:?} of market {}: {e}", self.market_id);
+                }
+                return;
+            }
+            (output, _) => output,
+        };
+        let Some((channel, data)) = output.encode() else {
+            return;
+        };
+        self.record(channel, &data);
+        if let Err(e) = self.transport.publish(channel, data).await { // Later messages are still published
+            tracing::error!("Failed to publish to the {channel:?} stream of market {}: {e}", self.market_id);
+            PUBLISH_FAILURES.with_label_values(&[&self.market_id.to_string(), stream(channel)]).inc();
+        }
+    }
+
+    fn record(&self, channel: Channel, data: &[u8]) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        let mut recorder = recorder.lock().unwrap(); // Before taking the time, so that timestamps never decrease
+        if let Err(e) = recorder.record(timestamp(Utc::now().naive_utc()), channel, data) {
+            tracing::error!("Failed to record a message of the {channel:?} stream of market {}: {e}", self.market_id);
+            RECORD_FAILURES.with_label_values(&[&self.market_id.to_string(), stream(channel)]).inc();
+        }
+    }
+}
+
+/// The name of a stream in the labels of metrics.
+fn stream(channel: Channel) -> &'static str {
+    match channel {
+        Channel::Orders => "orders",
+        Channel::Fills => "fills",
+        Channel::MarketData => "market_data",
+    }
+}
+
+// ----------------------------------------------------------------------
+
+impl OrderBook {
+    /// Processes the orders stream of the transport from its first message until the subscription ends.
+    ///
+    /// The order book moves to a thread of its own which reads decoded messages from a preallocated ring, and writes
+    /// what it produces to a second ring which a publisher drains concurrently. Decoding, recording, persisting and
+    /// publishing all happen outside of that thread, so matching never waits on I/O.
+    pub async fn run(&mut self) {
+        let Some(transport) = self.transport.clone() else {
+            return; // Detached order books have nothing to consume
+        };
+        let mut orders = transport.subscribe(Channel::Orders, Offset::First).await.unwrap();
+        let publisher = Publisher {
+            market_id: self.id,
+            transport,
+            db: self.db.take(),
+            recorder: self.recorder.take().map(|recorder| Arc::new(Mutex::new(recorder))),
+            matches: MATCHES.with_label_values(&[&self.id.to_string()]),
+        };
+        let settings = &config::get().engine;
+        let input = Arc::new(Ring::new(settings.input_capacity));
+        let output = Arc::new(Ring::new(settings.output_capacity));
+        let orderbook = std::mem::replace(self, OrderBook::detached(self.id));
+        let engine = thread::Builder::new()
+            .name("matching-engine".to_owned())
+            .spawn({
+                let (input, output) = (input.clone(), output.clone());
+                move || orderbook.consume(&input, &output)
+            })
+            .unwrap();
+
+        let feed = async {
+            while let Some(data) = orders.next().await {
+                publisher.record(Channel::Orders, &data);
+                let Some(mut message) = Input::decode(&data) else {
+                    continue;
+                };
+                let mut delay = Duration::from_micros(10);
+                while let Err(full) = input.push(message) { // Wait for the engine to catch up, without spinning
+                    message = full;
+                    async_std::task::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_FEED_DELAY);
+                }
+            }
+            input.close();
+        };
+        let publish = async {
+            loop {
+                match async_std::future::timeout(RESUME_INTERVAL, output.next()).await {
+                    Ok(Some(message)) => publisher.publish(message).await,
+                    Ok(None) => break,
+                    Err(_) => { // Persist the recording while idle
+                        if let Some(recorder) = &publisher.recorder {
+                            let _ = recorder.lock().unwrap().flush();
+                        }
+                    }
+                }
+            }
+        };
+        futures::join!(feed, publish);
+
+        *self = engine.join().unwrap(); // Finished once the publisher is
+        self.db = publisher.db;
+        self.recorder = publisher.recorder.and_then(|recorder| Arc::into_inner(recorder)?.into_inner().ok());
+    }
+
+    /// Processes the input until it is finished, resuming volatility auctions as they end and measuring the depth of
+    /// the book now and then. Runs on the thread of the engine, and hands the order book back once done.
+    fn consume(mut self, input: &Ring<Input>, output: &Ring<Output>) -> Self {
+        let waker = Unpark::current();
+        let backoff = Backoff::new();
+        let mut sampled = Instant::now();
+        loop {
+            if sampled.elapsed() >= SAMPLE_INTERVAL {
+                self.sample_depth();
+                sampled = Instant::now();
+            }
+            if self.resume(self.now()) {
+                self.publish_top_of_book();
+                self.forward(output);
+            }
+            if let Some(message) = input.pop() {
+                self.apply(message);
+                self.forward(output);
+                backoff.reset();
+            } else if input.is_finished() {
+                self.sample_depth();
+                break;
+            } else if backoff.is_completed() {
+                input.park(&waker, RESUME_INTERVAL); // Idle, but wake up to resume auctions on time
+            } else {
+                backoff.snooze();
+            }
+        }
+        output.close();
+        self
+    }
+
+    /// Moves the messages produced by the last input to the output ring, waiting for room if it is full.
+    fn forward(&mut self, output: &Ring<Output>) {
+        let backoff = Backoff::new();
+        for mut message in self.outbox.drain(..) {
+            while let Err(full) = output.push(message) {
+                message = full;
+                backoff.snooze();
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use chrono::{NaiveDateTime};
 use database::orders::Order;
 use database::OrderSide;
@@ -9,6 +9,7 @@ struct OrderIndex {
     id: i32,
     price: f32,
     timestamp: NaiveDateTime,
+    sequence: u64, // Arrival in the queue, which breaks ties of orders opened at the same time
     side: OrderSide,
 }
 
@@ -25,7 +26,7 @@ impl Ord for OrderIndex {
                 OrderSide::Sell | OrderSide::Ask | OrderSide::Short => Ordering::Less,
             }
         } else {
-            other.timestamp.cmp(&self.timestamp)
+            other.timestamp.cmp(&self.timestamp).then(other.sequence.cmp(&self.sequence))
         }
     }
 }
@@ -41,7 +42,7 @@ impl PartialEq for OrderIndex {
         if self.price > other.price || self.price < other.price {
             false
         } else {
-            self.timestamp == other.timestamp
+            self.timestamp == other.timestamp && self.sequence == other.sequence
         }
     }
 }
@@ -51,6 +52,7 @@ impl Eq for OrderIndex {}
 pub struct Queue {
     idx_queue: BinaryHeap<OrderIndex>, // Use Option in order to replace heap in mutable borrow
     orders: HashMap<i32, Order>,
+    sequence: u64, // Number of orders inserted so far
 }
 
 impl Queue {
@@ -58,6 +60,7 @@ impl Queue {
         Queue {
             idx_queue: BinaryHeap::with_capacity(capacity),
             orders: HashMap::with_capacity(capacity),
+            sequence: 0,
         }
     }
 
@@ -81,6 +84,14 @@ impl Queue {
         }
     }
 
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Order> {
+        self.orders.get(&id)
+    }
+
     pub fn insert(&mut self, order: Order) -> bool {
         if self.orders.contains_key(&order.id) {
             return false;
@@ -89,20 +100,44 @@ impl Queue {
             id: order.id,
             price: order.price.unwrap(),
             timestamp: order.open_at,
+            sequence: self.sequence,
             side: order.side.clone(),
         });
         self.orders.insert(order.id, order);
+        self.sequence += 1;
         true
     }
 
-    pub fn cancel(&mut self, id: i32) -> bool {
-        match self.orders.remove(&id) {
-            Some(_) => {
-                self.idx_queue.retain(|o| o.id != id);
-                true
-            },
-            None => false
+    pub fn cancel(&mut self, id: i32) -> Option<Order> {
+        let order = self.orders.remove(&id)?;
+        self.idx_queue.retain(|o| o.id != id);
+        Some(order)
+    }
+
+    /// Cancels every order of one of the sub-accounts in a single pass. Appends the cancelled orders by id, so that the
+    /// caller can reuse the buffer.
+    pub fn cancel_many(&mut self, sub_account_ids: &HashSet<i32>, cancelled: &mut Vec<Order>) {
+        let start = cancelled.len();
+        self.orders.retain(|_, order| {
+            let keep = !sub_account_ids.contains(&order.sub_account_id);
+            if !keep {
+                cancelled.push(order.clone());
+            }
+            keep
+        });
+        if cancelled.len() > start {
+            let orders = &self.orders;
+            self.idx_queue.retain(|o| orders.contains_key(&o.id));
         }
+        cancelled[start..].sort_unstable_by_key(|order| order.id); // Report them in a fixed order
+    }
+
+    /// Cancels every order. Appends the cancelled orders by id, so that the caller can reuse the buffer.
+    pub fn clear(&mut self, cancelled: &mut Vec<Order>) {
+        let start = cancelled.len();
+        self.idx_queue.clear();
+        cancelled.extend(self.orders.drain().map(|(_, order)| order));
+        cancelled[start..].sort_unstable_by_key(|order| order.id); // Report them in a fixed order
     }
 
     pub fn amend(&mut self, id: i32, size: f32) -> bool {
@@ -125,149 +160,168 @@ impl Queue {
     }
 }
 
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//     use chrono::Utc;
-//     use database::{OrderSide, OrderType};
-//
-//     fn bids() -> Queue {
-//         let mut queue = Queue::new(10);
-//         assert!(queue.insert(Order {
-//             id: 1,
-//             sub_account_id: 1,
-//             price: Some(1.01),
-//             size: 10.0,
-//             side: OrderSide::Bid,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         assert!(queue.insert(Order {
-//             id: 2,
-//             sub_account_id: 1,
-//             price: Some(1.02),
-//             size: 10.0,
-//             side: OrderSide::Bid,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         assert!(queue.insert(Order {
-//             id: 3,
-//             sub_account_id: 1,
-//             price: Some(1.02),
-//             size: 10.0,
-//             side: OrderSide::Bid,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         queue
-//     }
-//
-//     fn asks() -> Queue {
-//         let mut queue = Queue::new(10);
-//         assert!(queue.insert(Order {
-//             id: 1,
-//             sub_account_id: 1,
-//             price: Some(1.01),
-//             size: 10.0,
-//             side: OrderSide::Ask,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         assert!(queue.insert(Order {
-//             id: 2,
-//             sub_account_id: 1,
-//             price: Some(1.02),
-//             size: 10.0,
-//             side: OrderSide::Ask,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         assert!(queue.insert(Order {
-//             id: 3,
-//             sub_account_id: 1,
-//             price: Some(1.01),
-//             size: 10.0,
-//             side: OrderSide::Ask,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         queue
-//     }
-//
-//     #[test]
-//     fn insert_unique() {
-//         let mut queue = Queue::new(10);
-//         assert!(queue.peek().is_none());
-//         assert!(queue.insert(Order { // Insert unique with success
-//             id: 1,
-//             sub_account_id: 1,
-//             price: Some(1.01),
-//             size: 10.0,
-//             side: OrderSide::Bid,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//         assert!(!queue.insert(Order { // Insert existing with failure
-//             id: 1,
-//             sub_account_id: 1,
-//             price: Some(1.01),
-//             size: 10.0,
-//             side: OrderSide::Bid,
-//             r#type: OrderType::Limit,
-//             open_at: Utc::now().naive_utc(),
-//         }));
-//     }
-//
-//
-//     #[test]
-//     fn bids_ordering() {
-//         let mut queue = bids();
-//         assert_eq!(queue.pop().unwrap().id, 2);
-//         assert_eq!(queue.pop().unwrap().id, 3);
-//         assert_eq!(queue.pop().unwrap().id, 1);
-//     }
-//
-//     #[test]
-//     fn queue_operations_ordering_ask() {
-//         let mut queue = asks();
-//         assert_eq!(queue.pop().unwrap().id, 1);
-//         assert_eq!(queue.pop().unwrap().id, 3);
-//         assert_eq!(queue.pop().unwrap().id, 2);
-//     }
-//
-//     #[test]
-//     fn modify_tob() {
-//         let mut queue = bids();
-//         assert!(queue.modify_tob(5.0));
-//         assert_eq!(queue.pop().unwrap().size, 5.0);
-//     }
-//
-//     #[test]
-//     fn amend() {
-//         let mut queue = asks();
-//         assert!(queue.amend(1,1.0));
-//         assert!(queue.amend(2,2.0));
-//         assert!(queue.amend(3,3.0));
-//
-//         assert_eq!(queue.pop().unwrap().size, 1.0);
-//         assert_eq!(queue.pop().unwrap().size, 3.0);
-//         assert_eq!(queue.pop().unwrap().size, 2.0);
-//     }
-//
-//     #[test]
-//     fn cancel_bid() {
-//         let mut queue = bids();
-//         queue.cancel(1);
-//         assert_eq!(queue.pop().unwrap().id, 2);
-//         assert_eq!(queue.pop().unwrap().id, 3);
-//     }
-//
-//     #[test]
-//     fn cancel_ask() {
-//         let mut queue = asks();
-//         queue.cancel(1);
-//         assert_eq!(queue.pop().unwrap().id, 3);
-//         assert_eq!(queue.pop().unwrap().id, 2);
-//     }
-// }
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use database::{OrderSide, OrderType};
+
+    fn bids() -> Queue {
+        let mut queue = Queue::new(10);
+        assert!(queue.insert(Order {
+            id: 1,
+            sub_account_id: 1,
+            price: Some(1.01),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        assert!(queue.insert(Order {
+            id: 2,
+            sub_account_id: 1,
+            price: Some(1.02),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        assert!(queue.insert(Order {
+            id: 3,
+            sub_account_id: 1,
+            price: Some(1.02),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        queue
+    }
+
+    fn asks() -> Queue {
+        let mut queue = Queue::new(10);
+        assert!(queue.insert(Order {
+            id: 1,
+            sub_account_id: 1,
+            price: Some(1.01),
+            size: 10.0,
+            side: OrderSide::Ask,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        assert!(queue.insert(Order {
+            id: 2,
+            sub_account_id: 1,
+            price: Some(1.02),
+            size: 10.0,
+            side: OrderSide::Ask,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        assert!(queue.insert(Order {
+            id: 3,
+            sub_account_id: 1,
+            price: Some(1.01),
+            size: 10.0,
+            side: OrderSide::Ask,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        queue
+    }
+
+    #[test]
+    fn insert_unique() {
+        let mut queue = Queue::new(10);
+        assert!(queue.peek().is_none());
+        assert!(queue.insert(Order { // Insert unique with success
+            id: 1,
+            sub_account_id: 1,
+            price: Some(1.01),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+        assert!(!queue.insert(Order { // Insert existing with failure
+            id: 1,
+            sub_account_id: 1,
+            price: Some(1.01),
+            size: 10.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }));
+    }
+
+
+    #[test]
+    fn bids_ordering() {
+        let mut queue = bids();
+        assert_eq!(queue.pop().unwrap().id, 2);
+        assert_eq!(queue.pop().unwrap().id, 3);
+        assert_eq!(queue.pop().unwrap().id, 1);
+    }
+
+    #[test]
+    fn queue_operations_ordering_ask() {
+        let mut queue = asks();
+        assert_eq!(queue.pop().unwrap().id, 1);
+        assert_eq!(queue.pop().unwrap().id, 3);
+        assert_eq!(queue.pop().unwrap().id, 2);
+    }
+
+    #[test]
+    fn same_timestamp_ordering() {
+        let mut queue = Queue::new(10);
+        let open_at = Utc::now().naive_utc();
+        for id in [3, 1, 4, 2] {
+            assert!(queue.insert(Order {
+                id,
+                sub_account_id: 1,
+                price: Some(1.01),
+                size: 10.0,
+                side: OrderSide::Ask,
+                r#type: OrderType::Limit,
+                open_at,
+            }));
+        }
+        let ids: Vec<i32> = std::iter::from_fn(|| queue.pop()).map(|order| order.id).collect();
+        assert_eq!(ids, [3, 1, 4, 2]); // First in, first out
+    }
+
+    #[test]
+    fn modify_tob() {
+        let mut queue = bids();
+        assert!(queue.modify_tob(5.0));
+        assert_eq!(queue.pop().unwrap().size, 5.0);
+    }
+
+    #[test]
+    fn amend() {
+        let mut queue = asks();
+        assert!(queue.amend(1,1.0));
+        assert!(queue.amend(2,2.0));
+        assert!(queue.amend(3,3.0));
+
+        assert_eq!(queue.pop().unwrap().size, 1.0);
+        assert_eq!(queue.pop().unwrap().size, 3.0);
+        assert_eq!(queue.pop().unwrap().size, 2.0);
+    }
+
+    #[test]
+    fn cancel_bid() {
+        let mut queue = bids();
+        queue.cancel(1);
+        assert_eq!(queue.pop().unwrap().id, 2);
+        assert_eq!(queue.pop().unwrap().id, 3);
+    }
+
+    #[test]
+    fn cancel_ask() {
+        let mut queue = asks();
+        queue.cancel(1);
+        assert_eq!(queue.pop().unwrap().id, 3);
+        assert_eq!(queue.pop().unwrap().id, 2);
+    }
+}
@@ -0,0 +1,279 @@
+use database::orders::{Command, Order};
+use database::{OrderSide, OrderType};
+use crate::Level;
+
+// ----------------------------------------------------------------------
+
+/// A deliberately naive matching engine to check the order book against. Resting orders are kept in a single list in
+/// order of arrival, and every match scans the whole list for the best contra order, so price-time priority holds
+/// by construction rather than by the ordering of a heap.
+#[derive(Default)]
+pub struct Reference {
+    resting: Vec<Order>, // Earliest first
+}
+
+pub type Execution = (i32, f32, f32); // Order id, price and size of a fill
+
+fn is_buy(side: &OrderSide) -> bool {
+    matches!(side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long)
+}
+
+impl Reference {
+    /// Processes a command in continuous trading and returns the fills of the resting and the incoming order of every
+    /// match, in the order the order book publishes them.
+    pub fn execute(&mut self, command: Command) -> Vec<Execution> {
+        let mut executions = Vec::new();
+        match command {
+            Command::New(order) => self.process(order, &mut executions),
+            Command::Cancel(order) => {
+                self.remove(&order);
+            }
+            Command::Amend(order) => {
+                if let Some(position) = self.position(&order) {
+                    let resting = &mut self.resting[position];
+                    if resting.price == order.price && order.size <= resting.size {
+                        resting.size = order.size;
+                    } else {
+                        self.resting.remove(position);
+                        self.process(Order { r#type: OrderType::Limit, ..order }, &mut executions);
+                    }
+                }
+            }
+        }
+        executions
+    }
+
+    fn process(&mut self, mut order: Order, executions: &mut Vec<Execution>) {
+        while order.size > 0.0 {
+            let Some(position) = self.best_contra(&order) else {
+                if order.r#type == OrderType::Limit {
+                    self.resting.push(order);
+                }
+                return; // The remainder of market orders is not filled
+            };
+            let contra = &mut self.resting[position];
+            let (price, size) = (contra.price.unwrap(), f32::min(order.size, contra.size));
+            executions.push((contra.id, price, size));
+            executions.push((order.id, price, size));
+            contra.size -= size;
+            order.size -= size;
+            if contra.size == 0.0 {
+                self.resting.remove(position);
+            }
+        }
+    }
+
+    /// The earliest resting order at the best price which the order may match with.
+    fn best_contra(&self, order: &Order) -> Option<usize> {
+        let buy = is_buy(&order.side);
+        self.resting
+            .iter()
+            .enumerate()
+            .filter(|(_, resting)| is_buy(&resting.side) != buy)
+            .filter(|(_, resting)| match order.price {
+                Some(price) if buy => price >= resting.price.unwrap(),
+                Some(price) => price <= resting.price.unwrap(),
+                None => true,
+            })
+            .min_by(|(_, a), (_, b)| { // The first of equal elements is kept
+                let (a, b) = (a.price.unwrap(), b.price.unwrap());
+                if buy { a.total_cmp(&b) } else { b.total_cmp(&a) }
+            })
+            .map(|(position, _)| position)
+    }
+
+    /// Orders are looked up on the side of the command, like the order book keeps a queue per side.
+    fn position(&self, order: &Order) -> Option<usize> {
+        self.resting
+            .iter()
+            .position(|resting| resting.id == order.id && is_buy(&resting.side) == is_buy(&order.side))
+    }
+
+    fn remove(&mut self, order: &Order) -> Option<Order> {
+        self.position(order).map(|position| self.resting.remove(position))
+    }
+
+    /// Resting size aggregated per price level, best first, in the format of `OrderBook::depth`.
+    pub fn depth(&self) -> (Vec<Level>, Vec<Level>) {
+        let aggregate = |buy: bool| {
+            let mut orders: Vec<&Order> = self.resting.iter().filter(|order| is_buy(&order.side) == buy).collect();
+            orders.sort_by(|a, b| {
+                let (a, b) = (a.price.unwrap(), b.price.unwrap());
+                if buy { b.total_cmp(&a) } else { a.total_cmp(&b) }
+            });
+            let mut depth: Vec<Level> = Vec::new();
+            for order in orders {
+                match depth.last_mut() {
+                    Some(level) if level.0 == order.price.unwrap() => level.1 += order.size,
+                    _ => depth.push((order.price.unwrap(), order.size)),
+                }
+            }
+            depth
+        };
+        (aggregate(true), aggregate(false))
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::OrderBook;
+    use chrono::{Duration, NaiveDateTime};
+    use proptest::prelude::*;
+
+    const PRICES: std::ops::RangeInclusive<u8> = 95..=105; // Whole prices and sizes keep the sums of sizes exact
+
+    #[derive(Clone, Debug)]
+    enum Action {
+        Limit { buy: bool, price: u8, size: u8 },
+        Market { buy: bool, size: u8 },
+        Cancel { pick: usize }, // Of any order submitted so far, which may no longer rest
+        Amend { pick: usize, price: u8, size: u8 },
+    }
+
+    fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            5 => (any::<bool>(), PRICES, 1..=10u8).prop_map(|(buy, price, size)| Action::Limit { buy, price, size }),
+            1 => (any::<bool>(), 1..=20u8).prop_map(|(buy, size)| Action::Market { buy, size }),
+            2 => any::<usize>().prop_map(|pick| Action::Cancel { pick }),
+            2 => (any::<usize>(), PRICES, 1..=10u8).prop_map(|(pick, price, size)| Action::Amend { pick, price, size }),
+        ]
+    }
+
+    /// Actions paired with whether the clock advances before them, so that orders often share a timestamp.
+    fn actions() -> impl Strategy<Value = Vec<(bool, Action)>> {
+        prop::collection::vec((any::<bool>(), action()), 1..200)
+    }
+
+    /// Turns the actions into commands, numbering new orders and resolving cancels and amends to submitted orders.
+    fn commands(actions: Vec<(bool, Action)>) -> Vec<(NaiveDateTime, Command)> {
+        let mut now = NaiveDateTime::default();
+        let mut submitted: Vec<Order> = Vec::new();
+        let mut commands = Vec::new();
+        for (advance, action) in actions {
+            if advance {
+                now += Duration::seconds(1);
+            }
+            let order = |id: usize, buy: bool, r#type: OrderType, price: Option<u8>, size: u8| Order {
+                id: id as i32,
+                sub_account_id: 1,
+                price: price.map(f32::from),
+                size: size.into(),
+                side: match (buy, &r#type) {
+                    (true, OrderType::Limit) => OrderSide::Bid,
+                    (true, OrderType::Market) => OrderSide::Buy,
+                    (false, OrderType::Limit) => OrderSide::Ask,
+                    (false, OrderType::Market) => OrderSide::Sell,
+                },
+                r#type,
+                open_at: now,
+            };
+            let command = match action {
+                Action::Limit { buy, price, size } => {
+                    Command::New(order(submitted.len() + 1, buy, OrderType::Limit, Some(price), size))
+                }
+                Action::Market { buy, size } => {
+                    Command::New(order(submitted.len() + 1, buy, OrderType::Market, None, size))
+                }
+                Action::Cancel { pick } if !submitted.is_empty() => {
+                    Command::Cancel(submitted[pick % submitted.len()].clone())
+                }
+                Action::Amend { pick, price, size } if !submitted.is_empty() => {
+                    let original = &submitted[pick % submitted.len()];
+                    Command::Amend(Order { price: Some(price.into()), size: size.into(), open_at: now, ..original.clone() })
+                }
+                _ => continue,
+            };
+            if let Command::New(order) = &command {
+                submitted.push(order.clone());
+            }
+            commands.push((now, command));
+        }
+        commands
+    }
+
+    fn total(levels: &[Level]) -> f32 {
+        levels.iter().map(|level| level.1).sum()
+    }
+
+    proptest! {
+        /// The order book fills exactly like the reference and leaves the same orders resting.
+        #[test]
+        fn matches_reference(actions in actions()) {
+            let mut orderbook = OrderBook::detached(1);
+            let mut reference = Reference::default();
+            for (now, command) in commands(actions) {
+                let expected = reference.execute(command.clone());
+                let fills = orderbook.execute(command.clone(), now);
+                let executions: Vec<Execution> = fills.iter().map(|fill| (fill.order_id, fill.price, fill.size)).collect();
+                prop_assert_eq!(executions, expected, "{:?}", command);
+                prop_assert_eq!(orderbook.depth(usize::MAX), reference.depth(), "{:?}", command);
+            }
+        }
+
+        /// Invariants which hold after every command, regardless of any reference.
+        #[test]
+        fn invariants(actions in actions()) {
+            let mut orderbook = OrderBook::detached(1);
+            for (now, command) in commands(actions) {
+                let (order, resting) = match &command {
+                    Command::New(order) => (order, 0.0),
+                    Command::Cancel(order) | Command::Amend(order) => {
+                        let queue = if is_buy(&order.side) { &orderbook.bids } else { &orderbook.asks };
+                        (order, queue.get(order.id).map_or(0.0, |resting| resting.size))
+                    }
+                };
+                let buy = is_buy(&order.side);
+                let before = orderbook.depth(usize::MAX);
+                let fills = orderbook.execute(command.clone(), now);
+                let after = orderbook.depth(usize::MAX);
+
+                // Fills come in pairs of a resting and the incoming order, at the price of the resting order
+                prop_assert!(fills.len().is_multiple_of(2));
+                let mut filled = 0.0;
+                let mut last_price: Option<f32> = None;
+                for pair in fills.chunks(2) {
+                    let (contra, aggressor) = (&pair[0], &pair[1]);
+                    prop_assert_eq!(aggressor.order_id, order.id);
+                    prop_assert_eq!(is_buy(&contra.side), !buy);
+                    prop_assert_eq!((contra.price, contra.size), (aggressor.price, aggressor.size));
+                    prop_assert!(aggressor.size > 0.0);
+                    prop_assert!(aggressor.aggressor == Some(order.side.clone()));
+
+                    // Within the limit of the order, and walking the book from the best price
+                    let better = |a: f32, b: f32| if buy { a <= b } else { a >= b };
+                    prop_assert!(order.price.is_none_or(|limit| better(aggressor.price, limit)));
+                    prop_assert!(last_price.is_none_or(|last| better(last, aggressor.price)));
+                    last_price = Some(aggressor.price);
+                    filled += aggressor.size;
+                }
+                if let Some(best) = (if buy { &before.1 } else { &before.0 }).first() {
+                    prop_assert!(fills.first().is_none_or(|fill| fill.price == best.0));
+                }
+
+                // Quantity is conserved: what leaves the contra side was filled, and the order rests with the rest
+                let (own_before, contra_before) = if buy { (&before.0, &before.1) } else { (&before.1, &before.0) };
+                let (own_after, contra_after) = if buy { (&after.0, &after.1) } else { (&after.1, &after.0) };
+                prop_assert_eq!(total(contra_after), total(contra_before) - filled);
+                let rests = match &command {
+                    Command::New(order) if order.r#type == OrderType::Limit => order.size - filled,
+                    Command::Amend(order) if resting > 0.0 => order.size - filled,
+                    _ => 0.0, // Market orders are not stored and cancelled orders are removed
+                };
+                prop_assert!(filled <= order.size && rests >= 0.0);
+                prop_assert_eq!(total(own_after), total(own_before) - resting + rests);
+
+                // Nothing rests with a negative or zero size, and the book is not crossed at rest
+                for queue in [&orderbook.bids, &orderbook.asks] {
+                    prop_assert!(queue.orders().all(|order| order.size > 0.0));
+                }
+                if let (Some(bid), Some(ask)) = (after.0.first(), after.1.first()) {
+                    prop_assert!(bid.0 < ask.0, "crossed at {} / {}", bid.0, ask.0);
+                }
+                prop_assert_eq!(orderbook.top_of_book(), (after.0.first().map(|level| level.0), after.1.first().map(|level| level.0)));
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use database::fills::Fill;
+use protocol::Codec;
+use protocol::transport::Channel;
+
+use crate::capture::Recording;
+use crate::OrderBook;
+
+// ----------------------------------------------------------------------
+
+/// Outcome of replaying a recording, comparing the fills of the replay against the recorded ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub inputs: usize,
+    pub recorded: usize, // Fills in the recording
+    pub replayed: usize, // Fills produced by the replay
+    pub mismatch: Option<usize>, // Index of the first fill which differs
+}
+
+impl Report {
+    pub fn matches(&self) -> bool {
+        self.mismatch.is_none() && self.recorded == self.replayed
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inputs, {} recorded fills, {} replayed fills", self.inputs, self.recorded, self.replayed)?;
+        match self.mismatch {
+            Some(index) => write!(f, ", first mismatch at fill {index}"),
+            None if self.matches() => write!(f, ", identical"),
+            None => write!(f, ", differing in count"),
+        }
+    }
+}
+
+/// Fields of a fill which a deterministic engine reproduces. Timestamps differ between runs.
+fn key(fill: &Fill) -> impl PartialEq + '_ {
+    (fill.order_id, fill.sub_account_id, &fill.side, fill.price, fill.size, &fill.aggressor)
+}
+
+/// Feeds the orders of a recording into the order book, at the recorded pace divided by `speed` or as fast as possible
+/// if `None`. The order book sees the recorded time of each order, so auctions resume as they did when recorded.
+pub async fn replay(orderbook: &mut OrderBook, recording: Recording, speed: Option<f64>) -> io::Result<Report> {
+    let started = Instant::now();
+    let mut first = None;
+    let mut last = None;
+    let mut inputs = 0;
+    let (mut recorded, mut replayed) = (Vec::new(), Vec::new());
+    for event in recording {
+        let event = event?;
+        match event.channel {
+            Channel::Orders => {
+                let first = *first.get_or_insert(event.timestamp);
+                if let Some(speed) = speed {
+                    let offset = Duration::from_nanos(((event.timestamp - first).max(0) as f64 / speed) as u64);
+                    if let Some(wait) = offset.checked_sub(started.elapsed()) {
+                        async_std::task::sleep(wait).await;
+                    }
+                }
+                let time = event.time();
+                orderbook.clock = Some(time);
+                if orderbook.resume(time) {
+                    orderbook.publish_top_of_book();
+                }
+                orderbook.handle(&event.data);
+                replayed.extend(orderbook.drain_fills());
+                inputs += 1;
+                last = Some(time);
+            }
+            Channel::Fills => {
+                recorded.push(Fill::decode(&event.data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+            }
+            Channel::MarketData => {}
+        }
+    }
+    if let Some(last) = last { // Volatility auctions still running when the recording ended
+        orderbook.clock = Some(last);
+        orderbook.resume(last);
+        replayed.extend(orderbook.drain_fills());
+    }
+
+    let mismatch = recorded.iter().zip(&replayed).position(|(recorded, replayed)| key(recorded) != key(replayed));
+    Ok(Report { inputs, recorded: recorded.len(), replayed: replayed.len(), mismatch })
+}
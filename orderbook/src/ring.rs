@@ -0,0 +1,143 @@
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use crossbeam_queue::ArrayQueue;
+use futures::task::AtomicWaker;
+
+// ----------------------------------------------------------------------
+
+/// A bounded queue between threads whose slots are allocated when it is created, so that passing values through it
+/// neither locks nor allocates. Any number of producers may push, and a single consumer pops, either from a thread
+/// of its own or from a task.
+pub struct Ring<T> {
+    slots: ArrayQueue<T>,
+    closed: AtomicBool, // No more values will be pushed
+    consumer: AtomicWaker, // Woken by every push, once registered
+}
+
+impl<T> Ring<T> {
+    pub fn new(capacity: usize) -> Self {
+        Ring { slots: ArrayQueue::new(capacity), closed: AtomicBool::new(false), consumer: AtomicWaker::new() }
+    }
+
+    /// Adds a value and wakes the consumer, or returns the value if the ring is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        self.slots.push(value)?;
+        self.consumer.wake();
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.slots.pop()
+    }
+
+    /// Tells the consumer that the values in the ring are the last.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.consumer.wake();
+    }
+
+    /// Whether the ring is closed and every value was popped.
+    pub fn is_finished(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) && self.slots.is_empty()
+    }
+
+    /// Blocks the consuming thread until a value is pushed, the ring is closed or the timeout elapses. The waker must
+    /// unpark the thread, see `Unpark`.
+    pub fn park(&self, waker: &Waker, timeout: Duration) {
+        self.consumer.register(waker);
+        if self.slots.is_empty() && !self.closed.load(Ordering::SeqCst) { // Pushed before registering otherwise
+            thread::park_timeout(timeout);
+        }
+    }
+
+    /// Waits for the next value from a task. Returns `None` once the ring is finished.
+    pub async fn next(&self) -> Option<T> {
+        poll_fn(|cx| {
+            for _ in 0..2 { // Again after registering, in case a value was pushed in between
+                let closed = self.closed.load(Ordering::SeqCst);
+                if let Some(value) = self.slots.pop() {
+                    return Poll::Ready(Some(value));
+                } else if closed {
+                    return Poll::Ready(None);
+                }
+                self.consumer.register(cx.waker());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Wakes a consumer which waits on a thread of its own.
+pub struct Unpark(pub Thread);
+
+impl Unpark {
+    pub fn current() -> Waker {
+        Arc::new(Unpark(thread::current())).into()
+    }
+}
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn between_threads() {
+        let ring = Arc::new(Ring::new(4));
+        let consumer = thread::spawn({
+            let ring = ring.clone();
+            move || {
+                let waker = Unpark::current();
+                let mut values = Vec::new();
+                while !ring.is_finished() {
+                    match ring.pop() {
+                        Some(value) => values.push(value),
+                        None => ring.park(&waker, Duration::from_secs(1)),
+                    }
+                }
+                values
+            }
+        });
+        for value in 0..100 {
+            let mut value = value;
+            while let Err(full) = ring.push(value) { // Wait for the consumer to make room
+                value = full;
+                thread::yield_now();
+            }
+        }
+        ring.close();
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[async_std::test]
+    async fn to_task() {
+        let ring = Arc::new(Ring::new(8));
+        let producer = thread::spawn({
+            let ring = ring.clone();
+            move || {
+                for value in 0..5 {
+                    ring.push(value).unwrap();
+                    thread::sleep(Duration::from_millis(1));
+                }
+                ring.close();
+            }
+        });
+        let mut values = Vec::new();
+        while let Some(value) = ring.next().await {
+            values.push(value);
+        }
+        producer.join().unwrap();
+        assert_eq!(values, [0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use database::{api_keys, DatabaseConnection, Mutation, Query};
+
+use crate::error::Exception;
+
+// ----------------------------------------------------------------------
+
+const RECV_WINDOW: i64 = 30_000; // Maximum difference in milliseconds between the timestamp of a Logon and now
+
+/// Signs the Logon of an order entry session with the secret of an API key. The signature is the HMAC-SHA256 of the
+/// timestamp, nonce and fields of the Logon, each terminated by a newline so that different Logons never sign the same
+/// bytes.
+pub fn sign(secret: &str, timestamp: i64, nonce: i64, fields: &[&str]) -> [u8; 32] {
+    mac(secret, timestamp, nonce, fields).finalize().into_bytes().into()
+}
+
+fn mac(secret: &str, timestamp: i64, nonce: i64, fields: &[&str]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    for field in [timestamp.to_string().as_str(), nonce.to_string().as_str()].iter().chain(fields) {
+        mac.update(field.as_bytes());
+        mac.update(b"\n");
+    }
+    mac
+}
+
+/// Verifies the Logon of an order entry session signed with an API key and returns the key. Sessions trade for every
+/// sub-account of the client, so the key must have the trade permission and may not be scoped to a sub-account. The
+/// nonce is shared with the requests signed by the key through the API.
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    key: &str,
+    timestamp: i64,
+    nonce: i64,
+    fields: &[&str],
+    signature: &[u8],
+) -> Result<api_keys::Model, Exception> {
+    let api_key = Query::find_api_key_by_key(db, key.to_owned())
+        .await
+        .map_err(|_| Exception::Unauthorized("Invalid API key.".to_owned()))?;
+    if (Utc::now().timestamp_millis() - timestamp).abs() > RECV_WINDOW {
+        return Err(Exception::Unauthorized("Logon timestamp is outside of the receive window.".to_owned()));
+    }
+    mac(&api_key.secret, timestamp, nonce, fields)
+        .verify_slice(signature)
+        .map_err(|_| Exception::Unauthorized("Invalid signature.".to_owned()))?;
+    if !api_key.trade || api_key.sub_account_id.is_some() {
+        return Err(Exception::Unauthorized(
+            "API key must have the Trade permission and not be scoped to a sub-account.".to_owned()
+        ));
+    }
+    if !Mutation::use_api_key_nonce(db, api_key.id, nonce).await.map_err(Exception::Database)? {
+        return Err(Exception::Unauthorized("Nonce has already been used.".to_owned()));
+    }
+    Ok(api_key)
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature() {
+        let signature = sign("secret", 1, 2, &["A", "1"]);
+        assert_eq!(signature, sign("secret", 1, 2, &["A", "1"]));
+        assert_ne!(signature, sign("secret", 1, 2, &["A1"])); // Fields are delimited
+        assert_ne!(signature, sign("secret", 12, 2, &["A", "1"]));
+        assert_ne!(signature, sign("other", 1, 2, &["A", "1"]));
+        assert!(mac("secret", 1, 2, &["A", "1"]).verify_slice(&signature).is_ok());
+    }
+}
@@ -0,0 +1,753 @@
+use chrono::{DateTime, NaiveDateTime};
+
+use database::{MarketStatus, OrderSide, OrderType};
+use database::circuit_breakers;
+use database::fills::Fill;
+use database::markets::{CircuitBreakerChange, Indicative, StatusChange, TopOfBook};
+use database::orders::{Cancelled, Command, MassCancel, Order};
+
+use crate::error::Exception;
+
+// ----------------------------------------------------------------------
+
+pub const SCHEMA_ID: u16 = 1;
+pub const SCHEMA_VERSION: u16 = 1;
+/// Block length, template id, schema id and schema version, each a little-endian u16.
+pub const HEADER_LENGTH: usize = 8;
+/// Largest number of sub-accounts which a single MassCancel can carry.
+pub const MAX_MASS_CANCEL: usize = (u16::MAX as usize - 8) / 4;
+
+/// A message with a fixed-layout block preceded by an SBE-style message header.
+pub trait Codec: Sized {
+    const TEMPLATE_ID: u16;
+    const BLOCK_LENGTH: u16; // Minimum length of the block
+
+    fn encode_block(&self, buf: &mut Vec<u8>);
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception>;
+
+    /// Length of the encoded block, which exceeds `BLOCK_LENGTH` for messages ending in a list.
+    fn block_length(&self) -> u16 {
+        Self::BLOCK_LENGTH
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LENGTH + self.block_length() as usize);
+        self.encode_to(&mut buf);
+        buf
+    }
+
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.extend_from_slice(&self.block_length().to_le_bytes());
+        buf.extend_from_slice(&Self::TEMPLATE_ID.to_le_bytes());
+        buf.extend_from_slice(&SCHEMA_ID.to_le_bytes());
+        buf.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+        self.encode_block(buf);
+        debug_assert_eq!(buf.len() - start, HEADER_LENGTH + self.block_length() as usize);
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, Exception> {
+        let header = Header::decode(buf)?.ok_or_else(|| Exception::Garbled("Incomplete message.".to_owned()))?;
+        if header.template_id != Self::TEMPLATE_ID {
+            return Err(Exception::Garbled(format!(
+                "Expected template {} but received {}.", Self::TEMPLATE_ID, header.template_id
+            )));
+        }
+        header.decode_message(buf)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub block_length: u16,
+    pub template_id: u16,
+}
+
+impl Header {
+    /// Decodes the header at the start of the buffer. Returns `None` if the buffer does not yet contain a header.
+    pub fn decode(buf: &[u8]) -> Result<Option<Self>, Exception> {
+        if buf.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let mut reader = Reader::new(&buf[..HEADER_LENGTH]);
+        let block_length = reader.u16();
+        let template_id = reader.u16();
+        let schema_id = reader.u16();
+        let version = reader.u16();
+        if schema_id != SCHEMA_ID || version != SCHEMA_VERSION {
+            return Err(Exception::Garbled(format!("Unsupported schema {schema_id} version {version}.")));
+        }
+        Ok(Some(Header { block_length, template_id }))
+    }
+
+    pub fn message_length(&self) -> usize {
+        HEADER_LENGTH + self.block_length as usize
+    }
+
+    /// Returns a reader over the block following the header.
+    fn block<'a>(&self, buf: &'a [u8]) -> Result<Reader<'a>, Exception> {
+        if buf.len() < self.message_length() {
+            return Err(Exception::Garbled("Incomplete message.".to_owned()));
+        }
+        Ok(Reader::new(&buf[HEADER_LENGTH..self.message_length()]))
+    }
+
+    /// Decodes the message following the header, rejecting blocks shorter than the layout of the template. Longer
+    /// blocks are accepted so that fields can be appended in later schema versions.
+    pub fn decode_message<T: Codec>(&self, buf: &[u8]) -> Result<T, Exception> {
+        if (self.block_length as usize) < T::BLOCK_LENGTH as usize {
+            return Err(Exception::Garbled(format!("Block of template {} is too short.", self.template_id)));
+        }
+        T::decode_block(&mut self.block(buf)?)
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Reads little-endian fields in sequence from a block whose length has already been validated.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, offset: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let bytes = self.buf[self.offset..self.offset + N].try_into().unwrap();
+        self.offset += N;
+        bytes
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    pub fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.take())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    pub fn i64(&mut self) -> i64 {
+        i64::from_le_bytes(self.take())
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        self.take()
+    }
+
+    pub fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+
+    /// Reads an optional float where NaN is the null value.
+    pub fn optional_f32(&mut self) -> Option<f32> {
+        Some(self.f32()).filter(|value| !value.is_nan())
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    pub fn skip(&mut self, n: usize) {
+        self.offset += n;
+    }
+
+    pub fn side(&mut self) -> Result<OrderSide, Exception> {
+        match self.u8() {
+            0 => Ok(OrderSide::Ask),
+            1 => Ok(OrderSide::Bid),
+            2 => Ok(OrderSide::Buy),
+            3 => Ok(OrderSide::Long),
+            4 => Ok(OrderSide::Sell),
+            5 => Ok(OrderSide::Short),
+            side => Err(Exception::Garbled(format!("Unknown side {side}."))),
+        }
+    }
+
+    pub fn optional_side(&mut self) -> Result<Option<OrderSide>, Exception> {
+        if self.buf.get(self.offset) == Some(&u8::MAX) {
+            self.skip(1);
+            return Ok(None);
+        }
+        self.side().map(Some)
+    }
+
+    pub fn order_type(&mut self) -> Result<OrderType, Exception> {
+        match self.u8() {
+            0 => Ok(OrderType::Limit),
+            1 => Ok(OrderType::Market),
+            r#type => Err(Exception::Garbled(format!("Unknown order type {type}."))),
+        }
+    }
+
+    pub fn market_status(&mut self) -> Result<MarketStatus, Exception> {
+        match self.u8() {
+            0 => Ok(MarketStatus::PreOpen),
+            1 => Ok(MarketStatus::Auction),
+            2 => Ok(MarketStatus::Continuous),
+            3 => Ok(MarketStatus::Halted),
+            4 => Ok(MarketStatus::Closed),
+            status => Err(Exception::Garbled(format!("Unknown market status {status}."))),
+        }
+    }
+
+    /// Reads a timestamp encoded as nanoseconds since the epoch.
+    pub fn timestamp(&mut self) -> NaiveDateTime {
+        from_nanos(self.i64())
+    }
+
+    /// Reads an optional timestamp where `i64::MIN` is the null value.
+    pub fn optional_timestamp(&mut self) -> Option<NaiveDateTime> {
+        Some(self.i64()).filter(|nanos| *nanos != i64::MIN).map(from_nanos)
+    }
+}
+
+fn from_nanos(nanos: i64) -> NaiveDateTime {
+    DateTime::from_timestamp_nanos(nanos).naive_utc()
+}
+
+pub fn encode_side(side: &OrderSide, buf: &mut Vec<u8>) {
+    buf.push(match side {
+        OrderSide::Ask => 0,
+        OrderSide::Bid => 1,
+        OrderSide::Buy => 2,
+        OrderSide::Long => 3,
+        OrderSide::Sell => 4,
+        OrderSide::Short => 5,
+    });
+}
+
+pub fn encode_order_type(r#type: &OrderType, buf: &mut Vec<u8>) {
+    buf.push(match r#type {
+        OrderType::Limit => 0,
+        OrderType::Market => 1,
+    });
+}
+
+pub fn encode_market_status(status: &MarketStatus, buf: &mut Vec<u8>) {
+    buf.push(match status {
+        MarketStatus::PreOpen => 0,
+        MarketStatus::Auction => 1,
+        MarketStatus::Continuous => 2,
+        MarketStatus::Halted => 3,
+        MarketStatus::Closed => 4,
+    });
+}
+
+pub fn encode_optional_f32(value: Option<f32>, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&value.unwrap_or(f32::NAN).to_le_bytes());
+}
+
+pub fn encode_timestamp(timestamp: &NaiveDateTime, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&timestamp.and_utc().timestamp_nanos_opt().unwrap_or_default().to_le_bytes());
+}
+
+pub fn encode_optional_timestamp(timestamp: Option<&NaiveDateTime>, buf: &mut Vec<u8>) {
+    match timestamp {
+        Some(timestamp) => encode_timestamp(timestamp, buf),
+        None => buf.extend_from_slice(&i64::MIN.to_le_bytes()),
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Layout: action (u8), side (u8), type (u8), padding (u8), id (i32), sub_account_id (i32), price (f32),
+/// size (f32), open_at (i64).
+impl Codec for Command {
+    const TEMPLATE_ID: u16 = 1;
+    const BLOCK_LENGTH: u16 = 28;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        let (action, order) = match self {
+            Command::New(order) => (0, order),
+            Command::Cancel(order) => (1, order),
+            Command::Amend(order) => (2, order),
+        };
+        buf.push(action);
+        encode_side(&order.side, buf);
+        encode_order_type(&order.r#type, buf);
+        buf.push(0);
+        buf.extend_from_slice(&order.id.to_le_bytes());
+        buf.extend_from_slice(&order.sub_account_id.to_le_bytes());
+        encode_optional_f32(order.price, buf);
+        buf.extend_from_slice(&order.size.to_le_bytes());
+        encode_timestamp(&order.open_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let action = block.u8();
+        let side = block.side()?;
+        let r#type = block.order_type()?;
+        block.skip(1);
+        let order = Order {
+            id: block.i32(),
+            sub_account_id: block.i32(),
+            price: block.optional_f32(),
+            size: block.f32(),
+            side,
+            r#type,
+            open_at: block.timestamp(),
+        };
+        match action {
+            0 => Ok(Command::New(order)),
+            1 => Ok(Command::Cancel(order)),
+            2 => Ok(Command::Amend(order)),
+            action => Err(Exception::Garbled(format!("Unknown action {action}."))),
+        }
+    }
+}
+
+/// Layout: side (u8, 255 if none), padding (u8), count (u16), market_id (i32, 0 if none),
+/// sub_account_ids (i32 * count). Carries at most `MAX_MASS_CANCEL` sub-accounts.
+impl Codec for MassCancel {
+    const TEMPLATE_ID: u16 = 6;
+    const BLOCK_LENGTH: u16 = 8;
+
+    fn block_length(&self) -> u16 {
+        Self::BLOCK_LENGTH + 4 * self.sub_account_ids.len().min(MAX_MASS_CANCEL) as u16
+    }
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        let sub_account_ids = &self.sub_account_ids[..self.sub_account_ids.len().min(MAX_MASS_CANCEL)];
+        match &self.side {
+            Some(side) => encode_side(side, buf),
+            None => buf.push(u8::MAX),
+        }
+        buf.push(0);
+        buf.extend_from_slice(&(sub_account_ids.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&self.market_id.unwrap_or_default().to_le_bytes());
+        for sub_account_id in sub_account_ids {
+            buf.extend_from_slice(&sub_account_id.to_le_bytes());
+        }
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let side = block.optional_side()?;
+        block.skip(1);
+        let count = block.u16() as usize;
+        let market_id = Some(block.i32()).filter(|market_id| *market_id != 0);
+        let count = count.min(block.remaining() / 4); // Ignore ids which do not fit in the block
+        Ok(MassCancel { market_id, sub_account_ids: (0..count).map(|_| block.i32()).collect(), side })
+    }
+}
+
+/// Layout: order_id (i32), sub_account_id (i32), market_id (i32), remaining_size (f32), cancelled_at (i64).
+impl Codec for Cancelled {
+    const TEMPLATE_ID: u16 = 8;
+    const BLOCK_LENGTH: u16 = 24;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.order_id.to_le_bytes());
+        buf.extend_from_slice(&self.sub_account_id.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        buf.extend_from_slice(&self.remaining_size.to_le_bytes());
+        encode_timestamp(&self.cancelled_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(Cancelled {
+            order_id: block.i32(),
+            sub_account_id: block.i32(),
+            market_id: block.i32(),
+            remaining_size: block.f32(),
+            cancelled_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: side (u8), type (u8), aggressor (u8, 255 if none), padding (u8), price (f32), size (f32), quote_size (f32),
+/// sub_account_id (i32), market_id (i32), order_id (i32), created_at (i64).
+impl Codec for Fill {
+    const TEMPLATE_ID: u16 = 2;
+    const BLOCK_LENGTH: u16 = 36;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_side(&self.side, buf);
+        encode_order_type(&self.r#type, buf);
+        match &self.aggressor {
+            Some(aggressor) => encode_side(aggressor, buf),
+            None => buf.push(u8::MAX),
+        }
+        buf.push(0);
+        buf.extend_from_slice(&self.price.to_le_bytes());
+        buf.extend_from_slice(&self.size.to_le_bytes());
+        buf.extend_from_slice(&self.quote_size.to_le_bytes());
+        buf.extend_from_slice(&self.sub_account_id.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        buf.extend_from_slice(&self.order_id.to_le_bytes());
+        encode_timestamp(&self.created_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let side = block.side()?;
+        let r#type = block.order_type()?;
+        let aggressor = block.optional_side()?;
+        block.skip(1);
+        Ok(Fill {
+            price: block.f32(),
+            size: block.f32(),
+            quote_size: block.f32(),
+            side,
+            r#type,
+            aggressor,
+            sub_account_id: block.i32(),
+            market_id: block.i32(),
+            order_id: block.i32(),
+            created_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: status (u8), cancel_resting (u8), padding (u16), market_id (i32), changed_at (i64).
+impl Codec for StatusChange {
+    const TEMPLATE_ID: u16 = 3;
+    const BLOCK_LENGTH: u16 = 16;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_market_status(&self.status, buf);
+        buf.push(self.cancel_resting as u8);
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        encode_timestamp(&self.changed_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let status = block.market_status()?;
+        let cancel_resting = block.u8() != 0;
+        block.skip(2);
+        Ok(StatusChange {
+            market_id: block.i32(),
+            status,
+            cancel_resting,
+            changed_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: band_action (u8), padding (u8 x 3), band_percent (f32, NaN if none), band_window (i32),
+/// auction_duration (i32), market_id (i32), padding (u32), changed_at (i64).
+impl Codec for CircuitBreakerChange {
+    const TEMPLATE_ID: u16 = 9;
+    const BLOCK_LENGTH: u16 = 32;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_market_status(&self.band_action, buf);
+        buf.extend_from_slice(&[0, 0, 0]);
+        encode_optional_f32(self.band_percent, buf);
+        buf.extend_from_slice(&self.band_window.to_le_bytes());
+        buf.extend_from_slice(&self.auction_duration.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+        encode_timestamp(&self.changed_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let band_action = block.market_status()?;
+        block.skip(3);
+        let band_percent = block.optional_f32();
+        let (band_window, auction_duration, market_id) = (block.i32(), block.i32(), block.i32());
+        block.skip(4);
+        Ok(CircuitBreakerChange {
+            market_id,
+            band_percent,
+            band_window,
+            band_action,
+            auction_duration,
+            changed_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: price (f32, NaN if none), volume (f32), imbalance (f32), market_id (i32), created_at (i64).
+impl Codec for Indicative {
+    const TEMPLATE_ID: u16 = 4;
+    const BLOCK_LENGTH: u16 = 24;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_optional_f32(self.price, buf);
+        buf.extend_from_slice(&self.volume.to_le_bytes());
+        buf.extend_from_slice(&self.imbalance.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        encode_timestamp(&self.created_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(Indicative {
+            price: block.optional_f32(),
+            volume: block.f32(),
+            imbalance: block.f32(),
+            market_id: block.i32(),
+            created_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: bid (f32, NaN if none), ask (f32, NaN if none), market_id (i32), padding (u32), created_at (i64).
+impl Codec for TopOfBook {
+    const TEMPLATE_ID: u16 = 7;
+    const BLOCK_LENGTH: u16 = 24;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_optional_f32(self.bid, buf);
+        encode_optional_f32(self.ask, buf);
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+        encode_timestamp(&self.created_at, buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let (bid, ask) = (block.optional_f32(), block.optional_f32());
+        let market_id = block.i32();
+        block.skip(4);
+        Ok(TopOfBook {
+            bid,
+            ask,
+            market_id,
+            created_at: block.timestamp(),
+        })
+    }
+}
+
+/// Layout: status (u8), padding (u8 x 3), id (i32), market_id (i32), reference_price (f32), price (f32),
+/// padding (u32), created_at (i64), resumes_at (i64, `i64::MIN` if none).
+impl Codec for circuit_breakers::Model {
+    const TEMPLATE_ID: u16 = 5;
+    const BLOCK_LENGTH: u16 = 40;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        encode_market_status(&self.status, buf);
+        buf.extend_from_slice(&[0, 0, 0]);
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        buf.extend_from_slice(&self.reference_price.to_le_bytes());
+        buf.extend_from_slice(&self.price.to_le_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+        encode_timestamp(&self.created_at, buf);
+        encode_optional_timestamp(self.resumes_at.as_ref(), buf);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let status = block.market_status()?;
+        block.skip(3);
+        let (id, market_id) = (block.i32(), block.i32());
+        let (reference_price, price) = (block.f32(), block.f32());
+        block.skip(4);
+        Ok(circuit_breakers::Model {
+            id,
+            reference_price,
+            price,
+            status,
+            created_at: block.timestamp(),
+            resumes_at: block.optional_timestamp(),
+            market_id,
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn command() {
+        let order = Order {
+            id: 1,
+            sub_account_id: 2,
+            price: None,
+            size: 10.5,
+            side: OrderSide::Sell,
+            r#type: OrderType::Market,
+            open_at: Utc::now().naive_utc(),
+        };
+        let buf = Command::Cancel(order.clone()).encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 28);
+        let Command::Cancel(decoded) = Command::decode(&buf).unwrap() else {
+            panic!("Decoded the wrong action.");
+        };
+        assert_eq!(decoded.id, order.id);
+        assert_eq!(decoded.sub_account_id, order.sub_account_id);
+        assert_eq!(decoded.price, None);
+        assert_eq!(decoded.size, order.size);
+        assert_eq!(decoded.side, order.side);
+        assert_eq!(decoded.r#type, order.r#type);
+        assert_eq!(decoded.open_at, order.open_at);
+    }
+
+    #[test]
+    fn fill() {
+        let fill = Fill {
+            price: 100.0,
+            size: 2.0,
+            quote_size: 200.0,
+            side: OrderSide::Bid,
+            r#type: OrderType::Limit,
+            aggressor: Some(OrderSide::Bid),
+            created_at: Utc::now().naive_utc(),
+            sub_account_id: 1,
+            market_id: 2,
+            order_id: 3,
+        };
+        let decoded = Fill::decode(&fill.encode()).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{fill:?}"));
+        let fill = Fill { aggressor: None, ..fill };
+        let decoded = Fill::decode(&fill.encode()).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{fill:?}"));
+    }
+
+    #[test]
+    fn status_change() {
+        let change = StatusChange {
+            market_id: 3,
+            status: MarketStatus::Halted,
+            cancel_resting: true,
+            changed_at: Utc::now().naive_utc(),
+        };
+        let buf = change.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 16);
+        assert_eq!(Header::decode(&buf).unwrap().unwrap().template_id, StatusChange::TEMPLATE_ID);
+        assert_eq!(StatusChange::decode(&buf).unwrap(), change);
+    }
+
+    #[test]
+    fn circuit_breaker_change() {
+        let change = CircuitBreakerChange {
+            market_id: 3,
+            band_percent: Some(10.0),
+            band_window: 300,
+            band_action: MarketStatus::Auction,
+            auction_duration: 60,
+            changed_at: Utc::now().naive_utc(),
+        };
+        let buf = change.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 32);
+        assert_eq!(Header::decode(&buf).unwrap().unwrap().template_id, CircuitBreakerChange::TEMPLATE_ID);
+        assert_eq!(CircuitBreakerChange::decode(&buf).unwrap(), change);
+        let change = CircuitBreakerChange { band_percent: None, ..change };
+        assert_eq!(CircuitBreakerChange::decode(&change.encode()).unwrap(), change);
+    }
+
+    #[test]
+    fn indicative() {
+        let indicative = Indicative {
+            market_id: 1,
+            price: None,
+            volume: 0.0,
+            imbalance: -2.5,
+            created_at: Utc::now().naive_utc(),
+        };
+        let buf = indicative.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 24);
+        assert_eq!(Indicative::decode(&buf).unwrap(), indicative);
+        let indicative = Indicative { price: Some(10.5), volume: 3.0, ..indicative };
+        assert_eq!(Indicative::decode(&indicative.encode()).unwrap(), indicative);
+    }
+
+    #[test]
+    fn top_of_book() {
+        let top_of_book = TopOfBook {
+            market_id: 1,
+            bid: Some(99.5),
+            ask: None,
+            created_at: Utc::now().naive_utc(),
+        };
+        let buf = top_of_book.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 24);
+        assert_eq!(TopOfBook::decode(&buf).unwrap(), top_of_book);
+    }
+
+    #[test]
+    fn circuit_breaker() {
+        let now = Utc::now().naive_utc();
+        let circuit_breaker = circuit_breakers::Model {
+            id: 7,
+            reference_price: 100.0,
+            price: 112.5,
+            status: MarketStatus::Auction,
+            created_at: now,
+            resumes_at: Some(now + chrono::Duration::seconds(60)),
+            market_id: 2,
+        };
+        let buf = circuit_breaker.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 40);
+        assert_eq!(circuit_breakers::Model::decode(&buf).unwrap(), circuit_breaker);
+        let circuit_breaker = circuit_breakers::Model { status: MarketStatus::Halted, resumes_at: None, ..circuit_breaker };
+        assert_eq!(circuit_breakers::Model::decode(&circuit_breaker.encode()).unwrap(), circuit_breaker);
+    }
+
+    #[test]
+    fn mass_cancel() {
+        let mass_cancel = MassCancel { market_id: Some(2), sub_account_ids: vec![3, 1, 4], side: Some(OrderSide::Bid) };
+        let buf = mass_cancel.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 8 + 3 * 4);
+        assert_eq!(Header::decode(&buf).unwrap().unwrap().message_length(), buf.len());
+        assert_eq!(MassCancel::decode(&buf).unwrap(), mass_cancel);
+        let everywhere = MassCancel { market_id: None, sub_account_ids: vec![], side: None };
+        assert_eq!(MassCancel::decode(&everywhere.encode()).unwrap(), everywhere);
+
+        // A count larger than the block is truncated
+        let mut buf = mass_cancel.encode();
+        buf[HEADER_LENGTH + 2] = 100;
+        assert_eq!(MassCancel::decode(&buf).unwrap(), mass_cancel);
+    }
+
+    #[test]
+    fn cancelled() {
+        let cancelled = Cancelled {
+            order_id: 1,
+            sub_account_id: 2,
+            market_id: 3,
+            remaining_size: 4.5,
+            cancelled_at: Utc::now().naive_utc(),
+        };
+        let buf = cancelled.encode();
+        assert_eq!(buf.len(), HEADER_LENGTH + 24);
+        assert_eq!(Cancelled::decode(&buf).unwrap(), cancelled);
+        assert!(Fill::decode(&buf).is_err()); // Shares the fills stream
+    }
+
+    #[test]
+    fn garbled() {
+        let buf = Fill::decode(&[0; HEADER_LENGTH]);
+        assert!(buf.is_err()); // Unsupported schema
+        let mut buf = Command::New(Order {
+            id: 1,
+            sub_account_id: 1,
+            price: Some(1.0),
+            size: 1.0,
+            side: OrderSide::Buy,
+            r#type: OrderType::Limit,
+            open_at: Utc::now().naive_utc(),
+        }).encode();
+        assert!(Fill::decode(&buf).is_err()); // Wrong template
+        let mut unknown = buf.clone();
+        unknown[HEADER_LENGTH + 1] = 6;
+        assert!(Command::decode(&unknown).is_err()); // Unknown side
+        unknown[HEADER_LENGTH + 1] = 2;
+        unknown[HEADER_LENGTH + 2] = 2;
+        assert!(Command::decode(&unknown).is_err()); // Unknown order type
+        buf.pop();
+        assert!(Command::decode(&buf).is_err()); // Truncated
+
+        // A block shorter than the layout of the template
+        let mut buf = 4u16.to_le_bytes().to_vec();
+        buf.extend_from_slice(&Command::TEMPLATE_ID.to_le_bytes());
+        buf.extend_from_slice(&SCHEMA_ID.to_le_bytes());
+        buf.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        assert!(Command::decode(&buf).is_err());
+    }
+}
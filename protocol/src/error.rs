@@ -0,0 +1,16 @@
+use database::DbErr;
+
+use derive_more::{Display, Error};
+
+use crate::TransportError;
+
+// ----------------------------------------------------------------------
+
+#[derive(Debug, Display, Error)]
+pub enum Exception {
+    Io(std::io::Error),
+    Garbled(#[error(not(source))] String),
+    Unauthorized(#[error(not(source))] String),
+    Database(DbErr),
+    Transport(TransportError)
+}
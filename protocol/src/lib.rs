@@ -0,0 +1,150 @@
+pub mod auth;
+pub mod codec;
+mod error;
+pub mod message;
+mod session;
+pub mod transport;
+
+pub use codec::Codec;
+pub use error::Exception;
+pub use message::Message;
+pub use transport::{Channel, InMemory, Offset, OffsetStream, RabbitMQ, Transport, TransportError};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_std::channel::Sender;
+use async_std::net::{TcpListener, ToSocketAddrs};
+use async_std::task;
+use futures::StreamExt;
+
+use database::{DatabaseConnection, Engine};
+use database::fills::Fill;
+use database::orders::Cancelled;
+
+use crate::message::{Ack, AckStatus, RejectReason};
+
+// ----------------------------------------------------------------------
+
+/// The state of an order entered through a session, used to forward engine reports to the session.
+#[derive(Clone, Copy, Debug)]
+struct OrderState {
+    client_id: i32,
+    size: f32,
+    filled_size: f32,
+    cancel_client_order_id: Option<u64>, // Set while a cancel sent to the engine is pending
+}
+
+/// A TCP order gateway which accepts binary order entry sessions and forwards orders to the matching engine.
+pub struct Gateway {
+    db: DatabaseConnection,
+    transport: Option<Arc<dyn Transport>>, // Make optional for integration tests
+    sessions: Mutex<HashMap<i32, Sender<Message>>>, // Outbound queues of logged on sessions keyed by client id
+    orders: Mutex<HashMap<i32, OrderState>>, // Keyed by the engine ids of orders entered through the gateway
+}
+
+impl Gateway {
+    pub fn new(db: DatabaseConnection, transport: Option<Arc<dyn Transport>>) -> Self {
+        Gateway {
+            db,
+            transport,
+            sessions: Mutex::new(HashMap::new()),
+            orders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Connects to the database and to the orders stream of the matching engine.
+    pub async fn connect() -> Self {
+        let db = Engine::connect().await.unwrap(); // Allow to panic if unsuccessful
+        let transport = RabbitMQ::connect().await.unwrap(); // Allow to panic if unsuccessful
+        Gateway::new(db, Some(Arc::new(transport)))
+    }
+
+    pub async fn run(self, address: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        Arc::new(self).serve(listener).await
+    }
+
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        if let Some(transport) = self.transport.clone() {
+            let gateway = self.clone();
+            std::thread::spawn(move || task::block_on(gateway.consume_fills(transport))); // Subscribing is not Send
+        }
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let _ = stream.set_nodelay(true);
+            let gateway = self.clone();
+            task::spawn(async move {
+                if let Err(e) = session::handle(gateway, stream).await {
+                    tracing::error!("Session with {address} terminated: {e}");
+                }
+            });
+        }
+    }
+
+    async fn consume_fills(self: Arc<Self>, transport: Arc<dyn Transport>) {
+        let mut fills = transport.subscribe(Channel::Fills, Offset::Next).await.unwrap();
+        while let Some(data) = fills.next().await {
+            if let Ok(fill) = Fill::decode(&data) {
+                self.on_fill(fill);
+            } else if let Ok(cancelled) = Cancelled::decode(&data) {
+                self.on_cancelled(cancelled);
+            }
+        }
+    }
+
+    /// Forwards a fill to the session that entered the order, if it is logged on. A cancel still pending once the order
+    /// is filled is rejected.
+    pub fn on_fill(&self, fill: Fill) {
+        let order = {
+            let mut orders = self.orders.lock().unwrap();
+            let Some(order) = orders.get_mut(&fill.order_id) else {
+                return; // The order was not entered through the gateway
+            };
+            order.filled_size += fill.size;
+            let order = *order;
+            if order.filled_size >= order.size {
+                orders.remove(&fill.order_id); // Filled orders can no longer be cancelled or amended
+            }
+            order
+        };
+        let order_id = fill.order_id;
+        self.send(order.client_id, Message::Fill(fill));
+        if let (true, Some(client_order_id)) = (order.filled_size >= order.size, order.cancel_client_order_id) {
+            self.send(order.client_id, Message::Ack(Ack {
+                client_order_id,
+                order_id,
+                remaining_size: 0.0,
+                status: AckStatus::Rejected,
+                reason: RejectReason::UnknownOrder,
+            }));
+        }
+    }
+
+    /// Acknowledges an order pulled from the book by the engine to the session that entered it, if it is logged on.
+    pub fn on_cancelled(&self, cancelled: Cancelled) {
+        let Some(order) = self.orders.lock().unwrap().remove(&cancelled.order_id) else {
+            return; // The order was not entered through the gateway
+        };
+        self.send(order.client_id, Message::Ack(Ack {
+            client_order_id: order.cancel_client_order_id.unwrap_or(0), // 0 when not requested by the client
+            order_id: cancelled.order_id,
+            remaining_size: cancelled.remaining_size,
+            status: AckStatus::Cancelled,
+            reason: RejectReason::None,
+        }));
+    }
+
+    async fn publish(&self, message: &impl Codec) -> Result<(), Exception> {
+        if let Some(transport) = &self.transport {
+            transport.publish(Channel::Orders, message.encode()).await.map_err(Exception::Transport)?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, client_id: i32, message: Message) {
+        if let Some(outbound) = self.sessions.lock().unwrap().get(&client_id) {
+            let _ = outbound.try_send(message);
+        }
+    }
+}
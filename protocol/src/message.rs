@@ -0,0 +1,432 @@
+use database::{OrderSide, OrderType};
+use database::fills::Fill;
+
+use crate::auth;
+use crate::codec::{encode_optional_f32, encode_order_type, encode_side, Codec, Header, Reader};
+use crate::error::Exception;
+
+// ----------------------------------------------------------------------
+
+/// Opens a session for a client. Must be the first message on a connection, signed with an API key of the client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Logon {
+    pub client_id: i32,
+    pub heartbeat_interval: u16, // Seconds
+    pub cancel_on_disconnect: bool, // Cancel the orders entered through the session when it ends
+    pub timestamp: i64, // Milliseconds since the epoch
+    pub nonce: i64, // Must exceed every nonce used with the key
+    pub key: [u8; 16], // API key, hex decoded
+    pub signature: [u8; 32],
+}
+
+/// Ends a session, or rejects a Logon.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Logout {
+    pub reason: RejectReason,
+}
+
+/// Sent by either side whenever it has been idle for the heartbeat interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Heartbeat;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewOrder {
+    pub client_order_id: u64,
+    pub sub_account_id: i32,
+    pub market_id: i32,
+    pub price: Option<f32>, // None for market orders
+    pub size: f32,
+    pub side: OrderSide,
+    pub r#type: OrderType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CancelOrder {
+    pub client_order_id: u64,
+    pub order_id: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmendOrder {
+    pub client_order_id: u64,
+    pub order_id: i32,
+    pub price: Option<f32>, // None leaves the price unchanged
+    pub size: Option<f32>, // None leaves the size unchanged
+}
+
+/// The response of the gateway to a NewOrder, CancelOrder or AmendOrder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ack {
+    pub client_order_id: u64,
+    pub order_id: i32,
+    pub remaining_size: f32, // Unfilled size of the order, or the size cancelled
+    pub status: AckStatus,
+    pub reason: RejectReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AckStatus {
+    New,
+    Cancelled,
+    Amended,
+    Rejected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    None,
+    InvalidRequest,
+    UnknownOrder,
+    UnknownClient,
+    DuplicateSession,
+    HeartbeatTimeout,
+    Unauthorized,
+}
+
+impl Logon {
+    /// An unsigned Logon, as echoed by the gateway.
+    pub fn new(client_id: i32, heartbeat_interval: u16, cancel_on_disconnect: bool) -> Self {
+        Logon {
+            client_id,
+            heartbeat_interval,
+            cancel_on_disconnect,
+            timestamp: 0,
+            nonce: 0,
+            key: [0; 16],
+            signature: [0; 32],
+        }
+    }
+
+    /// Signs the Logon with an API key of the client. See `auth::sign` for the signature.
+    pub fn sign(self, key: &str, secret: &str, timestamp: i64, nonce: i64) -> Self {
+        let mut logon = Logon { timestamp, nonce, ..self };
+        let _ = hex::decode_to_slice(key, &mut logon.key); // Invalid keys are left zeroed and rejected by the gateway
+        let fields = logon.fields();
+        logon.signature = auth::sign(secret, timestamp, nonce, &fields.each_ref().map(String::as_str));
+        logon
+    }
+
+    /// Fields of the Logon covered by its signature besides the timestamp and nonce.
+    pub(crate) fn fields(&self) -> [String; 3] {
+        [
+            self.client_id.to_string(),
+            self.heartbeat_interval.to_string(),
+            (self.cancel_on_disconnect as u8).to_string(),
+        ]
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Layout: client_id (i32), heartbeat_interval (u16), cancel_on_disconnect (u8), padding (u8), timestamp (i64),
+/// nonce (i64), key (u8 * 16), signature (u8 * 32).
+impl Codec for Logon {
+    const TEMPLATE_ID: u16 = 10;
+    const BLOCK_LENGTH: u16 = 72;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.client_id.to_le_bytes());
+        buf.extend_from_slice(&self.heartbeat_interval.to_le_bytes());
+        buf.extend_from_slice(&[self.cancel_on_disconnect as u8, 0]);
+        buf.extend_from_slice(&self.timestamp.to_le_bytes());
+        buf.extend_from_slice(&self.nonce.to_le_bytes());
+        buf.extend_from_slice(&self.key);
+        buf.extend_from_slice(&self.signature);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        let client_id = block.i32();
+        let heartbeat_interval = block.u16();
+        let cancel_on_disconnect = block.u8() != 0;
+        block.skip(1);
+        Ok(Logon {
+            client_id,
+            heartbeat_interval,
+            cancel_on_disconnect,
+            timestamp: block.i64(),
+            nonce: block.i64(),
+            key: block.bytes(),
+            signature: block.bytes(),
+        })
+    }
+}
+
+/// Layout: reason (u8), padding (u8 * 3).
+impl Codec for Logout {
+    const TEMPLATE_ID: u16 = 11;
+    const BLOCK_LENGTH: u16 = 4;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&[self.reason.into(), 0, 0, 0]);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(Logout { reason: block.u8().into() })
+    }
+}
+
+impl Codec for Heartbeat {
+    const TEMPLATE_ID: u16 = 12;
+    const BLOCK_LENGTH: u16 = 0;
+
+    fn encode_block(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode_block(_block: &mut Reader) -> Result<Self, Exception> {
+        Ok(Heartbeat)
+    }
+}
+
+/// Layout: client_order_id (u64), sub_account_id (i32), market_id (i32), price (f32), size (f32), side (u8),
+/// type (u8), padding (u16).
+impl Codec for NewOrder {
+    const TEMPLATE_ID: u16 = 13;
+    const BLOCK_LENGTH: u16 = 28;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.client_order_id.to_le_bytes());
+        buf.extend_from_slice(&self.sub_account_id.to_le_bytes());
+        buf.extend_from_slice(&self.market_id.to_le_bytes());
+        encode_optional_f32(self.price, buf);
+        buf.extend_from_slice(&self.size.to_le_bytes());
+        encode_side(&self.side, buf);
+        encode_order_type(&self.r#type, buf);
+        buf.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(NewOrder {
+            client_order_id: block.u64(),
+            sub_account_id: block.i32(),
+            market_id: block.i32(),
+            price: block.optional_f32(),
+            size: block.f32(),
+            side: block.side()?,
+            r#type: block.order_type()?,
+        })
+    }
+}
+
+/// Layout: client_order_id (u64), order_id (i32), padding (u32).
+impl Codec for CancelOrder {
+    const TEMPLATE_ID: u16 = 14;
+    const BLOCK_LENGTH: u16 = 16;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.client_order_id.to_le_bytes());
+        buf.extend_from_slice(&self.order_id.to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(CancelOrder {
+            client_order_id: block.u64(),
+            order_id: block.i32(),
+        })
+    }
+}
+
+/// Layout: client_order_id (u64), order_id (i32), price (f32), size (f32), padding (u32).
+impl Codec for AmendOrder {
+    const TEMPLATE_ID: u16 = 15;
+    const BLOCK_LENGTH: u16 = 24;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.client_order_id.to_le_bytes());
+        buf.extend_from_slice(&self.order_id.to_le_bytes());
+        encode_optional_f32(self.price, buf);
+        encode_optional_f32(self.size, buf);
+        buf.extend_from_slice(&[0; 4]);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(AmendOrder {
+            client_order_id: block.u64(),
+            order_id: block.i32(),
+            price: block.optional_f32(),
+            size: block.optional_f32(),
+        })
+    }
+}
+
+/// Layout: client_order_id (u64), order_id (i32), remaining_size (f32), status (u8), reason (u8), padding (u16).
+impl Codec for Ack {
+    const TEMPLATE_ID: u16 = 16;
+    const BLOCK_LENGTH: u16 = 20;
+
+    fn encode_block(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.client_order_id.to_le_bytes());
+        buf.extend_from_slice(&self.order_id.to_le_bytes());
+        buf.extend_from_slice(&self.remaining_size.to_le_bytes());
+        buf.push(match self.status {
+            AckStatus::New => 0,
+            AckStatus::Cancelled => 1,
+            AckStatus::Amended => 2,
+            AckStatus::Rejected => 3,
+        });
+        buf.push(self.reason.into());
+        buf.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_block(block: &mut Reader) -> Result<Self, Exception> {
+        Ok(Ack {
+            client_order_id: block.u64(),
+            order_id: block.i32(),
+            remaining_size: block.f32(),
+            status: match block.u8() {
+                0 => AckStatus::New,
+                1 => AckStatus::Cancelled,
+                2 => AckStatus::Amended,
+                _ => AckStatus::Rejected,
+            },
+            reason: block.u8().into(),
+        })
+    }
+}
+
+impl From<RejectReason> for u8 {
+    fn from(reason: RejectReason) -> Self {
+        match reason {
+            RejectReason::None => 0,
+            RejectReason::InvalidRequest => 1,
+            RejectReason::UnknownOrder => 2,
+            RejectReason::UnknownClient => 3,
+            RejectReason::DuplicateSession => 4,
+            RejectReason::HeartbeatTimeout => 5,
+            RejectReason::Unauthorized => 6,
+        }
+    }
+}
+
+impl From<u8> for RejectReason {
+    fn from(reason: u8) -> Self {
+        match reason {
+            0 => RejectReason::None,
+            1 => RejectReason::InvalidRequest,
+            2 => RejectReason::UnknownOrder,
+            3 => RejectReason::UnknownClient,
+            4 => RejectReason::DuplicateSession,
+            5 => RejectReason::HeartbeatTimeout,
+            _ => RejectReason::Unauthorized,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Any message exchanged over an order gateway connection.
+#[derive(Clone, Debug)]
+pub enum Message {
+    Logon(Logon),
+    Logout(Logout),
+    Heartbeat,
+    NewOrder(NewOrder),
+    CancelOrder(CancelOrder),
+    AmendOrder(AmendOrder),
+    Ack(Ack),
+    Fill(Fill),
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Logon(message) => message.encode(),
+            Message::Logout(message) => message.encode(),
+            Message::Heartbeat => Heartbeat.encode(),
+            Message::NewOrder(message) => message.encode(),
+            Message::CancelOrder(message) => message.encode(),
+            Message::AmendOrder(message) => message.encode(),
+            Message::Ack(message) => message.encode(),
+            Message::Fill(message) => message.encode(),
+        }
+    }
+
+    /// Decodes the first message in the buffer. Returns the message and the number of bytes consumed, or `None` if
+    /// the buffer does not yet contain a complete message.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Message, usize)>, Exception> {
+        let Some(header) = Header::decode(buf)? else {
+            return Ok(None);
+        };
+        if buf.len() < header.message_length() {
+            return Ok(None);
+        }
+        let message = match header.template_id {
+            Logon::TEMPLATE_ID => Message::Logon(header.decode_message(buf)?),
+            Logout::TEMPLATE_ID => Message::Logout(header.decode_message(buf)?),
+            Heartbeat::TEMPLATE_ID => Message::Heartbeat,
+            NewOrder::TEMPLATE_ID => Message::NewOrder(header.decode_message(buf)?),
+            CancelOrder::TEMPLATE_ID => Message::CancelOrder(header.decode_message(buf)?),
+            AmendOrder::TEMPLATE_ID => Message::AmendOrder(header.decode_message(buf)?),
+            Ack::TEMPLATE_ID => Message::Ack(header.decode_message(buf)?),
+            Fill::TEMPLATE_ID => Message::Fill(header.decode_message(buf)?),
+            template_id => return Err(Exception::Garbled(format!("Unknown template {template_id}."))),
+        };
+        Ok(Some((message, header.message_length())))
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut buf = Vec::new();
+        NewOrder {
+            client_order_id: u64::MAX,
+            sub_account_id: 1,
+            market_id: 2,
+            price: Some(100.5),
+            size: 3.0,
+            side: OrderSide::Sell,
+            r#type: OrderType::Limit,
+        }.encode_to(&mut buf);
+        Heartbeat.encode_to(&mut buf);
+        AmendOrder {
+            client_order_id: 1,
+            order_id: 2,
+            price: None,
+            size: Some(4.0),
+        }.encode_to(&mut buf);
+
+        let Some((Message::NewOrder(order), consumed)) = Message::decode(&buf).unwrap() else {
+            panic!("Expected a NewOrder.");
+        };
+        assert_eq!(order.client_order_id, u64::MAX);
+        assert_eq!(order.price, Some(100.5));
+        assert_eq!(order.side, OrderSide::Sell);
+        buf.drain(..consumed);
+        assert!(matches!(Message::decode(&buf).unwrap(), Some((Message::Heartbeat, 8))));
+        buf.drain(..8);
+        let Some((Message::AmendOrder(amend), _)) = Message::decode(&buf).unwrap() else {
+            panic!("Expected an AmendOrder.");
+        };
+        assert_eq!(amend.price, None);
+        assert_eq!(amend.size, Some(4.0));
+    }
+
+    #[test]
+    fn incomplete() {
+        let buf = Ack {
+            client_order_id: 1,
+            order_id: 1,
+            remaining_size: 1.0,
+            status: AckStatus::Rejected,
+            reason: RejectReason::UnknownOrder,
+        }.encode();
+        assert!(Message::decode(&buf[..4]).unwrap().is_none());
+        assert!(Message::decode(&buf[..buf.len() - 1]).unwrap().is_none());
+        let Some((Message::Ack(ack), _)) = Message::decode(&buf).unwrap() else {
+            panic!("Expected an Ack.");
+        };
+        assert_eq!(ack.reason, RejectReason::UnknownOrder);
+    }
+
+    #[test]
+    fn garbled() {
+        let mut buf = Heartbeat.encode();
+        buf[2] = 99; // Unknown template
+        assert!(Message::decode(&buf).is_err());
+    }
+}
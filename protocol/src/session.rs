@@ -0,0 +1,267 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::channel::{self, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+
+use database::{DbErr, Mutation};
+use database::orders::{Command, MassCancel, Order};
+
+use crate::auth;
+use crate::error::Exception;
+use crate::message::{Ack, AckStatus, AmendOrder, CancelOrder, Logon, Logout, Message, NewOrder, RejectReason};
+use crate::{Gateway, OrderState};
+
+// ----------------------------------------------------------------------
+
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_HEARTBEAT_INTERVAL: u16 = 30;
+
+async fn read_message(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Result<Option<Message>, Exception> {
+    loop {
+        if let Some((message, consumed)) = Message::decode(buf)? {
+            buf.drain(..consumed);
+            return Ok(Some(message));
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.map_err(Exception::Io)?;
+        if n == 0 {
+            return Ok(None); // Connection closed by the counterparty
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn reject_reason(e: &DbErr) -> RejectReason {
+    match e {
+        DbErr::RecordNotFound(_) => RejectReason::UnknownOrder,
+        _ => RejectReason::InvalidRequest,
+    }
+}
+
+// ----------------------------------------------------------------------
+
+struct Session {
+    gateway: Arc<Gateway>,
+    client_id: i32,
+    outbound: Sender<Message>,
+    sub_accounts: HashSet<i32>, // Sub-accounts which orders were entered for through the session
+}
+
+/// Runs a session over an accepted connection from Logon until Logout or disconnection.
+pub(crate) async fn handle(gateway: Arc<Gateway>, mut stream: TcpStream) -> Result<(), Exception> {
+    let mut buf = Vec::with_capacity(4096);
+    let logon = match timeout(LOGON_TIMEOUT, read_message(&mut stream, &mut buf)).await {
+        Ok(Ok(Some(Message::Logon(logon)))) => logon,
+        Ok(Err(e)) => return Err(e),
+        _ => return Ok(()), // Silently drop connections which do not log on
+    };
+    let heartbeat_interval = match logon.heartbeat_interval {
+        0 => DEFAULT_HEARTBEAT_INTERVAL,
+        heartbeat_interval => heartbeat_interval,
+    };
+
+    let (outbound, receiver) = channel::unbounded();
+    let writer = task::spawn(write_messages(
+        stream.clone(),
+        receiver,
+        Duration::from_secs(heartbeat_interval as u64),
+    ));
+
+    // Authenticate the client and register the session
+    let fields = logon.fields();
+    let client = auth::authenticate(
+        &gateway.db,
+        &hex::encode(logon.key),
+        logon.timestamp,
+        logon.nonce,
+        &fields.each_ref().map(String::as_str),
+        &logon.signature,
+    ).await.and_then(|api_key| match api_key.client_id == logon.client_id {
+        true => Ok(api_key),
+        false => Err(Exception::Unauthorized(format!("API key does not belong to client {}.", logon.client_id))),
+    });
+    let registered = client.is_ok() && match gateway.sessions.lock().unwrap().entry(logon.client_id) {
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(outbound.clone());
+            true
+        }
+    };
+    let mut session = Session {
+        gateway: gateway.clone(),
+        client_id: logon.client_id,
+        outbound,
+        sub_accounts: HashSet::new(),
+    };
+    let result = if client.is_err() {
+        session.send(Message::Logout(Logout { reason: RejectReason::Unauthorized }));
+        Ok(())
+    } else if !registered {
+        session.send(Message::Logout(Logout { reason: RejectReason::DuplicateSession }));
+        Ok(())
+    } else {
+        session.send(Message::Logon(Logon::new(logon.client_id, heartbeat_interval, logon.cancel_on_disconnect)));
+        let result = session.run(&mut stream, &mut buf, Duration::from_secs(heartbeat_interval as u64)).await;
+        let cancelled = if logon.cancel_on_disconnect {
+            session.cancel_orders().await
+        } else {
+            Ok(())
+        };
+        gateway.sessions.lock().unwrap().remove(&logon.client_id);
+        result.and(cancelled)
+    };
+    drop(session); // Close the outbound queue so that the writer flushes and exits
+    writer.await;
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    result
+}
+
+/// Writes outbound messages, sending a Heartbeat whenever the session has been idle for the heartbeat interval.
+async fn write_messages(mut stream: TcpStream, receiver: Receiver<Message>, heartbeat_interval: Duration) {
+    loop {
+        let message = match timeout(heartbeat_interval, receiver.recv()).await {
+            Ok(Ok(message)) => message,
+            Ok(Err(_)) => break, // Session has ended
+            Err(_) => Message::Heartbeat,
+        };
+        if stream.write_all(&message.encode()).await.is_err() {
+            break;
+        }
+    }
+}
+
+impl Session {
+    fn send(&self, message: Message) {
+        let _ = self.outbound.try_send(message);
+    }
+
+    async fn run(&mut self, stream: &mut TcpStream, buf: &mut Vec<u8>, heartbeat_interval: Duration) -> Result<(), Exception> {
+        loop {
+            match timeout(heartbeat_interval * 2, read_message(stream, buf)).await {
+                Ok(Ok(Some(Message::Logout(_)))) => {
+                    self.send(Message::Logout(Logout { reason: RejectReason::None }));
+                    return Ok(());
+                }
+                Ok(Ok(Some(message))) => self.on_message(message).await?,
+                Ok(Ok(None)) => return Ok(()),
+                Ok(Err(e)) => return Err(e), // Framing cannot be recovered from a garbled binary stream
+                Err(_) => {
+                    self.send(Message::Logout(Logout { reason: RejectReason::HeartbeatTimeout }));
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn on_message(&mut self, message: Message) -> Result<(), Exception> {
+        match message {
+            Message::NewOrder(order) => self.on_new_order(order).await,
+            Message::CancelOrder(cancel) => self.on_cancel_order(cancel).await,
+            Message::AmendOrder(amend) => self.on_amend_order(amend).await,
+            _ => Ok(()), // Heartbeats and messages only sent by the gateway are ignored
+        }
+    }
+
+    async fn on_new_order(&mut self, order: NewOrder) -> Result<(), Exception> {
+        let created = Mutation::create_order(
+            &self.gateway.db,
+            self.client_id,
+            order.sub_account_id,
+            order.size,
+            order.side,
+            order.r#type,
+            order.price,
+            Some(order.client_order_id.to_string()),
+            Some(order.market_id),
+            None,
+            None,
+        ).await;
+        match created {
+            Ok(created) => {
+                let state = OrderState {
+                    client_id: self.client_id,
+                    size: created.size,
+                    filled_size: 0.0,
+                    cancel_client_order_id: None,
+                };
+                self.gateway.orders.lock().unwrap().insert(created.id, state);
+                self.sub_accounts.insert(created.sub_account_id);
+                self.gateway.publish(&Command::New(created.clone())).await?;
+                self.ack(order.client_order_id, &created, AckStatus::New);
+            }
+            Err(e) => self.reject(order.client_order_id, 0, reject_reason(&e)),
+        }
+        Ok(())
+    }
+
+    /// Sends the cancel to the matching engine, which is acknowledged once the engine pulls the order so that fills
+    /// already in flight are forwarded first.
+    async fn on_cancel_order(&mut self, cancel: CancelOrder) -> Result<(), Exception> {
+        match Mutation::cancel_order(&self.gateway.db, self.client_id, cancel.order_id).await {
+            Ok(cancelled) => {
+                self.gateway.orders.lock().unwrap()
+                    .entry(cancel.order_id)
+                    .or_insert(OrderState { // Entered elsewhere, so only its remaining size is known
+                        client_id: self.client_id,
+                        size: cancelled.size,
+                        filled_size: 0.0,
+                        cancel_client_order_id: None,
+                    })
+                    .cancel_client_order_id = Some(cancel.client_order_id);
+                self.gateway.publish(&Command::Cancel(cancelled)).await?;
+            }
+            Err(e) => self.reject(cancel.client_order_id, cancel.order_id, reject_reason(&e)),
+        }
+        Ok(())
+    }
+
+    async fn on_amend_order(&mut self, amend: AmendOrder) -> Result<(), Exception> {
+        match Mutation::amend_order(&self.gateway.db, self.client_id, amend.order_id, amend.price, amend.size).await {
+            Ok(amended) => {
+                if let Some(order) = self.gateway.orders.lock().unwrap().get_mut(&amend.order_id) {
+                    order.size = amend.size.unwrap_or(order.size);
+                }
+                self.gateway.publish(&Command::Amend(amended.clone())).await?;
+                self.ack(amend.client_order_id, &amended, AckStatus::Amended);
+            }
+            Err(e) => self.reject(amend.client_order_id, amend.order_id, reject_reason(&e)),
+        }
+        Ok(())
+    }
+
+    /// Pulls the resting orders of the sub-accounts traded through the session at once. The matching engine reports
+    /// every order it pulls, which closes it in the database.
+    async fn cancel_orders(&self) -> Result<(), Exception> {
+        if self.sub_accounts.is_empty() {
+            return Ok(());
+        }
+        let sub_account_ids = self.sub_accounts.iter().copied().collect();
+        self.gateway.publish(&MassCancel { market_id: None, sub_account_ids, side: None }).await
+    }
+
+    fn ack(&self, client_order_id: u64, order: &Order, status: AckStatus) {
+        self.send(Message::Ack(Ack {
+            client_order_id,
+            order_id: order.id,
+            remaining_size: order.size,
+            status,
+            reason: RejectReason::None,
+        }));
+    }
+
+    fn reject(&self, client_order_id: u64, order_id: i32, reason: RejectReason) {
+        self.send(Message::Ack(Ack {
+            client_order_id,
+            order_id,
+            remaining_size: 0.0,
+            status: AckStatus::Rejected,
+            reason,
+        }));
+    }
+}
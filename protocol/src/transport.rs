@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use derive_more::{Display, Error};
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::future::{self, BoxFuture, LocalBoxFuture};
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use prometheus::{register_histogram_vec, HistogramVec};
+
+use config::{Config, StreamsConfig};
+
+use rabbitmq_stream_client::{Environment, NoDedup, Producer};
+use rabbitmq_stream_client::error::{
+    ClientError, ConsumerCreateError, ProducerCreateError, ProducerPublishError, StreamCreateError,
+};
+use rabbitmq_stream_client::types::{ByteCapacity, Delivery, Message, OffsetSpecification, ResponseCode, SimpleValue};
+
+// ----------------------------------------------------------------------
+
+const PUBLISHED_AT: &str = "published_at"; // Application property of RabbitMQ messages, in microseconds since the epoch
+
+static PUBLISH_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "stream_publish_duration_seconds",
+        "Time until a message published to a stream is confirmed by the broker.",
+        &["stream"],
+        vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25]
+    )
+    .unwrap()
+});
+
+static CONSUMER_LAG: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "stream_consumer_lag_seconds",
+        "Time from the publication of a message until a subscriber of the stream consumes it.",
+        &["stream"],
+        vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0]
+    )
+    .unwrap()
+});
+
+/// A stream of the matching engine. Commands flow into the engine on the orders stream, fills and market data flow out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Orders, // Input
+    Fills,
+    MarketData,
+}
+
+/// Where a subscription starts reading its stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Offset {
+    First, // The oldest message retained by the stream
+    Next, // The first message published after subscribing
+    At(u64), // The message at the offset, waiting for it to be published if the stream is shorter
+}
+
+/// The messages of a stream, each with its offset in the stream.
+pub type OffsetStream = BoxStream<'static, (u64, Vec<u8>)>;
+
+#[derive(Debug, Display, Error)]
+pub enum TransportError {
+    Connect(ClientError),
+    CreateStream(StreamCreateError),
+    CreateProducer(ProducerCreateError),
+    CreateConsumer(ConsumerCreateError),
+    Publish(ProducerPublishError),
+    #[display(fmt = "The transport is closed.")]
+    Closed,
+}
+
+/// Carries the commands and events of the matching engine between the services of the exchange.
+pub trait Transport: Send + Sync {
+    /// Creates the stream, discarding any messages retained from a previous run. Called by the service which owns
+    /// the stream before it publishes to it.
+    fn create(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>>;
+
+    /// Creates the stream unless it exists, keeping the messages it retains and their offsets, for streams which
+    /// consumers resume from a stored offset.
+    fn declare(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>>;
+
+    /// Publishes a message, returning once it is stored by the transport.
+    fn publish(&self, channel: Channel, data: Vec<u8>) -> BoxFuture<'_, Result<(), TransportError>>;
+
+    /// Subscribes to the messages of the stream. The subscription ends when the transport fails or is closed. The
+    /// returned future is not Send, since subscribing to RabbitMQ is not.
+    fn subscribe(
+        &self,
+        channel: Channel,
+        offset: Offset,
+    ) -> LocalBoxFuture<'_, Result<BoxStream<'static, Vec<u8>>, TransportError>> {
+        self.subscribe_with_offsets(channel, offset)
+            .map(|subscription| subscription.map(|messages| messages.map(|(_, data)| data).boxed()))
+            .boxed_local()
+    }
+
+    /// Subscribes to the messages of the stream along with their offsets, from which a consumer resumes with
+    /// `Offset::At` after the last message it processed.
+    fn subscribe_with_offsets(
+        &self,
+        channel: Channel,
+        offset: Offset,
+    ) -> LocalBoxFuture<'_, Result<OffsetStream, TransportError>>;
+
+    /// Stops publishing. Messages which were not yet confirmed are lost.
+    fn close(&self) -> BoxFuture<'_, ()>;
+}
+
+// ----------------------------------------------------------------------
+
+/// RabbitMQ Streams, with a producer per stream which is built on the first publication.
+pub struct RabbitMQ {
+    environment: Environment,
+    streams: StreamsConfig,
+    producers: async_std::sync::Mutex<HashMap<Channel, Producer<NoDedup>>>,
+}
+
+impl RabbitMQ {
+    /// Connects to the stream port of the configured broker.
+    pub async fn connect() -> Result<Self, TransportError> {
+        Self::connect_with(config::get()).await
+    }
+
+    /// Connects to the stream port of the broker of the configuration, whose streams it names and retains.
+    pub async fn connect_with(config: &Config) -> Result<Self, TransportError> {
+        let environment = Environment::builder()
+            .host(&config.transport.host)
+            .port(config.transport.port)
+            .build()
+            .await
+            .map_err(TransportError::Connect)?;
+        Ok(RabbitMQ { environment, streams: config.streams.clone(), producers: Default::default() })
+    }
+
+    /// The maximum length and age of a stream. Fills are kept longer so that the fill persister can catch up.
+    fn retention(&self, channel: Channel) -> (ByteCapacity, Duration) {
+        let (max_length_mb, max_age_seconds) = match channel {
+            Channel::Fills => (self.streams.fills_max_length_mb, self.streams.fills_max_age_seconds),
+            Channel::Orders | Channel::MarketData => (self.streams.max_length_mb, self.streams.max_age_seconds),
+        };
+        (ByteCapacity::MB(max_length_mb), Duration::from_secs(max_age_seconds))
+    }
+
+    fn stream(&self, channel: Channel) -> &str {
+        match channel {
+            Channel::Orders => &self.streams.orders,
+            Channel::Fills => &self.streams.fills,
+            Channel::MarketData => &self.streams.market_data,
+        }
+    }
+
+    async fn producer(&self, channel: Channel) -> Result<Producer<NoDedup>, TransportError> {
+        let mut producers = self.producers.lock().await;
+        if let Some(producer) = producers.get(&channel) {
+            return Ok(producer.clone());
+        }
+        let producer = self.environment
+            .producer()
+            .build(self.stream(channel))
+            .await
+            .map_err(TransportError::CreateProducer)?;
+        producers.insert(channel, producer.clone());
+        Ok(producer)
+    }
+}
+
+impl Transport for RabbitMQ {
+    fn create(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>> {
+        async move {
+            self.producers.lock().await.remove(&channel); // Producers of a deleted stream can not publish
+            let _ = self.environment.delete_stream(self.stream(channel)).await; // Delete stream if it exists
+            let (max_length, max_age) = self.retention(channel);
+            self.environment
+                .stream_creator()
+                .max_length(max_length)
+                .max_age(max_age)
+                .create(self.stream(channel))
+                .await
+                .map_err(TransportError::CreateStream)
+        }.boxed()
+    }
+
+    fn declare(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>> {
+        async move {
+            let (max_length, max_age) = self.retention(channel);
+            let created = self.environment
+                .stream_creator()
+                .max_length(max_length)
+                .max_age(max_age)
+                .create(self.stream(channel))
+                .await;
+            match created {
+                Err(StreamCreateError::Create { status: ResponseCode::StreamAlreadyExists, .. }) => Ok(()),
+                created => created.map_err(TransportError::CreateStream),
+            }
+        }.boxed()
+    }
+
+    fn publish(&self, channel: Channel, data: Vec<u8>) -> BoxFuture<'_, Result<(), TransportError>> {
+        async move {
+            let producer = self.producer(channel).await?;
+            let start = Instant::now();
+            let message = Message::builder()
+                .body(data)
+                .application_properties()
+                .insert(PUBLISHED_AT, Utc::now().timestamp_micros()) // Measures the lag of consumers
+                .message_builder()
+                .build();
+            producer.send_with_confirm(message).await.map_err(TransportError::Publish)?;
+            PUBLISH_DURATION.with_label_values(&[self.stream(channel)]).observe(start.elapsed().as_secs_f64());
+            Ok(())
+        }.boxed()
+    }
+
+    fn subscribe_with_offsets(
+        &self,
+        channel: Channel,
+        offset: Offset,
+    ) -> LocalBoxFuture<'_, Result<OffsetStream, TransportError>> {
+        async move {
+            let consumer = self.environment
+                .consumer()
+                .offset(match offset {
+                    Offset::First => OffsetSpecification::First,
+                    Offset::Next => OffsetSpecification::Next,
+                    Offset::At(offset) => OffsetSpecification::Offset(offset),
+                })
+                .build(self.stream(channel))
+                .await
+                .map_err(TransportError::CreateConsumer)?;
+            let lag = CONSUMER_LAG.with_label_values(&[self.stream(channel)]);
+            Ok(consumer
+                .take_while(|delivery| future::ready(delivery.is_ok())) // TODO: Handle errors
+                .filter_map(move |delivery| {
+                    let delivery = delivery.ok();
+                    if let Some(published_at) = delivery.as_ref().and_then(published_at) {
+                        lag.observe((Utc::now().timestamp_micros() - published_at).max(0) as f64 / 1e6);
+                    }
+                    future::ready(delivery.and_then(|delivery| {
+                        delivery.message().data().map(|data| (delivery.offset(), data.to_vec()))
+                    }))
+                })
+                .boxed())
+        }.boxed_local()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        async move {
+            for (_, producer) in self.producers.lock().await.drain() {
+                let _ = producer.close().await;
+            }
+        }.boxed()
+    }
+}
+
+/// When the message was published, unless it was published before it was stamped.
+fn published_at(delivery: &Delivery) -> Option<i64> {
+    match delivery.message().application_properties()?.get(PUBLISHED_AT)? {
+        SimpleValue::Long(micros) => Some(*micros),
+        _ => None,
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Channels within the process, for running the exchange in tests without a broker. Every message published since
+/// a stream was created is retained so that subscriptions can start from the first, unless it is truncated.
+#[derive(Default)]
+pub struct InMemory {
+    streams: Mutex<HashMap<Channel, Log>>,
+    closed: AtomicBool,
+}
+
+#[derive(Default)]
+struct Log {
+    first: u64, // Offset of the first retained message
+    messages: Vec<Vec<u8>>, // Indexed by offset from the first
+    subscribers: Vec<UnboundedSender<(u64, Vec<u8>)>>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the messages of a channel before the offset, as the retention of a broker would.
+    pub fn truncate(&self, channel: Channel, offset: u64) {
+        let mut streams = self.streams.lock().unwrap();
+        let log = streams.entry(channel).or_default();
+        let dropped = offset.saturating_sub(log.first).min(log.messages.len() as u64);
+        log.messages.drain(..dropped as usize);
+        log.first += dropped;
+    }
+}
+
+impl Transport for InMemory {
+    fn create(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>> {
+        let mut streams = self.streams.lock().unwrap();
+        let log = streams.entry(channel).or_default();
+        log.messages.clear(); // Subscribers are kept
+        log.first = 0;
+        future::ready(Ok(())).boxed()
+    }
+
+    fn declare(&self, channel: Channel) -> BoxFuture<'_, Result<(), TransportError>> {
+        self.streams.lock().unwrap().entry(channel).or_default();
+        future::ready(Ok(())).boxed()
+    }
+
+    fn publish(&self, channel: Channel, data: Vec<u8>) -> BoxFuture<'_, Result<(), TransportError>> {
+        if self.closed.load(Ordering::SeqCst) {
+            return future::ready(Err(TransportError::Closed)).boxed();
+        }
+        let mut streams = self.streams.lock().unwrap();
+        let log = streams.entry(channel).or_default();
+        let offset = log.first + log.messages.len() as u64;
+        log.subscribers.retain(|subscriber| subscriber.unbounded_send((offset, data.clone())).is_ok());
+        log.messages.push(data);
+        future::ready(Ok(())).boxed()
+    }
+
+    fn subscribe_with_offsets(
+        &self,
+        channel: Channel,
+        offset: Offset,
+    ) -> LocalBoxFuture<'_, Result<OffsetStream, TransportError>> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut streams = self.streams.lock().unwrap();
+        let log = streams.entry(channel).or_default();
+        let first = match offset {
+            Offset::First => log.first,
+            Offset::Next => log.first + log.messages.len() as u64,
+            Offset::At(offset) => offset,
+        };
+        for (offset, data) in (log.first..).zip(&log.messages).skip_while(|(offset, _)| *offset < first) {
+            let _ = sender.unbounded_send((offset, data.clone()));
+        }
+        if !self.closed.load(Ordering::SeqCst) {
+            log.subscribers.push(sender); // Otherwise the subscription ends once the retained messages are read
+        }
+        let messages = receiver.filter(move |(offset, _)| future::ready(*offset >= first));
+        future::ready(Ok(messages.boxed())).boxed_local()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.closed.store(true, Ordering::SeqCst);
+        for log in self.streams.lock().unwrap().values_mut() {
+            log.subscribers.clear();
+        }
+        future::ready(()).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn in_memory() {
+        let transport = InMemory::new();
+        transport.publish(Channel::Orders, vec![1]).await.unwrap();
+        let mut next = transport.subscribe(Channel::Orders, Offset::Next).await.unwrap();
+        transport.publish(Channel::Orders, vec![2]).await.unwrap();
+        transport.publish(Channel::Fills, vec![3]).await.unwrap();
+        let first = transport.subscribe(Channel::Orders, Offset::First).await.unwrap();
+        transport.publish(Channel::Orders, vec![4]).await.unwrap();
+        transport.close().await;
+        assert!(transport.publish(Channel::Orders, vec![5]).await.is_err());
+        assert_eq!(next.next().await, Some(vec![2]));
+        assert_eq!(next.collect::<Vec<_>>().await, vec![vec![4]]);
+        assert_eq!(first.collect::<Vec<_>>().await, vec![vec![1], vec![2], vec![4]]);
+
+        // Subscriptions after closing replay the retained messages, and creating a stream discards them
+        let late = transport.subscribe(Channel::Fills, Offset::First).await.unwrap();
+        assert_eq!(late.collect::<Vec<_>>().await, vec![vec![3]]);
+        transport.create(Channel::Fills).await.unwrap();
+        let empty = transport.subscribe(Channel::Fills, Offset::First).await.unwrap();
+        assert!(empty.collect::<Vec<_>>().await.is_empty());
+    }
+
+    #[async_std::test]
+    async fn offsets() {
+        let transport = InMemory::new();
+        for data in [vec![1], vec![2]] {
+            transport.publish(Channel::Fills, data).await.unwrap();
+        }
+        let mut resumed = transport.subscribe_with_offsets(Channel::Fills, Offset::At(1)).await.unwrap();
+        let mut ahead = transport.subscribe_with_offsets(Channel::Fills, Offset::At(3)).await.unwrap();
+        for data in [vec![3], vec![4]] {
+            transport.publish(Channel::Fills, data).await.unwrap();
+        }
+        assert_eq!(resumed.next().await, Some((1, vec![2])));
+        assert_eq!(resumed.next().await, Some((2, vec![3])));
+        assert_eq!(ahead.next().await, Some((3, vec![4])));
+
+        // Declaring a stream keeps the messages it retains
+        transport.declare(Channel::Fills).await.unwrap();
+        transport.close().await;
+        let first = transport.subscribe_with_offsets(Channel::Fills, Offset::First).await.unwrap();
+        assert_eq!(first.map(|(offset, _)| offset).collect::<Vec<_>>().await, vec![0, 1, 2, 3]);
+
+        // Messages beyond the retention are no longer delivered, while the others keep their offsets
+        transport.truncate(Channel::Fills, 2);
+        let truncated = transport.subscribe_with_offsets(Channel::Fills, Offset::At(1)).await.unwrap();
+        assert_eq!(truncated.map(|(offset, _)| offset).collect::<Vec<_>>().await, vec![2, 3]);
+    }
+}
@@ -0,0 +1,304 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use chrono::Utc;
+
+use database::{Engine, Migrator, MigratorTrait, Mutation, OrderSide, OrderType};
+use database::api_keys;
+use database::fills::Fill;
+use database::orders::{Cancelled, Command, MassCancel};
+use futures::stream::BoxStream;
+use protocol::message::{AckStatus, AmendOrder, CancelOrder, Logon, Logout, NewOrder, RejectReason};
+use protocol::{Channel, Codec, Gateway, InMemory, Message, Offset, Transport};
+
+// ----------------------------------------------------------------------
+
+/// A minimal binary order entry client for driving the gateway.
+struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    async fn connect(address: std::net::SocketAddr) -> Self {
+        Client {
+            stream: TcpStream::connect(address).await.unwrap(),
+            buf: Vec::new(),
+        }
+    }
+
+    async fn send(&mut self, message: impl Codec) {
+        self.stream.write_all(&message.encode()).await.unwrap();
+    }
+
+    /// Receives the next message which is not a Heartbeat.
+    async fn receive(&mut self) -> Message {
+        loop {
+            match self.receive_any().await {
+                Message::Heartbeat => {}
+                message => return message,
+            }
+        }
+    }
+
+    async fn receive_any(&mut self) -> Message {
+        loop {
+            if let Some((message, consumed)) = Message::decode(&self.buf).unwrap() {
+                self.buf.drain(..consumed);
+                return message;
+            }
+            let mut chunk = [0u8; 4096];
+            let n = self.stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "Connection closed by the gateway.");
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    async fn logon(&mut self, client_id: i32, key: &api_keys::Model) -> Message {
+        self.send(sign(Logon::new(client_id, 1, false), key)).await;
+        self.receive().await
+    }
+}
+
+static NONCE: AtomicI64 = AtomicI64::new(0);
+
+fn sign(logon: Logon, key: &api_keys::Model) -> Logon {
+    let nonce = NONCE.fetch_add(1, Ordering::Relaxed) + 1;
+    logon.sign(&key.key, &key.secret, Utc::now().timestamp_millis(), nonce)
+}
+
+fn new_order(client_order_id: u64, market_id: i32) -> NewOrder {
+    NewOrder {
+        client_order_id,
+        sub_account_id: 1,
+        market_id,
+        price: Some(100.0),
+        size: 10.0,
+        side: OrderSide::Buy,
+        r#type: OrderType::Limit,
+    }
+}
+
+/// Reads the orders stream up to the next cancel of an order.
+async fn next_cancel(orders: &mut BoxStream<'static, Vec<u8>>) {
+    while !matches!(Command::decode(&orders.next().await.unwrap()), Ok(Command::Cancel(_))) {}
+}
+
+/// Reads the orders stream up to the next mass cancel, returning it with the number of commands published before it.
+async fn next_mass_cancel(orders: &mut BoxStream<'static, Vec<u8>>) -> (usize, MassCancel) {
+    let mut commands = 0;
+    loop {
+        match MassCancel::decode(&orders.next().await.unwrap()) {
+            Ok(mass_cancel) => return (commands, mass_cancel),
+            Err(_) => commands += 1,
+        }
+    }
+}
+
+#[async_std::test]
+async fn main() {
+    // Set up
+    let db = Engine::connect().await.unwrap();
+    Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
+    let _ = Mutation::create_client(&db, "a@gmail.com".to_owned()).await;
+    let _ = Mutation::create_sub_account(&db, 1, "Test".to_owned()).await;
+    let _ = Mutation::create_market(&db, "BTC".to_owned(), "USD".to_owned(), 0.01, 0.01).await;
+    let key = Mutation::create_api_key(&db, 1, None, true, true, false).await.unwrap();
+
+    // Mock gateway
+    let transport = Arc::new(InMemory::new());
+    let gateway = Arc::new(Gateway::new(db.clone(), Some(transport.clone())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    task::spawn(gateway.clone().serve(listener));
+
+    // Unsigned logon
+    let mut client = Client::connect(address).await;
+    client.send(Logon::new(1, 1, false)).await;
+    let Message::Logout(logout) = client.receive().await else {
+        panic!("Expected a Logout.");
+    };
+    assert_eq!(logout.reason, RejectReason::Unauthorized);
+
+    // Key of another client
+    let mut client = Client::connect(address).await;
+    let Message::Logout(logout) = client.logon(2, &key).await else {
+        panic!("Expected a Logout.");
+    };
+    assert_eq!(logout.reason, RejectReason::Unauthorized);
+
+    // Replayed logon
+    let logon = sign(Logon::new(1, 1, false), &key);
+    let mut client = Client::connect(address).await;
+    client.send(logon).await;
+    assert!(matches!(client.receive().await, Message::Logon(_)));
+    client.send(Logout { reason: RejectReason::None }).await;
+    assert!(matches!(client.receive().await, Message::Logout(_)));
+    let mut client = Client::connect(address).await;
+    client.send(logon).await;
+    let Message::Logout(logout) = client.receive().await else {
+        panic!("Expected a Logout.");
+    };
+    assert_eq!(logout.reason, RejectReason::Unauthorized);
+
+    // Log on
+    let mut client = Client::connect(address).await;
+    assert!(matches!(client.logon(1, &key).await, Message::Logon(Logon { client_id: 1, heartbeat_interval: 1, .. })));
+
+    // Duplicate session
+    let mut duplicate = Client::connect(address).await;
+    let Message::Logout(logout) = duplicate.logon(1, &key).await else {
+        panic!("Expected a Logout.");
+    };
+    assert_eq!(logout.reason, RejectReason::DuplicateSession);
+
+    // Idle session heartbeats
+    assert!(matches!(client.receive_any().await, Message::Heartbeat));
+
+    // New order
+    client.send(new_order(1, 1)).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!(ack.client_order_id, 1);
+    assert_eq!(ack.status, AckStatus::New);
+    assert_eq!(ack.remaining_size, 10.0);
+    let order_id = ack.order_id;
+
+    // Rejected order
+    client.send(new_order(2, 99)).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!(ack.status, AckStatus::Rejected);
+
+    // Fill from the engine
+    gateway.on_fill(Fill {
+        price: 100.0,
+        size: 4.0,
+        quote_size: 400.0,
+        side: OrderSide::Buy,
+        r#type: OrderType::Limit,
+        aggressor: Some(OrderSide::Buy),
+        created_at: Utc::now().naive_utc(),
+        sub_account_id: 1,
+        market_id: 1,
+        order_id,
+    });
+    let Message::Fill(fill) = client.receive().await else {
+        panic!("Expected a Fill.");
+    };
+    assert_eq!(fill.order_id, order_id);
+    assert_eq!(fill.size, 4.0);
+
+    // Amend
+    client.send(AmendOrder { client_order_id: 3, order_id, price: Some(101.0), size: Some(20.0) }).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!(ack.status, AckStatus::Amended);
+    assert_eq!(ack.order_id, order_id);
+
+    // Cancel, acknowledged once the engine pulls the order
+    let mut orders = transport.subscribe(Channel::Orders, Offset::Next).await.unwrap();
+    client.send(CancelOrder { client_order_id: 4, order_id }).await;
+    next_cancel(&mut orders).await;
+    let cancelled = Cancelled {
+        order_id,
+        sub_account_id: 1,
+        market_id: 1,
+        remaining_size: 16.0,
+        cancelled_at: Utc::now().naive_utc(),
+    };
+    Mutation::close_cancelled_order(&db, &cancelled).await.unwrap();
+    gateway.on_cancelled(cancelled);
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!((ack.client_order_id, ack.status, ack.remaining_size), (4, AckStatus::Cancelled, 16.0));
+
+    // Cancel unknown
+    client.send(CancelOrder { client_order_id: 5, order_id }).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!(ack.status, AckStatus::Rejected);
+    assert_eq!(ack.reason, RejectReason::UnknownOrder);
+
+    // Cancel of an order filled before the engine pulls it
+    client.send(new_order(9, 1)).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    let filled = ack.order_id;
+    client.send(CancelOrder { client_order_id: 10, order_id: filled }).await;
+    next_cancel(&mut orders).await;
+    gateway.on_fill(Fill { order_id: filled, size: 10.0, quote_size: 1000.0, ..fill });
+    assert!(matches!(client.receive().await, Message::Fill(_)));
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!((ack.client_order_id, ack.status, ack.reason), (10, AckStatus::Rejected, RejectReason::UnknownOrder));
+
+    // Mass cancel from the engine, which filled orders are no longer part of
+    gateway.on_cancelled(Cancelled {
+        order_id: filled,
+        sub_account_id: 1,
+        market_id: 1,
+        remaining_size: 10.0,
+        cancelled_at: Utc::now().naive_utc(),
+    });
+    client.send(new_order(8, 1)).await;
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    gateway.on_cancelled(Cancelled {
+        order_id: ack.order_id,
+        sub_account_id: 1,
+        market_id: 1,
+        remaining_size: 10.0,
+        cancelled_at: Utc::now().naive_utc(),
+    });
+    let Message::Ack(ack) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    assert_eq!(ack.status, AckStatus::Cancelled);
+
+    // Log out
+    client.send(Logout { reason: RejectReason::None }).await;
+    assert!(matches!(client.receive().await, Message::Logout(_)));
+
+    // Orders remain open after a disconnect without cancel-on-disconnect
+    let mut orders = transport.subscribe(Channel::Orders, Offset::Next).await.unwrap();
+    let mut client = Client::connect(address).await;
+    assert!(matches!(client.logon(1, &key).await, Message::Logon(_)));
+    client.send(new_order(6, 1)).await;
+    let Message::Ack(kept) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    drop(client);
+    task::sleep(std::time::Duration::from_millis(100)).await; // Let the gateway end the session
+
+    // Cancel on disconnect once the heartbeat lapses
+    let mut client = Client::connect(address).await;
+    client.send(sign(Logon::new(1, 1, true), &key)).await;
+    assert!(matches!(client.receive().await, Message::Logon(Logon { cancel_on_disconnect: true, .. })));
+    client.send(new_order(7, 1)).await;
+    let Message::Ack(pulled) = client.receive().await else {
+        panic!("Expected an Ack.");
+    };
+    let Message::Logout(logout) = client.receive().await else { // The client stops sending heartbeats
+        panic!("Expected a Logout.");
+    };
+    assert_eq!(logout.reason, RejectReason::HeartbeatTimeout);
+    let (commands, mass_cancel) = next_mass_cancel(&mut orders).await; // The engine closes the orders it pulls
+    assert_eq!(commands, 2);
+    assert_eq!(mass_cancel, MassCancel { market_id: None, sub_account_ids: vec![1], side: None });
+    assert_ne!(pulled.order_id, kept.order_id);
+
+    // Tear down
+    Migrator::reset(&db).await.unwrap(); // Rollback migrations
+}
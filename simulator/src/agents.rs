@@ -0,0 +1,295 @@
+use chrono::NaiveDateTime;
+use database::fills::Fill;
+use database::orders::Order;
+use database::OrderSide;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+// ----------------------------------------------------------------------
+
+/// What an agent observes of the market when it is activated.
+pub struct Market<'a> {
+    pub time: NaiveDateTime,
+    pub best_bid: Option<f32>,
+    pub best_ask: Option<f32>,
+    pub last_price: f32,
+    pub prices: &'a [f32], // Last traded price at the end of each past step, oldest first
+    pub fundamental: f32, // Value of the asset, observed only by fundamentalists
+    pub tick_size: f64,
+}
+
+impl Market<'_> {
+    /// Middle of the best prices. If a side of the book is empty, the last traded price bounded by the other side.
+    pub fn mid(&self) -> f32 {
+        match (self.best_bid, self.best_ask) {
+            (Some(bid), Some(ask)) => (bid + ask) / 2.0,
+            (Some(bid), None) => self.last_price.max(bid),
+            (None, Some(ask)) => self.last_price.min(ask),
+            (None, None) => self.last_price,
+        }
+    }
+
+    /// Rounds the price to the nearest tick, computing in double precision so that prices print as whole ticks.
+    pub fn round(&self, price: f32) -> f32 {
+        ((f64::from(price) / self.tick_size).round().max(1.0) * self.tick_size) as f32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Limit { side: OrderSide, price: f32, size: f32 }, // Bid or Ask
+    Market { side: OrderSide, size: f32 }, // Buy or Sell
+    Cancel(i32),
+}
+
+pub trait Agent {
+    fn kind(&self) -> Kind;
+
+    /// Decides what to do given the market and the orders of the agent which are resting in the book.
+    fn act(&mut self, market: &Market, open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action>;
+
+    /// Called with every fill of an order of the agent.
+    fn filled(&mut self, _fill: &Fill) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    LiquidityProvider,
+    Chartist,
+    Fundamentalist,
+    NoiseTrader,
+    Hawkes,
+}
+
+fn buy(side: &OrderSide) -> bool {
+    matches!(side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long)
+}
+
+/// Size drawn uniformly between half and one and a half times the given size.
+fn size(rng: &mut ChaCha8Rng, size: f32) -> f32 {
+    size * rng.gen_range(0.5..1.5)
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiquidityProviderConfig {
+    pub count: usize,
+    pub activity: f64, // Probability of acting in a step
+    pub spread: u32, // Maximum half spread in ticks
+    pub size: f32,
+    pub max_inventory: f32, // Stops quoting the side which would grow the inventory beyond this
+}
+
+impl Default for LiquidityProviderConfig {
+    fn default() -> Self {
+        LiquidityProviderConfig { count: 5, activity: 0.8, spread: 5, size: 5.0, max_inventory: 50.0 }
+    }
+}
+
+/// A high-frequency market maker which requotes both sides around the mid price. Its quotes are skewed against its
+/// inventory by up to twice the half spread, so that it unloads a full inventory aggressively.
+pub struct LiquidityProvider {
+    config: LiquidityProviderConfig,
+    inventory: f32,
+}
+
+impl LiquidityProvider {
+    pub fn new(config: LiquidityProviderConfig) -> Self {
+        LiquidityProvider { config, inventory: 0.0 }
+    }
+}
+
+impl Agent for LiquidityProvider {
+    fn kind(&self) -> Kind {
+        Kind::LiquidityProvider
+    }
+
+    fn act(&mut self, market: &Market, open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action> {
+        if !rng.gen_bool(self.config.activity) {
+            return Vec::new();
+        }
+        let mut actions: Vec<Action> = open.iter().map(|order| Action::Cancel(order.id)).collect();
+        let half_spread = (rng.gen_range(1..=self.config.spread.max(1)) as f64 * market.tick_size) as f32;
+        let skew = (self.inventory / self.config.max_inventory).clamp(-1.0, 1.0) * 2.0 * half_spread;
+        let mid = market.mid();
+        let bid = market.round(mid - half_spread - skew);
+        let ask = market.round(mid + half_spread - skew).max(market.round(bid + market.tick_size as f32));
+        if self.inventory < self.config.max_inventory {
+            actions.push(Action::Limit { side: OrderSide::Bid, price: bid, size: size(rng, self.config.size) });
+        }
+        if self.inventory > -self.config.max_inventory {
+            actions.push(Action::Limit { side: OrderSide::Ask, price: ask, size: size(rng, self.config.size) });
+        }
+        actions
+    }
+
+    fn filled(&mut self, fill: &Fill) {
+        self.inventory += if buy(&fill.side) { fill.size } else { -fill.size };
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartistConfig {
+    pub count: usize,
+    pub activity: f64,
+    pub short_window: usize, // Steps of the fast moving average
+    pub long_window: usize, // Steps of the slow moving average
+    pub size: f32,
+}
+
+impl Default for ChartistConfig {
+    fn default() -> Self {
+        ChartistConfig { count: 10, activity: 0.05, short_window: 10, long_window: 50, size: 1.0 }
+    }
+}
+
+/// A trend follower which buys when the fast moving average of prices is above the slow one and sells otherwise.
+pub struct Chartist {
+    config: ChartistConfig,
+}
+
+impl Chartist {
+    pub fn new(config: ChartistConfig) -> Self {
+        Chartist { config }
+    }
+}
+
+fn average(prices: &[f32], window: usize) -> f32 {
+    let window = &prices[prices.len() - window..];
+    window.iter().sum::<f32>() / window.len() as f32
+}
+
+impl Agent for Chartist {
+    fn kind(&self) -> Kind {
+        Kind::Chartist
+    }
+
+    fn act(&mut self, market: &Market, _open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action> {
+        let long_window = self.config.long_window.max(self.config.short_window).max(1);
+        if market.prices.len() < long_window || !rng.gen_bool(self.config.activity) {
+            return Vec::new();
+        }
+        let trend = average(market.prices, self.config.short_window.max(1)) - average(market.prices, long_window);
+        if trend == 0.0 {
+            return Vec::new();
+        }
+        let side = if trend > 0.0 { OrderSide::Buy } else { OrderSide::Sell };
+        vec![Action::Market { side, size: size(rng, self.config.size) }]
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FundamentalistConfig {
+    pub count: usize,
+    pub activity: f64,
+    pub threshold: f32, // Relative mispricing below which the agent does not trade
+    pub size: f32, // Size at the threshold, growing with the mispricing up to five times
+}
+
+impl Default for FundamentalistConfig {
+    fn default() -> Self {
+        FundamentalistConfig { count: 10, activity: 0.05, threshold: 0.002, size: 1.0 }
+    }
+}
+
+/// A value investor which buys below the fundamental value and sells above it.
+pub struct Fundamentalist {
+    config: FundamentalistConfig,
+}
+
+impl Fundamentalist {
+    pub fn new(config: FundamentalistConfig) -> Self {
+        Fundamentalist { config }
+    }
+}
+
+impl Agent for Fundamentalist {
+    fn kind(&self) -> Kind {
+        Kind::Fundamentalist
+    }
+
+    fn act(&mut self, market: &Market, _open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action> {
+        if !rng.gen_bool(self.config.activity) {
+            return Vec::new();
+        }
+        let mispricing = (market.fundamental - market.mid()) / market.mid();
+        if mispricing.abs() < self.config.threshold {
+            return Vec::new();
+        }
+        let side = if mispricing > 0.0 { OrderSide::Buy } else { OrderSide::Sell };
+        let scale = (mispricing.abs() / self.config.threshold).min(5.0);
+        vec![Action::Market { side, size: size(rng, self.config.size * scale) }]
+    }
+}
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseTraderConfig {
+    pub count: usize,
+    pub activity: f64,
+    pub market_probability: f64, // Probability of a market rather than a limit order
+    pub offset: f32, // Maximum relative distance of limit orders from the mid price
+    pub lifetime: i64, // Milliseconds after which limit orders are cancelled
+    pub size: f32,
+}
+
+impl Default for NoiseTraderConfig {
+    fn default() -> Self {
+        NoiseTraderConfig { count: 20, activity: 0.1, market_probability: 0.3, offset: 0.005, lifetime: 5000, size: 1.0 }
+    }
+}
+
+/// A zero-intelligence trader which submits market or passive limit orders on a random side.
+pub struct NoiseTrader {
+    config: NoiseTraderConfig,
+}
+
+impl NoiseTrader {
+    pub fn new(config: NoiseTraderConfig) -> Self {
+        NoiseTrader { config }
+    }
+}
+
+impl Agent for NoiseTrader {
+    fn kind(&self) -> Kind {
+        Kind::NoiseTrader
+    }
+
+    fn act(&mut self, market: &Market, open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action> {
+        let mut actions: Vec<Action> = open
+            .iter()
+            .filter(|order| (market.time - order.open_at).num_milliseconds() >= self.config.lifetime)
+            .map(|order| Action::Cancel(order.id))
+            .collect();
+        if !rng.gen_bool(self.config.activity) {
+            return actions;
+        }
+        let buy = rng.gen_bool(0.5);
+        let size = size(rng, self.config.size);
+        if rng.gen_bool(self.config.market_probability) {
+            let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+            actions.push(Action::Market { side, size });
+        } else {
+            let offset = market.mid() * rng.gen_range(0.0..=self.config.offset);
+            let (side, price) = if buy {
+                (OrderSide::Bid, market.round(market.mid() - offset))
+            } else {
+                (OrderSide::Ask, market.round(market.mid() + offset))
+            };
+            actions.push(Action::Limit { side, price, size });
+        }
+        actions
+    }
+}
@@ -0,0 +1,410 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use database::OrderSide;
+use serde::{Deserialize, Serialize};
+
+// ----------------------------------------------------------------------
+
+/// A trade, signed by the side of the aggressive order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trade {
+    pub time: f64, // Seconds
+    pub price: f64,
+    pub size: f64,
+    pub sign: Option<i8>, // 1 if a buyer, -1 if a seller was the aggressor, None for auction uncrosses
+}
+
+/// The best prices at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+    pub time: f64,
+    pub bid: f64,
+    pub ask: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    pub interval: f64, // Seconds between the prices from which returns are computed
+    pub max_lag: usize, // Of autocorrelation functions
+    pub impact_horizon: usize, // Trades after a trade at which its price impact is measured
+    pub impact_buckets: usize, // Size quantiles of the price impact curve
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig { interval: 1.0, max_lag: 20, impact_horizon: 10, impact_buckets: 10 }
+    }
+}
+
+impl AnalyticsConfig {
+    /// Rejects intervals which would not advance through the trades, such as zero or NaN read from a config file.
+    pub fn validate(&self) -> io::Result<()> {
+        if !(self.interval.is_finite() && self.interval > 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The interval must be a positive number of seconds, got {}.", self.interval),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Moments {
+    pub count: usize,
+    pub mean: f64,
+    pub std: f64,
+    pub skewness: f64,
+    pub kurtosis: f64, // Excess kurtosis, zero for normally distributed returns
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Clustering {
+    pub return_acf: Vec<f64>, // From lag 1, near zero in efficient markets
+    pub abs_return_acf: Vec<f64>, // From lag 1, positive and slowly decaying if volatility clusters
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ImpactBucket {
+    pub trades: usize,
+    pub size: f64, // Mean size of the trades in the bucket
+    pub impact: f64, // Mean log price change in the direction of the trade
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Spread {
+    pub count: usize,
+    pub mean: f64,
+    pub std: f64,
+    pub median: f64,
+    pub relative: f64, // Mean spread over the mid price
+    pub acf: Vec<f64>,
+}
+
+/// The stylized facts of a market: fat tailed returns, volatility clustering, a concave price impact, long memory of
+/// trade signs and the dynamics of the spread.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub config: AnalyticsConfig,
+    pub trades: usize,
+    pub duration: f64, // Seconds from the first to the last trade
+    pub returns: Moments,
+    pub volatility_clustering: Clustering,
+    pub price_impact: Vec<ImpactBucket>,
+    pub sign_acf: Vec<f64>, // Of trade signs from lag 1, excluding unsigned trades
+    pub spread: Option<Spread>, // Only if quotes were given
+}
+
+impl Report {
+    /// Computes the report of trades sorted by time, and of quotes sorted by time if any are available.
+    pub fn new(config: AnalyticsConfig, trades: &[Trade], quotes: &[Quote]) -> io::Result<Self> {
+        config.validate()?;
+        let returns = returns(trades, config.interval);
+        let absolute: Vec<f64> = returns.iter().map(|r| r.abs()).collect();
+        let signs: Vec<f64> = trades.iter().filter_map(|trade| trade.sign.map(f64::from)).collect();
+        let spreads: Vec<f64> = quotes.iter().map(|quote| quote.ask - quote.bid).collect();
+        let spread = (!spreads.is_empty()).then(|| {
+            let mut sorted = spreads.clone();
+            sorted.sort_by(f64::total_cmp);
+            let relative: Vec<f64> = quotes
+                .iter()
+                .map(|quote| 2.0 * (quote.ask - quote.bid) / (quote.ask + quote.bid))
+                .collect();
+            Spread {
+                count: spreads.len(),
+                mean: mean(&spreads),
+                std: moments(&spreads).std,
+                median: sorted[sorted.len() / 2],
+                relative: mean(&relative),
+                acf: acf(&spreads, config.max_lag),
+            }
+        });
+        Ok(Report {
+            trades: trades.len(),
+            duration: trades.last().zip(trades.first()).map_or(0.0, |(last, first)| last.time - first.time),
+            returns: moments(&returns),
+            volatility_clustering: Clustering {
+                return_acf: acf(&returns, config.max_lag),
+                abs_return_acf: acf(&absolute, config.max_lag),
+            },
+            price_impact: impact(trades, config.impact_horizon, config.impact_buckets),
+            sign_acf: acf(&signs, config.max_lag),
+            spread,
+            config,
+        })
+    }
+}
+
+/// Log returns of the last traded price in consecutive intervals, which must be positive. Intervals without trades carry
+/// the last price.
+fn returns(trades: &[Trade], interval: f64) -> Vec<f64> {
+    let Some(first) = trades.first() else {
+        return Vec::new();
+    };
+    let mut prices = vec![first.price];
+    let mut end = first.time + interval;
+    for trade in trades {
+        while trade.time >= end {
+            prices.push(*prices.last().unwrap());
+            end += interval;
+        }
+        *prices.last_mut().unwrap() = trade.price;
+    }
+    prices.windows(2).map(|pair| (pair[1] / pair[0]).ln()).collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn moments(values: &[f64]) -> Moments {
+    let mean = mean(values);
+    let central = |power: i32| self::mean(&values.iter().map(|v| (v - mean).powi(power)).collect::<Vec<_>>());
+    let variance = central(2);
+    let (skewness, kurtosis) = if variance > 0.0 {
+        (central(3) / variance.powf(1.5), central(4) / variance.powi(2) - 3.0)
+    } else {
+        (0.0, 0.0)
+    };
+    Moments { count: values.len(), mean, std: variance.sqrt(), skewness, kurtosis }
+}
+
+/// Sample autocorrelation at lags 1 to `max_lag`, or fewer if the series is shorter.
+fn acf(values: &[f64], max_lag: usize) -> Vec<f64> {
+    let mean = mean(values);
+    let deviations: Vec<f64> = values.iter().map(|v| v - mean).collect();
+    let variance: f64 = deviations.iter().map(|d| d * d).sum();
+    (1..=max_lag.min(values.len().saturating_sub(1)))
+        .map(|lag| {
+            if variance == 0.0 {
+                return 0.0;
+            }
+            deviations.iter().zip(&deviations[lag..]).map(|(a, b)| a * b).sum::<f64>() / variance
+        })
+        .collect()
+}
+
+/// Mean signed log price change from the trade before to `horizon` trades after each signed trade, in buckets of equal
+/// numbers of trades sorted by size.
+fn impact(trades: &[Trade], horizon: usize, buckets: usize) -> Vec<ImpactBucket> {
+    let mut impacts: Vec<(f64, f64)> = (1..trades.len().saturating_sub(horizon))
+        .filter_map(|n| {
+            let sign = f64::from(trades[n].sign?);
+            Some((trades[n].size, sign * (trades[n + horizon].price / trades[n - 1].price).ln()))
+        })
+        .collect();
+    if impacts.is_empty() || buckets == 0 {
+        return Vec::new();
+    }
+    impacts.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let chunk = impacts.len().div_ceil(buckets);
+    impacts
+        .chunks(chunk)
+        .map(|bucket| ImpactBucket {
+            trades: bucket.len(),
+            size: mean(&bucket.iter().map(|(size, _)| *size).collect::<Vec<_>>()),
+            impact: mean(&bucket.iter().map(|(_, impact)| *impact).collect::<Vec<_>>()),
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------------
+
+/// A row of the fills or trades written by a simulation or exported from the database. Simulations time rows in
+/// seconds, exports by timestamp.
+#[derive(Deserialize)]
+struct TradeRow {
+    time: Option<f64>,
+    created_at: Option<NaiveDateTime>,
+    side: Option<OrderSide>, // Only fills have sides
+    price: f64,
+    size: f64,
+    aggressor: Option<OrderSide>,
+}
+
+/// Reads trades from the `fills.csv` of a simulation, or from fills or trades exported as CSV from the database. Fills
+/// hold both sides of each trade, of which the side of the aggressor is kept. Times of exports are counted from the
+/// first row. Returns the trades sorted by time.
+pub fn read_trades(reader: impl Read) -> io::Result<Vec<Trade>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut trades = Vec::new();
+    let mut start = None;
+    for row in reader.deserialize() {
+        let row: TradeRow = row?;
+        let time = match (row.time, row.created_at) {
+            (Some(time), _) => time,
+            (None, Some(created_at)) => {
+                let start = *start.get_or_insert(created_at);
+                (created_at - start).num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9
+            }
+            (None, None) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Rows need a time or created_at.")),
+        };
+        let sign = row.aggressor.as_ref().map(|side| if buy(side) { 1 } else { -1 });
+        if let Some(side) = &row.side {
+            match &row.aggressor {
+                Some(aggressor) if aggressor != side => continue, // Resting side
+                None if !buy(side) => continue, // Uncrossed in an auction, keep the buy side
+                _ => {}
+            }
+        }
+        trades.push(Trade { time, price: row.price, size: row.size, sign });
+    }
+    trades.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(trades)
+}
+
+#[derive(Deserialize)]
+struct LevelRow {
+    time: f64,
+    side: OrderSide,
+    level: usize,
+    price: f64,
+}
+
+/// Reads the quotes from the best levels of the `book.csv` of a simulation, skipping snapshots with an empty side.
+pub fn read_quotes(reader: impl Read) -> io::Result<Vec<Quote>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut quotes: Vec<Quote> = Vec::new();
+    let mut pending: Option<(f64, Option<f64>, Option<f64>)> = None; // Time, bid and ask of the current snapshot
+    let flush = |pending: Option<(f64, Option<f64>, Option<f64>)>, quotes: &mut Vec<Quote>| {
+        if let Some((time, Some(bid), Some(ask))) = pending {
+            quotes.push(Quote { time, bid, ask });
+        }
+    };
+    for row in reader.deserialize() {
+        let row: LevelRow = row?;
+        if row.level != 0 {
+            continue;
+        }
+        if pending.is_some_and(|(time, _, _)| time != row.time) {
+            flush(pending.take(), &mut quotes);
+        }
+        let (_, bid, ask) = pending.get_or_insert((row.time, None, None));
+        if buy(&row.side) {
+            *bid = Some(row.price);
+        } else {
+            *ask = Some(row.price);
+        }
+    }
+    flush(pending, &mut quotes);
+    Ok(quotes)
+}
+
+/// Computes the report of a simulation output directory, or of a file of fills or trades.
+pub fn analyze(path: impl AsRef<Path>, config: AnalyticsConfig) -> io::Result<Report> {
+    let path = path.as_ref();
+    let (trades, quotes) = if path.is_dir() {
+        let book = path.join("book.csv");
+        let quotes = if book.exists() { read_quotes(File::open(book)?)? } else { Vec::new() };
+        (read_trades(File::open(path.join("fills.csv"))?)?, quotes)
+    } else {
+        (read_trades(File::open(path)?)?, Vec::new())
+    };
+    Report::new(config, &trades, &quotes)
+}
+
+fn buy(side: &OrderSide) -> bool {
+    matches!(side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{Config, Output, Simulation};
+
+    #[test]
+    fn statistics() {
+        let moments = moments(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!((moments.mean, moments.skewness), (3.0, 0.0));
+        assert!((moments.std - 2f64.sqrt()).abs() < 1e-12);
+        assert!((moments.kurtosis + 1.3).abs() < 1e-12);
+
+        let alternating: Vec<f64> = (0..100).map(|n| if n % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        let acf = acf(&alternating, 3);
+        assert!((acf[0] + 0.99).abs() < 1e-12 && (acf[1] - 0.98).abs() < 1e-12 && acf.len() == 3);
+        assert_eq!(super::acf(&[1.0, 1.0], 5), [0.0]);
+
+        let trade = |time: f64, price: f64| Trade { time, price, size: 1.0, sign: Some(1) };
+        let trades = [trade(0.0, 100.0), trade(0.5, 101.0), trade(2.5, 99.0)];
+        let returns = returns(&trades, 1.0);
+        assert_eq!(returns.len(), 2);
+        assert_eq!(returns[0], 0.0); // Flat through the empty interval
+        assert!((returns[1] - (99.0f64 / 101.0).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn read_inputs() {
+        // Only the aggressive side of fills, and one side of auction fills, are trades
+        let fills = "time,order_id,agent,kind,side,price,size,aggressor
+1.0,3,0,noise_trader,Ask,100.0,1.0,Buy
+1.0,4,1,chartist,Buy,100.0,1.0,Buy
+2.0,5,1,chartist,Sell,99.0,2.0,Sell
+2.0,6,0,noise_trader,Bid,99.0,2.0,Sell
+3.0,7,0,noise_trader,Bid,99.5,1.0,
+3.0,8,1,noise_trader,Ask,99.5,1.0,
+";
+        let trades = read_trades(fills.as_bytes()).unwrap();
+        assert_eq!(trades, [
+            Trade { time: 1.0, price: 100.0, size: 1.0, sign: Some(1) },
+            Trade { time: 2.0, price: 99.0, size: 2.0, sign: Some(-1) },
+            Trade { time: 3.0, price: 99.5, size: 1.0, sign: None },
+        ]);
+
+        // Exports are timed from the first row
+        let export = "id,price,size,aggressor,created_at
+1,100,1,Buy,2023-01-01T00:00:00
+2,101,1,Sell,2023-01-01T00:00:01.5
+";
+        let trades = read_trades(export.as_bytes()).unwrap();
+        let times: Vec<(f64, Option<i8>)> = trades.iter().map(|trade| (trade.time, trade.sign)).collect();
+        assert_eq!(times, [(0.0, Some(1)), (1.5, Some(-1))]);
+
+        let book = "time,side,level,price,size
+0.0,Bid,0,99.0,1.0
+0.0,Bid,1,98.0,1.0
+0.0,Ask,0,101.0,1.0
+1.0,Ask,0,100.5,1.0
+2.0,Bid,0,99.5,1.0
+2.0,Ask,0,100.0,1.0
+";
+        let quotes = read_quotes(book.as_bytes()).unwrap();
+        assert_eq!(quotes, [Quote { time: 0.0, bid: 99.0, ask: 101.0 }, Quote { time: 2.0, bid: 99.5, ask: 100.0 }]);
+    }
+
+    #[test]
+    fn report_of_simulation() {
+        let mut output = Output::new(Vec::new(), Vec::new(), Vec::new());
+        let summary = Simulation::new(Config { steps: 3000, ..Config::default() }).run(&mut output).unwrap();
+        let (fills, _, book) = output.finish().unwrap();
+        let trades = read_trades(fills.as_slice()).unwrap();
+        let quotes = read_quotes(book.as_slice()).unwrap();
+        assert_eq!(trades.len(), summary.trades);
+
+        let report = Report::new(AnalyticsConfig::default(), &trades, &quotes).unwrap();
+        assert_eq!(report.returns.count, 299);
+        assert_eq!(report.volatility_clustering.abs_return_acf.len(), 20);
+        assert_eq!(report.sign_acf.len(), 20);
+        assert_eq!(report.price_impact.len(), 10);
+        assert!(report.price_impact.windows(2).all(|pair| pair[0].size <= pair[1].size));
+        let spread = report.spread.as_ref().unwrap();
+        assert!(spread.mean > 0.0 && spread.relative < 0.01);
+
+        let json = serde_json::to_value(&report).unwrap();
+        for key in ["returns", "volatility_clustering", "price_impact", "sign_acf", "spread"] {
+            assert!(json.get(key).is_some(), "{key}");
+        }
+
+        for interval in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = AnalyticsConfig { interval, ..AnalyticsConfig::default() };
+            assert_eq!(Report::new(config, &trades, &quotes).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
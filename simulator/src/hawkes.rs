@@ -0,0 +1,688 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use database::orders::{Command, Order};
+use database::{DatabaseConnection, Mutation, OrderSide, OrderType};
+use protocol::{Channel, Codec, Transport};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Geometric};
+use serde::{Deserialize, Serialize};
+
+use crate::agents::{Action, Agent, Kind, Market};
+
+// ----------------------------------------------------------------------
+
+pub const DIMENSIONS: usize = 6;
+
+/// The kinds of order flow events, each a dimension of the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    MarketBuy,
+    MarketSell,
+    LimitBid,
+    LimitAsk,
+    CancelBid,
+    CancelAsk,
+}
+
+impl EventType {
+    pub const ALL: [EventType; DIMENSIONS] = [
+        EventType::MarketBuy,
+        EventType::MarketSell,
+        EventType::LimitBid,
+        EventType::LimitAsk,
+        EventType::CancelBid,
+        EventType::CancelAsk,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrival {
+    pub time: f64, // Seconds
+    pub r#type: EventType,
+}
+
+/// The exponential kernel `alpha * beta * exp(-beta * t)`. `alpha` is the expected number of events triggered by an
+/// event and `beta` the rate at which its effect decays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Kernel {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+/// A multivariate Hawkes process. The intensity of events of type `i` is `baseline[i]` plus the kernel
+/// `kernels[i][j]` of the time since each past event of type `j`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HawkesConfig {
+    pub baseline: [f64; DIMENSIONS], // Events per second, in the order of `EventType::ALL`
+    pub kernels: [[Kernel; DIMENSIONS]; DIMENSIONS],
+}
+
+impl Default for HawkesConfig {
+    /// Self-exciting flow in which market orders deplete the opposite side, which is replenished by limit orders, and
+    /// limit orders are followed by cancels on the same side.
+    fn default() -> Self {
+        let mut kernels = [[Kernel { alpha: 0.0, beta: 1.0 }; DIMENSIONS]; DIMENSIONS];
+        for (i, row) in kernels.iter_mut().enumerate() {
+            row[i] = Kernel { alpha: 0.3, beta: 2.0 };
+        }
+        let mut excite = |by: EventType, of: EventType, alpha: f64| {
+            kernels[of.index()][by.index()] = Kernel { alpha, beta: 5.0 };
+        };
+        excite(EventType::MarketBuy, EventType::LimitAsk, 0.2);
+        excite(EventType::MarketSell, EventType::LimitBid, 0.2);
+        excite(EventType::LimitBid, EventType::CancelBid, 0.3);
+        excite(EventType::LimitAsk, EventType::CancelAsk, 0.3);
+        HawkesConfig { baseline: [0.2, 0.2, 1.0, 1.0, 0.5, 0.5], kernels }
+    }
+}
+
+impl HawkesConfig {
+    /// Rejects processes whose intensities are negative or would not decay, and processes which are not stationary
+    /// since their order flow would grow without bound. Every kernel needs `0 <= alpha < beta`.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if let Some(baseline) = self.baseline.iter().find(|baseline| !(baseline.is_finite() && **baseline >= 0.0)) {
+            return invalid(format!("The Hawkes process has a baseline of {baseline}, which must not be negative."));
+        }
+        for kernel in self.kernels.iter().flatten() {
+            if !(kernel.alpha.is_finite() && kernel.beta.is_finite() && 0.0 <= kernel.alpha && kernel.alpha < kernel.beta)
+            {
+                return invalid(format!("The Hawkes kernel {kernel:?} must have 0 <= alpha < beta."));
+            }
+        }
+        let ratio = self.branching_ratio();
+        if ratio >= 1.0 {
+            return invalid(format!("The Hawkes process has a branching ratio of {ratio} and is not stationary."));
+        }
+        Ok(())
+    }
+
+    /// Spectral radius of the matrix of `alpha`s. The process is stationary if it is below one.
+    pub fn branching_ratio(&self) -> f64 {
+        let mut vector = [1.0; DIMENSIONS];
+        let mut radius = 0.0;
+        for _ in 0..1000 { // Power iteration, which converges for non-negative matrices
+            let mut next = [0.0; DIMENSIONS];
+            for (i, row) in self.kernels.iter().enumerate() {
+                next[i] = row.iter().zip(vector).map(|(kernel, v)| kernel.alpha * v).sum();
+            }
+            let norm = next.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if norm == 0.0 {
+                return 0.0;
+            }
+            radius = norm / vector.iter().map(|v| v * v).sum::<f64>().sqrt();
+            vector = next.map(|v| v / norm);
+        }
+        radius
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// Generates the arrivals of a Hawkes process by Ogata's thinning. Exponential kernels let the excitation be decayed
+/// in place instead of summing over the history.
+pub struct Hawkes {
+    config: HawkesConfig,
+    time: f64,
+    excitation: [[f64; DIMENSIONS]; DIMENSIONS], // Current intensity of type i due to past events of type j
+}
+
+impl Hawkes {
+    pub fn new(config: HawkesConfig) -> Self {
+        Hawkes { config, time: 0.0, excitation: [[0.0; DIMENSIONS]; DIMENSIONS] }
+    }
+
+    fn intensities(&self) -> [f64; DIMENSIONS] {
+        let mut intensities = self.config.baseline;
+        for (intensity, row) in intensities.iter_mut().zip(&self.excitation) {
+            *intensity += row.iter().sum::<f64>();
+        }
+        intensities
+    }
+
+    /// Draws the next arrival, or `None` if the process has died out.
+    pub fn next(&mut self, rng: &mut impl Rng) -> Option<Arrival> {
+        loop {
+            let bound: f64 = self.intensities().iter().sum(); // Intensities only decay until the next event
+            if bound <= 0.0 {
+                return None;
+            }
+            let wait = -(1.0 - rng.gen::<f64>()).ln() / bound;
+            self.time += wait;
+            for (row, kernels) in self.excitation.iter_mut().zip(&self.config.kernels) {
+                for (excitation, kernel) in row.iter_mut().zip(kernels) {
+                    *excitation *= (-kernel.beta * wait).exp();
+                }
+            }
+            let intensities = self.intensities();
+            let total: f64 = intensities.iter().sum();
+            let mut u = rng.gen::<f64>() * bound;
+            if u >= total {
+                continue; // Rejected
+            }
+            let i = intensities.iter().position(|intensity| {
+                u -= intensity;
+                u < 0.0
+            }).unwrap_or(DIMENSIONS - 1);
+            for (row, kernels) in self.excitation.iter_mut().zip(&self.config.kernels) {
+                row[i] += kernels[i].alpha * kernels[i].beta;
+            }
+            return Some(Arrival { time: self.time, r#type: EventType::ALL[i] });
+        }
+    }
+
+    /// Draws all arrivals up to the horizon in seconds.
+    pub fn generate(&mut self, horizon: f64, rng: &mut impl Rng) -> Vec<Arrival> {
+        let mut arrivals = Vec::new();
+        while let Some(arrival) = self.next(rng) {
+            if arrival.time > horizon {
+                break;
+            }
+            arrivals.push(arrival);
+        }
+        arrivals
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// A Hawkes process fitted by maximum likelihood.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Fit {
+    pub config: HawkesConfig,
+    pub log_likelihood: f64,
+    pub branching_ratio: f64,
+    pub events: usize,
+    pub horizon: f64,
+}
+
+/// Fits the baselines and `alpha`s of a process with a common decay by expectation maximization, choosing the decay
+/// among the candidates by likelihood. The arrivals must be sorted by time and lie within `[0, horizon]`.
+pub fn fit(arrivals: &[Arrival], horizon: f64, decays: &[f64], iterations: usize) -> Option<Fit> {
+    decays
+        .iter()
+        .filter(|beta| **beta > 0.0)
+        .map(|beta| fit_decay(arrivals, horizon, *beta, iterations))
+        .max_by(|a, b| a.log_likelihood.total_cmp(&b.log_likelihood))
+}
+
+fn fit_decay(arrivals: &[Arrival], horizon: f64, beta: f64, iterations: usize) -> Fit {
+    // Decayed counts of earlier arrivals of each type, by recursion over the arrivals
+    let mut decayed = vec![[0.0; DIMENSIONS]; arrivals.len()];
+    for n in 1..arrivals.len() {
+        let decay = (-beta * (arrivals[n].time - arrivals[n - 1].time)).exp();
+        let mut previous = decayed[n - 1];
+        previous[arrivals[n - 1].r#type.index()] += 1.0;
+        decayed[n] = previous.map(|count| count * decay);
+    }
+    // Integral of the kernel of each arrival up to the horizon
+    let mut compensator = [0.0; DIMENSIONS];
+    let mut counts = [0.0; DIMENSIONS];
+    for arrival in arrivals {
+        compensator[arrival.r#type.index()] += 1.0 - (-beta * (horizon - arrival.time)).exp();
+        counts[arrival.r#type.index()] += 1.0;
+    }
+
+    let mut baseline = counts.map(|count| count / horizon / 2.0);
+    let mut alpha = [[0.1; DIMENSIONS]; DIMENSIONS];
+    let mut log_likelihood = f64::NEG_INFINITY;
+    for _ in 0..iterations {
+        let mut background = [0.0; DIMENSIONS]; // Expected number of arrivals not triggered by another
+        let mut triggered = [[0.0; DIMENSIONS]; DIMENSIONS]; // Expected number of type i triggered by type j
+        let mut sum_log = 0.0;
+        for (arrival, decayed) in arrivals.iter().zip(&decayed) {
+            let i = arrival.r#type.index();
+            let excitation: [f64; DIMENSIONS] = std::array::from_fn(|j| alpha[i][j] * beta * decayed[j]);
+            let intensity = baseline[i] + excitation.iter().sum::<f64>();
+            sum_log += intensity.ln();
+            background[i] += baseline[i] / intensity;
+            for j in 0..DIMENSIONS {
+                triggered[i][j] += excitation[j] / intensity;
+            }
+        }
+        let expected: f64 = (0..DIMENSIONS)
+            .map(|i| baseline[i] * horizon + (0..DIMENSIONS).map(|j| alpha[i][j] * compensator[j]).sum::<f64>())
+            .sum();
+        let previous = log_likelihood;
+        log_likelihood = sum_log - expected;
+        baseline = background.map(|count| count / horizon);
+        for i in 0..DIMENSIONS {
+            for j in 0..DIMENSIONS {
+                alpha[i][j] = if compensator[j] > 0.0 { triggered[i][j] / compensator[j] } else { 0.0 };
+            }
+        }
+        if (log_likelihood - previous).abs() <= 1e-8 * log_likelihood.abs() {
+            break;
+        }
+    }
+
+    let config = HawkesConfig {
+        baseline,
+        kernels: alpha.map(|row| row.map(|alpha| Kernel { alpha, beta })),
+    };
+    Fit { branching_ratio: config.branching_ratio(), config, log_likelihood, events: arrivals.len(), horizon }
+}
+
+#[derive(Deserialize)]
+struct OrderRow {
+    time: f64,
+    action: String,
+    side: OrderSide,
+    r#type: OrderType,
+}
+
+#[derive(Deserialize)]
+struct FillRow {
+    time: f64,
+    order_id: i32,
+    side: OrderSide,
+    aggressor: Option<OrderSide>,
+}
+
+/// Reads the arrivals from the `orders.csv` or `fills.csv` written by a simulation, telling them apart by their
+/// header. Fills only yield market orders, one per aggressive order. Returns the arrivals sorted by time.
+pub fn read_arrivals(reader: impl Read) -> io::Result<Vec<Arrival>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut arrivals = Vec::new();
+    if reader.headers()?.iter().any(|header| header == "aggressor") {
+        let mut last = None;
+        for row in reader.deserialize() {
+            let row: FillRow = row?;
+            if row.aggressor.as_ref() != Some(&row.side) || last == Some(row.order_id) {
+                continue; // Resting side, or another trade of the same order
+            }
+            last = Some(row.order_id);
+            let r#type = if buy(&row.side) { EventType::MarketBuy } else { EventType::MarketSell };
+            arrivals.push(Arrival { time: row.time, r#type });
+        }
+    } else {
+        for row in reader.deserialize() {
+            let row: OrderRow = row?;
+            let r#type = match (row.action.as_str(), row.r#type, buy(&row.side)) {
+                ("cancel", _, true) => EventType::CancelBid,
+                ("cancel", _, false) => EventType::CancelAsk,
+                (_, OrderType::Market, true) => EventType::MarketBuy,
+                (_, OrderType::Market, false) => EventType::MarketSell,
+                (_, OrderType::Limit, true) => EventType::LimitBid,
+                (_, OrderType::Limit, false) => EventType::LimitAsk,
+            };
+            arrivals.push(Arrival { time: row.time, r#type });
+        }
+    }
+    arrivals.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(arrivals)
+}
+
+pub fn read_arrivals_from(path: impl AsRef<Path>) -> io::Result<Vec<Arrival>> {
+    read_arrivals(File::open(path)?)
+}
+
+fn buy(side: &OrderSide) -> bool {
+    matches!(side, OrderSide::Buy | OrderSide::Bid | OrderSide::Long)
+}
+
+// ----------------------------------------------------------------------
+
+/// Turns the arrivals of a Hawkes process into orders.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HawkesFlowConfig {
+    pub process: HawkesConfig,
+    pub market_size: f32,
+    pub limit_size: f32,
+    pub depth: f64, // Mean number of ticks of limit orders behind the best price of their side
+}
+
+impl Default for HawkesFlowConfig {
+    fn default() -> Self {
+        HawkesFlowConfig { process: HawkesConfig::default(), market_size: 1.0, limit_size: 1.0, depth: 2.0 }
+    }
+}
+
+impl HawkesFlowConfig {
+    /// Price of a limit order some ticks behind the reference, drawn geometrically with the configured mean.
+    fn limit_price(&self, reference: f64, tick_size: f64, bid: bool, rng: &mut impl Rng) -> f32 {
+        let ticks = Geometric::new(1.0 / (1.0 + self.depth.max(0.0))).map_or(0, |geometric| geometric.sample(rng));
+        let ticks = (reference / tick_size).round() + if bid { -(ticks as f64) } else { ticks as f64 };
+        (ticks.max(1.0) * tick_size) as f32
+    }
+}
+
+/// An agent whose orders arrive as a Hawkes process, in continuous time within each step of the simulation. Limit
+/// orders join behind the best price of their side and cancels pull a random resting order of the side.
+pub struct HawkesAgent {
+    config: HawkesFlowConfig,
+    process: Hawkes,
+    step: f64, // Seconds
+    origin: Option<NaiveDateTime>,
+    pending: Option<Arrival>, // First arrival after the current step
+}
+
+impl HawkesAgent {
+    pub fn new(config: HawkesFlowConfig, step_millis: i64) -> Self {
+        HawkesAgent {
+            process: Hawkes::new(config.process.clone()),
+            config,
+            step: step_millis as f64 / 1000.0,
+            origin: None,
+            pending: None,
+        }
+    }
+}
+
+impl Agent for HawkesAgent {
+    fn kind(&self) -> Kind {
+        Kind::Hawkes
+    }
+
+    fn act(&mut self, market: &Market, open: &[&Order], rng: &mut ChaCha8Rng) -> Vec<Action> {
+        let origin = *self.origin.get_or_insert(market.time);
+        let end = (market.time - origin).num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9 + self.step;
+        let mut cancelled = HashSet::new();
+        let mut actions = Vec::new();
+        while let Some(arrival) = self.pending.take().or_else(|| self.process.next(rng)) {
+            if arrival.time >= end {
+                self.pending = Some(arrival);
+                break;
+            }
+            let action = match arrival.r#type {
+                EventType::MarketBuy => Action::Market { side: OrderSide::Buy, size: self.config.market_size },
+                EventType::MarketSell => Action::Market { side: OrderSide::Sell, size: self.config.market_size },
+                EventType::LimitBid | EventType::LimitAsk => {
+                    let bid = arrival.r#type == EventType::LimitBid;
+                    let reference = if bid {
+                        market.best_bid.map_or(market.mid() as f64 - market.tick_size, f64::from)
+                    } else {
+                        market.best_ask.map_or(market.mid() as f64 + market.tick_size, f64::from)
+                    };
+                    Action::Limit {
+                        side: if bid { OrderSide::Bid } else { OrderSide::Ask },
+                        price: self.config.limit_price(reference, market.tick_size, bid, rng),
+                        size: self.config.limit_size,
+                    }
+                }
+                EventType::CancelBid | EventType::CancelAsk => {
+                    let bid = arrival.r#type == EventType::CancelBid;
+                    let candidates: Vec<i32> = open
+                        .iter()
+                        .filter(|order| buy(&order.side) == bid && !cancelled.contains(&order.id))
+                        .map(|order| order.id)
+                        .collect();
+                    if candidates.is_empty() {
+                        continue;
+                    }
+                    let id = candidates[rng.gen_range(0..candidates.len())];
+                    cancelled.insert(id);
+                    Action::Cancel(id)
+                }
+            };
+            actions.push(action);
+        }
+        actions
+    }
+}
+
+/// The sub-account and market to which a Hawkes order flow is published. Its orders are stored like those placed
+/// through the API, so that the fills of the matching engine can be applied to them.
+pub struct Venue<'a> {
+    pub db: &'a DatabaseConnection,
+    pub transport: &'a dyn Transport,
+    pub client_id: i32,
+    pub sub_account_id: i32,
+    pub market_id: i32,
+}
+
+/// Publishes a Hawkes order flow to the orders stream of the matching engine in real time for the given number of
+/// seconds, for load tests. Limit prices are placed around a fixed reference price since the book is not observed,
+/// and cancels pull random earlier limit orders, some of which will have been filled. Returns the number of commands
+/// published.
+pub async fn publish(
+    venue: &Venue<'_>,
+    config: &HawkesFlowConfig,
+    price: f32,
+    tick_size: f64,
+    seconds: f64,
+    seed: u64,
+) -> io::Result<usize> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut process = Hawkes::new(config.process.clone());
+    let mut resting: [Vec<Order>; 2] = [Vec::new(), Vec::new()]; // Bids and asks
+    let mut published = 0;
+    let started = Instant::now();
+    while let Some(arrival) = process.next(&mut rng) {
+        if arrival.time > seconds {
+            break;
+        }
+        if let Some(wait) = Duration::from_secs_f64(arrival.time).checked_sub(started.elapsed()) {
+            async_std::task::sleep(wait).await;
+        }
+        let order = |side: OrderSide, r#type: OrderType, price: Option<f32>, size: f32| async move {
+            Mutation::create_order(
+                venue.db,
+                venue.client_id,
+                venue.sub_account_id,
+                size,
+                side,
+                r#type,
+                price,
+                None,
+                Some(venue.market_id),
+                None,
+                None,
+            )
+            .await
+            .map_err(io::Error::other)
+        };
+        let command = match arrival.r#type {
+            EventType::MarketBuy | EventType::MarketSell => {
+                let side = if arrival.r#type == EventType::MarketBuy { OrderSide::Buy } else { OrderSide::Sell };
+                Command::New(order(side, OrderType::Market, None, config.market_size).await?)
+            }
+            EventType::LimitBid | EventType::LimitAsk => {
+                let bid = arrival.r#type == EventType::LimitBid;
+                let reference = f64::from(price) + if bid { -tick_size } else { tick_size };
+                let limit = config.limit_price(reference, tick_size, bid, &mut rng);
+                let side = if bid { OrderSide::Bid } else { OrderSide::Ask };
+                let order = order(side, OrderType::Limit, Some(limit), config.limit_size).await?;
+                resting[usize::from(!bid)].push(order.clone());
+                Command::New(order)
+            }
+            EventType::CancelBid | EventType::CancelAsk => {
+                let side = &mut resting[usize::from(arrival.r#type == EventType::CancelAsk)];
+                if side.is_empty() {
+                    continue;
+                }
+                Command::Cancel(side.swap_remove(rng.gen_range(0..side.len())))
+            }
+        };
+        let _ = venue.transport.publish(Channel::Orders, command.encode()).await;
+        published += 1;
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod test {
+
+    use database::{Cursor, Engine, Migrator, MigratorTrait, Query};
+    use futures::StreamExt;
+    use protocol::{InMemory, Offset};
+
+    use super::*;
+
+    #[test]
+    fn branching_ratio() {
+        let mut config = HawkesConfig { baseline: [1.0; DIMENSIONS], ..HawkesConfig::default() };
+        config.kernels = [[Kernel { alpha: 0.0, beta: 1.0 }; DIMENSIONS]; DIMENSIONS];
+        assert_eq!(config.branching_ratio(), 0.0);
+        config.kernels[0][0].alpha = 0.5;
+        config.kernels[1][1].alpha = 0.25;
+        assert!((config.branching_ratio() - 0.5).abs() < 1e-9);
+        config.kernels[0][1].alpha = 0.5;
+        config.kernels[1][0].alpha = 0.5;
+        assert!((config.branching_ratio() - (0.75 + 1.0625f64.sqrt()) / 2.0).abs() < 1e-9); // Largest eigenvalue
+        config.kernels[0][1].alpha = 0.7;
+        config.kernels[1][0].alpha = 0.7;
+        assert!(config.branching_ratio() > 1.0); // Explosive
+        assert!(HawkesConfig::default().branching_ratio() < 1.0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate() {
+        assert!(HawkesConfig::default().validate().is_ok());
+        for kernel in [
+            Kernel { alpha: -0.1, beta: 1.0 },
+            Kernel { alpha: 0.0, beta: 0.0 },
+            Kernel { alpha: 0.5, beta: 0.5 },
+            Kernel { alpha: f64::NAN, beta: 1.0 },
+        ] {
+            let mut config = HawkesConfig::default();
+            config.kernels[2][3] = kernel;
+            assert_eq!(config.validate().unwrap_err().kind(), io::ErrorKind::InvalidInput, "{kernel:?}");
+        }
+        let config = HawkesConfig { baseline: [-1.0; DIMENSIONS], ..HawkesConfig::default() };
+        assert!(config.validate().is_err());
+    }
+
+    #[async_std::test]
+    async fn publish_stored_orders() {
+        let db = Engine::connect().await.unwrap();
+        Migrator::refresh(&db).await.unwrap(); // Apply all pending migrations
+        let client = Mutation::create_client(&db, "a@gmail.com".to_owned()).await.unwrap();
+        let sub_account = Mutation::create_sub_account(&db, client.id, "Hawkes".to_owned()).await.unwrap();
+        let market = Mutation::create_market(&db, "BTC".to_owned(), "USD".to_owned(), 0.01, 0.01).await.unwrap();
+        let transport = InMemory::new();
+        let venue = Venue {
+            db: &db,
+            transport: &transport,
+            client_id: client.id,
+            sub_account_id: sub_account.id,
+            market_id: market.id,
+        };
+        let published = publish(&venue, &HawkesFlowConfig::default(), 100.0, 0.01, 1.0, 1).await.unwrap();
+        transport.close().await;
+
+        // Every new order is stored before it is published, under the id the engine fills it with
+        let commands: Vec<Command> = transport
+            .subscribe(Channel::Orders, Offset::First)
+            .await
+            .unwrap()
+            .map(|data| Command::decode(&data).unwrap())
+            .collect()
+            .await;
+        assert_eq!(commands.len(), published);
+        let cursor = Cursor { limit: Some(1000), ..Cursor::default() };
+        let stored = Query::find_client_related_orders(&db, client.id, Default::default(), cursor).await.unwrap();
+        let ids: HashSet<i32> = stored.data.iter().map(|order| order.id).collect();
+        let new: Vec<i32> = commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::New(order) => Some(order.id),
+                _ => None,
+            })
+            .collect();
+        assert!(!new.is_empty());
+        assert_eq!((new.len(), new.iter().all(|id| ids.contains(id))), (ids.len(), true));
+
+        Migrator::reset(&db).await.unwrap(); // Rollback migrations
+    }
+
+    #[test]
+    fn generate_and_fit() {
+        let config = HawkesConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let horizon = 3000.0;
+        let arrivals = Hawkes::new(config.clone()).generate(horizon, &mut rng);
+        assert!(arrivals.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+        // The mean rate of a stationary process is (I - alpha)^-1 baseline, here checked for market buys which are
+        // only self-exciting
+        let buys = arrivals.iter().filter(|arrival| arrival.r#type == EventType::MarketBuy).count() as f64;
+        assert!((buys / horizon - 0.2 / (1.0 - 0.3)).abs() < 0.03);
+
+        // A single decay cannot match the two of the default kernels, so fit one common to the self-exciting ones
+        let mut single = config.clone();
+        for (i, row) in single.kernels.iter_mut().enumerate() {
+            for (j, kernel) in row.iter_mut().enumerate() {
+                *kernel = if i == j { Kernel { alpha: 0.4, beta: 3.0 } } else { Kernel { alpha: 0.0, beta: 3.0 } };
+            }
+        }
+        let arrivals = Hawkes::new(single).generate(horizon, &mut rng);
+        let fit = fit(&arrivals, horizon, &[1.0, 3.0, 9.0], 200).unwrap();
+        assert_eq!(fit.config.kernels[0][0].beta, 3.0);
+        for (i, baseline) in fit.config.baseline.iter().enumerate() {
+            assert!((baseline - config.baseline[i]).abs() < 0.25 * config.baseline[i], "baseline {i}: {baseline}");
+            assert!((fit.config.kernels[i][i].alpha - 0.4).abs() < 0.08, "alpha {i}: {:?}", fit.config.kernels[i]);
+            for j in (0..DIMENSIONS).filter(|j| *j != i) {
+                assert!(fit.config.kernels[i][j].alpha < 0.05);
+            }
+        }
+        assert!((fit.branching_ratio - 0.4).abs() < 0.08);
+    }
+
+    #[test]
+    fn simulate_flow() {
+        use crate::{ChartistConfig, Config, FundamentalistConfig, LiquidityProviderConfig, NoiseTraderConfig, Output};
+        use crate::Simulation;
+
+        let config = Config {
+            steps: 3000,
+            liquidity_providers: LiquidityProviderConfig { count: 0, ..LiquidityProviderConfig::default() },
+            chartists: ChartistConfig { count: 0, ..ChartistConfig::default() },
+            fundamentalists: FundamentalistConfig { count: 0, ..FundamentalistConfig::default() },
+            noise_traders: NoiseTraderConfig { count: 0, ..NoiseTraderConfig::default() },
+            hawkes: Some(HawkesFlowConfig::default()),
+            ..Config::default()
+        };
+        let mut output = Output::new(Vec::new(), Vec::new(), Vec::new());
+        let summary = Simulation::new(config).run(&mut output).unwrap();
+        let (fills, orders, _) = output.finish().unwrap();
+        assert!(summary.trades > 0);
+
+        // Every kind of event reaches the book, and the fills yield the aggressive orders
+        let arrivals = read_arrivals(orders.as_slice()).unwrap();
+        for r#type in EventType::ALL {
+            assert!(arrivals.iter().any(|arrival| arrival.r#type == r#type), "{type:?}");
+        }
+        assert!(arrivals.last().unwrap().time < 300.0);
+        let aggressive = read_arrivals(fills.as_slice()).unwrap();
+        assert!(!aggressive.is_empty() && aggressive.len() <= summary.trades); // An order may trade more than once
+    }
+
+    #[test]
+    fn read_simulation_output() {
+        let orders = "time,order_id,agent,kind,action,side,type,price,size
+0.0,1,0,hawkes,new,Bid,Limit,99.9,1.0
+0.5,2,0,hawkes,new,Sell,Market,,1.0
+0.25,1,0,hawkes,cancel,Bid,Limit,99.9,1.0
+";
+        let arrivals = read_arrivals(orders.as_bytes()).unwrap();
+        let types: Vec<EventType> = arrivals.iter().map(|arrival| arrival.r#type).collect();
+        assert_eq!(types, [EventType::LimitBid, EventType::CancelBid, EventType::MarketSell]);
+
+        let fills = "time,order_id,agent,kind,side,price,size,aggressor
+1.0,3,0,hawkes,Ask,100.0,1.0,Buy
+1.0,4,0,hawkes,Buy,100.0,1.0,Buy
+1.0,5,0,hawkes,Ask,100.1,1.0,Buy
+1.0,4,0,hawkes,Buy,100.1,1.0,Buy
+2.0,6,0,hawkes,Sell,99.0,1.0,Sell
+2.0,7,0,hawkes,Bid,99.0,1.0,Sell
+";
+        let arrivals = read_arrivals(fills.as_bytes()).unwrap();
+        let types: Vec<EventType> = arrivals.iter().map(|arrival| arrival.r#type).collect();
+        assert_eq!(types, [EventType::MarketBuy, EventType::MarketSell]);
+    }
+}
@@ -0,0 +1,414 @@
+mod agents;
+mod analytics;
+mod hawkes;
+mod output;
+
+pub use agents::{
+    Action, Agent, Chartist, ChartistConfig, Fundamentalist, FundamentalistConfig, Kind, LiquidityProvider,
+    LiquidityProviderConfig, Market, NoiseTrader, NoiseTraderConfig,
+};
+pub use analytics::{
+    analyze, read_quotes, read_trades, AnalyticsConfig, Clustering, ImpactBucket, Moments, Quote, Report, Spread, Trade,
+};
+pub use hawkes::{
+    fit, publish, read_arrivals, read_arrivals_from, Arrival, EventType, Fit, Hawkes, HawkesAgent, HawkesConfig,
+    HawkesFlowConfig, Kernel, Venue,
+};
+pub use output::{FillRecord, LevelRecord, OrderRecord, Output};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use database::orders::{Command, Order};
+use database::{OrderSide, OrderType};
+use orderbook::OrderBook;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub seed: u64,
+    pub steps: usize,
+    pub step_millis: i64, // Simulated time between steps
+    pub market_id: i32,
+    pub initial_price: f32,
+    pub tick_size: f64,
+    pub volatility: f64, // Standard deviation of the log return of the fundamental value per step
+    pub snapshot_interval: usize, // Steps between snapshots of the book
+    pub snapshot_levels: usize,
+    pub liquidity_providers: LiquidityProviderConfig,
+    pub chartists: ChartistConfig,
+    pub fundamentalists: FundamentalistConfig,
+    pub noise_traders: NoiseTraderConfig,
+    pub hawkes: Option<HawkesFlowConfig>, // An additional agent whose orders arrive as a Hawkes process
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            seed: 42,
+            steps: 10_000,
+            step_millis: 100,
+            market_id: 1,
+            initial_price: 100.0,
+            tick_size: 0.01,
+            volatility: 0.0005,
+            snapshot_interval: 10,
+            snapshot_levels: 5,
+            liquidity_providers: LiquidityProviderConfig::default(),
+            chartists: ChartistConfig::default(),
+            fundamentalists: FundamentalistConfig::default(),
+            noise_traders: NoiseTraderConfig::default(),
+            hawkes: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads a configuration from a JSON file. Omitted fields take their default values. The configuration is
+    /// checked by `validate`.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let config: Config = serde_json::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects probabilities outside of [0, 1], on which sampling the agents would panic, and Hawkes processes which
+    /// `HawkesConfig::validate` rejects.
+    pub fn validate(&self) -> io::Result<()> {
+        let probabilities = [
+            ("liquidity_providers.activity", self.liquidity_providers.activity),
+            ("chartists.activity", self.chartists.activity),
+            ("fundamentalists.activity", self.fundamentalists.activity),
+            ("noise_traders.activity", self.noise_traders.activity),
+            ("noise_traders.market_probability", self.noise_traders.market_probability),
+        ];
+        if let Some((name, p)) = probabilities.iter().find(|(_, p)| !(0.0..=1.0).contains(p)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The probability {name} must be between 0 and 1, got {p}."),
+            ));
+        }
+        if let Some(hawkes) = &self.hawkes {
+            hawkes.process.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Totals of a finished simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub steps: usize,
+    pub orders: usize,
+    pub trades: usize,
+    pub volume: f32,
+    pub last_price: f32,
+    pub fundamental: f32,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} steps, {} orders, {} trades, {} volume, last price {}, fundamental value {}",
+            self.steps, self.orders, self.trades, self.volume, self.last_price, self.fundamental
+        )
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// An agent with the orders it has resting in the book.
+struct Participant {
+    agent: Box<dyn Agent>,
+    open: BTreeMap<i32, Order>,
+}
+
+/// Drives an in-process order book with a population of agents. Each step the agents act in a random order on what
+/// they observe of the market, and the fundamental value follows a geometric random walk. All randomness is drawn from
+/// a single generator seeded by the configuration, so a configuration always produces the same output.
+pub struct Simulation {
+    config: Config,
+    rng: ChaCha8Rng,
+    orderbook: OrderBook,
+    participants: Vec<Participant>,
+    owners: HashMap<i32, usize>, // Agent of each resting order
+    next_id: i32,
+    start: NaiveDateTime,
+    fundamental: f64,
+    last_price: f32,
+    prices: Vec<f32>,
+}
+
+impl Simulation {
+    pub fn new(config: Config) -> Self {
+        let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+        for _ in 0..config.liquidity_providers.count {
+            agents.push(Box::new(LiquidityProvider::new(config.liquidity_providers.clone())));
+        }
+        for _ in 0..config.chartists.count {
+            agents.push(Box::new(Chartist::new(config.chartists.clone())));
+        }
+        for _ in 0..config.fundamentalists.count {
+            agents.push(Box::new(Fundamentalist::new(config.fundamentalists.clone())));
+        }
+        for _ in 0..config.noise_traders.count {
+            agents.push(Box::new(NoiseTrader::new(config.noise_traders.clone())));
+        }
+        if let Some(hawkes) = &config.hawkes {
+            agents.push(Box::new(HawkesAgent::new(hawkes.clone(), config.step_millis)));
+        }
+        Self::with_agents(config, agents)
+    }
+
+    /// Creates a simulation of a custom population, ignoring the populations of the configuration.
+    pub fn with_agents(config: Config, agents: Vec<Box<dyn Agent>>) -> Self {
+        Simulation {
+            rng: ChaCha8Rng::seed_from_u64(config.seed),
+            orderbook: OrderBook::detached(config.market_id),
+            participants: agents.into_iter().map(|agent| Participant { agent, open: BTreeMap::new() }).collect(),
+            owners: HashMap::new(),
+            next_id: 1,
+            start: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            fundamental: config.initial_price as f64,
+            last_price: config.initial_price,
+            prices: Vec::with_capacity(config.steps),
+            config,
+        }
+    }
+
+    /// Runs all steps, writing the order flow, fills and book snapshots to the output.
+    pub fn run<W: Write>(&mut self, output: &mut Output<W>) -> io::Result<Summary> {
+        let mut summary = Summary {
+            steps: self.config.steps,
+            orders: 0,
+            trades: 0,
+            volume: 0.0,
+            last_price: 0.0,
+            fundamental: 0.0,
+        };
+        for step in 0..self.config.steps {
+            let now = self.start + Duration::milliseconds(self.config.step_millis * step as i64);
+            let mut order: Vec<usize> = (0..self.participants.len()).collect();
+            order.shuffle(&mut self.rng);
+            let mut sequence = 0;
+            for index in order {
+                let (best_bid, best_ask) = self.orderbook.top_of_book();
+                let market = Market {
+                    time: now,
+                    best_bid,
+                    best_ask,
+                    last_price: self.last_price,
+                    prices: &self.prices,
+                    fundamental: self.fundamental as f32,
+                    tick_size: self.config.tick_size,
+                };
+                let participant = &mut self.participants[index];
+                let open: Vec<&Order> = participant.open.values().collect();
+                let actions = participant.agent.act(&market, &open, &mut self.rng);
+                for action in actions {
+                    // Distinct times keep price-time priority in the order of submission
+                    let time = now + Duration::nanoseconds(sequence);
+                    sequence += 1;
+                    let (trades, volume) = self.execute(index, action, time, output)?;
+                    summary.orders += 1;
+                    summary.trades += trades;
+                    summary.volume += volume;
+                }
+            }
+            self.prices.push(self.last_price);
+            if self.config.snapshot_interval > 0 && step % self.config.snapshot_interval == 0 {
+                self.snapshot(now, output)?;
+            }
+            let shock: f64 = StandardNormal.sample(&mut self.rng);
+            self.fundamental *= (self.config.volatility * shock).exp();
+        }
+        summary.last_price = self.last_price;
+        summary.fundamental = self.fundamental as f32;
+        Ok(summary)
+    }
+
+    /// Submits the action of an agent to the order book and returns the number of trades and volume it caused.
+    fn execute<W: Write>(
+        &mut self,
+        agent: usize,
+        action: Action,
+        time: NaiveDateTime,
+        output: &mut Output<W>,
+    ) -> io::Result<(usize, f32)> {
+        let (command, action) = match action {
+            Action::Limit { side, price, size } => {
+                let order = self.order(agent, side, OrderType::Limit, Some(price), size, time);
+                (Command::New(order), "new")
+            }
+            Action::Market { side, size } => {
+                let order = self.order(agent, side, OrderType::Market, None, size, time);
+                (Command::New(order), "new")
+            }
+            Action::Cancel(id) => match self.participants[agent].open.remove(&id) {
+                Some(order) => {
+                    self.owners.remove(&id);
+                    (Command::Cancel(order), "cancel")
+                }
+                None => return Ok((0, 0.0)),
+            },
+        };
+        let order = match &command {
+            Command::New(order) | Command::Cancel(order) | Command::Amend(order) => order.clone(),
+        };
+        output.order(&OrderRecord {
+            time: self.elapsed(time),
+            order_id: order.id,
+            agent,
+            kind: self.participants[agent].agent.kind(),
+            action,
+            side: order.side.clone(),
+            r#type: order.r#type.clone(),
+            price: order.price,
+            size: order.size,
+        })?;
+
+        let fills = self.orderbook.execute(command, time);
+        let mut remaining = order.size;
+        let (mut trades, mut volume) = (0, 0.0);
+        for fill in &fills {
+            let owner = if fill.order_id == order.id {
+                remaining -= fill.size;
+                trades += 1; // Count each trade once, on the side of the incoming order
+                volume += fill.size;
+                self.last_price = fill.price;
+                agent
+            } else {
+                let owner = self.owners[&fill.order_id];
+                let open = &mut self.participants[owner].open;
+                if let Some(resting) = open.get_mut(&fill.order_id) {
+                    resting.size -= fill.size;
+                    if resting.size <= f32::EPSILON {
+                        open.remove(&fill.order_id);
+                        self.owners.remove(&fill.order_id);
+                    }
+                }
+                owner
+            };
+            self.participants[owner].agent.filled(fill);
+            output.fill(&FillRecord {
+                time: self.elapsed(time),
+                order_id: fill.order_id,
+                agent: owner,
+                kind: self.participants[owner].agent.kind(),
+                side: fill.side.clone(),
+                price: fill.price,
+                size: fill.size,
+                aggressor: fill.aggressor.clone(),
+            })?;
+        }
+        if action == "new" && order.r#type == OrderType::Limit && remaining > f32::EPSILON { // The remainder rests
+            self.owners.insert(order.id, agent);
+            self.participants[agent].open.insert(order.id, Order { size: remaining, ..order });
+        }
+        Ok((trades, volume))
+    }
+
+    fn order(
+        &mut self,
+        agent: usize,
+        side: OrderSide,
+        r#type: OrderType,
+        price: Option<f32>,
+        size: f32,
+        time: NaiveDateTime,
+    ) -> Order {
+        let id = self.next_id;
+        self.next_id += 1;
+        Order { id, sub_account_id: agent as i32 + 1, price, size, side, r#type, open_at: time }
+    }
+
+    fn snapshot<W: Write>(&self, time: NaiveDateTime, output: &mut Output<W>) -> io::Result<()> {
+        let (bids, asks) = self.orderbook.depth(self.config.snapshot_levels);
+        for (side, levels) in [(OrderSide::Bid, bids), (OrderSide::Ask, asks)] {
+            for (level, (price, size)) in levels.into_iter().enumerate() {
+                output.level(&LevelRecord { time: self.elapsed(time), side: side.clone(), level, price, size })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn elapsed(&self, time: NaiveDateTime) -> f64 {
+        (time - self.start).num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn simulate(config: Config) -> (Summary, Vec<u8>, Vec<u8>, Vec<u8>) {
+        let mut output = Output::new(Vec::new(), Vec::new(), Vec::new());
+        let summary = Simulation::new(config).run(&mut output).unwrap();
+        let (fills, orders, book) = output.finish().unwrap();
+        (summary, fills, orders, book)
+    }
+
+    #[test]
+    fn reproducible() {
+        let config = Config { steps: 2000, ..Config::default() };
+        let (summary, fills, orders, book) = simulate(config.clone());
+        assert!(summary.trades > 0);
+        assert!(summary.orders > summary.trades);
+        assert_eq!(
+            String::from_utf8(fills.clone()).unwrap().lines().next(),
+            Some("time,order_id,agent,kind,side,price,size,aggressor")
+        );
+
+        // The same seed produces the same output, another seed does not
+        assert_eq!(simulate(config.clone()), (summary, fills.clone(), orders, book));
+        let (_, other, _, _) = simulate(Config { seed: 7, ..config });
+        assert_ne!(other, fills);
+    }
+
+    #[test]
+    fn fundamentalists_track_value() {
+        // Without noise the price follows the fundamental value through the quotes of the liquidity providers
+        let config = Config {
+            steps: 3000,
+            volatility: 0.001,
+            chartists: ChartistConfig { count: 0, ..ChartistConfig::default() },
+            noise_traders: NoiseTraderConfig { count: 0, ..NoiseTraderConfig::default() },
+            fundamentalists: FundamentalistConfig { activity: 0.2, ..FundamentalistConfig::default() },
+            ..Config::default()
+        };
+        let (summary, _, _, _) = simulate(config);
+        assert!((summary.last_price - summary.fundamental).abs() / summary.fundamental < 0.02);
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Config::default().validate().is_ok());
+        let config = Config {
+            noise_traders: NoiseTraderConfig { market_probability: 1.5, ..NoiseTraderConfig::default() },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let config = Config { chartists: ChartistConfig { activity: f64::NAN, ..ChartistConfig::default() }, ..config };
+        assert!(config.validate().is_err());
+
+        // Files are rejected before the agents would panic on sampling the probability
+        let path = std::env::temp_dir().join(format!("simulator-{}.json", std::process::id()));
+        fs::write(&path, r#"{"liquidity_providers": {"activity": -0.1}}"#).unwrap();
+        let result = Config::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use database::{OrderSide, OrderType};
+use serde::Serialize;
+
+use crate::agents::Kind;
+
+// ----------------------------------------------------------------------
+
+/// A fill of an order of an agent. Every trade produces a fill for each of its two sides.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FillRecord {
+    pub time: f64, // Seconds since the start of the simulation
+    pub order_id: i32,
+    pub agent: usize,
+    pub kind: Kind,
+    pub side: OrderSide,
+    pub price: f32,
+    pub size: f32,
+    pub aggressor: Option<OrderSide>,
+}
+
+/// An order submitted or cancelled by an agent. Cancels carry the id of the cancelled order.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OrderRecord {
+    pub time: f64,
+    pub order_id: i32,
+    pub agent: usize,
+    pub kind: Kind,
+    pub action: &'static str, // new or cancel
+    pub side: OrderSide,
+    pub r#type: OrderType,
+    pub price: Option<f32>,
+    pub size: f32,
+}
+
+/// A price level of a snapshot of the book, level 0 being the best price.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LevelRecord {
+    pub time: f64,
+    pub side: OrderSide,
+    pub level: usize,
+    pub price: f32,
+    pub size: f32,
+}
+
+/// Writes the records of a simulation as CSV, one writer per kind of record.
+pub struct Output<W: Write> {
+    fills: csv::Writer<W>,
+    orders: csv::Writer<W>,
+    book: csv::Writer<W>,
+}
+
+impl Output<File> {
+    /// Creates `fills.csv`, `orders.csv` and `book.csv` in the directory.
+    pub fn create(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        Ok(Self::new(
+            File::create(dir.join("fills.csv"))?,
+            File::create(dir.join("orders.csv"))?,
+            File::create(dir.join("book.csv"))?,
+        ))
+    }
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(fills: W, orders: W, book: W) -> Self {
+        Output {
+            fills: csv::Writer::from_writer(fills),
+            orders: csv::Writer::from_writer(orders),
+            book: csv::Writer::from_writer(book),
+        }
+    }
+
+    pub fn fill(&mut self, record: &FillRecord) -> io::Result<()> {
+        self.fills.serialize(record).map_err(io::Error::from)
+    }
+
+    pub fn order(&mut self, record: &OrderRecord) -> io::Result<()> {
+        self.orders.serialize(record).map_err(io::Error::from)
+    }
+
+    pub fn level(&mut self, record: &LevelRecord) -> io::Result<()> {
+        self.book.serialize(record).map_err(io::Error::from)
+    }
+
+    /// Flushes the writers and returns the underlying fills, orders and book writers.
+    pub fn finish(self) -> io::Result<(W, W, W)> {
+        let into_inner = |writer: csv::Writer<W>| writer.into_inner().map_err(|e| e.into_error());
+        Ok((into_inner(self.fills)?, into_inner(self.orders)?, into_inner(self.book)?))
+    }
+}
@@ -1,3 +1,22 @@
+use std::env;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage: api [--config <path>]";
+
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [] => None,
+        [option, path] if option == "--config" => Some(Path::new(path)),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = config::init(path) { // Fail before serving anything
+        eprintln!("{e}");
+        process::exit(2);
+    }
     api::main();
 }
@@ -0,0 +1,26 @@
+use std::env;
+use std::path::Path;
+use std::process;
+
+use fix::Acceptor;
+
+const USAGE: &str = "Usage: fix_acceptor [--config <path>]";
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
+
+#[async_std::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [] => None,
+        [option, path] if option == "--config" => Some(Path::new(path)),
+        _ => exit(USAGE),
+    };
+    let config = config::init(path).unwrap_or_else(|e| exit(&e.to_string())); // Fail before accepting sessions
+    tracing_subscriber::fmt().init(); // Log sessions which end in an error
+    let acceptor = Acceptor::connect("EXCHANGE", "fix_store").await;
+    acceptor.run((config.fix.host.as_str(), config.fix.port)).await.unwrap();
+}
@@ -0,0 +1,60 @@
+use std::env;
+use std::process;
+
+use database::{DatabaseConnection, Engine, Mutation};
+use protocol::RabbitMQ;
+use simulator::{fit, publish, read_arrivals_from, Config, HawkesFlowConfig, Venue};
+
+const USAGE: &str = "Usage: hawkes fit <orders.csv|fills.csv> [decay...]
+       hawkes publish <seconds> [config.json]";
+const DECAYS: [f64; 7] = [0.1, 0.3, 1.0, 3.0, 10.0, 30.0, 100.0]; // Per second
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
+
+/// Creates a client and sub-account of its own for the flow, whose orders the matching engine fills.
+async fn account(db: &DatabaseConnection) -> (i32, i32) {
+    let email = format!("hawkes-{}@example.com", process::id());
+    let client = Mutation::create_client(db, email).await.unwrap_or_else(|e| exit(&format!("Could not create client: {e}")));
+    let sub_account = Mutation::create_sub_account(db, client.id, "Hawkes".to_owned()).await.unwrap();
+    (client.id, sub_account.id)
+}
+
+#[async_std::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["fit", path, decays @ ..] => {
+            let decays: Vec<f64> = if decays.is_empty() {
+                DECAYS.to_vec()
+            } else {
+                decays.iter().map(|decay| decay.parse().unwrap_or_else(|_| exit(USAGE))).collect()
+            };
+            let arrivals = read_arrivals_from(path).unwrap_or_else(|e| exit(&format!("Could not read {path}: {e}")));
+            let horizon = arrivals.last().map_or(0.0, |arrival| arrival.time);
+            let Some(fit) = fit(&arrivals, horizon, &decays, 500).filter(|_| horizon > 0.0) else {
+                exit("Not enough arrivals to fit.");
+            };
+            println!("{}", serde_json::to_string_pretty(&fit).unwrap());
+        }
+        ["publish", seconds, config @ ..] if config.len() <= 1 => {
+            let seconds: f64 = seconds.parse().unwrap_or_else(|_| exit(USAGE));
+            let config = match config.first() {
+                Some(path) => Config::from_file(path).unwrap_or_else(|e| exit(&format!("Could not read {path}: {e}"))),
+                None => Config::default(),
+            };
+            let flow = config.hawkes.clone().unwrap_or_else(HawkesFlowConfig::default);
+            let db = Engine::connect().await.unwrap_or_else(|e| exit(&format!("Could not connect to the database: {e}")));
+            let transport = RabbitMQ::connect().await.unwrap_or_else(|e| exit(&format!("Could not connect: {e}")));
+            let (client_id, sub_account_id) = account(&db).await;
+            let venue = Venue { db: &db, transport: &transport, client_id, sub_account_id, market_id: config.market_id };
+            let published = publish(&venue, &flow, config.initial_price, config.tick_size, seconds, config.seed)
+                .await
+                .unwrap_or_else(|e| exit(&format!("Could not publish: {e}")));
+            println!("Published {published} commands in {seconds} seconds");
+        }
+        _ => exit(USAGE),
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::process;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use futures::StreamExt;
+use hdrhistogram::Histogram;
+use serde_json::json;
+
+use api::Client;
+use database::api_keys::Created;
+use database::fills::Fill;
+use database::{ClientTier, DatabaseConnection, Engine, Mutation};
+use protocol::{Channel, Codec, Offset, RabbitMQ, Transport};
+
+const USAGE: &str = "Usage: load_generator [--orders <n>] [--rate <orders per second>] [--connections <n>] \
+                     [--market <id>] [--price <price>] [--api <host:port>] [--output <directory>] \
+                     [--config <path>]";
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5); // How long to wait for the last fills
+const MAX_LATENCY: u64 = 60_000_000; // Microseconds
+
+/// Arrival time of the fill of every incoming order, by order id.
+type Arrivals = Arc<Mutex<HashMap<i32, Instant>>>;
+
+struct Options {
+    orders: usize, // Pairs of a resting and an incoming order
+    rate: f64,
+    connections: usize,
+    market_id: i32,
+    price: f32,
+    api: SocketAddr,
+    output: Option<String>,
+}
+
+/// What a connection measured.
+struct Run {
+    acknowledged: Histogram<u64>,
+    sent: Vec<(i32, Instant)>, // Incoming orders and when they were due
+    rejected: usize,
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
+
+fn parse() -> Options {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args.chunks(2).find_map(|pair| match pair {
+        [option, path] if option == "--config" => Some(Path::new(path)),
+        _ => None,
+    });
+    let config = config::init(path).unwrap_or_else(|e| exit(&e.to_string()));
+    let api = format!("{}:{}", config.api.host, config.api.port); // Where the API of the configuration listens
+    let mut options = Options {
+        orders: 10_000,
+        rate: 100.0,
+        connections: 4,
+        market_id: 1,
+        price: 100.0,
+        api: resolve(&api).unwrap_or_else(|| exit(&format!("Could not resolve {api}"))),
+        output: None,
+    };
+    for pair in args.chunks(2) {
+        let [option, value] = pair else { exit(USAGE) };
+        match option.as_str() {
+            "--orders" => options.orders = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--rate" => options.rate = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--connections" => options.connections = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--market" => options.market_id = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--price" => options.price = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--api" => options.api = resolve(value).unwrap_or_else(|| exit(USAGE)),
+            "--output" => options.output = Some(value.clone()),
+            "--config" => {} // Read above
+            _ => exit(USAGE),
+        }
+    }
+    if options.rate <= 0.0 || options.connections == 0 {
+        exit(USAGE);
+    }
+    options
+}
+
+fn resolve(address: &str) -> Option<SocketAddr> {
+    address.to_socket_addrs().ok().and_then(|mut addrs| addrs.next())
+}
+
+// ----------------------------------------------------------------------
+
+/// Creates a client of the market maker tier, so that the rate limits of the API allow the load, with a sub-account
+/// and an API key which may trade.
+async fn client(db: &DatabaseConnection, api: SocketAddr, n: usize) -> (Client, i32, i32) {
+    let email = format!("load-{}-{n}@example.com", process::id());
+    let client = Mutation::create_client(db, email).await.unwrap_or_else(|e| exit(&format!("Could not create client: {e}")));
+    Mutation::update_client_tier(db, client.id, ClientTier::MarketMaker).await.unwrap();
+    let sub_account = Mutation::create_sub_account(db, client.id, "Load".to_owned()).await.unwrap();
+    let key = Created::from(Mutation::create_api_key(db, client.id, None, true, true, false).await.unwrap());
+    (Client::new(api, key.key, key.secret), client.id, sub_account.id)
+}
+
+/// Records when the fills of incoming orders arrive, from the moment it returns. Subscriptions are not Send, so the
+/// consumer runs on a thread of its own.
+fn consume_fills(transport: Arc<RabbitMQ>) -> Arrivals {
+    let arrivals = Arrivals::default();
+    let (ready, subscribed) = mpsc::channel();
+    std::thread::spawn({
+        let arrivals = arrivals.clone();
+        move || task::block_on(async move {
+            let mut fills = transport
+                .subscribe(Channel::Fills, Offset::Next)
+                .await
+                .unwrap_or_else(|e| exit(&format!("Could not subscribe to fills: {e}")));
+            ready.send(()).unwrap();
+            while let Some(data) = fills.next().await {
+                let now = Instant::now();
+                if let Ok(fill) = Fill::decode(&data) {
+                    if fill.aggressor.as_ref() == Some(&fill.side) { // The first fill of the incoming order
+                        arrivals.lock().unwrap().entry(fill.order_id).or_insert(now);
+                    }
+                }
+            }
+        })
+    });
+    subscribed.recv().unwrap();
+    arrivals
+}
+
+/// Alternates between a resting ask and an incoming market buy which fills against it, on a fixed schedule.
+/// Latencies are measured from when an order was due rather than when it was sent, so that a slow response does not
+/// hide the delay it causes to the orders behind it.
+async fn generate(client: Client, client_id: i32, sub_account_id: i32, options: Arc<Options>, start: Instant, n: usize) -> Run {
+    let interval = Duration::from_secs_f64(options.connections as f64 / options.rate);
+    let offset = interval.mul_f64(n as f64 / options.connections as f64); // Spread the connections evenly
+    let count = 2 * options.orders.div_ceil(options.connections);
+    let mut run = Run { acknowledged: Histogram::new_with_bounds(1, MAX_LATENCY, 3).unwrap(), sent: Vec::new(), rejected: 0 };
+    let path = format!("/orders/{client_id}");
+    for i in 0..count {
+        let due = start + offset + interval * i as u32;
+        task::sleep(due.saturating_duration_since(Instant::now())).await;
+        let (side, r#type, price) = if i % 2 == 0 { ("Ask", "Limit", Some(options.price)) } else { ("Buy", "Market", None) };
+        let body = json!({
+            "sub_account_id": sub_account_id,
+            "size": 1.0,
+            "side": side,
+            "type": r#type,
+            "price": price,
+            "market_id": options.market_id,
+        });
+        match client.request("POST", &path, Some(&body)).await {
+            Ok((200, order)) => {
+                let _ = run.acknowledged.record(due.elapsed().as_micros() as u64);
+                if let (Some(id), "Market") = (order["id"].as_i64(), r#type) {
+                    run.sent.push((id as i32, due));
+                }
+            }
+            _ => run.rejected += 1,
+        }
+    }
+    run
+}
+
+// ----------------------------------------------------------------------
+
+/// The percentile distribution in the text format of HdrHistogram, in milliseconds, which its plotter reads.
+fn distribution(histogram: &Histogram<u64>) -> String {
+    let mut text = format!("{:>12} {:>14} {:>10} {:>14}\n\n", "Value", "Percentile", "TotalCount", "1/(1-Percentile)");
+    let mut total = 0;
+    for value in histogram.iter_quantiles(5) {
+        total += value.count_since_last_iteration();
+        let quantile = value.quantile_iterated_to();
+        let _ = write!(text, "{:12.3} {:14.12} {total:10}", value.value_iterated_to() as f64 / 1000.0, quantile);
+        if quantile < 1.0 {
+            let _ = write!(text, " {:14.2}", 1.0 / (1.0 - quantile));
+        }
+        text.push('\n');
+    }
+    let _ = writeln!(
+        text,
+        "#[Mean    = {:12.3}, StdDeviation   = {:12.3}]\n#[Max     = {:12.3}, Total count    = {:12}]",
+        histogram.mean() / 1000.0,
+        histogram.stdev() / 1000.0,
+        histogram.max() as f64 / 1000.0,
+        histogram.len(),
+    );
+    text
+}
+
+fn summary(name: &str, histogram: &Histogram<u64>) -> String {
+    let percentile = |quantile: f64| histogram.value_at_quantile(quantile) as f64 / 1000.0;
+    format!(
+        "{name:<14} count {:>8}  p50 {:>9.3} ms  p99 {:>9.3} ms  p99.9 {:>9.3} ms  max {:>9.3} ms",
+        histogram.len(),
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+        histogram.max() as f64 / 1000.0,
+    )
+}
+
+#[async_std::main]
+async fn main() {
+    let options = Arc::new(parse());
+    let db = Engine::connect().await.unwrap_or_else(|e| exit(&format!("Could not connect to the database: {e}")));
+    let transport = Arc::new(RabbitMQ::connect().await.unwrap_or_else(|e| exit(&format!("Could not connect: {e}"))));
+    let arrivals = consume_fills(transport);
+
+    let mut clients = Vec::new();
+    for n in 0..options.connections {
+        clients.push(client(&db, options.api, n).await);
+    }
+    let start = Instant::now();
+    let workers: Vec<_> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(n, (client, client_id, sub_account_id))| {
+            task::spawn(generate(client, client_id, sub_account_id, options.clone(), start, n))
+        })
+        .collect();
+    let runs = futures::future::join_all(workers).await;
+    let elapsed = start.elapsed();
+
+    // Wait for the fills of the last orders
+    let sent: Vec<(i32, Instant)> = runs.iter().flat_map(|run| run.sent.iter().copied()).collect();
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while Instant::now() < deadline && sent.iter().any(|(id, _)| !arrivals.lock().unwrap().contains_key(id)) {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    let arrivals = arrivals.lock().unwrap();
+    let mut acknowledged = Histogram::<u64>::new_with_bounds(1, MAX_LATENCY, 3).unwrap();
+    let mut filled = acknowledged.clone();
+    for run in &runs {
+        acknowledged.add(&run.acknowledged).unwrap();
+    }
+    for (id, due) in &sent {
+        if let Some(arrival) = arrivals.get(id) {
+            let _ = filled.record(arrival.saturating_duration_since(*due).as_micros() as u64);
+        }
+    }
+
+    let rejected: usize = runs.iter().map(|run| run.rejected).sum();
+    println!(
+        "Sent {} orders in {:.1} s ({:.0} per second), {rejected} rejected, {} incoming orders without fills",
+        acknowledged.len() as usize + rejected,
+        elapsed.as_secs_f64(),
+        (acknowledged.len() as usize + rejected) as f64 / elapsed.as_secs_f64(),
+        sent.len() - filled.len() as usize,
+    );
+    println!("{}", summary("order to ack", &acknowledged));
+    println!("{}", summary("order to fill", &filled));
+    if let Some(directory) = &options.output {
+        fs::create_dir_all(directory).unwrap_or_else(|e| exit(&format!("Could not create {directory}: {e}")));
+        for (name, histogram) in [("order_to_ack.hgrm", &acknowledged), ("order_to_fill.hgrm", &filled)] {
+            let path = Path::new(directory).join(name);
+            fs::write(&path, distribution(histogram)).unwrap_or_else(|e| exit(&format!("Could not write {path:?}: {e}")));
+        }
+    }
+}
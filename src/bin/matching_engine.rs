@@ -1,7 +1,42 @@
-use orderbook::OrderBook;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use async_std::net::TcpListener;
+use async_std::task;
+
+use orderbook::{serve_metrics, OrderBook};
+use protocol::RabbitMQ;
+
+const USAGE: &str = "Usage: matching_engine [--config <path>] [--record <path>]";
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
 
 #[async_std::main]
 async fn main() {
-    let mut orderbook = OrderBook::new(1).await;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (mut path, mut recording) = (None, None);
+    for pair in args.chunks(2) {
+        match pair {
+            [option, value] if option == "--config" => path = Some(Path::new(value)),
+            [option, value] if option == "--record" => recording = Some(value), // Inputs and outputs, see the replay binary
+            _ => exit(USAGE),
+        }
+    }
+    let config = config::init(path).unwrap_or_else(|e| exit(&e.to_string())); // Fail before consuming anything
+    tracing_subscriber::fmt().init(); // Log failures to persist circuit breakers and market statuses
+    let admin = (config.engine.admin_host.as_str(), config.engine.admin_port);
+    let admin = TcpListener::bind(admin).await.unwrap_or_else(|e| exit(&format!("Could not bind the admin port: {e}")));
+    task::spawn(serve_metrics(admin));
+
+    let transport = RabbitMQ::connect().await.unwrap(); // Allow to panic if unsuccessful
+    let mut orderbook = OrderBook::connect(config.engine.market_id, Arc::new(transport)).await;
+    if let Some(path) = recording {
+        orderbook.record_to(path).unwrap();
+    }
     orderbook.run().await;
-}
\ No newline at end of file
+}
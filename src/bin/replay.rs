@@ -0,0 +1,52 @@
+use std::env;
+use std::process;
+
+use orderbook::{replay, OrderBook, Recording};
+
+const USAGE: &str = "Usage: replay <path> [--speed <factor>|max] [--circuit-breaker]";
+
+#[async_std::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(path) = args.first() else {
+        eprintln!("{USAGE}");
+        process::exit(2);
+    };
+    let mut speed = Some(1.0); // Original pace
+    let mut circuit_breaker = false;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        match (option.as_str(), options.clone().next().map(String::as_str)) {
+            ("--speed", Some("max")) => speed = None,
+            ("--speed", Some(factor)) if factor.parse::<f64>().is_ok_and(|factor| factor > 0.0) => {
+                speed = factor.parse().ok()
+            }
+            ("--circuit-breaker", _) => {
+                circuit_breaker = true;
+                continue;
+            }
+            _ => {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            }
+        }
+        options.next();
+    }
+
+    let recording = Recording::open(path).unwrap_or_else(|e| {
+        eprintln!("Could not open {path}: {e}");
+        process::exit(2);
+    });
+    let mut orderbook = OrderBook::detached(recording.market_id);
+    if circuit_breaker {
+        orderbook.load_circuit_breaker().await;
+    }
+    let report = replay(&mut orderbook, recording, speed).await.unwrap_or_else(|e| {
+        eprintln!("Could not read {path}: {e}");
+        process::exit(2);
+    });
+    println!("{report}");
+    if !report.matches() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,28 @@
+use std::env;
+use std::process;
+
+use simulator::{Config, Output, Simulation};
+
+const USAGE: &str = "Usage: simulator <output directory> [config.json]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(dir) = args.first() else {
+        eprintln!("{USAGE}");
+        process::exit(2);
+    };
+    let config = match args.get(1) {
+        Some(path) => Config::from_file(path).unwrap_or_else(|e| {
+            eprintln!("Could not read {path}: {e}");
+            process::exit(2);
+        }),
+        None => Config::default(),
+    };
+    let mut output = Output::create(dir).unwrap_or_else(|e| {
+        eprintln!("Could not create {dir}: {e}");
+        process::exit(2);
+    });
+    let summary = Simulation::new(config).run(&mut output).unwrap();
+    output.finish().unwrap();
+    println!("{summary}");
+}
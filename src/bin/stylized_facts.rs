@@ -0,0 +1,29 @@
+use std::env;
+use std::process;
+
+use simulator::{analyze, AnalyticsConfig};
+
+const USAGE: &str = "Usage: stylized_facts <simulation directory|fills.csv|trades.csv> [config.json]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (Some(path), None) = (args.first(), args.get(2)) else {
+        eprintln!("{USAGE}");
+        process::exit(2);
+    };
+    let config: AnalyticsConfig = match args.get(1) {
+        Some(config) => std::fs::read_to_string(config)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Could not read {config}: {e}");
+                process::exit(2);
+            }),
+        None => AnalyticsConfig::default(),
+    };
+    let report = analyze(path, config).unwrap_or_else(|e| {
+        eprintln!("Could not analyze {path}: {e}");
+        process::exit(2);
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
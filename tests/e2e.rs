@@ -0,0 +1,237 @@
+use std::net::{SocketAddr, TcpListener};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use serde_json::{json, Value};
+
+use database::{DatabaseConnection, Engine, Migrator, MigratorTrait, Mutation};
+use database::api_keys::Created;
+use orderbook::OrderBook;
+use protocol::{InMemory, Transport};
+
+// ----------------------------------------------------------------------
+
+const MARKET_ID: i32 = 1; // The matching engine serves a single market
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const POLL_ATTEMPTS: usize = 50;
+
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+/// The API, the matching engine and the fill persister of the API running in this process. They communicate through
+/// the in-memory transport and share a database of their own on the postgres server given by the environment.
+struct Exchange {
+    address: SocketAddr,
+    db: DatabaseConnection,
+    database: String,
+    transport: Arc<InMemory>,
+}
+
+impl Exchange {
+    async fn start() -> Self {
+        let database = format!("exchange_e2e_{}_{}", process::id(), DATABASES.fetch_add(1, Ordering::Relaxed));
+        let db = Engine::create_database(&database).await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        Mutation::create_market(&db, "BTC".to_owned(), "USD".to_owned(), 0.01, 0.01).await.unwrap();
+
+        let transport = Arc::new(InMemory::new());
+        let mut orderbook = OrderBook::load(MARKET_ID, transport.clone(), db.clone()).await;
+        actix_web::rt::spawn(async move { orderbook.run().await });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_web::rt::spawn(api::serve(db.clone(), transport.clone(), listener));
+        Exchange { address, db, database, transport }
+    }
+
+    /// Creates a client with a sub-account and an API key which may trade.
+    async fn client(&self, email: &str) -> Client {
+        let client = Mutation::create_client(&self.db, email.to_owned()).await.unwrap();
+        let sub_account = Mutation::create_sub_account(&self.db, client.id, "Main".to_owned()).await.unwrap();
+        let key = Mutation::create_api_key(&self.db, client.id, None, true, true, false).await.unwrap();
+        let key = Created::from(key);
+        Client {
+            http: api::Client::new(self.address, key.key, key.secret),
+            id: client.id,
+            sub_account_id: sub_account.id,
+        }
+    }
+
+    /// The metrics served by the API, in the text format of Prometheus.
+    async fn scrape(&self) -> String {
+        let mut stream = TcpStream::connect(self.address).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        response
+    }
+}
+
+impl Drop for Exchange {
+    /// Stops the matching engine and the consumers of the API, and drops the database, also when a test fails.
+    fn drop(&mut self) {
+        let transport = self.transport.clone();
+        let database = self.database.clone();
+        let _ = std::thread::spawn(move || async_std::task::block_on(async move { // Outside of the actix runtime
+            transport.close().await;
+            Engine::drop_database(&database).await.unwrap();
+        }))
+        .join();
+    }
+}
+
+// ----------------------------------------------------------------------
+
+/// A client of the exchange trading through the API.
+struct Client {
+    http: api::Client,
+    id: i32,
+    sub_account_id: i32,
+}
+
+impl Client {
+    async fn request(&self, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        self.http.request(method, path, body.as_ref()).await.unwrap()
+    }
+
+    async fn order(&self, side: &str, r#type: &str, price: Option<f32>, size: f32) -> Value {
+        let body = json!({
+            "sub_account_id": self.sub_account_id,
+            "size": size,
+            "side": side,
+            "type": r#type,
+            "price": price,
+            "market_id": MARKET_ID,
+        });
+        let (status, order) = self.request("POST", &format!("/orders/{}", self.id), Some(body)).await;
+        assert_eq!(status, 200, "{order}");
+        order
+    }
+
+    /// Polls a page of the client until its records satisfy the condition, since fills are persisted asynchronously.
+    async fn poll(&self, resource: &str, condition: impl Fn(&[Value]) -> bool) -> Vec<Value> {
+        let path = format!("/{resource}/{}", self.id);
+        for _ in 0..POLL_ATTEMPTS {
+            let (status, page) = self.request("GET", &path, None).await;
+            assert_eq!(status, 200, "{page}");
+            let records = page["data"].as_array().cloned().unwrap_or_default();
+            if condition(&records) {
+                return records;
+            }
+            actix_web::rt::time::sleep(POLL_INTERVAL).await;
+        }
+        let (_, page) = self.request("GET", &path, None).await;
+        panic!("{path} did not reach the expected state: {page}");
+    }
+}
+
+fn number(value: &Value) -> f64 {
+    value.as_f64().unwrap()
+}
+
+// ----------------------------------------------------------------------
+
+#[actix_web::test]
+async fn crossing_orders() {
+    let exchange = Exchange::start().await;
+    let maker = exchange.client("maker@gmail.com").await;
+    let taker = exchange.client("taker@gmail.com").await;
+
+    // A marketable limit order partially fills the resting order at its price
+    let ask = maker.order("Ask", "Limit", Some(100.0), 10.0).await;
+    let bid = taker.order("Bid", "Limit", Some(101.0), 4.0).await;
+    for (client, side) in [(&taker, "Bid"), (&maker, "Ask")] {
+        let fills = client.poll("fills", |fills| !fills.is_empty()).await;
+        assert_eq!(fills.len(), 1);
+        assert_eq!((number(&fills[0]["price"]), number(&fills[0]["size"])), (100.0, 4.0));
+        assert_eq!(fills[0]["side"], side);
+    }
+
+    // The taker order is closed and the maker order is still open
+    let orders = taker.poll("orders", |orders| orders.iter().all(|order| order["status"] == "Closed")).await;
+    assert_eq!((orders[0]["id"].clone(), number(&orders[0]["filled_size"])), (bid["id"].clone(), 4.0));
+    let orders = maker.poll("orders", |orders| orders.iter().all(|order| number(&order["filled_size"]) == 4.0)).await;
+    assert_eq!((orders[0]["id"].clone(), orders[0]["status"].clone()), (ask["id"].clone(), json!("Open")));
+
+    // Positions are opposite
+    for (client, side) in [(&taker, "Long"), (&maker, "Short")] {
+        let positions = client.poll("positions", |positions| !positions.is_empty()).await;
+        assert_eq!(positions[0]["side"], side);
+        assert_eq!((number(&positions[0]["size"]), number(&positions[0]["avg_entry_price"])), (4.0, 100.0));
+    }
+
+    // Cancelled orders no longer match
+    let (status, _) = maker.request("POST", &format!("/orders/{}/cancel_all", maker.id), None).await;
+    assert_eq!(status, 202);
+    maker.poll("orders", |orders| orders.iter().all(|order| order["status"] == "Closed")).await;
+    taker.order("Bid", "Limit", Some(101.0), 6.0).await;
+    maker.order("Ask", "Limit", Some(101.0), 1.0).await;
+    let fills = taker.poll("fills", |fills| fills.len() == 2).await;
+    assert_eq!((number(&fills[1]["price"]), number(&fills[1]["size"])), (101.0, 1.0));
+
+}
+
+#[actix_web::test]
+async fn positions_net_fills() {
+    let exchange = Exchange::start().await;
+    let maker = exchange.client("maker@gmail.com").await;
+    let taker = exchange.client("taker@gmail.com").await;
+
+    // A market order sweeps two levels, entering at their average price
+    maker.order("Ask", "Limit", Some(100.0), 5.0).await;
+    maker.order("Ask", "Limit", Some(102.0), 5.0).await;
+    taker.order("Buy", "Market", None, 10.0).await;
+    for (client, side) in [(&taker, "Long"), (&maker, "Short")] {
+        let positions = client.poll("positions", |positions| {
+            positions.first().is_some_and(|position| number(&position["size"]) == 10.0)
+        }).await;
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0]["side"], side);
+        assert_eq!(number(&positions[0]["avg_entry_price"]), 101.0);
+    }
+    let orders = maker.poll("orders", |orders| orders.iter().all(|order| order["status"] == "Closed")).await;
+    assert_eq!(orders.len(), 2);
+
+    // Reducing a position keeps its entry price, and flipping it enters at the price of the fill
+    taker.order("Ask", "Limit", Some(99.0), 4.0).await;
+    maker.order("Buy", "Market", None, 4.0).await;
+    let positions = taker.poll("positions", |positions| number(&positions[0]["size"]) == 6.0).await;
+    assert_eq!((positions[0]["side"].clone(), number(&positions[0]["avg_entry_price"])), (json!("Long"), 101.0));
+    taker.order("Ask", "Limit", Some(98.0), 8.0).await;
+    maker.order("Buy", "Market", None, 8.0).await;
+    let positions = taker.poll("positions", |positions| positions[0]["side"] == "Short").await;
+    assert_eq!((number(&positions[0]["size"]), number(&positions[0]["avg_entry_price"])), (2.0, 98.0));
+    let positions = maker.poll("positions", |positions| positions[0]["side"] == "Long").await;
+    assert_eq!((number(&positions[0]["size"]), number(&positions[0]["avg_entry_price"])), (2.0, 98.0));
+
+}
+
+#[actix_web::test]
+async fn metrics() {
+    let exchange = Exchange::start().await;
+    let maker = exchange.client("maker@gmail.com").await;
+    let taker = exchange.client("taker@gmail.com").await;
+
+    maker.order("Ask", "Limit", Some(100.0), 1.0).await;
+    taker.order("Bid", "Limit", Some(100.0), 1.0).await;
+    taker.poll("fills", |fills| !fills.is_empty()).await;
+    let body = json!({"sub_account_id": taker.sub_account_id, "size": 1.0, "side": "Bid", "type": "Market", "market_id": MARKET_ID});
+    let (status, _) = taker.request("POST", &format!("/orders/{}", maker.id), Some(body)).await; // Not its own client
+    assert_eq!(status, 403);
+
+    // Requests are timed by route, and the matches and queries they cause are measured
+    let metrics = exchange.scrape().await;
+    for series in [
+        r#"api_request_duration_seconds_count{method="POST",route="/orders/{client_id}",status="200"}"#,
+        r#"api_request_duration_seconds_count{method="GET",route="/fills/{client_id}",status="200"}"#,
+        "api_orders_accepted_total",
+        r#"api_orders_rejected_total{reason="forbidden"}"#,
+        r#"engine_matches_total{market="1"}"#,
+        r#"db_query_duration_seconds_count{operation="insert",outcome="ok"}"#,
+    ] {
+        assert!(metrics.contains(series), "{series} missing from {metrics}");
+    }
+}